@@ -0,0 +1,20 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../src/output/lint.rs"]
+mod lint;
+#[path = "../../src/output/parse.rs"]
+mod parse;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = lint::lint_hostsfile(data, std::net::Ipv4Addr::UNSPECIFIED.into());
+    let _ = lint::lint_lua(data);
+    let _ = lint::lint_rpz(data, std::net::Ipv4Addr::UNSPECIFIED.into());
+    let _ = lint::lint_sha256(data);
+    let _ = lint::lint_regex(data);
+    let _ = lint::lint_sni(data);
+    let _ = lint::lint_bpf(data);
+    if let Ok(hostname) = std::str::from_utf8(data) {
+        let _ = lint::is_valid_hostname(hostname);
+    }
+});
@@ -0,0 +1,323 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{config::Config, peer::file_digest, report::unix_timestamp, DOWNLOAD_PATH};
+
+/// Version identifies the contents of a downloaded list or an output file
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Version {
+    /// the id of the list or the file name of the output
+    pub name: String,
+    /// hex encoded SHA-256 digest of the file, None if it doesn't exist
+    pub sha256: Option<String>,
+}
+
+impl Version {
+    /// returns the version of a file
+    ///
+    /// * `name`: the id of the list or the file name of the output
+    /// * `path`: the file system path of the file
+    fn of(name: &str, path: &Path) -> Self {
+        Self {
+            name: name.to_string(),
+            sha256: file_digest(path).ok(),
+        }
+    }
+}
+
+/// AuditRecord documents the configuration, the list versions and the outputs of a
+/// run, so it can be proven what was blocked when
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AuditRecord {
+    /// the id of the run, shared by the records of all projects built in the run
+    pub run: String,
+    /// unix timestamp of the end of the run
+    pub timestamp: u64,
+    /// the project the outputs were built for
+    pub project: Option<String>,
+    /// hex encoded SHA-256 digest of the configuration
+    pub config_sha256: String,
+    /// the downloaded lists the outputs were built from
+    pub lists: Vec<Version>,
+    /// the directory containing the output files
+    pub output_dir: String,
+    /// the output files after the run
+    pub outputs: Vec<Version>,
+}
+
+impl AuditRecord {
+    /// creates the record of a finished run and copies the configuration and the
+    /// downloaded lists into the input store, so the run can be rebuilt later on
+    ///
+    /// * `config`: the configuration the run was performed with
+    /// * `run`: the id of the run
+    /// * `store`: the directory of the input store, if any
+    pub fn new(config: &Config, run: &str, store: Option<&Path>) -> anyhow::Result<Self> {
+        // the previous configuration isn't part of the one in effect
+        let mut effective = config.clone();
+        effective.cached_config = None;
+        let config_json = serde_json::to_vec(&effective)?;
+        let config_sha256: String = Sha256::digest(&config_json)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        let download_dir = config.download_dir(DOWNLOAD_PATH);
+        let lists: Vec<Version> = config
+            .lists
+            .iter()
+            .map(|l| Version::of(&l.id, &download_dir.join(&l.id)))
+            .collect();
+        if let Some(store) = store {
+            fs::create_dir_all(store)
+                .with_context(|| format!("could not create input store {:?}", store))?;
+            let config_path = store.join(&config_sha256);
+            if !config_path.exists() {
+                store_contents(&config_path, &config_json)?;
+            }
+            for list in lists.iter() {
+                if let Some(sha256) = &list.sha256 {
+                    store_file(&download_dir.join(&list.name), &store.join(sha256))?;
+                }
+            }
+        }
+        let output_dir = Path::new(config.build_dir());
+        let outputs = config
+            .get_tags()
+            .iter()
+            .flat_map(|tag| config.output_files(tag))
+            .map(|(file_name, _)| Version::of(&file_name, &output_dir.join(&file_name)))
+            .collect();
+        Ok(Self {
+            run: run.to_string(),
+            timestamp: unix_timestamp(),
+            project: config.project.clone(),
+            config_sha256,
+            lists,
+            output_dir: config.build_dir().to_string(),
+            outputs,
+        })
+    }
+
+    /// appends the record as a line of JSON to the audit log, existing records are
+    /// never modified
+    ///
+    /// * `path`: the file system path of the audit log
+    pub fn append(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| "could not create audit log directory")?;
+        }
+        let mut line = serde_json::to_string(self)?;
+        line.push('\n');
+        // a single write keeps the lines of concurrent runs apart
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .with_context(|| format!("could not append to audit log {:?}", path))
+    }
+
+    /// returns the configuration the run of the record was performed with, which
+    /// builds into the given cache directory from the downloads restored from the
+    /// input store without publishing anything
+    ///
+    /// * `store`: the directory of the input store
+    /// * `cache_dir`: the cache directory the run is rebuilt in
+    pub fn replay_config(&self, store: &Path, cache_dir: &Path) -> anyhow::Result<Config> {
+        let stored = fs::read(store.join(&self.config_sha256)).with_context(|| {
+            format!(
+                "configuration {} of run {} not found in the input store",
+                self.config_sha256, self.run
+            )
+        })?;
+        let stored: Config =
+            serde_json::from_slice(&stored).with_context(|| "invalid stored configuration")?;
+
+        let mut config = stored.dry_run_config();
+        config.cache_dir = cache_dir.to_string_lossy().to_string();
+        config.output_dir = cache_dir.join("output").to_string_lossy().to_string();
+        config.projects = vec![];
+        config.project = self.project.clone();
+        // every list is extracted and every output is written again
+        config.cached_config = None;
+
+        let download_dir = config.download_dir(DOWNLOAD_PATH);
+        fs::create_dir_all(&download_dir)
+            .with_context(|| format!("could not create {:?}", download_dir))?;
+        for list in self.lists.iter() {
+            let sha256 = match &list.sha256 {
+                Some(sha256) => sha256,
+                None => {
+                    warn!("{}: no download recorded in run {}", list.name, self.run);
+                    continue;
+                }
+            };
+            fs::copy(store.join(sha256), download_dir.join(&list.name)).with_context(|| {
+                format!(
+                    "download {} of {} not found in the input store",
+                    sha256, list.name
+                )
+            })?;
+        }
+        Ok(config)
+    }
+
+    /// returns the names of the recorded outputs whose rebuilt file differs
+    ///
+    /// * `output_dir`: the directory containing the rebuilt output files
+    pub fn differing_outputs(&self, output_dir: &Path) -> Vec<String> {
+        self.outputs
+            .iter()
+            .filter(|o| Version::of(&o.name, &output_dir.join(&o.name)).sha256 != o.sha256)
+            .map(|o| o.name.clone())
+            .collect()
+    }
+}
+
+/// returns the records of a run found in the audit log
+///
+/// * `path`: the file system path of the audit log
+/// * `run`: the id of the run
+pub fn find_records(path: &Path, run: &str) -> anyhow::Result<Vec<AuditRecord>> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("could not read audit log {:?}", path))?;
+    let mut records = vec![];
+    for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+        let record: AuditRecord = serde_json::from_str(line)
+            .with_context(|| format!("invalid record in audit log {:?}", path))?;
+        if record.run == run {
+            records.push(record);
+        }
+    }
+    Ok(records)
+}
+
+/// copies a file into the input store unless it's stored already
+///
+/// * `from`: the file system path of the file
+/// * `to`: the file system path within the store named after the file's digest
+fn store_file(from: &Path, to: &Path) -> anyhow::Result<()> {
+    if to.exists() {
+        return Ok(());
+    }
+    let contents = fs::read(from).with_context(|| format!("could not read {:?}", from))?;
+    store_contents(to, &contents)
+}
+
+/// writes contents into the input store, a partial file is never visible under its
+/// digest
+///
+/// * `to`: the file system path within the store named after the digest
+/// * `contents`: the contents to be stored
+fn store_contents(to: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    let mut tmp_path = PathBuf::from(to);
+    tmp_path.set_file_name(format!(
+        ".{}.tmp",
+        to.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    fs::write(&tmp_path, contents).with_context(|| format!("could not write {:?}", tmp_path))?;
+    fs::rename(&tmp_path, to).with_context(|| format!("could not store {:?}", to))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{filter_list::FilterList, tests::helper::cache_file_creator::CacheFileCreator};
+
+    use super::*;
+
+    #[test]
+    fn test_audit_record() {
+        let mut config = CacheFileCreator::new("test_audit_record", "in", "out").new_test_config();
+        config.lists = vec![
+            FilterList {
+                id: "downloaded".to_string(),
+                tags: vec!["malware".to_string()],
+                ..Default::default()
+            },
+            FilterList {
+                id: "missing".to_string(),
+                tags: vec!["malware".to_string()],
+                ..Default::default()
+            },
+        ];
+        let download_dir = config.download_dir(DOWNLOAD_PATH);
+        fs::create_dir_all(&download_dir).unwrap();
+        fs::write(download_dir.join("downloaded"), "one.domain\n").unwrap();
+        fs::create_dir_all(&config.output_dir).unwrap();
+        fs::write(
+            Path::new(&config.output_dir).join("malware"),
+            "0.0.0.0 one.domain\n",
+        )
+        .unwrap();
+        let log_path = Path::new(&config.cache_dir).join("audit.log");
+        fs::remove_file(&log_path).ok();
+
+        let record = AuditRecord::new(&config, "1", None).unwrap();
+        assert!(record.lists[0].sha256.is_some());
+        assert_eq!(record.lists[1].sha256, None);
+        assert_eq!(record.outputs[0].name, "malware");
+        assert!(record.outputs[0].sha256.is_some());
+        record.append(&log_path).unwrap();
+
+        // a changed configuration is told apart by its digest
+        config.output_format = crate::output::OutputType::Rpz;
+        let changed = AuditRecord::new(&config, "2", None).unwrap();
+        assert_ne!(changed.config_sha256, record.config_sha256);
+        changed.append(&log_path).unwrap();
+
+        let records: Vec<AuditRecord> = fs::read_to_string(&log_path)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(records, vec![record, changed.clone()]);
+        assert_eq!(find_records(&log_path, "2").unwrap(), vec![changed]);
+    }
+
+    #[test]
+    fn test_replay_config() {
+        let mut config = CacheFileCreator::new("test_replay_config", "in", "out").new_test_config();
+        config.lists = vec![FilterList {
+            id: "downloaded".to_string(),
+            tags: vec!["malware".to_string()],
+            ..Default::default()
+        }];
+        config.audit_log = Some("audit.log".to_string());
+        let download_dir = config.download_dir(DOWNLOAD_PATH);
+        fs::create_dir_all(&download_dir).unwrap();
+        fs::write(download_dir.join("downloaded"), "one.domain\n").unwrap();
+        fs::create_dir_all(&config.output_dir).unwrap();
+        fs::write(
+            Path::new(&config.output_dir).join("malware"),
+            "0.0.0.0 one.domain\n",
+        )
+        .unwrap();
+        let store = Path::new(&config.cache_dir).join("store");
+        let record = AuditRecord::new(&config, "1", Some(&store)).unwrap();
+
+        // the download changes after the run
+        fs::write(download_dir.join("downloaded"), "two.domain\n").unwrap();
+        let replay_dir = Path::new(&config.cache_dir).join("replay");
+        let replay = record.replay_config(&store, &replay_dir).unwrap();
+        assert_eq!(replay.cache_dir, replay_dir.to_string_lossy());
+        assert_eq!(replay.audit_log, None);
+        assert_eq!(replay.lists[0].id, "downloaded");
+        let restored = replay.download_dir(DOWNLOAD_PATH).join("downloaded");
+        assert_eq!(fs::read_to_string(restored).unwrap(), "one.domain\n");
+
+        let output_dir = Path::new(&replay.output_dir);
+        fs::create_dir_all(output_dir).unwrap();
+        fs::write(output_dir.join("malware"), "0.0.0.0 one.domain\n").unwrap();
+        assert!(record.differing_outputs(output_dir).is_empty());
+        fs::write(output_dir.join("malware"), "0.0.0.0 two.domain\n").unwrap();
+        assert_eq!(record.differing_outputs(output_dir), vec!["malware"]);
+    }
+}
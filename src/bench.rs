@@ -0,0 +1,288 @@
+#[cfg(feature = "alloc-stats")]
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::{
+    collections::BTreeSet,
+    fmt,
+    fs::{self, File},
+    io::Write,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use bytes::Bytes;
+use futures::lock::Mutex;
+
+use crate::{
+    config::Config,
+    filter_list::FilterList,
+    input::{file::FileInput, Input},
+    io::entry_file::EntryFile,
+    output::hostsfile::DEFAULT_ADDRESS,
+    stages::extract::{regex_match, ListRegex},
+};
+
+/// Sub path for the files written by the bench sub command
+pub const BENCH_PATH: &str = "bench";
+/// regex extracting the entries of the synthetic hosts file lines
+const SYNTHETIC_REGEX: &str = r"^0\.0\.0\.0 (.+)$";
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// CountingAllocator counts the allocations of the process, so the bench sub command
+/// can report them per stage. It's only installed with the alloc-stats feature.
+#[cfg(feature = "alloc-stats")]
+pub struct CountingAllocator;
+
+#[cfg(feature = "alloc-stats")]
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size as u64, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[cfg(feature = "alloc-stats")]
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// AllocStats are the allocations made by the whole process during a stage
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AllocStats {
+    pub allocations: u64,
+    pub bytes: u64,
+}
+
+impl AllocStats {
+    /// returns the allocations made so far, None if they aren't counted
+    fn now() -> Option<Self> {
+        if !cfg!(feature = "alloc-stats") {
+            return None;
+        }
+        Some(Self {
+            allocations: ALLOCATIONS.load(Ordering::Relaxed),
+            bytes: ALLOCATED_BYTES.load(Ordering::Relaxed),
+        })
+    }
+
+    /// returns the allocations made since an earlier snapshot
+    ///
+    /// * `earlier`: the snapshot taken at the start of a stage
+    fn since(earlier: Option<Self>) -> Option<Self> {
+        let (now, earlier) = (Self::now()?, earlier?);
+        Some(Self {
+            allocations: now.allocations - earlier.allocations,
+            bytes: now.bytes - earlier.bytes,
+        })
+    }
+}
+
+/// StageStats are the throughput of a stage of the pipeline
+#[derive(Debug, Clone, PartialEq)]
+pub struct StageStats {
+    pub stage: String,
+    /// number of entries the stage produced
+    pub entries: usize,
+    pub duration: Duration,
+    pub allocations: Option<AllocStats>,
+}
+
+impl StageStats {
+    /// returns the entries produced per second
+    pub fn entries_per_sec(&self) -> f64 {
+        self.entries as f64 / self.duration.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+impl fmt::Display for StageStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<20} {:>10} {:>10.3}s {:>12.0}/s",
+            self.stage,
+            self.entries,
+            self.duration.as_secs_f64(),
+            self.entries_per_sec()
+        )?;
+        match self.allocations {
+            Some(a) => write!(
+                f,
+                " {:>12} allocations {:>10.1} MiB",
+                a.allocations,
+                a.bytes as f64 / (1024.0 * 1024.0)
+            ),
+            None => Ok(()),
+        }
+    }
+}
+
+/// returns lines of a hosts file with distinct, made up domains
+///
+/// * `count`: the number of lines
+fn synthetic_lines(count: usize) -> Vec<String> {
+    // a linear congruential generator varies the labels reproducibly
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    (0..count)
+        .map(|i| {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            let label: String = (0..(state >> 60) as usize + 4)
+                .map(|n| (b'a' + (state.rotate_right(n as u32 * 5) % 26) as u8) as char)
+                .collect();
+            format!("0.0.0.0 {}-{}.example.com", label, i)
+        })
+        .collect()
+}
+
+/// runs lines through the extraction, normalization and every output format of the
+/// configuration and returns the throughput of each stage. The outputs are written
+/// to the bench directory within the cache directory.
+///
+/// * `config`: the configuration whose output formats are measured
+/// * `input`: a recorded list, synthetic lines are generated if None
+/// * `regex`: the regex extracting the entries of the recorded list
+/// * `entries`: the number of synthetic lines
+pub async fn bench(
+    config: &Config,
+    input: Option<&Path>,
+    regex: Option<&str>,
+    entries: usize,
+) -> anyhow::Result<Vec<StageStats>> {
+    let bench_path = Path::new(&config.cache_dir).join(BENCH_PATH);
+    fs::create_dir_all(&bench_path)
+        .with_context(|| format!("could not create {:?}", bench_path))?;
+    let (lines, regex) = match input {
+        Some(path) => {
+            let mut file = FileInput::new(path.to_path_buf(), None);
+            let mut lines = vec![];
+            while let Some(chunk) = file.chunk().await? {
+                lines.push(String::from_utf8_lossy(&chunk).to_string());
+            }
+            (lines, regex.unwrap_or("^(.+)$"))
+        }
+        None => (synthetic_lines(entries), SYNTHETIC_REGEX),
+    };
+    let list = Arc::new(ListRegex::new(&FilterList {
+        id: BENCH_PATH.to_string(),
+        regex: regex.to_string(),
+        ..Default::default()
+    })?);
+    let mut stats = vec![];
+
+    // the extraction applies the list's regex to every line
+    let (start, allocations) = (Instant::now(), AllocStats::now());
+    let mut extracted = vec![];
+    for line in lines {
+        if let Some(entry) = regex_match(list.clone(), Some(Bytes::from(line))).await? {
+            extracted.push(String::from_utf8(entry.to_vec())?);
+        }
+    }
+    stats.push(StageStats {
+        stage: "parse".to_string(),
+        entries: extracted.len(),
+        duration: start.elapsed(),
+        allocations: AllocStats::since(allocations),
+    });
+
+    // the categorization normalizes, deduplicates and sorts the entries
+    let (start, allocations) = (Instant::now(), AllocStats::now());
+    let normalized: BTreeSet<String> = extracted
+        .iter()
+        .filter_map(|e| EntryFile::normalize(e))
+        .collect();
+    stats.push(StageStats {
+        stage: "transform".to_string(),
+        entries: normalized.len(),
+        duration: start.elapsed(),
+        allocations: AllocStats::since(allocations),
+    });
+
+    let entries_path = bench_path.join("entries");
+    let mut entries_file = File::create(&entries_path)
+        .with_context(|| format!("could not create {:?}", entries_path))?;
+    for entry in normalized.iter() {
+        writeln!(entries_file, "{}", entry)?;
+    }
+    drop(entries_file);
+
+    // every output format reads the entries from a file like from the entry store
+    let formats =
+        std::iter::once(config.output_format.clone()).chain(config.additional_formats.clone());
+    for format in formats {
+        let reader: Arc<Mutex<dyn Input + Send>> =
+            Arc::new(Mutex::new(FileInput::new(entries_path.clone(), None)));
+        let out_path = bench_path.join(format!("output.{}", format.extension()));
+        let writer = Arc::new(Mutex::new(
+            File::create(&out_path).with_context(|| format!("could not create {:?}", out_path))?,
+        ));
+        let (start, allocations) = (Instant::now(), AllocStats::now());
+        format
+            .get_adapter(
+                DEFAULT_ADDRESS,
+                reader,
+                writer,
+                Arc::new(AtomicBool::new(true)),
+            )
+            .await;
+        stats.push(StageStats {
+            stage: format!("output ({})", format.extension()),
+            entries: normalized.len(),
+            duration: start.elapsed(),
+            allocations: AllocStats::since(allocations),
+        });
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{output::OutputType, tests::helper::cache_file_creator::CacheFileCreator};
+
+    use super::*;
+
+    #[test]
+    fn test_synthetic_lines() {
+        let lines = synthetic_lines(1000);
+        let re = regex::Regex::new(SYNTHETIC_REGEX).unwrap();
+        assert!(lines.iter().all(|l| re.is_match(l)));
+        // the index keeps the domains distinct
+        assert_eq!(lines.iter().collect::<BTreeSet<_>>().len(), 1000);
+        assert_eq!(synthetic_lines(10), synthetic_lines(10));
+    }
+
+    #[tokio::test]
+    async fn test_bench() {
+        let cache = CacheFileCreator::new("test_bench", "categorize", "output");
+        let mut config = cache.new_test_config();
+        config.additional_formats = vec![OutputType::Lua];
+        let stats = bench(&config, None, None, 100).await.unwrap();
+        let stages: Vec<&str> = stats.iter().map(|s| s.stage.as_str()).collect();
+        assert_eq!(
+            stages,
+            vec!["parse", "transform", "output (hosts)", "output (lua)"]
+        );
+        assert!(stats.iter().all(|s| s.entries == 100));
+
+        let hosts = Path::new(&config.cache_dir)
+            .join(BENCH_PATH)
+            .join("output.hosts");
+        assert_eq!(fs::read_to_string(hosts).unwrap().lines().count(), 100);
+    }
+}
@@ -0,0 +1,258 @@
+use anyhow::Context;
+use reqwest::{Client, Url};
+use serde::{Deserialize, Serialize};
+
+/// table the entries are inserted into if no table was configured
+pub const DEFAULT_ENTRIES_TABLE: &str = "harvester_entries";
+/// table the run statistics are inserted into if no table was configured
+pub const DEFAULT_RUNS_TABLE: &str = "harvester_runs";
+/// maximum number of rows inserted in one request if no limit was configured
+pub const DEFAULT_BATCH_SIZE: usize = 100_000;
+
+/// ClickHouseSettings configures the insertion of the outputs' entries and run
+/// statistics into ClickHouse by its HTTP interface
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ClickHouseSettings {
+    /// URL of the HTTP interface (e.g. "http://127.0.0.1:8123")
+    pub url: String,
+    /// database containing the tables, defaults to the user's default database
+    pub database: Option<String>,
+    /// table the entries are inserted into
+    pub entries_table: Option<String>,
+    /// table the run statistics are inserted into
+    pub runs_table: Option<String>,
+    pub username: Option<String>,
+    /// file containing the password of the user
+    pub password_file: Option<String>,
+    /// maximum number of rows inserted in one request
+    pub batch_size: Option<usize>,
+}
+
+/// EntryRow is an entry of an output in a run
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct EntryRow {
+    /// unix timestamp of the run
+    pub run: u64,
+    pub project: String,
+    /// the tag of the output
+    pub category: String,
+    pub domain: String,
+    /// the id of the list the entry was read from
+    pub source: String,
+    /// 1 if the entry was found in more than one list of the category
+    pub multiple_sources: u8,
+    /// unix timestamp of the first run the entry was written in
+    pub first_seen: u64,
+    /// unix timestamp of the last run the entry was written in
+    pub last_seen: u64,
+    /// number of the autonomous system announcing an IP entry, 0 if it's unknown
+    pub asn: u32,
+    /// ISO 3166 country code of the autonomous system, empty if it's unknown
+    pub country: String,
+}
+
+/// RunRow are the statistics of an output in a run
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RunRow {
+    /// unix timestamp of the run
+    pub run: u64,
+    pub project: String,
+    /// the tag of the output
+    pub category: String,
+    /// number of entries of the output
+    pub entries: u64,
+    /// number of entries added in the run
+    pub added: u64,
+    /// number of entries removed in the run
+    pub removed: u64,
+}
+
+impl ClickHouseSettings {
+    /// returns the maximum number of rows inserted in one request
+    pub fn batch_size(&self) -> usize {
+        self.batch_size.unwrap_or(DEFAULT_BATCH_SIZE).max(1)
+    }
+
+    /// creates the tables if they don't exist and inserts the rows in batches
+    ///
+    /// * `entries`: the entries of the outputs written in a run
+    /// * `runs`: the statistics of the outputs written in a run
+    pub async fn insert(&self, entries: &[EntryRow], runs: &[RunRow]) -> anyhow::Result<()> {
+        if runs.is_empty() {
+            return Ok(());
+        }
+        let client = Client::new();
+        let entries_table = self
+            .entries_table
+            .as_deref()
+            .unwrap_or(DEFAULT_ENTRIES_TABLE);
+        let runs_table = self.runs_table.as_deref().unwrap_or(DEFAULT_RUNS_TABLE);
+        self.execute(
+            &client,
+            &create_entries_table(entries_table)?,
+            String::new(),
+        )
+        .await?;
+        self.execute(
+            &client,
+            &upgrade_entries_table(entries_table)?,
+            String::new(),
+        )
+        .await?;
+        self.execute(&client, &create_runs_table(runs_table)?, String::new())
+            .await?;
+        for batch in entries.chunks(self.batch_size()) {
+            self.execute(&client, &insert_query(entries_table), json_each_row(batch)?)
+                .await?;
+        }
+        self.execute(&client, &insert_query(runs_table), json_each_row(runs)?)
+            .await?;
+        debug!(
+            "Inserted {} entries of {} outputs into ClickHouse",
+            entries.len(),
+            runs.len()
+        );
+        Ok(())
+    }
+
+    /// executes a query, the body holds the data of INSERT queries
+    ///
+    /// * `client`: the HTTP client
+    /// * `query`: the SQL query
+    /// * `body`: the rows to be inserted
+    async fn execute(&self, client: &Client, query: &str, body: String) -> anyhow::Result<()> {
+        let mut url = Url::parse(&self.url)?;
+        url.query_pairs_mut().append_pair("query", query);
+        if let Some(database) = &self.database {
+            url.query_pairs_mut().append_pair("database", database);
+        }
+        let mut request = client.post(url).body(body);
+        if let Some(username) = &self.username {
+            request = request.header("X-ClickHouse-User", username);
+            if let Some(password_file) = &self.password_file {
+                let password = std::fs::read_to_string(password_file).with_context(|| {
+                    format!("could not read ClickHouse password file {}", password_file)
+                })?;
+                request = request.header("X-ClickHouse-Key", password.trim_end());
+            }
+        }
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("could not connect to ClickHouse at {}", self.url))?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(anyhow::anyhow!(
+                "ClickHouse query failed with {}: {}",
+                status,
+                response.text().await?.trim_end()
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// returns an error if the table name isn't a plain identifier, it's part of the SQL
+/// queries
+///
+/// * `table`: the configured table name
+fn check_identifier(table: &str) -> anyhow::Result<()> {
+    let valid = !table.is_empty()
+        && table
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+    if !valid {
+        return Err(anyhow::anyhow!("invalid ClickHouse table name {}", table));
+    }
+    Ok(())
+}
+
+/// returns the query creating the table of the entries
+///
+/// * `table`: the name of the table
+fn create_entries_table(table: &str) -> anyhow::Result<String> {
+    check_identifier(table)?;
+    Ok(format!(
+        "CREATE TABLE IF NOT EXISTS {} (run DateTime, project LowCardinality(String), \
+         category LowCardinality(String), domain String, source LowCardinality(String), \
+         multiple_sources UInt8, first_seen DateTime, last_seen DateTime, asn UInt32, \
+         country LowCardinality(String)) \
+         ENGINE = MergeTree ORDER BY (category, domain, run)",
+        table
+    ))
+}
+
+/// returns the query adding the columns of the appearances and autonomous systems to
+/// a table of the entries created by an earlier version
+///
+/// * `table`: the name of the table
+fn upgrade_entries_table(table: &str) -> anyhow::Result<String> {
+    check_identifier(table)?;
+    Ok(format!(
+        "ALTER TABLE {} ADD COLUMN IF NOT EXISTS first_seen DateTime, \
+         ADD COLUMN IF NOT EXISTS last_seen DateTime, ADD COLUMN IF NOT EXISTS asn UInt32, \
+         ADD COLUMN IF NOT EXISTS country LowCardinality(String)",
+        table
+    ))
+}
+
+/// returns the query creating the table of the run statistics
+///
+/// * `table`: the name of the table
+fn create_runs_table(table: &str) -> anyhow::Result<String> {
+    check_identifier(table)?;
+    Ok(format!(
+        "CREATE TABLE IF NOT EXISTS {} (run DateTime, project LowCardinality(String), \
+         category LowCardinality(String), entries UInt64, added UInt64, removed UInt64) \
+         ENGINE = MergeTree ORDER BY (category, run)",
+        table
+    ))
+}
+
+/// returns the query inserting rows in the JSONEachRow format
+///
+/// * `table`: the name of the table
+fn insert_query(table: &str) -> String {
+    format!("INSERT INTO {} FORMAT JSONEachRow", table)
+}
+
+/// returns the rows as one JSON object per line
+///
+/// * `rows`: the rows to be inserted
+fn json_each_row<T: Serialize>(rows: &[T]) -> anyhow::Result<String> {
+    let mut body = String::new();
+    for row in rows {
+        body += &serde_json::to_string(row)?;
+        body += "\n";
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_each_row() {
+        let rows = vec![RunRow {
+            run: 1669852800,
+            project: String::new(),
+            category: "malware".to_string(),
+            entries: 10,
+            added: 2,
+            removed: 1,
+        }];
+        assert_eq!(
+            json_each_row(&rows).unwrap(),
+            "{\"run\":1669852800,\"project\":\"\",\"category\":\"malware\",\"entries\":10,\"added\":2,\"removed\":1}\n"
+        );
+    }
+
+    #[test]
+    fn test_table_names() {
+        assert!(create_entries_table("analytics.entries").is_ok());
+        assert!(upgrade_entries_table("analytics.entries").is_ok());
+        assert!(create_runs_table("runs; DROP TABLE runs").is_err());
+        assert!(create_runs_table("").is_err());
+    }
+}
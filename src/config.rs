@@ -1,15 +1,62 @@
 use std::io::prelude::*;
 use std::{
     fs::{self, File},
+    net::IpAddr,
     path::{Path, PathBuf},
 };
 
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
-use crate::{filter_list::FilterList, output::OutputType};
+use crate::{
+    clickhouse::ClickHouseSettings,
+    elasticsearch::ElasticsearchSettings,
+    encryption::EncryptionSettings,
+    filter_list::FilterList,
+    health::HealthSettings,
+    hook::Hook,
+    input::url::{ClientSettings, Redirects, Timeouts},
+    ip2asn::AsnDatabase,
+    kafka::KafkaSettings,
+    mqtt::MqttSettings,
+    output::{hostsfile::DEFAULT_ADDRESS, OutputType},
+    output_settings::OutputSettings,
+    peer::PeerSettings,
+    post_processing::StageConfig,
+    shared_cache::SharedCacheSettings,
+    syslog::SyslogSettings,
+    telemetry::TelemetrySettings,
+    DRY_RUN_PATH, PROJECTS_PATH, PUBLISHED_PATH, SEEN_PATH,
+};
 
 pub const CACHED_CONF_FILE_NAME: &str = "last_config.json";
+/// default number of entries passed from one part of the pipeline to the next at once
+pub const DEFAULT_BATCH_SIZE: usize = 1024;
+/// symlink Kubernetes swaps atomically when a mounted ConfigMap or secret is updated
+const K8S_DATA_DIR: &str = "..data";
+
+/// Project bundles lists and outputs which are processed independently from other
+/// projects. All projects share the downloaded lists.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Project {
+    /// must be unique among all projects
+    pub name: String,
+    pub lists: Vec<FilterList>,
+    pub output_dir: String,
+    pub output_format: OutputType,
+    /// formats every category list is written in besides the output_format
+    #[serde(default)]
+    pub additional_formats: Vec<OutputType>,
+    /// directory the outputs are written to before being promoted to the output_dir
+    pub staging_dir: Option<String>,
+    /// settings for individual output files
+    #[serde(default)]
+    pub outputs: Vec<OutputSettings>,
+    /// commands executed after a successful run
+    #[serde(default)]
+    pub hooks: Vec<Hook>,
+}
 
 /// Config contains all relevant information to start the data processing.
 /// Relevant information is considered most of all data sources and destinations
@@ -19,41 +66,123 @@ pub struct Config {
     pub cache_dir: String,
     pub output_dir: String,
     pub output_format: OutputType,
+    /// formats every category list is written in besides the output_format
+    #[serde(default)]
+    pub additional_formats: Vec<OutputType>,
+    /// directory the outputs are written to before being promoted to the output_dir
+    pub staging_dir: Option<String>,
+    /// number of bytes a category list may occupy in memory before being spooled to disk
+    pub spool_threshold: Option<usize>,
+    /// settings for individual output files
+    #[serde(default)]
+    pub outputs: Vec<OutputSettings>,
+    /// stages every category list passes before being written in the output format
+    #[serde(default)]
+    pub post_processing: Vec<StageConfig>,
+    /// commands executed after a successful run
+    #[serde(default)]
+    pub hooks: Vec<Hook>,
+    /// independent sets of lists and outputs
+    #[serde(default)]
+    pub projects: Vec<Project>,
+    /// export of traces to an OTLP collector
+    pub telemetry: Option<TelemetrySettings>,
+    /// health check endpoint and heartbeat file in watch mode
+    pub health: Option<HealthSettings>,
+    /// publication of change events to an MQTT broker
+    pub mqtt: Option<MqttSettings>,
+    /// production of the outputs to a Kafka topic
+    pub kafka: Option<KafkaSettings>,
+    /// notification of a syslog server about runs
+    pub syslog: Option<SyslogSettings>,
+    /// indexing of the outputs' entries into Elasticsearch
+    pub elasticsearch: Option<ElasticsearchSettings>,
+    /// insertion of the outputs' entries and run statistics into ClickHouse
+    pub clickhouse: Option<ClickHouseSettings>,
+    /// iptoasn.com database the IP entries indexed into Elasticsearch or inserted into
+    /// ClickHouse are annotated with the autonomous system and country from
+    pub asn_database: Option<String>,
+    /// maximum seconds a scheduled run is delayed by at random
+    pub schedule_jitter: Option<u64>,
+    /// maximum number of lists downloaded at once, unlimited by default
+    pub max_concurrent_downloads: Option<usize>,
+    /// approximate number of bytes the pipeline may hold in memory, unlimited by
+    /// default. The spools move entries to disk and fewer lists are processed at once
+    /// to stay within the budget.
+    pub memory_budget: Option<usize>,
+    /// number of entries passed from one part of the pipeline to the next at once,
+    /// defaults to DEFAULT_BATCH_SIZE
+    pub batch_size: Option<usize>,
+    /// directory the deploy sub command backs up the targets to, defaults to tmp_dir
+    pub backup_dir: Option<String>,
+    /// proxy the lists with an URL source are downloaded through unless they
+    /// configure one of their own
+    pub proxy: Option<String>,
+    /// timeouts of the requests of lists with an URL source, a list's own timeouts
+    /// take precedence
+    pub timeouts: Option<Timeouts>,
+    /// limits of the redirects the requests of lists with an URL source follow, a
+    /// list's own limits take precedence
+    pub redirects: Option<Redirects>,
+    /// configuration of the HTTP client shared by the lists with an URL source
+    pub http_client: Option<ClientSettings>,
+    /// download directory shared by a fleet of instances
+    pub shared_cache: Option<SharedCacheSettings>,
+    /// synchronization of the outputs from a primary to secondary instances
+    pub peer: Option<PeerSettings>,
+    /// file the audit records of the runs are appended to
+    pub audit_log: Option<String>,
+    /// directory the configurations and downloads of the audited runs are stored in
+    /// by their SHA-256 digest
+    pub input_store: Option<String>,
+    /// the name of the project this configuration was derived from
+    #[serde(skip)]
+    pub project: Option<String>,
     pub cached_config: Option<Box<Self>>,
 }
 
 impl Config {
-    /// Populates the Config struct from a json file
+    /// Populates the Config struct from a json file or a directory of json fragments
     ///
     /// * `path`: file system path the the configuration file
     pub fn load(path: &Path) -> anyhow::Result<Self> {
-        let contents = fs::read_to_string(path).with_context(|| "error reading config file")?;
-        let mut config: Config = serde_json::from_str(&contents).with_context(|| "invalid json")?;
+        let mut config: Config =
+            serde_json::from_value(load_value(path)?).with_context(|| "invalid configuration")?;
 
         // just do one recursion
         if path.ends_with(CACHED_CONF_FILE_NAME) {
             return Ok(config);
         }
 
-        // load cached config if available
-        let cached_config_path =
-            PathBuf::from(format!("{}/{}", config.cache_dir, CACHED_CONF_FILE_NAME));
-        if let Ok(c) = Config::load(&cached_config_path) {
+        config.load_cached_config();
+        Ok(config)
+    }
+
+    /// attaches the configuration of the last run if available
+    fn load_cached_config(&mut self) {
+        if let Ok(c) = Config::load(&self.cached_config_path()) {
             debug!("found cached config");
-            config.cached_config = Some(Box::new(c));
+            self.cached_config = Some(Box::new(c));
         } else {
             debug!("no cached config found");
         }
+    }
 
-        Ok(config)
+    /// returns the file system path of the configuration used on the last run
+    fn cached_config_path(&self) -> PathBuf {
+        let mut cached_config_path = PathBuf::from(&self.cache_dir);
+        cached_config_path.push(self.stage_path(CACHED_CONF_FILE_NAME));
+        cached_config_path
     }
 
     /// write used config to the cache folder for use on next run
     pub fn save_to_cache(&mut self) -> anyhow::Result<()> {
         // don't grow recursively
         self.cached_config = None;
-        let mut last_conf_path = PathBuf::from(&self.cache_dir);
-        last_conf_path.push(CACHED_CONF_FILE_NAME);
+        let last_conf_path = self.cached_config_path();
+        if let Some(parent) = last_conf_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
         let mut last_conf = File::create(&last_conf_path)?;
         let conf_str = serde_json::to_string(&self)?;
         last_conf.write_all(conf_str.as_bytes())?;
@@ -80,4 +209,409 @@ impl Config {
         let lists: Vec<&FilterList> = self.lists.iter().filter(|l| l.tags.contains(tag)).collect();
         lists
     }
+
+    /// returns the directory the outputs are written to, which is the staging directory
+    /// if configured
+    pub fn build_dir(&self) -> &str {
+        self.staging_dir.as_deref().unwrap_or(&self.output_dir)
+    }
+
+    /// returns the file names and formats of the outputs written for a category list,
+    /// the output_format comes first and is named after the tag, the additional formats
+    /// carry the format's extension
+    ///
+    /// * `tag`: the tag of the category list
+    pub fn output_files(&self, tag: &str) -> Vec<(String, OutputType)> {
+        let mut files = vec![(tag.to_string(), self.output_format.clone())];
+        files.extend(
+            self.additional_formats
+                .iter()
+                .map(|f| (format!("{}.{}", tag, f.extension()), f.clone())),
+        );
+        files
+    }
+
+    /// returns the path relative to the cache directory where the data of a stage is
+    /// stored, projects keep their data in a sub directory
+    ///
+    /// * `stage_path`: the stage's path
+    pub fn stage_path(&self, stage_path: &str) -> String {
+        match &self.project {
+            Some(name) => format!("{}/{}/{}", PROJECTS_PATH, name, stage_path),
+            None => stage_path.to_string(),
+        }
+    }
+
+    /// returns the directory the lists are downloaded to, which is the shared cache if
+    /// one is configured
+    ///
+    /// * `download_base_path`: the download stage's path within the cache directory
+    pub fn download_dir(&self, download_base_path: &str) -> PathBuf {
+        match &self.shared_cache {
+            Some(shared_cache) => PathBuf::from(&shared_cache.dir),
+            None => PathBuf::from(&self.cache_dir).join(download_base_path),
+        }
+    }
+
+    /// returns the configured number of entries passed on at once or the default one
+    pub fn batch_size(&self) -> usize {
+        self.batch_size.unwrap_or(DEFAULT_BATCH_SIZE).max(1)
+    }
+
+    /// returns the directory containing the first and last appearance of the entries of
+    /// the category lists
+    pub fn seen_dir(&self) -> PathBuf {
+        Path::new(&self.cache_dir).join(self.stage_path(SEEN_PATH))
+    }
+
+    /// reads the database the IP entries are annotated from, None if none is configured
+    pub fn load_asn_database(&self) -> anyhow::Result<Option<AsnDatabase>> {
+        self.asn_database
+            .as_ref()
+            .map(|path| AsnDatabase::load(Path::new(path)))
+            .transpose()
+    }
+
+    /// returns the directory containing the entries of the outputs as they were last
+    /// published
+    ///
+    /// * `target`: the name of the target the outputs are published to
+    pub fn published_dir(&self, target: &str) -> PathBuf {
+        Path::new(&self.cache_dir)
+            .join(self.stage_path(PUBLISHED_PATH))
+            .join(target)
+    }
+
+    /// returns the lists of the configuration and all projects, lists sharing an id
+    /// must be configured identically since they're downloaded once
+    pub fn all_lists(&self) -> anyhow::Result<Vec<FilterList>> {
+        let mut lists = self.lists.clone();
+        for list in self.projects.iter().flat_map(|p| p.lists.iter()) {
+            match lists.iter().find(|l| l.id == list.id) {
+                Some(l) if l != list => {
+                    return Err(anyhow::anyhow!(
+                        "list {} is configured differently by several projects",
+                        list.id
+                    ));
+                }
+                Some(_) => {}
+                None => lists.push(list.clone()),
+            }
+        }
+        Ok(lists)
+    }
+
+    /// returns a configuration containing the lists of all projects for the download stage
+    pub fn download_config(&self) -> anyhow::Result<Self> {
+        let mut config = self.clone();
+        config.lists = self.all_lists()?;
+        // lists without a proxy, timeouts or redirect limits of their own use the
+        // global ones
+        if let Some(proxy) = &self.proxy {
+            for list in config.lists.iter_mut() {
+                let http = list.http.get_or_insert_with(Default::default);
+                http.proxy.get_or_insert_with(|| proxy.clone());
+            }
+        }
+        if let Some(timeouts) = self.timeouts {
+            for list in config.lists.iter_mut() {
+                let http = list.http.get_or_insert_with(Default::default);
+                http.timeouts = http.timeouts.or(timeouts);
+            }
+        }
+        if let Some(redirects) = self.redirects {
+            for list in config.lists.iter_mut() {
+                let http = list.http.get_or_insert_with(Default::default);
+                http.redirects = http.redirects.or(redirects);
+            }
+        }
+        config.projects = vec![];
+        config.cached_config = None;
+        Ok(config)
+    }
+
+    /// returns a configuration for each project with the last run's configuration attached
+    pub fn project_configs(&self) -> Vec<Self> {
+        self.projects
+            .iter()
+            .map(|p| {
+                let mut config = Config {
+                    lists: p.lists.clone(),
+                    output_dir: p.output_dir.clone(),
+                    output_format: p.output_format.clone(),
+                    additional_formats: p.additional_formats.clone(),
+                    staging_dir: p.staging_dir.clone(),
+                    outputs: p.outputs.clone(),
+                    hooks: p.hooks.clone(),
+                    projects: vec![],
+                    project: Some(p.name.clone()),
+                    cached_config: None,
+                    ..self.clone()
+                };
+                config.load_cached_config();
+                config
+            })
+            .collect()
+    }
+
+    /// returns a configuration which builds all outputs into a separate location within
+    /// the cache directory without running any hooks or publishing any changes
+    pub fn dry_run_config(&self) -> Self {
+        let mut config = self.clone();
+        let mut dry_run_path = PathBuf::from(&self.cache_dir);
+        dry_run_path.push(DRY_RUN_PATH);
+        config.cache_dir = dry_run_path.to_string_lossy().to_string();
+        dry_run_path.push("output");
+        config.output_dir = dry_run_path.to_string_lossy().to_string();
+        config.staging_dir = None;
+        config.hooks = vec![];
+        // a dry run must not change the zones of DNS servers or notify anyone
+        config.mqtt = None;
+        config.kafka = None;
+        config.syslog = None;
+        config.elasticsearch = None;
+        config.clickhouse = None;
+        // the downloads of a dry run must not be used by other instances
+        config.shared_cache = None;
+        config.peer = None;
+        // the outputs of a dry run aren't deployed, so they aren't audited
+        config.audit_log = None;
+        config.input_store = None;
+        let outputs = config.outputs.iter_mut().chain(
+            config
+                .projects
+                .iter_mut()
+                .flat_map(|p| p.outputs.iter_mut()),
+        );
+        for output in outputs {
+            output.dns_update = None;
+            output.hosts_file = None;
+        }
+        for project in config.projects.iter_mut() {
+            project.output_dir = dry_run_path
+                .join(&project.name)
+                .to_string_lossy()
+                .to_string();
+            project.staging_dir = None;
+            project.hooks = vec![];
+        }
+        config.cached_config = None;
+        config.load_cached_config();
+        config
+    }
+
+    /// returns the output settings for the category list with the given tag
+    ///
+    /// * `tag`: the tag the output settings belong to
+    pub fn output_settings(&self, tag: &str) -> Option<&OutputSettings> {
+        self.outputs.iter().find(|o| o.tag == tag)
+    }
+
+    /// returns the weights of the lists included in a category list
+    ///
+    /// * `tag`: the tag of the category list
+    pub fn list_weights(&self, tag: &String) -> Vec<(&str, u32)> {
+        self.lists_with_tag(tag)
+            .iter()
+            .map(|l| (l.id.as_str(), l.weight()))
+            .collect()
+    }
+
+    /// returns the encryption settings of the output of a category list, if any
+    ///
+    /// * `tag`: the tag of the category list
+    pub fn output_encryption(&self, tag: &str) -> Option<&EncryptionSettings> {
+        self.output_settings(tag)
+            .and_then(|s| s.encryption.as_ref())
+    }
+
+    /// returns the address the blocked domains of the output of a category list
+    /// resolve to
+    ///
+    /// * `tag`: the tag of the category list
+    pub fn output_address(&self, tag: &str) -> IpAddr {
+        self.output_settings(tag)
+            .map_or(DEFAULT_ADDRESS, |s| s.address())
+    }
+}
+
+/// reads the configuration from a json file or merges all json fragments found in a
+/// directory in alphabetical order
+///
+/// * `path`: file system path of the configuration file or directory
+pub fn load_value(path: &Path) -> anyhow::Result<Value> {
+    if !path.is_dir() {
+        let contents = fs::read_to_string(path).with_context(|| "error reading config file")?;
+        return serde_json::from_str(&contents).with_context(|| "invalid json");
+    }
+
+    // reading through the symlink of a mounted ConfigMap makes sure all fragments belong
+    // to the same revision even if the mount is rotated meanwhile
+    let data_dir = path.join(K8S_DATA_DIR);
+    let dir = if data_dir.is_dir() {
+        data_dir.canonicalize()?
+    } else {
+        path.to_path_buf()
+    };
+    let mut fragments: Vec<PathBuf> = fs::read_dir(&dir)
+        .with_context(|| "error reading config directory")?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|e| e == "json"))
+        .filter(|p| {
+            p.file_name()
+                .is_some_and(|n| !n.to_string_lossy().starts_with('.'))
+        })
+        .collect();
+    fragments.sort();
+    if fragments.is_empty() {
+        return Err(anyhow::anyhow!("no json files found in {:?}", dir));
+    }
+
+    let mut config = Value::Object(Map::new());
+    for fragment in fragments {
+        let contents = fs::read_to_string(&fragment)
+            .with_context(|| format!("error reading config file {:?}", fragment))?;
+        let value = serde_json::from_str(&contents)
+            .with_context(|| format!("invalid json in {:?}", fragment))?;
+        merge_json(&mut config, value);
+    }
+    Ok(config)
+}
+
+/// merges a configuration fragment into the configuration. Objects are merged
+/// recursively, arrays are concatenated and all other values are replaced.
+///
+/// * `base`: the configuration merged so far
+/// * `fragment`: the fragment to be merged
+fn merge_json(base: &mut Value, fragment: Value) {
+    match (base, fragment) {
+        (Value::Object(base), Value::Object(fragment)) => {
+            for (key, value) in fragment {
+                match base.get_mut(&key) {
+                    Some(b) => merge_json(b, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (Value::Array(base), Value::Array(fragment)) => base.extend(fragment),
+        (base, fragment) => *base = fragment,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        input::url::{HttpSettings, DIRECT_PROXY},
+        tests::helper::cache_file_creator::CacheFileCreator,
+    };
+
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_load_fragments() {
+        let cache = CacheFileCreator::new("test_load_fragments", "config", "out");
+        let mut dir = PathBuf::from(cache.new_test_config().cache_dir);
+        dir.push("config");
+        fs::remove_dir_all(&dir).ok();
+        // a mounted ConfigMap links its files to the current revision
+        let revision = dir.join("..2022_12_01");
+        fs::create_dir_all(&revision).unwrap();
+        fs::write(
+            revision.join("base.json"),
+            r#"{"cache_dir": "cache", "output_dir": "out", "output_format": "Lua", "lists": []}"#,
+        )
+        .unwrap();
+        fs::write(
+            revision.join("lists.json"),
+            r#"{"output_format": "Hostsfile", "lists": [{"id": "one", "source": "https://one", "tags": [], "regex": "(.*)"}]}"#,
+        )
+        .unwrap();
+        std::os::unix::fs::symlink("..2022_12_01", dir.join(K8S_DATA_DIR)).unwrap();
+        std::os::unix::fs::symlink("..data/base.json", dir.join("base.json")).unwrap();
+        std::os::unix::fs::symlink("..data/lists.json", dir.join("lists.json")).unwrap();
+
+        let config = Config::load(&dir).unwrap();
+        assert_eq!(config.cache_dir, "cache");
+        assert!(matches!(config.output_format, OutputType::Hostsfile));
+        assert_eq!(config.lists.len(), 1);
+    }
+
+    #[test]
+    fn test_all_lists() {
+        let cache = CacheFileCreator::new("test_all_lists", "in", "out");
+        let mut config = cache.new_test_config();
+        let list = |id: &str, source: &str| FilterList {
+            id: id.to_string(),
+            source: source.to_string(),
+            ..Default::default()
+        };
+        config.lists = vec![list("one", "https://one")];
+        config.projects = vec![Project {
+            name: "customer".to_string(),
+            lists: vec![list("one", "https://one"), list("two", "https://two")],
+            output_dir: "out".to_string(),
+            output_format: OutputType::Hostsfile,
+            additional_formats: vec![],
+            staging_dir: None,
+            outputs: vec![],
+            hooks: vec![],
+        }];
+
+        // lists shared between projects are downloaded once
+        let ids: Vec<String> = config
+            .all_lists()
+            .unwrap()
+            .into_iter()
+            .map(|l| l.id)
+            .collect();
+        assert_eq!(ids, vec!["one", "two"]);
+
+        // a shared id must not point to different sources
+        config.projects[0].lists[0].source = "https://other".to_string();
+        assert!(config.all_lists().is_err());
+
+        // nor differ in any other setting
+        config.projects[0].lists[0].source = "https://one".to_string();
+        config.projects[0].lists[0].regex = "^(.+)$".to_string();
+        assert!(config.all_lists().is_err());
+    }
+
+    #[test]
+    fn test_download_config_proxy() {
+        let cache = CacheFileCreator::new("test_download_config_proxy", "in", "out");
+        let mut config = cache.new_test_config();
+        config.proxy = Some("socks5h://proxy.corp:1080".to_string());
+        config.projects = vec![];
+        config.lists = vec![
+            FilterList {
+                id: "one".to_string(),
+                ..Default::default()
+            },
+            FilterList {
+                id: "two".to_string(),
+                http: Some(HttpSettings {
+                    proxy: Some(DIRECT_PROXY.to_string()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        ];
+        let proxies: Vec<Option<String>> = config
+            .download_config()
+            .unwrap()
+            .lists
+            .into_iter()
+            .map(|l| l.http.and_then(|h| h.proxy))
+            .collect();
+        assert_eq!(
+            proxies,
+            vec![
+                Some("socks5h://proxy.corp:1080".to_string()),
+                Some(DIRECT_PROXY.to_string())
+            ]
+        );
+    }
 }
@@ -0,0 +1,467 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config, encryption::decode_output, hook::DEFAULT_HOOK_TIMEOUT, output::OutputType,
+    output_settings::OutputSettings, platform, BACKUP_PATH,
+};
+
+/// environment variable containing the path of the installed file
+pub const TARGET_ENV: &str = "HARVESTER_TARGET";
+
+/// DeploySettings describe how an output is installed into a system location
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DeploySettings {
+    /// the path the output is installed to, e.g. /etc/dnsmasq.d/harvester.conf
+    pub target: String,
+    /// the file of the output in the output directory, defaults to the tag
+    pub file: Option<String>,
+    /// command line checking the installed file, the previous file is restored if it
+    /// fails
+    pub validate: Option<String>,
+    /// command line executed after the file was installed or rolled back, e.g.
+    /// `systemctl reload dnsmasq`
+    pub reload: Option<String>,
+}
+
+impl DeploySettings {
+    /// executes a command line of the deployment and waits for it to finish
+    ///
+    /// * `command_line`: the validate or reload command line
+    async fn execute(&self, command_line: &str) -> anyhow::Result<()> {
+        let mut child = platform::shell(command_line)
+            .env(TARGET_ENV, &self.target)
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("could not execute `{}`", command_line))?;
+        let timeout = Duration::from_secs(DEFAULT_HOOK_TIMEOUT);
+        let status = tokio::time::timeout(timeout, child.wait())
+            .await
+            .with_context(|| format!("`{}` timed out", command_line))??;
+        if !status.success() {
+            return Err(anyhow::anyhow!("`{}` failed: {}", command_line, status));
+        }
+        Ok(())
+    }
+
+    /// installs the content into the target after the previous file was backed up,
+    /// returns false if the target already had the content
+    ///
+    /// * `contents`: the content of the output
+    /// * `backup`: the file the previous target is backed up to
+    pub async fn install(&self, contents: &[u8], backup: &Path) -> anyhow::Result<bool> {
+        let target = Path::new(&self.target);
+        let previous = match fs::read(target) {
+            Ok(previous) => Some(previous),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e).with_context(|| format!("could not read {:?}", target)),
+        };
+        if previous.as_deref() == Some(contents) {
+            return Ok(false);
+        }
+        if let Some(parent) = backup.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // the backup of the last deployment is kept until the new file was validated
+        let pending = PathBuf::from(format!("{}.pending", backup.display()));
+        write_backup(previous.as_deref(), &pending)
+            .with_context(|| format!("could not back up {:?}", target))?;
+        platform::write_system_file(target, contents)
+            .with_context(|| format!("could not write {:?}", target))?;
+        if let Some(validate) = &self.validate
+            && let Err(e) = self.execute(validate).await
+        {
+            self.restore(&pending)?;
+            remove_backup(&pending);
+            return Err(e).with_context(|| format!("{:?} was restored", target));
+        }
+        remove_backup(backup);
+        for (from, to) in [
+            (pending.clone(), backup.to_path_buf()),
+            (absent_marker(&pending), absent_marker(backup)),
+        ] {
+            if from.exists() {
+                fs::rename(&from, &to).with_context(|| format!("could not write {:?}", to))?;
+            }
+        }
+        if let Some(reload) = &self.reload {
+            self.execute(reload).await?;
+        }
+        Ok(true)
+    }
+
+    /// restores the target from the backup, returns false if there's no backup
+    ///
+    /// * `backup`: the file the previous target was backed up to
+    pub async fn rollback(&self, backup: &Path) -> anyhow::Result<bool> {
+        if !self.restore(backup)? {
+            return Ok(false);
+        }
+        remove_backup(backup);
+        if let Some(reload) = &self.reload {
+            self.execute(reload).await?;
+        }
+        Ok(true)
+    }
+
+    /// writes the backup back to the target, returns false if there's no backup
+    ///
+    /// * `backup`: the file the previous target was backed up to
+    fn restore(&self, backup: &Path) -> anyhow::Result<bool> {
+        let target = Path::new(&self.target);
+        if absent_marker(backup).exists() {
+            match fs::remove_file(target) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(e).with_context(|| format!("could not remove {:?}", target))
+                }
+                _ => return Ok(true),
+            }
+        }
+        let previous = match fs::read(backup) {
+            Ok(previous) => previous,
+            Err(_) => return Ok(false),
+        };
+        platform::write_system_file(target, &previous)
+            .with_context(|| format!("could not restore {:?}", target))?;
+        Ok(true)
+    }
+}
+
+/// returns the file marking that the target didn't exist before it was installed
+///
+/// * `backup`: the file the previous target is backed up to
+fn absent_marker(backup: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.absent", backup.display()))
+}
+
+/// backs up the previous content of a target, a target which didn't exist is marked
+/// so a rollback removes it
+///
+/// * `previous`: the content of the target, None if it didn't exist
+/// * `backup`: the file the target is backed up to
+fn write_backup(previous: Option<&[u8]>, backup: &Path) -> std::io::Result<()> {
+    remove_backup(backup);
+    match previous {
+        Some(previous) => fs::write(backup, previous),
+        None => fs::write(absent_marker(backup), ""),
+    }
+}
+
+/// removes a backup together with its marker
+///
+/// * `backup`: the file the target was backed up to
+fn remove_backup(backup: &Path) {
+    fs::remove_file(backup).ok();
+    fs::remove_file(absent_marker(backup)).ok();
+}
+
+/// Deployment is an output installed into a system location
+struct Deployment {
+    settings: DeploySettings,
+    output: OutputSettings,
+    /// the format of the installed file, None if it isn't a file of the output
+    format: Option<OutputType>,
+    /// whether the file is surrounded by the header and footer files
+    has_includes: bool,
+    /// the output file in the output directory
+    source: PathBuf,
+    /// the file the previous target is backed up to
+    backup: PathBuf,
+}
+
+impl Deployment {
+    /// reads the output file and checks that it holds nothing but the entries in its
+    /// format and the configured includes, so a compromised harvesting process can't
+    /// install arbitrary content, e.g. options of a DNS server
+    fn read(&self) -> anyhow::Result<String> {
+        let format = self.format.as_ref().with_context(|| {
+            format!(
+                "{:?} isn't a file of the output {}",
+                self.source, self.output.tag
+            )
+        })?;
+        let contents = platform::read_regular_file(&self.source)
+            .with_context(|| format!("could not read output {:?}", self.source))?;
+        let contents = decode_output(contents, &self.source, self.output.encryption.as_ref())?;
+        let mut entries = contents.as_bytes();
+        if self.has_includes {
+            let (header, footer) = self.output.includes()?;
+            entries = entries
+                .strip_prefix(header.as_slice())
+                .and_then(|e| e.strip_suffix(footer.as_slice()))
+                .with_context(|| {
+                    format!(
+                        "{:?} doesn't match the header and footer files",
+                        self.source
+                    )
+                })?;
+        }
+        format
+            .lint_reader(entries, self.output.address())
+            .with_context(|| format!("output {:?} is malformed", self.source))?;
+        Ok(contents)
+    }
+
+    /// returns an error unless the header, footer and key files read with the output
+    /// can only be modified by root, otherwise a user writing the header could install
+    /// arbitrary lines through a privileged deployment
+    fn check_trusted(&self) -> anyhow::Result<()> {
+        let files = [&self.output.header_file, &self.output.footer_file]
+            .into_iter()
+            .flatten()
+            .chain(self.output.encryption.as_ref().map(|e| &e.key_file));
+        for file in files {
+            platform::check_trusted(Path::new(file)).with_context(|| {
+                format!(
+                    "output {}: {} of a privileged deployment isn't trusted",
+                    self.output.tag, file
+                )
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// returns the deployed outputs of a configuration and its projects
+///
+/// * `config`: the configuration containing the deploy settings
+fn deployments(config: &Config) -> Vec<Deployment> {
+    let mut configs = vec![config.clone()];
+    configs.extend(config.project_configs());
+    let mut deployments = vec![];
+    for config in configs {
+        let backup_dir = backup_dir(&config);
+        for output in config.outputs.iter() {
+            if let Some(settings) = &output.deploy {
+                let file = settings.file.clone().unwrap_or_else(|| output.tag.clone());
+                let format = config
+                    .output_files(&output.tag)
+                    .into_iter()
+                    .find(|(name, _)| *name == file)
+                    .map(|(_, format)| format);
+                deployments.push(Deployment {
+                    settings: settings.clone(),
+                    output: output.clone(),
+                    format,
+                    has_includes: file == output.tag,
+                    source: Path::new(&config.output_dir).join(file),
+                    backup: backup_dir.join(&output.tag),
+                });
+            }
+        }
+    }
+    deployments
+}
+
+/// returns the directory the targets of a configuration are backed up to
+///
+/// * `config`: the configuration containing the deploy settings
+fn backup_dir(config: &Config) -> PathBuf {
+    Path::new(config.backup_dir.as_ref().unwrap_or(&config.cache_dir))
+        .join(config.stage_path(BACKUP_PATH))
+}
+
+/// refuses to run with the privileges of root unless the configuration, the files it
+/// includes and the backups can only be modified by root. The files written by the
+/// unprivileged harvesting process are checked before they are installed.
+///
+/// * `config`: the configuration containing the deploy settings
+/// * `config_path`: the configuration file or directory
+fn check_privileges(config: &Config, config_path: &Path) -> anyhow::Result<()> {
+    if !platform::is_privileged() {
+        return Ok(());
+    }
+    platform::check_trusted(config_path)
+        .with_context(|| "the configuration of a privileged deployment isn't trusted")?;
+    let backup_base = Path::new(config.backup_dir.as_ref().unwrap_or(&config.cache_dir));
+    fs::create_dir_all(backup_base)?;
+    platform::check_trusted(backup_base).with_context(|| {
+        "the backups of a privileged deployment aren't trusted, configure a backup_dir"
+    })?;
+    deployments(config)
+        .iter()
+        .try_for_each(Deployment::check_trusted)
+}
+
+/// installs the outputs of a configuration and its projects into their targets,
+/// returns the number of targets changed
+///
+/// * `config`: the configuration containing the deploy settings
+/// * `config_path`: the configuration file or directory
+pub async fn deploy_all(config: &Config, config_path: &Path) -> anyhow::Result<usize> {
+    check_privileges(config, config_path)?;
+    let mut installed = 0;
+    for d in deployments(config) {
+        let contents = d.read()?;
+        if d.settings.install(contents.as_bytes(), &d.backup).await? {
+            info!("Deployed: {} to {}", d.output.tag, d.settings.target);
+            installed += 1;
+        } else {
+            info!("Unchanged: {}", d.settings.target);
+        }
+    }
+    Ok(installed)
+}
+
+/// restores the targets of a configuration and its projects from their backups,
+/// returns the number of targets restored
+///
+/// * `config`: the configuration containing the deploy settings
+/// * `config_path`: the configuration file or directory
+pub async fn rollback_all(config: &Config, config_path: &Path) -> anyhow::Result<usize> {
+    check_privileges(config, config_path)?;
+    let mut restored = 0;
+    for d in deployments(config) {
+        if d.settings.rollback(&d.backup).await? {
+            info!("Rolled back: {}", d.settings.target);
+            restored += 1;
+        } else {
+            warn!("No backup of {} found", d.settings.target);
+        }
+    }
+    Ok(restored)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{encryption::EncryptionSettings, tests::helper::cache_file_creator::TEST_CACHE};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_install_rollback() {
+        let dir = PathBuf::from(TEST_CACHE).join("test_install_rollback");
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("hosts");
+        let backup = dir.join("backup").join("ads");
+        fs::write(&target, "127.0.0.1 localhost\n").unwrap();
+        let mut deploy = DeploySettings {
+            target: target.to_string_lossy().to_string(),
+            validate: Some(format!("grep -q localhost \"${}\"", TARGET_ENV)),
+            ..Default::default()
+        };
+
+        let contents = b"127.0.0.1 localhost\n0.0.0.0 one.domain\n";
+        assert!(deploy.install(contents, &backup).await.unwrap());
+        assert!(!deploy.install(contents, &backup).await.unwrap());
+        assert_eq!(fs::read(&target).unwrap(), contents);
+
+        // the previous file is restored if the validation fails
+        assert!(deploy
+            .install(b"0.0.0.0 two.domain\n", &backup)
+            .await
+            .is_err());
+        assert_eq!(fs::read(&target).unwrap(), contents);
+
+        assert!(deploy.rollback(&backup).await.unwrap());
+        assert_eq!(fs::read(&target).unwrap(), b"127.0.0.1 localhost\n");
+        assert!(!deploy.rollback(&backup).await.unwrap());
+
+        // a target which didn't exist is removed again
+        deploy.target = dir.join("new.conf").to_string_lossy().to_string();
+        deploy.validate = None;
+        assert!(deploy
+            .install(b"address=/one.domain/\n", &backup)
+            .await
+            .unwrap());
+        assert!(deploy.rollback(&backup).await.unwrap());
+        assert!(!dir.join("new.conf").exists());
+    }
+
+    #[test]
+    fn test_read() {
+        let dir = PathBuf::from(TEST_CACHE).join("test_deployment_read");
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        let header_file = dir.join("hosts.head");
+        fs::write(&header_file, "127.0.0.1 localhost\n").unwrap();
+        let mut deployment = Deployment {
+            settings: DeploySettings::default(),
+            output: OutputSettings {
+                tag: "ads".to_string(),
+                header_file: Some(header_file.to_string_lossy().to_string()),
+                ..Default::default()
+            },
+            format: Some(OutputType::Hostsfile),
+            has_includes: true,
+            source: dir.join("ads"),
+            backup: dir.join("backup"),
+        };
+        fs::write(
+            &deployment.source,
+            "127.0.0.1 localhost\n0.0.0.0 one.domain\n",
+        )
+        .unwrap();
+        assert!(deployment.read().is_ok());
+
+        // content the output format can't contain is never installed
+        fs::write(
+            &deployment.source,
+            "127.0.0.1 localhost\nconf-file=/tmp/x\n",
+        )
+        .unwrap();
+        assert!(deployment.read().is_err());
+        fs::write(&deployment.source, "0.0.0.0 one.domain\n").unwrap();
+        assert!(deployment.read().is_err());
+
+        #[cfg(unix)]
+        {
+            deployment.has_includes = false;
+            fs::remove_file(&deployment.source).unwrap();
+            fs::write(dir.join("other"), "0.0.0.0 one.domain\n").unwrap();
+            std::os::unix::fs::symlink(dir.join("other"), &deployment.source).unwrap();
+            assert!(deployment.read().is_err());
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_trusted() {
+        use std::os::unix::fs::PermissionsExt;
+
+        if !platform::is_privileged() {
+            return;
+        }
+        let dir = PathBuf::from(TEST_CACHE).join("test_deployment_check_trusted");
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        let header_file = dir.join("hosts.head");
+        let key_file = dir.join("key");
+        for file in [&header_file, &key_file] {
+            fs::write(file, "").unwrap();
+            fs::set_permissions(file, fs::Permissions::from_mode(0o644)).unwrap();
+        }
+        let deployment = Deployment {
+            settings: DeploySettings::default(),
+            output: OutputSettings {
+                tag: "ads".to_string(),
+                header_file: Some(header_file.to_string_lossy().to_string()),
+                encryption: Some(EncryptionSettings {
+                    key_file: key_file.to_string_lossy().to_string(),
+                }),
+                ..Default::default()
+            },
+            format: Some(OutputType::Hostsfile),
+            has_includes: true,
+            source: dir.join("ads"),
+            backup: dir.join("backup"),
+        };
+        assert!(deployment.check_trusted().is_ok());
+
+        // the includes and keys of other users are never read as root
+        for file in [&header_file, &key_file] {
+            std::os::unix::fs::chown(file, Some(65534), None).unwrap();
+            assert!(deployment.check_trusted().is_err());
+            std::os::unix::fs::chown(file, Some(0), None).unwrap();
+        }
+        fs::set_permissions(&header_file, fs::Permissions::from_mode(0o666)).unwrap();
+        assert!(deployment.check_trusted().is_err());
+    }
+}
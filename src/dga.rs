@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+
+/// domains scoring at least this are likely generated if no score was configured
+pub const DEFAULT_MIN_DGA_SCORE: u32 = 60;
+/// labels shorter than this are too short to tell generated from chosen names
+const MIN_LABEL_LEN: usize = 8;
+
+/// returns the Shannon entropy of a label in bits per character
+///
+/// * `label`: a label of a domain
+fn entropy(label: &str) -> f64 {
+    let mut counts: HashMap<char, usize> = HashMap::new();
+    for c in label.chars() {
+        *counts.entry(c).or_default() += 1;
+    }
+    let len = label.chars().count() as f64;
+    counts
+        .values()
+        .map(|&n| {
+            let p = n as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// returns the length of the longest run of consonants in a label
+///
+/// * `label`: a label of a domain
+fn max_consonant_run(label: &str) -> usize {
+    label
+        .split(|c: char| !c.is_ascii_alphabetic() || "aeiouy".contains(c))
+        .map(|run| run.len())
+        .max()
+        .unwrap_or(0)
+}
+
+/// scores how likely a domain was made up by a domain generation algorithm, from 0
+/// (a chosen name) to 100. The longest label below the top level domain is judged by
+/// its entropy, its share of vowels, its runs of consonants, digits mixed with letters
+/// and its length, since generated names are long and unpronounceable.
+///
+/// * `domain`: the domain to be scored
+pub fn dga_score(domain: &str) -> u32 {
+    let domain = domain.trim_end_matches('.').to_lowercase();
+    let labels: Vec<&str> = domain.split('.').collect();
+    let label = match labels[..labels.len().saturating_sub(1)]
+        .iter()
+        .max_by_key(|l| l.len())
+    {
+        Some(label) => label.replace('-', ""),
+        None => return 0,
+    };
+    let len = label.len();
+    if len < MIN_LABEL_LEN {
+        return 0;
+    }
+
+    let mut score = 0;
+    let entropy = entropy(&label);
+    if entropy >= 3.5 {
+        score += 25;
+    } else if entropy >= 3.0 {
+        score += 10;
+    }
+    let vowels = label.chars().filter(|c| "aeiou".contains(*c)).count();
+    if (vowels as f64) < len as f64 * 0.25 {
+        score += 25;
+    }
+    match max_consonant_run(&label) {
+        run if run >= 5 => score += 20,
+        4 => score += 10,
+        _ => {}
+    }
+    let digits = label.chars().filter(|c| c.is_ascii_digit()).count();
+    if digits > 0 && digits < len {
+        score += 15;
+    }
+    if len >= 16 {
+        score += 15;
+    } else if len >= 12 {
+        score += 10;
+    }
+    score.min(100)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dga_score() {
+        for domain in [
+            "google.com",
+            "wikipedia.org",
+            "stackoverflow.com",
+            "www.microsoftonline.com",
+            "login.my-favourite-bank.co.uk",
+        ] {
+            assert!(
+                dga_score(domain) < DEFAULT_MIN_DGA_SCORE,
+                "{} scored {}",
+                domain,
+                dga_score(domain)
+            );
+        }
+        for domain in [
+            "xjwqkzvbtrplmn.com",
+            "a1b2c3d4e5f6g7h8.net",
+            "3f2a9c81d7e4b6a0.info",
+            "cdn.qxkfhtrwzpbnvl.ru",
+        ] {
+            assert!(
+                dga_score(domain) >= DEFAULT_MIN_DGA_SCORE,
+                "{} scored {}",
+                domain,
+                dga_score(domain)
+            );
+        }
+        assert_eq!(dga_score("com"), 0);
+        assert_eq!(dga_score(""), 0);
+    }
+}
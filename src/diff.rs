@@ -0,0 +1,132 @@
+use std::{
+    collections::BTreeSet,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use colored::*;
+
+use crate::{
+    config::Config,
+    encryption::{read_output, EncryptionSettings},
+};
+
+/// OutputDiff contains the entries added to and removed from an output file
+#[derive(Debug)]
+pub struct OutputDiff {
+    pub name: String,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl OutputDiff {
+    /// compares the lines of a deployed output file with a newly built one
+    ///
+    /// * `name`: the output's name
+    /// * `deployed_path`: the file currently deployed, a missing file counts as empty
+    /// * `built_path`: the newly built file
+    /// * `encryption`: the encryption settings of the output, if any
+    pub fn new(
+        name: &str,
+        deployed_path: &Path,
+        built_path: &Path,
+        encryption: Option<&EncryptionSettings>,
+    ) -> anyhow::Result<Self> {
+        let deployed = read_lines(deployed_path, encryption).unwrap_or_default();
+        let built = read_lines(built_path, encryption)?;
+        Ok(Self {
+            name: name.to_string(),
+            added: built.difference(&deployed).cloned().collect(),
+            removed: deployed.difference(&built).cloned().collect(),
+        })
+    }
+}
+
+impl Display for OutputDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{}: {} {}",
+            self.name.bold(),
+            format!("+{}", self.added.len()).green(),
+            format!("-{}", self.removed.len()).red()
+        )?;
+        for line in self.added.iter() {
+            writeln!(f, "{}", format!("+ {}", line).green())?;
+        }
+        for line in self.removed.iter() {
+            writeln!(f, "{}", format!("- {}", line).red())?;
+        }
+        Ok(())
+    }
+}
+
+/// returns the distinct lines of a file
+///
+/// * `path`: the file system path of the file
+/// * `encryption`: the encryption settings of the file, if any
+fn read_lines(
+    path: &Path,
+    encryption: Option<&EncryptionSettings>,
+) -> anyhow::Result<BTreeSet<String>> {
+    let contents = read_output(path, encryption)?;
+    Ok(contents.lines().map(|l| l.to_string()).collect())
+}
+
+/// returns the differences between the outputs of the deployed configuration and
+/// the outputs built by a dry run
+///
+/// * `deployed`: the configuration pointing to the deployed outputs
+/// * `dry_run`: the configuration the dry run was performed with
+pub fn output_diffs(deployed: &Config, dry_run: &Config) -> anyhow::Result<Vec<OutputDiff>> {
+    let mut diffs = vec![];
+    let configs = [(deployed.clone(), dry_run.clone())].into_iter().chain(
+        deployed
+            .project_configs()
+            .into_iter()
+            .zip(dry_run.project_configs()),
+    );
+    for (deployed, dry_run) in configs {
+        for tag in dry_run.get_tags() {
+            let name = match &dry_run.project {
+                Some(project) => format!("{}/{}", project, tag),
+                None => tag.clone(),
+            };
+            diffs.push(OutputDiff::new(
+                &name,
+                &PathBuf::from(&deployed.output_dir).join(&tag),
+                &PathBuf::from(&dry_run.output_dir).join(&tag),
+                dry_run.output_encryption(&tag),
+            )?);
+        }
+    }
+    Ok(diffs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::tests::helper::cache_file_creator::TEST_CACHE;
+
+    use super::*;
+
+    #[test]
+    fn test_output_diff() {
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_output_diff");
+        fs::create_dir_all(&dir).unwrap();
+        let deployed_path = dir.join("deployed");
+        let built_path = dir.join("built");
+        fs::write(
+            &deployed_path,
+            "0.0.0.0 kept.domain\n0.0.0.0 removed.domain\n",
+        )
+        .unwrap();
+        fs::write(&built_path, "0.0.0.0 kept.domain\n0.0.0.0 added.domain\n").unwrap();
+
+        let diff = OutputDiff::new("malware", &deployed_path, &built_path, None).unwrap();
+        assert_eq!(diff.added, vec!["0.0.0.0 added.domain"]);
+        assert_eq!(diff.removed, vec!["0.0.0.0 removed.domain"]);
+    }
+}
@@ -0,0 +1,408 @@
+use std::{
+    net::IpAddr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use crate::output::rpz::RPZ_TTL;
+
+/// maximum number of records changed by a single update message, keeps the message
+/// below the 64 KiB limit of DNS over TCP
+const MAX_RECORDS_PER_MESSAGE: usize = 200;
+/// seconds the clocks of client and server may differ for a TSIG signature to be valid
+const TSIG_FUDGE: u16 = 300;
+/// the only TSIG algorithm supported
+const TSIG_ALGORITHM: &str = "hmac-sha256";
+
+const OPCODE_UPDATE: u16 = 5 << 11;
+const TYPE_A: u16 = 1;
+pub(crate) const TYPE_SOA: u16 = 6;
+const TYPE_CNAME: u16 = 5;
+const TYPE_AAAA: u16 = 28;
+const TYPE_TSIG: u16 = 250;
+const TYPE_ANY: u16 = 255;
+pub(crate) const CLASS_IN: u16 = 1;
+const CLASS_ANY: u16 = 255;
+
+/// DnsUpdateSettings configures the publication of an output's changes to the zone of a
+/// DNS server by dynamic updates (RFC 2136)
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DnsUpdateSettings {
+    /// address of the primary server (e.g. "127.0.0.1:53")
+    pub server: String,
+    /// name of the TSIG key the updates are signed with
+    pub tsig_key: Option<String>,
+    /// file containing the base64 encoded secret of the TSIG key (hmac-sha256)
+    pub tsig_secret_file: Option<String>,
+}
+
+/// Tsig signs messages with a shared secret (RFC 8945)
+pub(crate) struct Tsig {
+    key_name: String,
+    secret: Vec<u8>,
+}
+
+impl Tsig {
+    /// returns the TSIG key if configured
+    ///
+    /// * `tsig_key`: the name of the key
+    /// * `tsig_secret_file`: file containing the base64 encoded secret of the key
+    pub(crate) fn load(
+        tsig_key: &Option<String>,
+        tsig_secret_file: &Option<String>,
+    ) -> anyhow::Result<Option<Self>> {
+        let (key_name, secret_file) = match (tsig_key, tsig_secret_file) {
+            (Some(key_name), Some(secret_file)) => (key_name, secret_file),
+            (None, None) => return Ok(None),
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "tsig_key and tsig_secret_file must be configured together"
+                ))
+            }
+        };
+        let secret = std::fs::read_to_string(secret_file)
+            .with_context(|| format!("could not read TSIG secret {}", secret_file))?;
+        let secret = base64::decode(secret.trim())
+            .with_context(|| format!("TSIG secret {} isn't base64 encoded", secret_file))?;
+        Ok(Some(Tsig {
+            key_name: key_name.clone(),
+            secret,
+        }))
+    }
+}
+
+impl DnsUpdateSettings {
+    /// replaces the records of the removed domains by those of the added domains in the
+    /// zone, the records answer the domains with NXDOMAIN as in a response policy zone
+    /// or with the address of the warn page
+    ///
+    /// * `zone`: the name of the zone
+    /// * `added`: domains to be added to the zone
+    /// * `removed`: domains to be removed from the zone
+    /// * `address`: the address the blocked domains resolve to
+    pub async fn publish(
+        &self,
+        zone: &str,
+        added: &[String],
+        removed: &[String],
+        address: IpAddr,
+    ) -> anyhow::Result<()> {
+        let tsig = Tsig::load(&self.tsig_key, &self.tsig_secret_file)?;
+        let updates: Vec<Update> = removed
+            .iter()
+            .map(|d| Update::Delete(d.as_str()))
+            .chain(added.iter().map(|d| Update::Add(d.as_str())))
+            .collect();
+        for batch in updates.chunks(MAX_RECORDS_PER_MESSAGE) {
+            let message = update_message(message_id(), zone, batch, address, tsig.as_ref())?;
+            self.send(&message).await.with_context(|| {
+                format!("dynamic update of zone {} on {} failed", zone, self.server)
+            })?;
+        }
+        info!(
+            "Published {} additions and {} removals to zone {} on {}",
+            added.len(),
+            removed.len(),
+            zone,
+            self.server
+        );
+        Ok(())
+    }
+
+    /// sends a message over TCP and checks the response code
+    ///
+    /// * `message`: the update message
+    async fn send(&self, message: &[u8]) -> anyhow::Result<()> {
+        let mut stream = TcpStream::connect(&self.server).await?;
+        stream
+            .write_all(&(message.len() as u16).to_be_bytes())
+            .await?;
+        stream.write_all(message).await?;
+        let len = stream.read_u16().await?;
+        let mut response = vec![0; len as usize];
+        stream.read_exact(&mut response).await?;
+        if response.len() < 12 || response[..2] != message[..2] {
+            return Err(anyhow::anyhow!("invalid response"));
+        }
+        match u16::from_be_bytes([response[2], response[3]]) & 0x000f {
+            0 => Ok(()),
+            rcode => Err(anyhow::anyhow!(
+                "server responded with {}",
+                rcode_name(rcode)
+            )),
+        }
+    }
+}
+
+/// a change of the zone's records
+#[derive(Debug, Clone, Copy)]
+enum Update<'a> {
+    Add(&'a str),
+    Delete(&'a str),
+}
+
+/// returns an id for a new message
+pub(crate) fn message_id() -> u16 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or_default();
+    (nanos ^ std::process::id()) as u16
+}
+
+/// returns the mnemonic of a response code
+///
+/// * `rcode`: the response code
+pub(crate) fn rcode_name(rcode: u16) -> String {
+    match rcode {
+        1 => "FORMERR".to_string(),
+        2 => "SERVFAIL".to_string(),
+        3 => "NXDOMAIN".to_string(),
+        4 => "NOTIMP".to_string(),
+        5 => "REFUSED".to_string(),
+        9 => "NOTAUTH".to_string(),
+        10 => "NOTZONE".to_string(),
+        _ => format!("RCODE {}", rcode),
+    }
+}
+
+/// appends a domain name in wire format
+///
+/// * `buf`: the message
+/// * `name`: the domain name, relative names are treated as absolute
+pub(crate) fn write_name(buf: &mut Vec<u8>, name: &str) -> anyhow::Result<()> {
+    for label in name
+        .trim_end_matches('.')
+        .split('.')
+        .filter(|l| !l.is_empty())
+    {
+        if label.len() > 63 {
+            return Err(anyhow::anyhow!("label of {} exceeds 63 bytes", name));
+        }
+        buf.push(label.len() as u8);
+        buf.extend(label.as_bytes());
+    }
+    buf.push(0);
+    Ok(())
+}
+
+/// appends a resource record
+///
+/// * `buf`: the message
+/// * `name`: the owner of the record
+/// * `rtype`: the type of the record
+/// * `class`: the class of the record
+/// * `ttl`: the time to live of the record
+/// * `rdata`: the data of the record
+fn write_record(
+    buf: &mut Vec<u8>,
+    name: &str,
+    rtype: u16,
+    class: u16,
+    ttl: u32,
+    rdata: &[u8],
+) -> anyhow::Result<()> {
+    write_name(buf, name)?;
+    buf.extend(rtype.to_be_bytes());
+    buf.extend(class.to_be_bytes());
+    buf.extend(ttl.to_be_bytes());
+    buf.extend((rdata.len() as u16).to_be_bytes());
+    buf.extend(rdata);
+    Ok(())
+}
+
+/// returns an update message for the zone, signed if a TSIG key is given
+///
+/// * `id`: the id of the message
+/// * `zone`: the name of the zone
+/// * `updates`: the changes of the zone's records
+/// * `address`: the address the added domains resolve to
+/// * `tsig`: the key the message is signed with
+fn update_message(
+    id: u16,
+    zone: &str,
+    updates: &[Update],
+    address: IpAddr,
+    tsig: Option<&Tsig>,
+) -> anyhow::Result<Vec<u8>> {
+    let mut buf = vec![];
+    buf.extend(id.to_be_bytes());
+    buf.extend(OPCODE_UPDATE.to_be_bytes());
+    // one zone, no prerequisites, the updates and no additional records yet
+    for count in [1, 0, updates.len() as u16, 0] {
+        buf.extend(count.to_be_bytes());
+    }
+    write_name(&mut buf, zone)?;
+    buf.extend(TYPE_SOA.to_be_bytes());
+    buf.extend(CLASS_IN.to_be_bytes());
+
+    let zone = zone.trim_end_matches('.');
+    // CNAME to the root answers with NXDOMAIN
+    let (rtype, rdata) = match address {
+        address if address.is_unspecified() => (TYPE_CNAME, vec![0]),
+        IpAddr::V4(address) => (TYPE_A, address.octets().to_vec()),
+        IpAddr::V6(address) => (TYPE_AAAA, address.octets().to_vec()),
+    };
+    for update in updates {
+        match update {
+            Update::Add(domain) => {
+                let name = format!("{}.{}", domain, zone);
+                write_record(&mut buf, &name, rtype, CLASS_IN, RPZ_TTL, &rdata)?;
+            }
+            Update::Delete(domain) => {
+                // all records of the name, whichever policy they were added with
+                let name = format!("{}.{}", domain, zone);
+                write_record(&mut buf, &name, TYPE_ANY, CLASS_ANY, 0, &[])?;
+            }
+        }
+    }
+    if let Some(tsig) = tsig {
+        let time_signed = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        sign(&mut buf, tsig, time_signed)?;
+    }
+    Ok(buf)
+}
+
+/// appends the TSIG record signing the message
+///
+/// * `buf`: the message
+/// * `tsig`: the key the message is signed with
+/// * `time_signed`: the unix timestamp of the signature
+pub(crate) fn sign(buf: &mut Vec<u8>, tsig: &Tsig, time_signed: u64) -> anyhow::Result<()> {
+    let time_signed = &time_signed.to_be_bytes()[2..];
+    let mut algorithm = vec![];
+    write_name(&mut algorithm, TSIG_ALGORITHM)?;
+    let mut key_name = vec![];
+    write_name(&mut key_name, &tsig.key_name.to_lowercase())?;
+
+    // the MAC covers the message and the TSIG variables
+    let mut mac = Hmac::<Sha256>::new_from_slice(&tsig.secret)
+        .map_err(|e| anyhow::anyhow!("invalid TSIG secret: {}", e))?;
+    mac.update(buf);
+    mac.update(&key_name);
+    mac.update(&CLASS_ANY.to_be_bytes());
+    mac.update(&0u32.to_be_bytes());
+    mac.update(&algorithm);
+    mac.update(time_signed);
+    mac.update(&TSIG_FUDGE.to_be_bytes());
+    // no error and no other data
+    mac.update(&[0, 0, 0, 0]);
+    let mac = mac.finalize().into_bytes();
+
+    let mut rdata = algorithm;
+    rdata.extend(time_signed);
+    rdata.extend(TSIG_FUDGE.to_be_bytes());
+    rdata.extend((mac.len() as u16).to_be_bytes());
+    rdata.extend(mac.as_slice());
+    rdata.extend(&buf[..2]);
+    rdata.extend([0, 0, 0, 0]);
+    write_record(buf, &tsig.key_name, TYPE_TSIG, CLASS_ANY, 0, &rdata)?;
+
+    // the TSIG record is counted as additional record
+    let additional = u16::from_be_bytes([buf[10], buf[11]]) + 1;
+    buf[10..12].copy_from_slice(&additional.to_be_bytes());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use crate::output::hostsfile::DEFAULT_ADDRESS;
+
+    use super::*;
+
+    #[test]
+    fn test_update_message() {
+        let updates = [Update::Delete("a.domain"), Update::Add("b.domain")];
+        let message = update_message(0x1234, "rpz", &updates, DEFAULT_ADDRESS, None).unwrap();
+        let want: Vec<u8> = [
+            // header: id, opcode UPDATE, 1 zone, 0 prerequisites, 2 updates, 0 additional
+            &[0x12, 0x34, 0x28, 0x00, 0, 1, 0, 0, 0, 2, 0, 0][..],
+            // zone rpz. SOA IN
+            &[3, b'r', b'p', b'z', 0, 0, 6, 0, 1],
+            // delete a.domain.rpz. ANY ANY
+            &[
+                1, b'a', 6, b'd', b'o', b'm', b'a', b'i', b'n', 3, b'r', b'p', b'z', 0,
+            ],
+            &[0, 255, 0, 255, 0, 0, 0, 0, 0, 0],
+            // add b.domain.rpz. 300 IN CNAME .
+            &[
+                1, b'b', 6, b'd', b'o', b'm', b'a', b'i', b'n', 3, b'r', b'p', b'z', 0,
+            ],
+            &[0, 5, 0, 1, 0, 0, 1, 44, 0, 1, 0],
+        ]
+        .concat();
+        assert_eq!(message, want);
+
+        // add b.domain.rpz. 300 IN A 192.0.2.80
+        let address = IpAddr::from([192, 0, 2, 80]);
+        let message = update_message(1, "rpz", &updates[1..], address, None).unwrap();
+        assert!(message.ends_with(&[0, 1, 0, 1, 0, 0, 1, 44, 0, 4, 192, 0, 2, 80]));
+    }
+
+    #[test]
+    fn test_sign() {
+        let tsig = Tsig {
+            key_name: "key".to_string(),
+            secret: b"secret".to_vec(),
+        };
+        let unsigned =
+            update_message(1, "rpz", &[Update::Add("a.domain")], DEFAULT_ADDRESS, None).unwrap();
+        let mut signed = unsigned.clone();
+        sign(&mut signed, &tsig, 1_700_000_000).unwrap();
+
+        // the unsigned message is kept, only the additional count changes
+        assert_eq!(&signed[..10], &unsigned[..10]);
+        assert_eq!(&signed[10..12], &[0, 1]);
+        assert_eq!(&signed[12..unsigned.len()], &unsigned[12..]);
+        let tsig_record = &signed[unsigned.len()..];
+        assert!(tsig_record.starts_with(&[3, b'k', b'e', b'y', 0, 0, 250, 0, 255]));
+        // the record ends with the original id, no error and no other data
+        assert_eq!(&tsig_record[tsig_record.len() - 6..], &[0, 1, 0, 0, 0, 0]);
+    }
+
+    #[tokio::test]
+    async fn test_publish() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let settings = DnsUpdateSettings {
+            server: listener.local_addr().unwrap().to_string(),
+            tsig_key: None,
+            tsig_secret_file: None,
+        };
+        // the server accepts the first message and refuses the second
+        let server = tokio::spawn(async move {
+            for rcode in [0, 5] {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let len = stream.read_u16().await.unwrap();
+                let mut message = vec![0; len as usize];
+                stream.read_exact(&mut message).await.unwrap();
+                let mut response = message[..12].to_vec();
+                response[2] |= 0x80;
+                response[3] |= rcode;
+                stream.write_u16(response.len() as u16).await.unwrap();
+                stream.write_all(&response).await.unwrap();
+            }
+        });
+
+        let added = vec!["a.domain".to_string()];
+        settings
+            .publish("rpz", &added, &[], DEFAULT_ADDRESS)
+            .await
+            .unwrap();
+        let err = settings
+            .publish("rpz", &added, &[], DEFAULT_ADDRESS)
+            .await
+            .unwrap_err();
+        assert!(format!("{:?}", err).contains("REFUSED"));
+        server.await.unwrap();
+    }
+}
@@ -0,0 +1,310 @@
+use anyhow::Context;
+use reqwest::{header::CONTENT_TYPE, Client, Method, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+
+/// maximum number of documents indexed in one bulk request if no limit was configured
+pub const DEFAULT_BATCH_SIZE: usize = 5000;
+
+/// RolloverConditions are the conditions any of which starts a new backing index of
+/// the alias, unset conditions are ignored
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RolloverConditions {
+    /// maximum age of the index (e.g. "7d")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<String>,
+    /// maximum number of documents in the index
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_docs: Option<u64>,
+    /// maximum size of the largest primary shard of the index (e.g. "50gb")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_primary_shard_size: Option<String>,
+}
+
+/// ElasticsearchSettings configures the indexing of the outputs' entries into an
+/// Elasticsearch or OpenSearch cluster
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ElasticsearchSettings {
+    /// base URL of the cluster (e.g. "https://127.0.0.1:9200")
+    pub url: String,
+    /// the index, or the write alias if rollover is configured
+    pub index: String,
+    pub username: Option<String>,
+    /// file containing the password of the user
+    pub password_file: Option<String>,
+    /// rolls the alias over to a new index if any of the conditions is met
+    pub rollover: Option<RolloverConditions>,
+    /// maximum number of documents indexed in one bulk request
+    pub batch_size: Option<usize>,
+}
+
+/// EntryDocument is the indexed document of an entry of an output
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct EntryDocument {
+    /// time of the run in RFC 3339 format
+    #[serde(rename = "@timestamp")]
+    pub timestamp: String,
+    pub domain: String,
+    /// the tag of the output
+    pub category: String,
+    /// the id of the list the entry was read from
+    pub source: Option<String>,
+    /// the entry was found in more than one list of the category
+    pub multiple_sources: bool,
+    /// time of the first run the entry was written in, in RFC 3339 format
+    pub first_seen: Option<String>,
+    /// time of the last run the entry was written in, in RFC 3339 format
+    pub last_seen: Option<String>,
+    /// number of the autonomous system announcing an IP entry
+    pub asn: Option<u32>,
+    /// ISO 3166 country code of the autonomous system
+    pub country: Option<String>,
+    /// the name of the project the output belongs to
+    pub project: Option<String>,
+}
+
+impl ElasticsearchSettings {
+    /// returns the maximum number of documents indexed in one bulk request
+    pub fn batch_size(&self) -> usize {
+        self.batch_size.unwrap_or(DEFAULT_BATCH_SIZE).max(1)
+    }
+
+    /// indexes the documents by bulk requests, the alias is rolled over before if
+    /// rollover is configured
+    ///
+    /// * `documents`: the documents of a run
+    pub async fn index(&self, documents: &[EntryDocument]) -> anyhow::Result<()> {
+        if documents.is_empty() {
+            return Ok(());
+        }
+        let client = Client::new();
+        if let Some(conditions) = &self.rollover {
+            self.rollover(&client, conditions).await?;
+        }
+        for batch in documents.chunks(self.batch_size()) {
+            let response = self
+                .request(&client, Method::POST, &format!("{}/_bulk", self.index))?
+                .header(CONTENT_TYPE, "application/x-ndjson")
+                .body(bulk_body(batch)?)
+                .send()
+                .await
+                .with_context(|| format!("could not index into {}", self.index))?;
+            let status = response.status();
+            let body = response.text().await?;
+            if !status.is_success() {
+                return Err(anyhow::anyhow!(
+                    "bulk request to {} failed with {}: {}",
+                    self.index,
+                    status,
+                    body
+                ));
+            }
+            check_bulk_response(&body).with_context(|| format!("index {}", self.index))?;
+        }
+        debug!("Indexed {} documents into {}", documents.len(), self.index);
+        Ok(())
+    }
+
+    /// creates the first backing index of the alias if the alias doesn't exist yet and
+    /// rolls the alias over if any of the conditions is met
+    ///
+    /// * `client`: the HTTP client
+    /// * `conditions`: the rollover conditions
+    async fn rollover(
+        &self,
+        client: &Client,
+        conditions: &RolloverConditions,
+    ) -> anyhow::Result<()> {
+        let alias = self
+            .request(client, Method::HEAD, &format!("_alias/{}", self.index))?
+            .send()
+            .await
+            .with_context(|| format!("could not look up alias {}", self.index))?;
+        if alias.status() == StatusCode::NOT_FOUND {
+            let body = serde_json::json!({
+                "aliases": { self.index.clone(): { "is_write_index": true } }
+            });
+            self.send(
+                client,
+                Method::PUT,
+                &format!("{}-000001", self.index),
+                body.to_string(),
+            )
+            .await?;
+            info!("Created index {}-000001", self.index);
+        }
+        let body = serde_json::json!({ "conditions": conditions });
+        let response = self
+            .send(
+                client,
+                Method::POST,
+                &format!("{}/_rollover", self.index),
+                body.to_string(),
+            )
+            .await?;
+        let response: serde_json::Value = serde_json::from_str(&response)?;
+        if response["rolled_over"] == true {
+            info!(
+                "Rolled {} over to {}",
+                self.index,
+                response["new_index"].as_str().unwrap_or_default()
+            );
+        }
+        Ok(())
+    }
+
+    /// sends a JSON request and returns the response body
+    ///
+    /// * `client`: the HTTP client
+    /// * `method`: the HTTP method
+    /// * `path`: the path relative to the base URL
+    /// * `body`: the JSON body
+    async fn send(
+        &self,
+        client: &Client,
+        method: Method,
+        path: &str,
+        body: String,
+    ) -> anyhow::Result<String> {
+        let response = self
+            .request(client, method, path)?
+            .header(CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await
+            .with_context(|| format!("request to {} failed", path))?;
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            return Err(anyhow::anyhow!(
+                "request to {} failed with {}: {}",
+                path,
+                status,
+                body
+            ));
+        }
+        Ok(body)
+    }
+
+    /// returns a request authenticated with the configured credentials
+    ///
+    /// * `client`: the HTTP client
+    /// * `method`: the HTTP method
+    /// * `path`: the path relative to the base URL
+    fn request(
+        &self,
+        client: &Client,
+        method: Method,
+        path: &str,
+    ) -> anyhow::Result<RequestBuilder> {
+        let url = format!("{}/{}", self.url.trim_end_matches('/'), path);
+        let mut request = client.request(method, url);
+        if let Some(username) = &self.username {
+            let password = match &self.password_file {
+                Some(password_file) => Some(
+                    std::fs::read_to_string(password_file)
+                        .with_context(|| {
+                            format!(
+                                "could not read Elasticsearch password file {}",
+                                password_file
+                            )
+                        })?
+                        .trim_end()
+                        .to_string(),
+                ),
+                None => None,
+            };
+            request = request.basic_auth(username, password);
+        }
+        Ok(request)
+    }
+}
+
+/// returns the newline delimited body of a bulk request indexing the documents
+///
+/// * `documents`: the documents to be indexed
+fn bulk_body(documents: &[EntryDocument]) -> anyhow::Result<String> {
+    let mut body = String::new();
+    for document in documents {
+        body += "{\"index\":{}}\n";
+        body += &serde_json::to_string(document)?;
+        body += "\n";
+    }
+    Ok(body)
+}
+
+/// returns an error describing the first failed item of a bulk response
+///
+/// * `body`: the body of the bulk response
+fn check_bulk_response(body: &str) -> anyhow::Result<()> {
+    let response: serde_json::Value = serde_json::from_str(body)?;
+    if response["errors"] != true {
+        return Ok(());
+    }
+    let items = response["items"].as_array().cloned().unwrap_or_default();
+    let failed = items.iter().filter(|i| !i["index"]["error"].is_null());
+    let count = failed.clone().count();
+    let reason = failed
+        .map(|i| i["index"]["error"]["reason"].to_string())
+        .next()
+        .unwrap_or_default();
+    Err(anyhow::anyhow!(
+        "{} documents were rejected: {}",
+        count,
+        reason
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bulk_body() {
+        let document = EntryDocument {
+            timestamp: "2022-12-01T00:00:00Z".to_string(),
+            domain: "a.domain".to_string(),
+            category: "malware".to_string(),
+            source: Some("list".to_string()),
+            multiple_sources: false,
+            first_seen: Some("2022-11-01T00:00:00Z".to_string()),
+            last_seen: Some("2022-12-01T00:00:00Z".to_string()),
+            asn: Some(13335),
+            country: Some("US".to_string()),
+            project: None,
+        };
+        let body = bulk_body(&[document.clone(), document]).unwrap();
+        let lines: Vec<&str> = body.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], "{\"index\":{}}");
+        let value: serde_json::Value = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!(value["@timestamp"], "2022-12-01T00:00:00Z");
+        assert_eq!(value["source"], "list");
+        assert_eq!(value["first_seen"], "2022-11-01T00:00:00Z");
+        assert_eq!(value["asn"], 13335);
+        assert!(body.ends_with('\n'));
+    }
+
+    #[test]
+    fn test_check_bulk_response() {
+        assert!(check_bulk_response(r#"{"errors":false,"items":[]}"#).is_ok());
+        let rejected = r#"{"errors":true,"items":[
+            {"index":{"status":201}},
+            {"index":{"status":400,"error":{"reason":"mapper_parsing_exception"}}}
+        ]}"#;
+        let e = check_bulk_response(rejected).unwrap_err().to_string();
+        assert!(e.contains("1 documents") && e.contains("mapper_parsing_exception"));
+    }
+
+    #[test]
+    fn test_rollover_conditions() {
+        let conditions = RolloverConditions {
+            max_docs: Some(1000),
+            ..Default::default()
+        };
+        // unset conditions must not be sent
+        assert_eq!(
+            serde_json::to_string(&conditions).unwrap(),
+            r#"{"max_docs":1000}"#
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Nonce,
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::platform;
+
+/// marks an encrypted output file, followed by the nonce and the ciphertext
+pub const MAGIC: &[u8] = b"HARVESTER-AES-256-GCM\n";
+/// length of the random nonce in bytes
+const NONCE_LEN: usize = 12;
+
+/// EncryptionSettings encrypt an output file with AES-256-GCM, so a curated list which
+/// is confidential itself can be published to shared storage
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct EncryptionSettings {
+    /// file containing the base64 encoded 256 bit key
+    pub key_file: String,
+}
+
+impl EncryptionSettings {
+    /// reads the key and returns the cipher
+    fn cipher(&self) -> anyhow::Result<Aes256Gcm> {
+        let key = fs::read_to_string(&self.key_file)
+            .with_context(|| format!("could not read encryption key {}", self.key_file))?;
+        let key = base64::decode(key.trim())
+            .with_context(|| format!("encryption key {} isn't base64 encoded", self.key_file))?;
+        Aes256Gcm::new_from_slice(&key)
+            .map_err(|_| anyhow::anyhow!("encryption key {} must be 32 bytes", self.key_file))
+    }
+
+    /// encrypts a file in place, a file which is already encrypted is left unchanged
+    ///
+    /// * `path`: the file system path of the file
+    pub fn encrypt_file(&self, path: &Path) -> anyhow::Result<()> {
+        let plaintext = fs::read(path).with_context(|| format!("could not read {:?}", path))?;
+        if is_encrypted(&plaintext) {
+            return Ok(());
+        }
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher()?
+            .encrypt(&nonce, plaintext.as_ref())
+            .map_err(|_| anyhow::anyhow!("could not encrypt {:?}", path))?;
+        let mut contents = MAGIC.to_vec();
+        contents.extend_from_slice(&nonce);
+        contents.extend(ciphertext);
+
+        // the plain file is replaced at once, so readers never see a partial file
+        let mut tmp_path = PathBuf::from(path);
+        tmp_path.set_file_name(format!(
+            ".{}.enc",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        ));
+        fs::write(&tmp_path, contents)
+            .with_context(|| format!("could not write {:?}", tmp_path))?;
+        platform::replace_file(&tmp_path, path)
+            .with_context(|| format!("could not move {:?} into place", path))?;
+        Ok(())
+    }
+
+    /// decrypts the contents of an encrypted file
+    ///
+    /// * `contents`: the contents including the marker and the nonce
+    fn decrypt(&self, contents: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let contents = &contents[MAGIC.len()..];
+        if contents.len() < NONCE_LEN {
+            return Err(anyhow::anyhow!("encrypted file is truncated"));
+        }
+        let (nonce, ciphertext) = contents.split_at(NONCE_LEN);
+        self.cipher()?
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow::anyhow!("decryption failed, the key doesn't match"))
+    }
+}
+
+/// returns true if the contents of a file were encrypted
+///
+/// * `contents`: the contents of the file
+fn is_encrypted(contents: &[u8]) -> bool {
+    contents.starts_with(MAGIC)
+}
+
+/// returns the contents of an output file, which are decrypted if the file is
+/// encrypted
+///
+/// * `path`: the file system path of the output file
+/// * `encryption`: the encryption settings of the output, if any
+pub fn read_output(path: &Path, encryption: Option<&EncryptionSettings>) -> anyhow::Result<String> {
+    let contents = fs::read(path).with_context(|| format!("could not read {:?}", path))?;
+    decode_output(contents, path, encryption)
+}
+
+/// returns the contents of an output file read before, which are decrypted if the
+/// file is encrypted
+///
+/// * `contents`: the contents of the output file
+/// * `path`: the file system path of the output file
+/// * `encryption`: the encryption settings of the output, if any
+pub fn decode_output(
+    contents: Vec<u8>,
+    path: &Path,
+    encryption: Option<&EncryptionSettings>,
+) -> anyhow::Result<String> {
+    let contents = match (is_encrypted(&contents), encryption) {
+        (false, _) => contents,
+        (true, Some(encryption)) => encryption
+            .decrypt(&contents)
+            .with_context(|| format!("could not decrypt {:?}", path))?,
+        (true, None) => {
+            return Err(anyhow::anyhow!(
+                "{:?} is encrypted but no key is configured",
+                path
+            ))
+        }
+    };
+    String::from_utf8(contents).with_context(|| format!("{:?} isn't valid UTF-8", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::helper::cache_file_creator::TEST_CACHE;
+
+    use super::*;
+
+    #[test]
+    fn test_encrypt_file() {
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_encrypt_file");
+        fs::create_dir_all(&dir).unwrap();
+        let key_file = dir.join("key");
+        fs::write(&key_file, base64::encode([7u8; 32]) + "\n").unwrap();
+        let settings = EncryptionSettings {
+            key_file: key_file.to_string_lossy().to_string(),
+        };
+        let path = dir.join("malware");
+        let plaintext = "0.0.0.0 confidential.domain\n";
+        fs::write(&path, plaintext).unwrap();
+
+        settings.encrypt_file(&path).unwrap();
+        let encrypted = fs::read(&path).unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert!(!String::from_utf8_lossy(&encrypted).contains("confidential"));
+        assert_eq!(read_output(&path, Some(&settings)).unwrap(), plaintext);
+
+        // an encrypted file isn't encrypted twice
+        settings.encrypt_file(&path).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), encrypted);
+
+        assert!(read_output(&path, None).is_err());
+        fs::write(&key_file, base64::encode([8u8; 32])).unwrap();
+        assert!(read_output(&path, Some(&settings)).is_err());
+    }
+}
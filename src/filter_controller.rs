@@ -8,12 +8,14 @@ use std::{
     },
 };
 
+use bytes::Bytes;
 use futures::Future;
-use tokio::task::JoinHandle;
+use opentelemetry::KeyValue;
+use tokio::{sync::Semaphore, task::JoinHandle};
 
 use crate::{
     config::Config, filter_list::FilterList, input::Input, io::category_list_io::CategoryListIO,
-    io::filter_list_io::FilterListIO,
+    io::filter_list_io::FilterListIO, telemetry,
 };
 
 /// These structs represent the stages of a program run
@@ -33,25 +35,45 @@ pub struct FilterController<'config, Stage, R: Input + Send, W: Write + Send> {
     pub is_processing: Arc<AtomicBool>,
 }
 
+/// ListOutcome tells whether a list passed to `process` was read and written completely
+#[derive(Debug)]
+pub struct ListOutcome {
+    /// the id of the list
+    pub id: String,
+    /// false if reading or writing the list failed or the processing was aborted
+    pub complete: bool,
+}
+
 /// `process` is the main data processing function. It reads chunks from the source
 /// applies a transformation function and writes the data to the output
 ///
 /// * `filter_lists`: a list of FilterListIO to be processed
+/// * `fn_prepare`: builds what the transformation needs of a list once per list, e.g.
+///   its compiled regex. Lists it fails for are skipped
 /// * `fn_transform`: the function to apply to every chunk the FilterListIO's reader returns
-/// * `command_rx`: a channel receiver listening for commands
-/// * `message_tx`: a channel sender for messaging purpose
-pub async fn process<SRC, DST, FN, RES>(
+/// * `is_processing`: false once the processing is aborted
+/// * `limit`: limits the number of lists read at once, unlimited if None
+/// * `batch_size`: number of chunks read and written at once
+///
+/// Returns the tasks processing the lists, each resolving to the list's outcome
+pub async fn process<SRC, DST, PREP, CTX, FN, RES>(
     filter_lists: &mut Vec<FilterListIO<SRC, DST>>,
+    fn_prepare: &'static PREP,
     fn_transform: &'static FN,
     is_processing: Arc<AtomicBool>,
-) -> Vec<JoinHandle<()>>
+    limit: Option<Arc<Semaphore>>,
+    batch_size: usize,
+) -> Vec<JoinHandle<ListOutcome>>
 where
     SRC: Input + Send + 'static,
-    FN: Fn(Arc<FilterList>, Option<Vec<u8>>) -> RES + Send + Sync + 'static,
+    PREP: Fn(&FilterList) -> anyhow::Result<CTX>,
+    CTX: Send + Sync + 'static,
+    FN: Fn(Arc<CTX>, Option<Bytes>) -> RES + Send + Sync + 'static,
     DST: Write + Send + 'static,
-    RES: Future<Output = anyhow::Result<Option<Vec<u8>>>> + Send + Sync + 'static,
+    RES: Future<Output = anyhow::Result<Option<Bytes>>> + Send + Sync + 'static,
 {
-    let mut handles: Vec<JoinHandle<()>> = Vec::new();
+    let batch_size = batch_size.max(1);
+    let mut handles: Vec<JoinHandle<ListOutcome>> = Vec::new();
     for FilterListIO {
         reader,
         writer,
@@ -76,56 +98,108 @@ where
                 continue;
             }
         };
+        let context = match fn_prepare(filter_list) {
+            Ok(context) => Arc::new(context),
+            Err(e) => {
+                error!("Error: {:#}", e);
+                continue;
+            }
+        };
         let filter_list = Arc::new(filter_list.clone());
         let list = Arc::clone(&filter_list);
 
         let is_proc = Arc::clone(&is_processing);
-        let handle = tokio::spawn(async move {
+        let limit = limit.clone();
+        let attributes = vec![KeyValue::new("list", filter_list.id.clone())];
+        let handle = telemetry::spawn_traced("list", attributes, async move {
+            // the permit is held until the list is read completely
+            let _permit = match limit {
+                Some(limit) => limit.acquire_owned().await.ok(),
+                None => None,
+            };
             let mut chunks_matched = 0;
             let mut chunks_skipped = 0;
-            loop {
+            let mut chunks_failed = 0;
+            let mut first_error = None;
+            let mut complete = true;
+            let mut batch = Vec::with_capacity(batch_size);
+            let mut exhausted = false;
+            while !exhausted {
                 if !is_proc.load(Ordering::SeqCst) {
                     debug!("quitting task: {}", list.id);
-                    return;
+                    return ListOutcome {
+                        id: list.id.clone(),
+                        complete: false,
+                    };
+                }
+                // the reader and the writer are locked once per batch instead of per chunk
+                {
+                    let mut reader = reader.lock().await;
+                    while batch.len() < batch_size {
+                        match reader.chunk().await {
+                            Ok(Some(chunk)) => batch.push(chunk),
+                            // reader exhausted
+                            Ok(None) => {
+                                exhausted = true;
+                                break;
+                            }
+                            // reader error
+                            Err(e) => {
+                                error!("Error: {}", e);
+                                complete = false;
+                                exhausted = true;
+                                break;
+                            }
+                        }
+                    }
                 }
-                // stop task on quit message
-                let result = reader.lock().await.chunk().await;
-                match result {
-                    Ok(Some(chunk)) => match fn_transform(list.clone(), Some(chunk)).await {
+                let mut matched = Vec::with_capacity(batch.len());
+                for chunk in batch.drain(..) {
+                    match fn_transform(context.clone(), Some(chunk)).await {
                         // regex matched
                         Ok(Some(chunk)) => {
                             chunks_matched += 1;
-                            if let Err(e) = writer.lock().await.write_all(&chunk) {
-                                error!("{}", e);
-                            }
+                            matched.push(chunk);
                         }
                         // regex did not match
                         Ok(None) => {
                             chunks_skipped += 1;
                         }
-                        // regex error
+                        // a line which can't be transformed doesn't discard the others
                         Err(e) => {
-                            error!("Error: {}", e);
-                            break;
+                            chunks_failed += 1;
+                            first_error.get_or_insert(e);
                         }
-                    },
-                    // reader exhausted
-                    Ok(None) => {
-                        break;
                     }
-                    // reader error
-                    Err(e) => {
-                        error!("Error: {}", e);
-                        break;
+                }
+                let mut writer = writer.lock().await;
+                for chunk in matched {
+                    if let Err(e) = writer.write_all(&chunk) {
+                        error!("{}", e);
+                        complete = false;
                     }
                 }
             }
+            if let Err(e) = writer.lock().await.flush() {
+                error!("{}", e);
+                complete = false;
+            }
+            if let Some(e) = first_error {
+                warn!(
+                    "{}: {} lines skipped which couldn't be transformed, the first: {}",
+                    list.id, chunks_failed, e
+                );
+            }
             if chunks_matched == 0 {
                 warn!("No lines machted in list {}", list.id);
             } else {
                 debug!("{}: {} lines matched", list.id, chunks_matched);
                 debug!("{}: {} lines skipped", list.id, chunks_skipped);
             }
+            ListOutcome {
+                id: list.id.clone(),
+                complete,
+            }
         });
         handles.push(handle);
     }
@@ -136,6 +210,7 @@ where
 mod tests {
     use std::io::Cursor;
 
+    use crate::config::DEFAULT_BATCH_SIZE;
     use crate::filter_list::FilterList;
     use crate::tests::helper::cursor_input::CursorInput;
     use futures::future::join_all;
@@ -161,6 +236,7 @@ mod tests {
             source: "".to_string(),
             tags: vec![],
             regex: "".to_string(),
+            ..Default::default()
         };
 
         // wrap the Filterlist in the FilterListIO object
@@ -172,14 +248,80 @@ mod tests {
         // process the data with a transform function just forwarding the data
         let handles = process(
             &mut vec![filter_list_io],
+            &|_| Ok(()),
             &|_, c| async { Ok(c) },
             is_processing.clone(),
+            None,
+            1,
         )
         .await;
-        join_all(handles).await;
+        let outcomes = join_all(handles).await;
+        assert!(outcomes[0].as_ref().unwrap().complete);
         let o = output.lock().await.clone().into_inner();
 
         // the data in the out put should be the same as the input data
-        assert!(String::from_utf8_lossy(&o).starts_with(&input_data));
+        assert!(String::from_utf8_lossy(&o).starts_with(input_data));
+    }
+
+    /// tests that `process` doesn't read lists while the limit is exhausted
+    #[tokio::test]
+    async fn test_process_limit() {
+        let input_data = "line one\nline two\n";
+        let output = Arc::new(Mutex::new(Cursor::new(vec![])));
+        let mut filter_list_io: FilterListIO<CursorInput, Cursor<Vec<u8>>> =
+            FilterListIO::new(FilterList::default());
+        filter_list_io.reader = Some(Arc::new(Mutex::new(CursorInput::new(input_data))));
+        filter_list_io.writer = Some(output.clone());
+
+        let limit = Arc::new(Semaphore::new(1));
+        let permit = limit.clone().acquire_owned().await.unwrap();
+        let handles = process(
+            &mut vec![filter_list_io],
+            &|_| Ok(()),
+            &|_, c| async { Ok(c) },
+            Arc::new(AtomicBool::new(true)),
+            Some(limit.clone()),
+            DEFAULT_BATCH_SIZE,
+        )
+        .await;
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert!(output.lock().await.get_ref().is_empty());
+
+        // the list is read once the permit is released and returns its own
+        drop(permit);
+        join_all(handles).await;
+        assert_eq!(output.lock().await.get_ref(), input_data.as_bytes());
+        assert_eq!(limit.available_permits(), 1);
+    }
+
+    /// tests that `process` keeps the lines around a line failing to be transformed
+    #[tokio::test]
+    async fn test_process_transform_error() {
+        let input_data = "line one\nline two\nline three\n";
+        let output = Arc::new(Mutex::new(Cursor::new(vec![])));
+        let mut filter_list_io: FilterListIO<CursorInput, Cursor<Vec<u8>>> =
+            FilterListIO::new(FilterList::default());
+        filter_list_io.reader = Some(Arc::new(Mutex::new(CursorInput::new(input_data))));
+        filter_list_io.writer = Some(output.clone());
+
+        let handles = process(
+            &mut vec![filter_list_io],
+            &|_| Ok(()),
+            &|_, c: Option<Bytes>| async move {
+                match c {
+                    Some(c) if c.starts_with(b"line two") => Err(anyhow::anyhow!("invalid line")),
+                    c => Ok(c),
+                }
+            },
+            Arc::new(AtomicBool::new(true)),
+            None,
+            DEFAULT_BATCH_SIZE,
+        )
+        .await;
+        join_all(handles).await;
+        assert_eq!(
+            output.lock().await.get_ref(),
+            b"line one\nline three\n".as_slice()
+        );
     }
 }
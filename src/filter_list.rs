@@ -1,9 +1,73 @@
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use reqwest::Url;
 use serde::{Deserialize, Serialize};
 
-use crate::input::file::Compression;
+use crate::{
+    input::{
+        api::ApiSettings,
+        axfr::AxfrSettings,
+        azure::AzureSettings,
+        file::{Compression, DEFAULT_SCAN_TIMEOUT},
+        ftp::FtpSettings,
+        gcs::GcsSettings,
+        git::GitSettings,
+        lines::DEFAULT_READ_SIZE,
+        s3::S3Settings,
+        sftp::SftpSettings,
+        source::SourceType,
+        url::{HttpSettings, DEFAULT_RETRIES},
+    },
+    schedule::FetchWindow,
+};
+
+/// ListFormat determines how the lines of a list are read before the regex is applied
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ListFormat {
+    /// every line is matched against the regex
+    #[default]
+    Lines,
+    /// the list is a DNS zone file, the regex is matched against its owner names
+    Zone {
+        /// names below the origin are matched relative to it
+        #[serde(default)]
+        strip_origin: bool,
+    },
+    /// the list is a response policy zone file, the regex is matched against the
+    /// domains it blocks
+    Rpz,
+    /// the list is a Maltrail trail file, the regex is matched against its trails
+    Maltrail,
+    /// the list is a Suricata or Snort ruleset, the regex is matched against the
+    /// domains and addresses its rules match
+    Suricata,
+    /// the list is a JSON document or a JSON Lines file, the regex is matched against
+    /// the values selected by the JSONPath
+    Json {
+        /// JSONPath of the indicators, e.g. `$.data[*].domain`
+        path: String,
+    },
+    /// the list is an XML document, the regex is matched against the texts or
+    /// attributes selected by the path
+    Xml {
+        /// XPath-like selector of the indicators, e.g. `//item/link`
+        path: String,
+    },
+    /// the list holds hex encoded SHA-256 hash prefixes, the regex is matched against
+    /// the domains of other lists whose hashes start with them
+    HashPrefix {
+        /// prepended to the domains before hashing
+        #[serde(default)]
+        salt: String,
+        /// appended to the domains before hashing
+        #[serde(default)]
+        suffix: String,
+    },
+}
 
 /// FilterList contains the information needed to process a single filter list
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
 pub struct FilterList {
     /// can be any string, must be unique among all filter lists
     pub id: String,
@@ -11,10 +75,101 @@ pub struct FilterList {
     pub comment: Option<String>,
     /// compressed indicated if the downloaded list will be a compressed archive
     pub compression: Option<Compression>,
+    /// seconds a tar archive may be scanned for the list file
+    pub scan_timeout: Option<u64>,
+    /// remember the position of the list file within a tar archive for subsequent runs
+    #[serde(default)]
+    pub archive_index: bool,
+    /// number of bytes read from the downloaded file at once
+    pub read_size: Option<usize>,
     /// source is the path to where to get the list from (probably a URL)
     pub source: String,
+    /// source_type determines how the source is read, defaults to an URL
+    pub source_type: Option<SourceType>,
+    /// read the files in sub directories of a Dir source as well
+    #[serde(default)]
+    pub recursive: bool,
+    /// http describes the headers and credentials sent to the source type Url
+    pub http: Option<HttpSettings>,
+    /// api describes the requests of the source type Api
+    pub api: Option<ApiSettings>,
+    /// s3 describes how the bucket of the source type S3 is accessed
+    pub s3: Option<S3Settings>,
+    /// gcs describes how the bucket of the source type Gcs is accessed
+    pub gcs: Option<GcsSettings>,
+    /// azure describes how the container of the source type Azure is accessed
+    pub azure: Option<AzureSettings>,
+    /// ftp describes how the server of the source type Ftp is accessed
+    pub ftp: Option<FtpSettings>,
+    /// sftp describes how the server of the source type Sftp is accessed
+    pub sftp: Option<SftpSettings>,
+    /// git describes which file of the repository of the source type Git is read
+    pub git: Option<GitSettings>,
+    /// axfr describes how the zone of the source type Axfr is transferred
+    pub axfr: Option<AxfrSettings>,
+    /// format determines how the list is read, defaults to one entry per line
+    pub format: Option<ListFormat>,
+    /// the local times the list may be downloaded in, any time if empty
+    #[serde(default)]
+    pub fetch_windows: Vec<FetchWindow>,
+    /// maximum seconds the download is delayed by at random
+    pub random_delay: Option<u64>,
+    /// times a failed request of an URL is repeated, defaults to 3
+    pub retries: Option<u32>,
     /// tags describe the destinations where the processed URLs will end up
     pub tags: Vec<String>,
+    /// confidence in the list's entries when an output is scored, defaults to 1
+    pub weight: Option<u32>,
     /// regex to extract URL from a line
     pub regex: String,
 }
+
+impl FilterList {
+    /// returns the configured source type or the default one
+    pub fn source_type(&self) -> SourceType {
+        self.source_type.unwrap_or_default()
+    }
+
+    /// returns true if the list may be downloaded at the local time
+    ///
+    /// * `time`: a local date and time
+    pub fn in_fetch_window(&self, time: NaiveDateTime) -> bool {
+        self.fetch_windows.is_empty() || self.fetch_windows.iter().any(|w| w.contains(time))
+    }
+
+    /// returns true if each download only adds the new indicators of an API
+    pub fn is_incremental(&self) -> bool {
+        self.source_type() == SourceType::Api && self.api.as_ref().is_some_and(|a| a.checkpoint)
+    }
+
+    /// returns the configured weight or the default one
+    pub fn weight(&self) -> u32 {
+        self.weight.unwrap_or(1)
+    }
+
+    /// returns the configured number of retries or the default one
+    pub fn retries(&self) -> u32 {
+        self.retries.unwrap_or(DEFAULT_RETRIES)
+    }
+
+    /// returns the configured compression, lists with an URL source ending in the
+    /// extension of a compressed file are decompressed without one
+    pub fn compression(&self) -> Option<Compression> {
+        if self.compression.is_some() || self.source_type() != SourceType::Url {
+            return self.compression.clone();
+        }
+        Url::parse(&self.source)
+            .ok()
+            .and_then(|url| Compression::from_path(url.path()))
+    }
+
+    /// returns the configured archive scan timeout or the default one
+    pub fn scan_timeout(&self) -> Duration {
+        Duration::from_secs(self.scan_timeout.unwrap_or(DEFAULT_SCAN_TIMEOUT))
+    }
+
+    /// returns the configured number of bytes read at once or the default one
+    pub fn read_size(&self) -> usize {
+        self.read_size.unwrap_or(DEFAULT_READ_SIZE)
+    }
+}
@@ -0,0 +1,79 @@
+use std::{fs, path::Path};
+
+use regex::Regex;
+
+use crate::{
+    config::Config,
+    input::{file::FileInput, Input},
+    output::lint::is_valid_hostname,
+    DOWNLOAD_PATH,
+};
+
+/// maximum number of rejected lines exported per list
+const MAX_SAMPLES_PER_LIST: usize = 100;
+
+/// writes lines of the downloaded lists which were rejected during processing to a
+/// fuzzing corpus directory, one file per line. Returns the number of files written.
+///
+/// * `config`: the configuration containing the lists
+/// * `corpus_dir`: the directory the corpus files are written to
+pub async fn export(config: &Config, corpus_dir: &Path) -> anyhow::Result<usize> {
+    fs::create_dir_all(corpus_dir)?;
+    let download_path = config.download_dir(DOWNLOAD_PATH);
+
+    let mut exported = 0;
+    for list in config.all_lists()? {
+        let re = Regex::new(&list.regex)?;
+        let mut input = FileInput::new(download_path.join(&list.id), list.compression())
+            .with_archive_settings(list.scan_timeout(), list.archive_index);
+        let mut samples = 0;
+        while samples < MAX_SAMPLES_PER_LIST {
+            let chunk = match input.chunk().await {
+                Ok(Some(c)) => c,
+                Ok(None) => break,
+                Err(e) => {
+                    warn!("{}: {}", list.id, e);
+                    break;
+                }
+            };
+            if is_rejected(&re, &chunk) {
+                fs::write(corpus_dir.join(format!("{}-{}", list.id, samples)), &chunk)?;
+                samples += 1;
+            }
+        }
+        debug!("{}: {} rejected lines exported", list.id, samples);
+        exported += samples;
+    }
+    Ok(exported)
+}
+
+/// returns true if the line isn't valid utf-8 or the extracted entry isn't a valid
+/// host name. Lines not matching the regex like comments are skipped regularly and
+/// therefore not considered rejected.
+///
+/// * `re`: the list's regex
+/// * `chunk`: a line of the list
+fn is_rejected(re: &Regex, chunk: &[u8]) -> bool {
+    let line = match std::str::from_utf8(chunk) {
+        Ok(l) => l,
+        Err(_) => return true,
+    };
+    match re.captures(line).and_then(|c| c.get(1)) {
+        Some(m) => !is_valid_hostname(m.as_str().trim()),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_rejected() {
+        let re = Regex::new(r"^0\.0\.0\.0 (.*)").unwrap();
+        assert!(!is_rejected(&re, b"0.0.0.0 valid.domain\n"));
+        assert!(!is_rejected(&re, b"# comment\n"));
+        assert!(is_rejected(&re, b"0.0.0.0 invalid domain\n"));
+        assert!(is_rejected(&re, &[0x30, 0xff, 0xfe]));
+    }
+}
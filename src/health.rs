@@ -0,0 +1,238 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::Semaphore,
+    task::JoinHandle,
+};
+
+use crate::report::unix_timestamp;
+
+/// path of the health check endpoint
+pub const HEALTHZ_PATH: &str = "/healthz";
+/// seconds between two heartbeats if no interval was configured
+pub const DEFAULT_HEARTBEAT_INTERVAL: u64 = 30;
+/// time a client has to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// maximum number of connections served at once, further ones wait to be accepted
+const MAX_CONNECTIONS: usize = 16;
+
+/// HealthSettings configures how the state of watch mode is exposed
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct HealthSettings {
+    /// address the health check endpoint listens on (e.g. "0.0.0.0:8080")
+    pub listen: Option<String>,
+    /// file the health status is written to periodically
+    pub heartbeat_file: Option<String>,
+    /// seconds between two writes of the heartbeat file
+    pub heartbeat_interval: Option<u64>,
+}
+
+/// SchedulerStatus tells what watch mode is currently doing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum SchedulerStatus {
+    /// waiting for modifications
+    #[default]
+    Idle,
+    /// processing the lists
+    Running,
+}
+
+/// HealthStatus is the state reported to liveness probes
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct HealthStatus {
+    pub status: SchedulerStatus,
+    /// unix timestamp of the end of the last successful run
+    pub last_success: Option<u64>,
+    /// unix timestamp of the end of the last failed run
+    pub last_failure: Option<u64>,
+    /// true if the most recent run failed
+    pub last_run_failed: bool,
+    /// unix timestamp of the moment the status was reported
+    pub heartbeat: u64,
+}
+
+/// Health keeps track of the runs in watch mode and exposes the state through an HTTP
+/// endpoint and a heartbeat file
+#[derive(Debug, Clone, Default)]
+pub struct Health {
+    status: Arc<Mutex<HealthStatus>>,
+}
+
+impl Health {
+    /// marks the beginning of a run
+    pub fn run_started(&self) {
+        self.status.lock().unwrap().status = SchedulerStatus::Running;
+    }
+
+    /// marks the end of a run
+    ///
+    /// * `success`: true if the run succeeded
+    pub fn run_finished(&self, success: bool) {
+        let mut status = self.status.lock().unwrap();
+        status.status = SchedulerStatus::Idle;
+        status.last_run_failed = !success;
+        match success {
+            true => status.last_success = Some(unix_timestamp()),
+            false => status.last_failure = Some(unix_timestamp()),
+        }
+    }
+
+    /// returns the current status
+    pub fn status(&self) -> HealthStatus {
+        let mut status = self.status.lock().unwrap().clone();
+        status.heartbeat = unix_timestamp();
+        status
+    }
+
+    /// starts the health check endpoint and the heartbeat file writer as configured
+    ///
+    /// * `settings`: the health check configuration
+    pub async fn serve(&self, settings: &HealthSettings) -> anyhow::Result<Vec<JoinHandle<()>>> {
+        let mut handles = vec![];
+        if let Some(listen) = &settings.listen {
+            let listener = TcpListener::bind(listen)
+                .await
+                .with_context(|| format!("could not listen on {}", listen))?;
+            info!("Serving health checks on http://{}{}", listen, HEALTHZ_PATH);
+            handles.push(self.serve_listener(listener));
+        }
+        if let Some(heartbeat_file) = &settings.heartbeat_file {
+            let path = PathBuf::from(heartbeat_file);
+            let interval = settings
+                .heartbeat_interval
+                .unwrap_or(DEFAULT_HEARTBEAT_INTERVAL);
+            if interval == 0 {
+                return Err(anyhow::anyhow!("heartbeat_interval must be at least 1"));
+            }
+            let health = self.clone();
+            handles.push(tokio::spawn(async move {
+                let mut interval = tokio::time::interval(Duration::from_secs(interval));
+                loop {
+                    interval.tick().await;
+                    if let Err(e) = health.write_heartbeat(&path) {
+                        warn!("{:?}", e);
+                    }
+                }
+            }));
+        }
+        Ok(handles)
+    }
+
+    /// answers the health checks of the clients connecting to the listener
+    ///
+    /// * `listener`: the bound health check endpoint
+    fn serve_listener(&self, listener: TcpListener) -> JoinHandle<()> {
+        let health = self.clone();
+        let connections = Arc::new(Semaphore::new(MAX_CONNECTIONS));
+        tokio::spawn(async move {
+            loop {
+                // the semaphore is never closed
+                let Ok(permit) = connections.clone().acquire_owned().await else {
+                    return;
+                };
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        let health = health.clone();
+                        tokio::spawn(async move {
+                            if let Err(e) = health.respond(stream).await {
+                                debug!("health check: {}", e);
+                            }
+                            drop(permit);
+                        });
+                    }
+                    Err(e) => warn!("health check: {}", e),
+                }
+            }
+        })
+    }
+
+    /// answers a single HTTP request with the current status
+    ///
+    /// * `stream`: the connection to the client
+    async fn respond(&self, mut stream: TcpStream) -> anyhow::Result<()> {
+        let mut buf = [0; 1024];
+        // a connection sending nothing mustn't be held open forever
+        let n = tokio::time::timeout(REQUEST_TIMEOUT, stream.read(&mut buf))
+            .await
+            .with_context(|| "request timed out")??;
+        let request = String::from_utf8_lossy(&buf[..n]);
+        let path = request.split_whitespace().nth(1).unwrap_or_default();
+
+        let status = self.status();
+        let (code, body) = match path {
+            HEALTHZ_PATH if !status.last_run_failed => ("200 OK", serde_json::to_string(&status)?),
+            HEALTHZ_PATH => ("503 Service Unavailable", serde_json::to_string(&status)?),
+            _ => ("404 Not Found", String::new()),
+        };
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            code,
+            body.len(),
+            body
+        );
+        stream.write_all(response.as_bytes()).await?;
+        stream.shutdown().await?;
+        Ok(())
+    }
+
+    /// writes the current status to the heartbeat file
+    ///
+    /// * `path`: the file system path of the heartbeat file
+    fn write_heartbeat(&self, path: &Path) -> anyhow::Result<()> {
+        let status = serde_json::to_string(&self.status())?;
+        // write to a temporary file first so readers never see partial content
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, status)
+            .with_context(|| format!("could not write heartbeat file {:?}", tmp_path))?;
+        std::fs::rename(&tmp_path, path)
+            .with_context(|| format!("could not write heartbeat file {:?}", path))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_healthz() {
+        let health = Health::default();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        health.serve_listener(listener);
+
+        let request = |path: &'static str| async move {
+            let mut stream = TcpStream::connect(address).await.unwrap();
+            let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+
+        health.run_started();
+        health.run_finished(true);
+        assert!(request(HEALTHZ_PATH).await.starts_with("HTTP/1.1 200"));
+        health.run_finished(false);
+        assert!(request(HEALTHZ_PATH).await.starts_with("HTTP/1.1 503"));
+        assert!(request("/other").await.starts_with("HTTP/1.1 404"));
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_interval() {
+        let settings = HealthSettings {
+            listen: None,
+            heartbeat_file: Some("heartbeat".to_string()),
+            heartbeat_interval: Some(0),
+        };
+        assert!(Health::default().serve(&settings).await.is_err());
+    }
+}
@@ -0,0 +1,68 @@
+use std::{path::Path, time::Duration};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::platform;
+
+/// environment variable containing the path to the run report
+pub const REPORT_ENV: &str = "HARVESTER_REPORT";
+/// seconds a hook may run if no timeout was configured
+pub const DEFAULT_HOOK_TIMEOUT: u64 = 60;
+
+/// Hook describes a command executed after a successful run
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Hook {
+    /// the command line which is passed to `sh -c`
+    pub command: String,
+    /// seconds after which the command is killed
+    pub timeout: Option<u64>,
+}
+
+impl Hook {
+    /// executes the hook command and waits for it to finish
+    ///
+    /// * `report_path`: path to the run report exposed to the command
+    pub async fn run(&self, report_path: &Path) -> anyhow::Result<()> {
+        let mut child = platform::shell(&self.command)
+            .env(REPORT_ENV, report_path)
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("could not execute hook `{}`", self.command))?;
+        let timeout = Duration::from_secs(self.timeout.unwrap_or(DEFAULT_HOOK_TIMEOUT));
+        let status = tokio::time::timeout(timeout, child.wait())
+            .await
+            .with_context(|| format!("hook `{}` timed out", self.command))??;
+        if !status.success() {
+            return Err(anyhow::anyhow!(
+                "hook `{}` failed: {}",
+                self.command,
+                status
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_hook_success() {
+        let hook = Hook {
+            command: format!("test -n \"${}\"", REPORT_ENV),
+            timeout: None,
+        };
+        assert!(hook.run(Path::new("report.json")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_hook_timeout() {
+        let hook = Hook {
+            command: "sleep 5".to_string(),
+            timeout: Some(0),
+        };
+        assert!(hook.run(Path::new("report.json")).await.is_err());
+    }
+}
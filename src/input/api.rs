@@ -0,0 +1,477 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs,
+    path::PathBuf,
+};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use bytes::Bytes;
+use reqwest::{header::CONTENT_TYPE, Client, Method};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    input::{
+        json::{json_path, scalar},
+        Input,
+    },
+    report::{rfc3339, unix_timestamp},
+};
+
+/// the maximum number of pages requested if none was configured
+pub const DEFAULT_MAX_PAGES: usize = 100;
+
+/// ApiMethod is the HTTP method of the requests
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ApiMethod {
+    #[default]
+    Get,
+    Post,
+}
+
+/// Pagination determines how the next page of a response is requested, the values are
+/// inserted into the `{cursor}`, `{page}`, `{offset}` and `{limit}` placeholders of
+/// the source URL and the body
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Pagination {
+    /// the response tells the cursor of the next page, the last page has none
+    Cursor {
+        /// JSONPath of the cursor in the response
+        path: String,
+    },
+    /// pages are numbered, the first page without indicators is the last one
+    Page {
+        /// number of the first page
+        #[serde(default = "default_start")]
+        start: u64,
+    },
+    /// pages start at an offset, a page with less than `limit` indicators is the last
+    /// one
+    Offset {
+        /// number of indicators per page
+        limit: u64,
+    },
+}
+
+fn default_start() -> u64 {
+    1
+}
+
+/// ApiSettings describe how the indicators are requested from an API
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ApiSettings {
+    #[serde(default)]
+    pub method: ApiMethod,
+    /// template of the JSON request body
+    pub body: Option<String>,
+    /// additional request headers
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// file containing a token sent as bearer token
+    pub token_file: Option<String>,
+    pub pagination: Option<Pagination>,
+    /// JSONPath of the indicators in the response, e.g. `$.data[*].domain`
+    pub indicators: String,
+    /// maximum number of pages requested per run
+    pub max_pages: Option<usize>,
+    /// keep the position of the last page and the time of the last complete run, so
+    /// the next run only requests new indicators
+    #[serde(default)]
+    pub checkpoint: bool,
+}
+
+/// Checkpoint is the position the next run continues from
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+struct Checkpoint {
+    /// the cursor of the next page
+    cursor: Option<String>,
+    /// unix timestamp of the start of the last complete run
+    since: Option<u64>,
+}
+
+/// PageState tracks the pages requested since the last reset
+#[derive(Debug, Default)]
+struct PageState {
+    pages: usize,
+    cursor: Option<String>,
+    /// the cursor of the last request
+    requested: Option<String>,
+    done: bool,
+}
+
+/// ApiInput requests the pages of a JSON API and provides the indicators found in the
+/// responses, one indicator per line
+#[derive(Debug)]
+pub struct ApiInput {
+    /// the URL, it may contain pagination placeholders
+    pub url: String,
+    settings: ApiSettings,
+    client: Client,
+    lines: VecDeque<Bytes>,
+    state: PageState,
+    /// file the checkpoint is kept in
+    checkpoint_path: Option<PathBuf>,
+    /// the checkpoint of the previous run
+    start: Checkpoint,
+    /// unix timestamp of the creation of the input
+    started: u64,
+}
+
+impl ApiInput {
+    /// Initialize a new ApiInput
+    ///
+    /// * `url`: the URL, it may contain pagination placeholders
+    /// * `settings`: describe the requests and responses
+    pub fn new(url: &str, settings: ApiSettings) -> Self {
+        Self {
+            url: url.to_string(),
+            settings,
+            client: Client::new(),
+            lines: VecDeque::new(),
+            state: PageState::default(),
+            checkpoint_path: None,
+            start: Checkpoint::default(),
+            started: unix_timestamp(),
+        }
+    }
+
+    /// continues from the checkpoint kept in the file and updates it once the last
+    /// page was read
+    ///
+    /// * `path`: the file the checkpoint is kept in
+    pub fn with_checkpoint(mut self, path: PathBuf) -> Self {
+        // without a checkpoint everything is requested again
+        self.start = fs::read_to_string(&path)
+            .ok()
+            .and_then(|c| serde_json::from_str(&c).ok())
+            .unwrap_or_default();
+        self.state.cursor = self.start.cursor.clone();
+        self.checkpoint_path = Some(path);
+        self
+    }
+
+    /// writes the checkpoint for the next run
+    ///
+    /// * `complete`: all pages were read, so the next run may skip older indicators
+    fn save_checkpoint(&self, complete: bool) -> anyhow::Result<()> {
+        let path = match &self.checkpoint_path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let checkpoint = Checkpoint {
+            // the last page is requested again if the API tells no further cursor
+            cursor: self
+                .state
+                .cursor
+                .clone()
+                .or_else(|| self.state.requested.clone()),
+            since: match complete {
+                true => Some(self.started),
+                false => self.start.since,
+            },
+        };
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_string(&checkpoint)?)
+            .with_context(|| format!("could not write {:?}", tmp_path))?;
+        fs::rename(&tmp_path, path).with_context(|| format!("could not write {:?}", path))?;
+        Ok(())
+    }
+
+    /// requests the next page and queues its indicators
+    async fn next_page(&mut self) -> anyhow::Result<()> {
+        let state = &self.state;
+        let max_pages = self.settings.max_pages.unwrap_or(DEFAULT_MAX_PAGES);
+        if state.pages >= max_pages {
+            warn!("{}: stopped after {} pages", self.url, max_pages);
+            self.state.done = true;
+            return self.save_checkpoint(false);
+        }
+        let pages = state.pages as u64;
+        let since = self.start.since.unwrap_or_default();
+        let (page, offset, limit) = match self.settings.pagination {
+            Some(Pagination::Page { start }) => (start + pages, 0, 0),
+            Some(Pagination::Offset { limit }) => (pages + 1, pages * limit, limit),
+            _ => (pages + 1, 0, 0),
+        };
+        let values = [
+            ("{cursor}", state.cursor.clone().unwrap_or_default()),
+            ("{page}", page.to_string()),
+            ("{offset}", offset.to_string()),
+            ("{limit}", limit.to_string()),
+            ("{since}", rfc3339(since)),
+            ("{since_unix}", since.to_string()),
+        ];
+        let url = fill(&self.url, &values, url_escape);
+        let method = match self.settings.method {
+            ApiMethod::Get => Method::GET,
+            ApiMethod::Post => Method::POST,
+        };
+        let mut request = self.client.request(method, &url);
+        for (name, value) in &self.settings.headers {
+            request = request.header(name, value);
+        }
+        if let Some(token_file) = &self.settings.token_file {
+            let token = fs::read_to_string(token_file)
+                .with_context(|| format!("could not read {}", token_file))?;
+            request = request.bearer_auth(token.trim());
+        }
+        if let Some(body) = &self.settings.body {
+            let body = fill(body, &values, json_escape);
+            request = request.header(CONTENT_TYPE, "application/json").body(body);
+        }
+
+        let response = request.send().await.with_context(|| url.clone())?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(anyhow::anyhow!("status code {}: {}", status, url));
+        }
+        let value: Value = serde_json::from_str(&response.text().await?)
+            .with_context(|| format!("{} returned no JSON", url))?;
+        let indicators: Vec<String> = json_path(&value, &self.settings.indicators)?
+            .into_iter()
+            .filter_map(scalar)
+            .collect();
+        debug!("{}: {} indicators", url, indicators.len());
+
+        self.state.pages += 1;
+        self.state.requested = self.state.cursor.clone();
+        self.state.done = match &self.settings.pagination {
+            None => true,
+            Some(Pagination::Cursor { path }) => {
+                let cursor = json_path(&value, path)?.into_iter().find_map(scalar);
+                let done = cursor.is_none() || cursor == self.state.cursor;
+                self.state.cursor = cursor.filter(|c| !c.is_empty());
+                done || self.state.cursor.is_none()
+            }
+            Some(Pagination::Page { .. }) => indicators.is_empty(),
+            Some(Pagination::Offset { limit }) => (indicators.len() as u64) < *limit,
+        };
+        self.lines.extend(
+            indicators
+                .into_iter()
+                .map(|i| Bytes::from(format!("{}\n", i))),
+        );
+        if self.state.done {
+            self.save_checkpoint(true)?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Input for ApiInput {
+    async fn chunk(&mut self) -> anyhow::Result<Option<Bytes>> {
+        loop {
+            if let Some(line) = self.lines.pop_front() {
+                return Ok(Some(line));
+            }
+            if self.state.done {
+                return Ok(None);
+            }
+            self.next_page().await?;
+        }
+    }
+
+    /// start over with the first page
+    async fn reset(&mut self) -> anyhow::Result<()> {
+        self.lines.clear();
+        self.state = PageState {
+            cursor: self.start.cursor.clone(),
+            ..Default::default()
+        };
+        Ok(())
+    }
+
+    async fn len(&mut self) -> anyhow::Result<u64> {
+        Err(anyhow::anyhow!("API {} has no content length", self.url))
+    }
+}
+
+/// replaces the placeholders of a template by their escaped values
+///
+/// * `template`: the template
+/// * `values`: the placeholders and their values
+/// * `escape`: escapes a value for the context of the template
+fn fill(template: &str, values: &[(&str, String)], escape: impl Fn(&str) -> String) -> String {
+    values
+        .iter()
+        .fold(template.to_string(), |t, (placeholder, value)| {
+            t.replace(placeholder, &escape(value))
+        })
+}
+
+/// escapes a value for a URL query
+///
+/// * `value`: the value of a placeholder
+fn url_escape(value: &str) -> String {
+    url::form_urlencoded::byte_serialize(value.as_bytes()).collect()
+}
+
+/// escapes a value for a JSON string
+///
+/// * `value`: the value of a placeholder
+fn json_escape(value: &str) -> String {
+    let quoted = Value::String(value.to_string()).to_string();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use crate::tests::helper::cache_file_creator::TEST_CACHE;
+
+    use super::*;
+
+    #[test]
+    fn test_fill() {
+        let values = [("{cursor}", "a\"b c".to_string())];
+        assert_eq!(
+            fill(r#"{"cursor":"{cursor}"}"#, &values, json_escape),
+            r#"{"cursor":"a\"b c"}"#
+        );
+        assert_eq!(
+            fill("/list?c={cursor}", &values, url_escape),
+            "/list?c=a%22b+c"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cursor_pagination() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/search", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let mut bodies = vec![];
+            for page in 0..2 {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = vec![];
+                let mut buf = [0; 1024];
+                // read until the JSON body is complete
+                while !request.ends_with(b"}") {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                }
+                let request = String::from_utf8_lossy(&request).to_string();
+                assert!(request.starts_with("POST /search "));
+                bodies.push(request.split("\r\n\r\n").nth(1).unwrap().to_string());
+                let body = match page {
+                    0 => {
+                        r#"{"items":[{"domain":"one.domain"},{"domain":"two.domain"}],"next":"p2"}"#
+                    }
+                    _ => r#"{"items":[{"domain":"three.domain"}],"next":null}"#,
+                };
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+                stream.shutdown().await.unwrap();
+            }
+            bodies
+        });
+
+        let settings = ApiSettings {
+            method: ApiMethod::Post,
+            body: Some(r#"{"query":"malware","cursor":"{cursor}"}"#.to_string()),
+            pagination: Some(Pagination::Cursor {
+                path: "$.next".to_string(),
+            }),
+            indicators: "$.items[*].domain".to_string(),
+            ..Default::default()
+        };
+        let mut input = ApiInput::new(&url, settings);
+        let mut lines = vec![];
+        while let Some(chunk) = input.chunk().await.unwrap() {
+            lines.push(String::from_utf8(chunk.to_vec()).unwrap());
+        }
+        assert_eq!(
+            lines,
+            vec!["one.domain\n", "two.domain\n", "three.domain\n"]
+        );
+
+        let bodies = server.await.unwrap();
+        assert_eq!(bodies[0], r#"{"query":"malware","cursor":""}"#);
+        assert_eq!(bodies[1], r#"{"query":"malware","cursor":"p2"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!(
+            "http://{}/feed?cursor={{cursor}}&since={{since_unix}}",
+            listener.local_addr().unwrap()
+        );
+        let server = tokio::spawn(async move {
+            let mut paths = vec![];
+            for page in 0..3 {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = vec![];
+                let mut buf = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                }
+                let request = String::from_utf8_lossy(&request).to_string();
+                paths.push(request.split(' ').nth(1).unwrap().to_string());
+                let body = match page {
+                    0 => r#"{"items":["one.domain"],"next":"p2"}"#,
+                    _ => r#"{"items":["two.domain"],"next":null}"#,
+                };
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+                stream.shutdown().await.unwrap();
+            }
+            paths
+        });
+
+        let mut checkpoint_path = PathBuf::from(TEST_CACHE);
+        checkpoint_path.push("test_checkpoint");
+        fs::create_dir_all(&checkpoint_path).unwrap();
+        checkpoint_path.push(".api.checkpoint");
+        fs::remove_file(&checkpoint_path).ok();
+        let settings = ApiSettings {
+            pagination: Some(Pagination::Cursor {
+                path: "$.next".to_string(),
+            }),
+            indicators: "$.items[*]".to_string(),
+            max_pages: Some(1),
+            checkpoint: true,
+            ..Default::default()
+        };
+        let mut runs = vec![];
+        for _ in 0..3 {
+            let mut input =
+                ApiInput::new(&url, settings.clone()).with_checkpoint(checkpoint_path.clone());
+            let mut lines = vec![];
+            while let Some(chunk) = input.chunk().await.unwrap() {
+                lines.push(String::from_utf8(chunk.to_vec()).unwrap());
+            }
+            runs.push(lines);
+        }
+        assert_eq!(
+            runs,
+            vec![
+                vec!["one.domain\n"],
+                vec!["two.domain\n"],
+                vec!["two.domain\n"]
+            ]
+        );
+
+        let paths = server.await.unwrap();
+        assert_eq!(paths[0], "/feed?cursor=&since=0");
+        // the first run stopped early, so older indicators may still be missing
+        assert_eq!(paths[1], "/feed?cursor=p2&since=0");
+        // the second run read the last page and is continued from its start
+        assert!(paths[2].starts_with("/feed?cursor=p2&since="));
+        assert_ne!(paths[2], "/feed?cursor=p2&since=0");
+    }
+}
@@ -0,0 +1,454 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use crate::{
+    dns_update::{message_id, rcode_name, sign, write_name, Tsig, CLASS_IN, TYPE_SOA},
+    input::{url::Timeouts, Input},
+};
+
+/// port of the server if none is part of the source
+const DEFAULT_PORT: u16 = 53;
+/// labels and compression pointers followed per name, more only occur in a pointer loop
+const MAX_NAME_STEPS: usize = 128;
+
+const TYPE_NS: u16 = 2;
+const TYPE_AXFR: u16 = 252;
+
+/// AxfrSettings describe how the zone is transferred
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AxfrSettings {
+    /// name of the TSIG key the request is signed with
+    pub tsig_key: Option<String>,
+    /// file containing the base64 encoded secret of the TSIG key (hmac-sha256)
+    pub tsig_secret_file: Option<String>,
+    /// names below the zone are read relative to it, e.g. the listed domains of a
+    /// response policy zone
+    #[serde(default)]
+    pub strip_origin: bool,
+}
+
+/// AxfrInput transfers a zone from a DNS server (AXFR, RFC 5936) and yields the owner
+/// names of its records one per line. SOA and NS records as well as the zone apex are
+/// skipped and wildcard owners yield the domain they cover like in zone files.
+#[derive(Debug)]
+pub struct AxfrInput {
+    /// the host and port of the server
+    addr: String,
+    /// the name of the zone without trailing dot
+    zone: String,
+    settings: AxfrSettings,
+    /// time establishing the connection may take
+    connect_timeout: Duration,
+    /// time the next part of a message is waited for
+    read_timeout: Duration,
+    /// the connection of the running transfer
+    stream: Option<TcpStream>,
+    /// the id of the request, repeated by every response message
+    id: u16,
+    /// SOA records received so far, the second one closes the transfer
+    soa_records: usize,
+    /// the last name returned, so records sharing an owner yield it once
+    last_name: Option<String>,
+    done: bool,
+}
+
+impl AxfrInput {
+    /// Initialize a new AxfrInput
+    ///
+    /// * `source`: the server and the zone, e.g. `axfr://ns1.example.com/rpz.local`
+    /// * `settings`: the TSIG key and whether names are read relative to the zone
+    pub fn new(source: &str, settings: AxfrSettings) -> anyhow::Result<Self> {
+        let (host, zone) = source
+            .strip_prefix("axfr://")
+            .and_then(|s| s.split_once('/'))
+            .map(|(host, zone)| (host, zone.trim_end_matches('.')))
+            .filter(|(host, zone)| !host.is_empty() && !zone.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("{} is not an axfr://server/zone location", source))?;
+        let addr = match host.rsplit_once(':') {
+            Some((_, port)) if !host.ends_with(']') => {
+                port.parse::<u16>()
+                    .with_context(|| format!("invalid port in {}", source))?;
+                host.to_string()
+            }
+            _ => format!("{}:{}", host, DEFAULT_PORT),
+        };
+        Ok(Self {
+            addr,
+            zone: zone.to_lowercase(),
+            settings,
+            connect_timeout: Timeouts::default().connect(),
+            read_timeout: Timeouts::default().read(),
+            stream: None,
+            id: 0,
+            soa_records: 0,
+            last_name: None,
+            done: false,
+        })
+    }
+
+    /// sets the timeouts of the transfer, a server accepting the connection but never
+    /// answering fails the transfer
+    ///
+    /// * `timeouts`: the timeouts of the list
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.connect_timeout = timeouts.connect();
+        self.read_timeout = timeouts.read();
+        self
+    }
+
+    /// connects to the server and requests the transfer of the zone
+    async fn start(&mut self) -> anyhow::Result<()> {
+        let tsig = Tsig::load(&self.settings.tsig_key, &self.settings.tsig_secret_file)?;
+        self.id = message_id();
+        let request = request_message(self.id, &self.zone, tsig.as_ref())?;
+        let mut stream = tokio::time::timeout(self.connect_timeout, TcpStream::connect(&self.addr))
+            .await
+            .map_err(|_| {
+                anyhow::anyhow!("no connection within {}s", self.connect_timeout.as_secs())
+            })
+            .and_then(|stream| Ok(stream?))
+            .with_context(|| format!("could not connect to {}", self.addr))?;
+        stream
+            .write_all(&(request.len() as u16).to_be_bytes())
+            .await?;
+        stream.write_all(&request).await?;
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    /// returns the next message of the transfer
+    async fn read_message(&mut self) -> anyhow::Result<Vec<u8>> {
+        let stream = self.stream.as_mut().context("no transfer running")?;
+        let timed_out = || {
+            anyhow::anyhow!(
+                "transfer of zone {} from {}: no data received within {}s",
+                self.zone,
+                self.addr,
+                self.read_timeout.as_secs()
+            )
+        };
+        let len = tokio::time::timeout(self.read_timeout, stream.read_u16())
+            .await
+            .map_err(|_| timed_out())?
+            .with_context(|| {
+                format!(
+                    "transfer of zone {} from {} ended before its closing SOA record",
+                    self.zone, self.addr
+                )
+            })?;
+        let mut message = vec![0; len as usize];
+        tokio::time::timeout(self.read_timeout, stream.read_exact(&mut message))
+            .await
+            .map_err(|_| timed_out())??;
+        Ok(message)
+    }
+
+    /// returns the owner names of the records of a response message, one per line
+    ///
+    /// * `message`: the response message
+    fn owner_names(&mut self, message: &[u8]) -> anyhow::Result<String> {
+        if message.len() < 12 || message[..2] != self.id.to_be_bytes() {
+            return Err(anyhow::anyhow!("invalid response"));
+        }
+        let count = |i: usize| u16::from_be_bytes([message[i], message[i + 1]]);
+        match count(2) & 0x000f {
+            0 => {}
+            rcode => {
+                return Err(anyhow::anyhow!(
+                    "server responded with {}",
+                    rcode_name(rcode)
+                ))
+            }
+        }
+
+        let mut pos = 12;
+        for _ in 0..count(4) {
+            pos = read_name(message, pos)?.1 + 4;
+        }
+        let mut lines = String::new();
+        for _ in 0..count(6) {
+            let (owner, next) = read_name(message, pos)?;
+            let fields = message
+                .get(next..next + 10)
+                .context("truncated resource record")?;
+            let rtype = u16::from_be_bytes([fields[0], fields[1]]);
+            pos = next + 10 + u16::from_be_bytes([fields[8], fields[9]]) as usize;
+
+            if rtype == TYPE_SOA {
+                self.soa_records += 1;
+                if self.soa_records == 2 {
+                    self.done = true;
+                    break;
+                }
+                continue;
+            }
+            if self.soa_records == 0 {
+                return Err(anyhow::anyhow!("transfer doesn't start with a SOA record"));
+            }
+            let owner = owner.to_lowercase();
+            if rtype == TYPE_NS || owner == self.zone {
+                continue;
+            }
+            let suffix = format!(".{}", self.zone);
+            let name = match owner.strip_suffix(&suffix) {
+                Some(relative) if self.settings.strip_origin => relative,
+                _ => &owner,
+            };
+            // wildcard owners yield the domain they cover
+            let name = name.strip_prefix("*.").unwrap_or(name).to_string();
+            if self.last_name.as_ref() == Some(&name) {
+                continue;
+            }
+            lines.push_str(&name);
+            lines.push('\n');
+            self.last_name = Some(name);
+        }
+        if self.done {
+            debug!("Transferred zone {} from {}", self.zone, self.addr);
+            self.stream = None;
+        }
+        Ok(lines)
+    }
+}
+
+#[async_trait]
+impl Input for AxfrInput {
+    async fn chunk(&mut self) -> anyhow::Result<Option<Bytes>> {
+        loop {
+            if self.done {
+                return Ok(None);
+            }
+            if self.stream.is_none() {
+                self.start().await?;
+            }
+            let message = self.read_message().await?;
+            let lines = self.owner_names(&message).with_context(|| {
+                format!("transfer of zone {} from {} failed", self.zone, self.addr)
+            })?;
+            if !lines.is_empty() {
+                return Ok(Some(Bytes::from(lines)));
+            }
+        }
+    }
+
+    async fn reset(&mut self) -> anyhow::Result<()> {
+        self.stream = None;
+        self.soa_records = 0;
+        self.last_name = None;
+        self.done = false;
+        Ok(())
+    }
+
+    async fn len(&mut self) -> anyhow::Result<u64> {
+        Err(anyhow::anyhow!(
+            "transfer of zone {} has no content length",
+            self.zone
+        ))
+    }
+}
+
+/// returns the request of a zone transfer, signed if a TSIG key is given. Only the
+/// request is signed, the signatures of the responses aren't verified.
+///
+/// * `id`: the id of the message
+/// * `zone`: the name of the zone
+/// * `tsig`: the key the message is signed with
+fn request_message(id: u16, zone: &str, tsig: Option<&Tsig>) -> anyhow::Result<Vec<u8>> {
+    let mut buf = vec![];
+    buf.extend(id.to_be_bytes());
+    // a standard query without recursion
+    buf.extend(0u16.to_be_bytes());
+    for count in [1u16, 0, 0, 0] {
+        buf.extend(count.to_be_bytes());
+    }
+    write_name(&mut buf, zone)?;
+    buf.extend(TYPE_AXFR.to_be_bytes());
+    buf.extend(CLASS_IN.to_be_bytes());
+    if let Some(tsig) = tsig {
+        let time_signed = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        sign(&mut buf, tsig, time_signed)?;
+    }
+    Ok(buf)
+}
+
+/// returns a domain name of a message and the position behind it, compressed names are
+/// followed to their labels
+///
+/// * `message`: the message
+/// * `pos`: the position of the name
+fn read_name(message: &[u8], mut pos: usize) -> anyhow::Result<(String, usize)> {
+    let mut labels = vec![];
+    // the position behind the first compression pointer
+    let mut end = None;
+    for _ in 0..MAX_NAME_STEPS {
+        let len = *message.get(pos).context("truncated domain name")? as usize;
+        match len {
+            0 => {
+                return Ok((labels.join("."), end.unwrap_or(pos + 1)));
+            }
+            len if len & 0xc0 == 0xc0 => {
+                let low = *message.get(pos + 1).context("truncated domain name")? as usize;
+                end.get_or_insert(pos + 2);
+                pos = ((len & 0x3f) << 8) | low;
+            }
+            len if len < 64 => {
+                let label = message
+                    .get(pos + 1..pos + 1 + len)
+                    .context("truncated domain name")?;
+                labels.push(String::from_utf8_lossy(label).to_string());
+                pos += 1 + len;
+            }
+            _ => return Err(anyhow::anyhow!("unsupported label type {:#x}", len)),
+        }
+    }
+    Err(anyhow::anyhow!(
+        "domain name exceeds {} labels",
+        MAX_NAME_STEPS
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    const ZONE: &str = "rpz.local";
+
+    /// returns a response message with records of the owners, which are relative to the
+    /// zone and compressed by a pointer to the zone name of the question
+    ///
+    /// * `id`: the id of the request
+    /// * `records`: the owners and types of the records
+    fn response(id: &[u8], records: &[(&str, u16)]) -> Vec<u8> {
+        let mut buf = id.to_vec();
+        buf.extend(0x8400u16.to_be_bytes());
+        for count in [1, records.len() as u16, 0, 0] {
+            buf.extend(count.to_be_bytes());
+        }
+        write_name(&mut buf, ZONE).unwrap();
+        buf.extend(TYPE_AXFR.to_be_bytes());
+        buf.extend(CLASS_IN.to_be_bytes());
+        for (owner, rtype) in records {
+            for label in owner.split('.').filter(|l| *l != "@") {
+                buf.push(label.len() as u8);
+                buf.extend(label.as_bytes());
+            }
+            buf.extend([0xc0, 0x0c]);
+            buf.extend(rtype.to_be_bytes());
+            buf.extend(CLASS_IN.to_be_bytes());
+            buf.extend(300u32.to_be_bytes());
+            buf.extend(1u16.to_be_bytes());
+            buf.push(0);
+        }
+        buf
+    }
+
+    /// transfers a zone from a fake server and returns the lines read
+    ///
+    /// * `strip_origin`: read the names relative to the zone
+    async fn transfer(strip_origin: bool) -> Vec<String> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let source = format!("axfr://{}/{}.", listener.local_addr().unwrap(), ZONE);
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let len = stream.read_u16().await.unwrap();
+            let mut request = vec![0; len as usize];
+            stream.read_exact(&mut request).await.unwrap();
+            assert_eq!(request[request.len() - 4..], [0, 252, 0, 1]);
+            let messages = [
+                response(
+                    &request[..2],
+                    &[
+                        ("@", TYPE_SOA),
+                        ("@", TYPE_NS),
+                        ("Bad.com", 5),
+                        ("bad.com", 1),
+                    ],
+                ),
+                response(&request[..2], &[("*.bad.com", 5), ("@", TYPE_SOA)]),
+            ];
+            for message in messages {
+                stream
+                    .write_all(&(message.len() as u16).to_be_bytes())
+                    .await
+                    .unwrap();
+                stream.write_all(&message).await.unwrap();
+            }
+        });
+
+        let settings = AxfrSettings {
+            strip_origin,
+            ..Default::default()
+        };
+        let mut input = AxfrInput::new(&source, settings).unwrap();
+        let mut lines = vec![];
+        while let Some(chunk) = input.chunk().await.unwrap() {
+            lines.extend(
+                String::from_utf8(chunk.to_vec())
+                    .unwrap()
+                    .lines()
+                    .map(String::from),
+            );
+        }
+        server.await.unwrap();
+        lines
+    }
+
+    #[tokio::test]
+    async fn test_transfer() {
+        assert_eq!(transfer(true).await, vec!["bad.com"]);
+        assert_eq!(transfer(false).await, vec!["bad.com.rpz.local"]);
+    }
+
+    #[tokio::test]
+    async fn test_transfer_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let source = format!("axfr://{}/{}", listener.local_addr().unwrap(), ZONE);
+        // the server accepts the request but never answers
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            drop(stream);
+        });
+
+        let timeouts = Timeouts {
+            read: Some(1),
+            ..Default::default()
+        };
+        let mut input = AxfrInput::new(&source, AxfrSettings::default())
+            .unwrap()
+            .with_timeouts(timeouts);
+        let e = input.chunk().await.unwrap_err();
+        assert!(e.to_string().contains("no data received within 1s"));
+        server.abort();
+    }
+
+    #[test]
+    fn test_read_name() {
+        // a name compressed by a pointer to the zone name at the start
+        let message = [3, b'r', b'p', b'z', 0, 3, b'b', b'a', b'd', 0xc0, 0];
+        assert_eq!(read_name(&message, 5).unwrap(), ("bad.rpz".to_string(), 11));
+        // pointers to themselves never end
+        assert!(read_name(&[0xc0, 0], 0).is_err());
+        assert!(read_name(&[3, b'b', b'a'], 0).is_err());
+    }
+
+    #[test]
+    fn test_new() {
+        let input =
+            AxfrInput::new("axfr://ns1.example.com/RPZ.local.", AxfrSettings::default()).unwrap();
+        assert_eq!(input.addr, "ns1.example.com:53");
+        assert_eq!(input.zone, "rpz.local");
+        assert!(AxfrInput::new("axfr://ns1.example.com", AxfrSettings::default()).is_err());
+        assert!(AxfrInput::new("axfr://ns1:x/rpz", AxfrSettings::default()).is_err());
+    }
+}
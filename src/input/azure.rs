@@ -0,0 +1,270 @@
+use std::{env, fs};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use bytes::Bytes;
+use reqwest::{
+    header::{AUTHORIZATION, CONTENT_LENGTH},
+    Client, Method, Response, StatusCode, Url,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::input::Input;
+
+/// resource the access tokens of a managed identity are requested for
+const STORAGE_RESOURCE: &str = "https://storage.azure.com/";
+/// endpoint of the instance metadata service providing the tokens of the managed identity
+const IMDS_TOKEN_URL: &str = "http://169.254.169.254/metadata/identity/oauth2/token";
+/// version of the Blob service API, requests authorized by a token need at least 2017-11-09
+const BLOB_API_VERSION: &str = "2021-08-06";
+
+/// AzureSettings describe how the container is accessed, a shared access signature is
+/// used if one is configured or found in the environment, otherwise the managed identity
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AzureSettings {
+    /// file containing the SAS token, defaults to `AZURE_STORAGE_SAS_TOKEN`
+    pub sas_token_file: Option<String>,
+    /// client id of a user assigned managed identity, defaults to the system assigned one
+    pub client_id: Option<String>,
+}
+
+/// TokenResponse is the answer of the managed identity endpoints
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+/// AzureInput reads a blob of an Azure Blob Storage container with a shared access
+/// signature or the token of a managed identity
+#[derive(Debug)]
+pub struct AzureInput {
+    /// the URL of the blob without the SAS token
+    url: Url,
+    settings: AzureSettings,
+    /// the access token of the managed identity, requested once per input
+    token: Option<String>,
+    response: Option<Response>,
+}
+
+impl AzureInput {
+    /// Initialize a new AzureInput
+    ///
+    /// * `source`: the URL of the blob, e.g.
+    ///   `https://account.blob.core.windows.net/container/lists/malware.txt`
+    /// * `settings`: the SAS token file or the managed identity
+    pub fn new(source: &str, settings: AzureSettings) -> anyhow::Result<Self> {
+        let url = Url::parse(source)
+            .ok()
+            .filter(|url| matches!(url.scheme(), "https" | "http") && url.query().is_none())
+            .filter(|url| {
+                url.path()
+                    .trim_start_matches('/')
+                    .split_once('/')
+                    .is_some_and(|(container, blob)| !container.is_empty() && !blob.is_empty())
+            })
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "{} is not a https://account.blob.core.windows.net/container/blob URL",
+                    source
+                )
+            })?;
+        Ok(Self {
+            url,
+            settings,
+            token: None,
+            response: None,
+        })
+    }
+
+    /// returns the configured SAS token or the one of the environment
+    fn sas_token(&self) -> anyhow::Result<Option<String>> {
+        let token = match &self.settings.sas_token_file {
+            Some(path) => Some(
+                fs::read_to_string(path)
+                    .with_context(|| format!("could not read SAS token {}", path))?,
+            ),
+            None => env::var("AZURE_STORAGE_SAS_TOKEN").ok(),
+        };
+        Ok(token
+            .map(|t| t.trim().trim_start_matches('?').to_string())
+            .filter(|t| !t.is_empty()))
+    }
+
+    /// requests an access token from the endpoint of the App Service if it's present in
+    /// the environment, otherwise from the instance metadata service
+    async fn request_token(&self) -> anyhow::Result<String> {
+        let endpoint = env::var("IDENTITY_ENDPOINT").ok();
+        let url = identity_url(endpoint.as_deref(), self.settings.client_id.as_deref())?;
+        let request = match env::var("IDENTITY_HEADER") {
+            Ok(header) if endpoint.is_some() => Client::new()
+                .get(url)
+                .header("X-IDENTITY-HEADER", header.as_str()),
+            _ => Client::new().get(url).header("Metadata", "true"),
+        };
+        let response = request
+            .send()
+            .await
+            .with_context(|| "could not request an access token of the managed identity")?;
+        let status = response.status();
+        let body = response.text().await?;
+        if status != StatusCode::OK {
+            return Err(anyhow::anyhow!(
+                "managed identity token request failed with {}: {}",
+                status,
+                body.trim_end()
+            ));
+        }
+        let token: TokenResponse = serde_json::from_str(&body)
+            .with_context(|| "invalid managed identity token response")?;
+        Ok(token.access_token)
+    }
+
+    /// sends an authorized request for the blob
+    ///
+    /// * `method`: GET or HEAD
+    async fn request(&mut self, method: Method) -> anyhow::Result<Response> {
+        let request = match self.sas_token()? {
+            Some(sas_token) => {
+                let mut url = self.url.clone();
+                url.set_query(Some(&sas_token));
+                Client::new().request(method, url)
+            }
+            None => {
+                if self.token.is_none() {
+                    self.token = Some(self.request_token().await?);
+                }
+                Client::new().request(method, self.url.clone()).header(
+                    AUTHORIZATION,
+                    format!("Bearer {}", self.token.as_ref().unwrap()),
+                )
+            }
+        };
+        // the SAS token is left out of the messages
+        let response = request
+            .header("x-ms-version", BLOB_API_VERSION)
+            .send()
+            .await
+            .with_context(|| format!("{}", self.url))?;
+        let status = response.status();
+        if status != StatusCode::OK {
+            return Err(anyhow::anyhow!("status code {}: {}", status, self.url));
+        }
+        Ok(response)
+    }
+}
+
+/// returns the URL requesting an access token of the managed identity for the storage
+///
+/// * `endpoint`: the identity endpoint of the App Service, the instance metadata service
+///   if None
+/// * `client_id`: the client id of a user assigned identity
+fn identity_url(endpoint: Option<&str>, client_id: Option<&str>) -> anyhow::Result<Url> {
+    let (endpoint, api_version) = match endpoint {
+        Some(endpoint) => (endpoint, "2019-08-01"),
+        None => (IMDS_TOKEN_URL, "2018-02-01"),
+    };
+    let mut url = Url::parse(endpoint)
+        .with_context(|| format!("invalid managed identity endpoint {}", endpoint))?;
+    url.query_pairs_mut()
+        .append_pair("api-version", api_version)
+        .append_pair("resource", STORAGE_RESOURCE);
+    if let Some(client_id) = client_id {
+        url.query_pairs_mut().append_pair("client_id", client_id);
+    }
+    Ok(url)
+}
+
+#[async_trait]
+impl Input for AzureInput {
+    async fn chunk(&mut self) -> anyhow::Result<Option<Bytes>> {
+        if self.response.is_none() {
+            self.response = Some(self.request(Method::GET).await?);
+        }
+        match self.response.as_mut().unwrap().chunk().await {
+            Ok(chunk) => Ok(chunk),
+            Err(e) => Err(anyhow::anyhow!(e)).with_context(|| format!("{}", self.url)),
+        }
+    }
+
+    /// requests the blob again to read it from the beginning
+    async fn reset(&mut self) -> anyhow::Result<()> {
+        self.response = None;
+        Ok(())
+    }
+
+    /// get the blob size from the response of a HEAD request
+    async fn len(&mut self) -> anyhow::Result<u64> {
+        let head = self.request(Method::HEAD).await?;
+        head.headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+            .with_context(|| {
+                format!(
+                    "request to {} returned without header {}",
+                    self.url, CONTENT_LENGTH
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::helper::cache_file_creator::TEST_CACHE;
+
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let source = "https://account.blob.core.windows.net/lists/threat intel/malware.txt";
+        let input = AzureInput::new(source, AzureSettings::default()).unwrap();
+        assert_eq!(
+            input.url.as_str(),
+            "https://account.blob.core.windows.net/lists/threat%20intel/malware.txt"
+        );
+        let settings = AzureSettings::default();
+        assert!(AzureInput::new(
+            "https://account.blob.core.windows.net/lists",
+            settings.clone()
+        )
+        .is_err());
+        assert!(AzureInput::new("s3://lists/malware.txt", settings.clone()).is_err());
+        // the SAS token belongs into the settings
+        assert!(AzureInput::new(&format!("{}?sig=secret", source), settings).is_err());
+    }
+
+    #[test]
+    fn test_sas_token() {
+        let path = format!("{}/test_azure_sas_token", TEST_CACHE);
+        fs::create_dir_all(TEST_CACHE).unwrap();
+        fs::write(&path, "?sv=2021-08-06&sp=r&sig=secret\n").unwrap();
+        let input = AzureInput::new(
+            "https://account.blob.core.windows.net/lists/malware.txt",
+            AzureSettings {
+                sas_token_file: Some(path),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            input.sas_token().unwrap().unwrap(),
+            "sv=2021-08-06&sp=r&sig=secret"
+        );
+    }
+
+    #[test]
+    fn test_identity_url() {
+        assert_eq!(
+            identity_url(None, Some("client")).unwrap().as_str(),
+            "http://169.254.169.254/metadata/identity/oauth2/token?api-version=2018-02-01\
+             &resource=https%3A%2F%2Fstorage.azure.com%2F&client_id=client"
+        );
+        assert_eq!(
+            identity_url(Some("http://localhost:8081/msi/token"), None)
+                .unwrap()
+                .as_str(),
+            "http://localhost:8081/msi/token?api-version=2019-08-01\
+             &resource=https%3A%2F%2Fstorage.azure.com%2F"
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use std::process::Stdio;
+
+use crate::{input::Input, platform};
+use anyhow::Context;
+use async_trait::async_trait;
+use bytes::Bytes;
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::{Child, ChildStdout},
+};
+
+/// CommandInput reads data from the standard output of a command
+#[derive(Debug)]
+pub struct CommandInput {
+    /// the command line which is passed to `sh -c`
+    pub command: String,
+    child: Option<Child>,
+    stdout: Option<BufReader<ChildStdout>>,
+}
+
+impl CommandInput {
+    /// Initialize a new CommandInput
+    ///
+    /// * `command`: the command line to be executed
+    pub fn new(command: &str) -> Self {
+        Self {
+            command: command.to_string(),
+            child: None,
+            stdout: None,
+        }
+    }
+
+    /// starts the command and attaches its standard output
+    fn spawn(&mut self) -> anyhow::Result<()> {
+        let mut child = platform::shell(&self.command)
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("could not execute command `{}`", self.command))?;
+        let stdout = child
+            .stdout
+            .take()
+            .with_context(|| format!("command `{}` has no stdout", self.command))?;
+        self.stdout = Some(BufReader::new(stdout));
+        self.child = Some(child);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Input for CommandInput {
+    async fn chunk(&mut self) -> anyhow::Result<Option<Bytes>> {
+        if self.stdout.is_none() {
+            self.spawn()?;
+        }
+        let mut line = Vec::new();
+        // stdout can be safely unwrapped here since it's initialized at the beginning of the function
+        match self
+            .stdout
+            .as_mut()
+            .unwrap()
+            .read_until(b'\n', &mut line)
+            .await
+        {
+            Ok(0) => {
+                let status = self.child.as_mut().unwrap().wait().await?;
+                if !status.success() {
+                    return Err(anyhow::anyhow!(
+                        "command `{}` failed: {}",
+                        self.command,
+                        status
+                    ));
+                }
+                Ok(None)
+            }
+            Ok(_) => Ok(Some(Bytes::from(line))),
+            Err(e) => Err(anyhow::anyhow!("Error reading from command: {}", e)),
+        }
+    }
+
+    /// run the command again to read its output from the beginning
+    async fn reset(&mut self) -> anyhow::Result<()> {
+        self.stdout.take();
+        self.child.take();
+        self.spawn()
+    }
+
+    /// the output length of a command is unknown in advance
+    async fn len(&mut self) -> anyhow::Result<u64> {
+        Err(anyhow::anyhow!(
+            "command `{}` has no content length",
+            self.command
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_command_input() {
+        let mut input = CommandInput::new("printf 'one.domain\\ntwo.domain\\n'");
+        let mut got = vec![];
+        while let Some(chunk) = input.chunk().await.unwrap() {
+            got.push(String::from_utf8(chunk.to_vec()).unwrap());
+        }
+        assert_eq!(got, vec!["one.domain\n", "two.domain\n"]);
+    }
+
+    #[tokio::test]
+    async fn test_command_input_failure() {
+        let mut input = CommandInput::new("exit 1");
+        assert!(input.chunk().await.is_err());
+    }
+}
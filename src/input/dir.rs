@@ -0,0 +1,290 @@
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use bytes::Bytes;
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncSeekExt, SeekFrom},
+};
+
+use crate::input::{
+    file::{is_pattern, FileInput},
+    Input,
+};
+
+/// FileSet determines the files read by a DirInput
+#[derive(Debug)]
+enum FileSet {
+    /// the files of a directory
+    Dir {
+        /// path of the directory on the file system
+        path: PathBuf,
+        /// read the files in sub directories as well
+        recursive: bool,
+    },
+    /// the files matching a glob pattern
+    Pattern(String),
+}
+
+/// DirInput reads the files of a directory or the files matching a glob pattern one
+/// after another in the order of their paths, e.g. the list fragments a vendor drops
+/// into a folder
+#[derive(Debug)]
+pub struct DirInput {
+    /// the files to be read
+    file_set: FileSet,
+    /// the files to be read, listed on the first read
+    files: Option<Vec<PathBuf>>,
+    /// index of the file currently read
+    index: usize,
+    /// the file currently read
+    current: Option<FileInput>,
+    /// the data read from the current file doesn't end with a newline
+    open_line: bool,
+}
+
+impl DirInput {
+    /// Initialize a new DirInput
+    ///
+    /// * `path`: path of the directory on the file system
+    /// * `recursive`: read the files in sub directories as well
+    pub fn new(path: PathBuf, recursive: bool) -> Self {
+        Self::with_file_set(FileSet::Dir { path, recursive })
+    }
+
+    /// Initialize a new DirInput reading the files matching a glob pattern
+    ///
+    /// * `pattern`: the glob pattern, e.g. `/data/lists/*.txt`
+    pub fn matching(pattern: &str) -> Self {
+        Self::with_file_set(FileSet::Pattern(pattern.to_string()))
+    }
+
+    fn with_file_set(file_set: FileSet) -> Self {
+        Self {
+            file_set,
+            files: None,
+            index: 0,
+            current: None,
+            open_line: false,
+        }
+    }
+
+    /// returns the files to be read sorted by their paths. Hidden files and
+    /// directories are skipped since tools keep their temporary files there.
+    async fn list_files(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let (path, recursive) = match &self.file_set {
+            FileSet::Dir { path, recursive } => (path, *recursive),
+            FileSet::Pattern(pattern) => return list_matches(pattern),
+        };
+        let mut files = vec![];
+        let mut dirs = vec![path.clone()];
+        while let Some(dir) = dirs.pop() {
+            let mut entries = fs::read_dir(&dir)
+                .await
+                .with_context(|| format!("could not read directory {:?}", dir))?;
+            while let Some(entry) = entries.next_entry().await? {
+                if entry.file_name().to_string_lossy().starts_with('.') {
+                    continue;
+                }
+                let path = entry.path();
+                // symlinked files are read, symlinked directories aren't followed
+                if entry.file_type().await?.is_dir() {
+                    if recursive {
+                        dirs.push(path);
+                    }
+                } else if fs::metadata(&path).await.is_ok_and(|m| m.is_file()) {
+                    files.push(path);
+                }
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+}
+
+/// returns the files matching a glob pattern sorted by their paths
+///
+/// * `pattern`: the glob pattern
+fn list_matches(pattern: &str) -> anyhow::Result<Vec<PathBuf>> {
+    let options = glob::MatchOptions {
+        require_literal_separator: true,
+        require_literal_leading_dot: true,
+        ..Default::default()
+    };
+    let mut files: Vec<PathBuf> = glob::glob_with(pattern, options)
+        .with_context(|| format!("invalid pattern {}", pattern))?
+        .filter_map(|p| p.ok())
+        .filter(|p| p.is_file())
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// splits a glob pattern into the directory in front of the first component
+/// containing a wildcard and the remaining components
+///
+/// * `pattern`: the glob pattern
+pub fn pattern_base(pattern: &str) -> (PathBuf, PathBuf) {
+    let mut base = PathBuf::new();
+    let mut rest = PathBuf::new();
+    for component in Path::new(pattern).components() {
+        let is_wildcard =
+            matches!(component, Component::Normal(c) if is_pattern(&c.to_string_lossy()));
+        match is_wildcard || !rest.as_os_str().is_empty() {
+            true => rest.push(component),
+            false => base.push(component),
+        }
+    }
+    if base.as_os_str().is_empty() {
+        base.push(".");
+    }
+    (base, rest)
+}
+
+/// returns the number of bytes a file contributes to the input, which ends its last
+/// line with a newline if it's missing
+///
+/// * `path`: the file system path of the file
+async fn contributed_len(path: &Path) -> anyhow::Result<u64> {
+    let mut file = fs::File::open(path)
+        .await
+        .with_context(|| format!("could not open {:?}", path))?;
+    let len = file.metadata().await?.len();
+    if len == 0 {
+        return Ok(0);
+    }
+    file.seek(SeekFrom::End(-1)).await?;
+    match file.read_u8().await? {
+        b'\n' => Ok(len),
+        _ => Ok(len + 1),
+    }
+}
+
+#[async_trait]
+impl Input for DirInput {
+    async fn chunk(&mut self) -> anyhow::Result<Option<Bytes>> {
+        if self.files.is_none() {
+            self.files = Some(self.list_files().await?);
+        }
+        loop {
+            if self.current.is_none() {
+                // files can be safely unwrapped here since they're listed above
+                let path = match self.files.as_ref().unwrap().get(self.index) {
+                    Some(path) => path.clone(),
+                    None => return Ok(None),
+                };
+                // the files are copied as they are, decompression happens later on
+                self.current = Some(FileInput::new(path, None).unframed());
+            }
+            match self.current.as_mut().unwrap().chunk().await? {
+                Some(block) => {
+                    self.open_line = !block.ends_with(b"\n");
+                    return Ok(Some(block));
+                }
+                None => {
+                    self.current = None;
+                    self.index += 1;
+                    // the last line of a file mustn't be joined with the next file's first
+                    if std::mem::take(&mut self.open_line) {
+                        return Ok(Some(Bytes::from_static(b"\n")));
+                    }
+                }
+            }
+        }
+    }
+
+    /// lists the files again and starts reading from the first one
+    async fn reset(&mut self) -> anyhow::Result<()> {
+        self.files = None;
+        self.index = 0;
+        self.current = None;
+        self.open_line = false;
+        Ok(())
+    }
+
+    /// get the sum of the lengths of the files
+    async fn len(&mut self) -> anyhow::Result<u64> {
+        let mut len = 0;
+        for path in self.list_files().await? {
+            len += contributed_len(&path).await?;
+        }
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::helper::cache_file_creator::TEST_CACHE;
+
+    use super::*;
+
+    /// returns everything the input yields
+    async fn read_all(input: &mut DirInput) -> String {
+        let mut got = vec![];
+        while let Some(chunk) = input.chunk().await.unwrap() {
+            got.push(String::from_utf8(chunk.to_vec()).unwrap());
+        }
+        got.concat()
+    }
+
+    #[tokio::test]
+    async fn test_dir_input() {
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_dir_input");
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("b.txt"), "b.domain\n").unwrap();
+        // the last line misses its newline
+        std::fs::write(dir.join("a.txt"), "a1.domain\na2.domain").unwrap();
+        std::fs::write(dir.join(".a.txt.swp"), "hidden.domain\n").unwrap();
+        std::fs::write(dir.join("sub/c.txt"), "c.domain\n").unwrap();
+
+        let mut input = DirInput::new(dir.clone(), false);
+        let got = read_all(&mut input).await;
+        assert_eq!(got, "a1.domain\na2.domain\nb.domain\n");
+        assert_eq!(input.len().await.unwrap(), got.len() as u64);
+        input.reset().await.unwrap();
+        assert_eq!(read_all(&mut input).await, got);
+
+        let mut input = DirInput::new(dir, true);
+        let got = read_all(&mut input).await;
+        assert_eq!(got, "a1.domain\na2.domain\nb.domain\nc.domain\n");
+        assert_eq!(input.len().await.unwrap(), got.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn test_matching_files() {
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_matching_files");
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("b.txt"), "b.domain\n").unwrap();
+        std::fs::write(dir.join("a.txt"), "a.domain\n").unwrap();
+        std::fs::write(dir.join("a.csv"), "csv.domain\n").unwrap();
+        std::fs::write(dir.join(".c.txt"), "hidden.domain\n").unwrap();
+        std::fs::write(dir.join("sub/d.txt"), "sub.domain\n").unwrap();
+
+        let pattern = format!("{}/*.txt", dir.to_string_lossy());
+        let mut input = DirInput::matching(&pattern);
+        let got = read_all(&mut input).await;
+        assert_eq!(got, "a.domain\nb.domain\n");
+        assert_eq!(input.len().await.unwrap(), got.len() as u64);
+    }
+
+    #[test]
+    fn test_pattern_base() {
+        assert_eq!(
+            pattern_base("/data/lists/*.txt"),
+            (PathBuf::from("/data/lists"), PathBuf::from("*.txt"))
+        );
+        assert_eq!(
+            pattern_base("/data/*/lists/list.txt"),
+            (PathBuf::from("/data"), PathBuf::from("*/lists/list.txt"))
+        );
+        assert_eq!(
+            pattern_base("*.txt"),
+            (PathBuf::from("."), PathBuf::from("*.txt"))
+        );
+    }
+}
@@ -0,0 +1,23 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+
+use super::Input;
+
+/// EmptyInput provides no data, e.g. for an output written without entries
+#[derive(Debug, Default)]
+pub struct EmptyInput;
+
+#[async_trait]
+impl Input for EmptyInput {
+    async fn chunk(&mut self) -> anyhow::Result<Option<Bytes>> {
+        Ok(None)
+    }
+
+    async fn reset(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn len(&mut self) -> anyhow::Result<u64> {
+        Ok(0)
+    }
+}
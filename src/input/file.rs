@@ -1,29 +1,322 @@
-use std::path::{Path, PathBuf};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{Context as TaskContext, Poll},
+    time::{Duration, UNIX_EPOCH},
+};
 
-use crate::input::Input;
+use crate::{
+    input::{
+        lines::{LineBuffer, DEFAULT_READ_SIZE},
+        Input,
+    },
+    platform,
+};
 use anyhow::Context;
-use async_compression::tokio::bufread::GzipDecoder;
+use async_compression::tokio::bufread::{
+    BzDecoder, DeflateDecoder, GzipDecoder, XzDecoder, ZstdDecoder,
+};
 use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
 use futures::StreamExt;
 use serde::{Deserialize, Serialize};
 use tokio::{
     fs::{self, File},
-    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader},
+    io::{AsyncBufRead, AsyncRead, AsyncReadExt, AsyncSeekExt, BufReader, ReadBuf, SeekFrom, Take},
 };
-use tokio_tar::{Archive, Entry};
+use tokio_tar::{Archive, Entries, Entry};
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(tag = "type", content = "archive_list_file")]
 pub enum Compression {
     Gz,
     TarGz(String),
+    Zstd,
+    TarZstd(String),
+    Bzip2,
+    Xz,
+    TarXz(String),
+    Zip(String),
+    Tar(String),
 }
 
+impl Compression {
+    /// returns the compression of a single compressed file by the extension of its
+    /// path, None for other files. Archives need the path of the list file within
+    /// them and therefore aren't recognized.
+    ///
+    /// * `path`: the path of the file, e.g. of an URL
+    pub fn from_path(path: &str) -> Option<Self> {
+        let path = path.to_lowercase();
+        if [".tar.gz", ".tgz", ".tar.zst", ".tar.xz"]
+            .iter()
+            .any(|e| path.ends_with(e))
+        {
+            return None;
+        }
+        match path.rsplit_once('.')?.1 {
+            "gz" => Some(Compression::Gz),
+            "zst" => Some(Compression::Zstd),
+            "bz2" => Some(Compression::Bzip2),
+            "xz" => Some(Compression::Xz),
+            _ => None,
+        }
+    }
+
+    /// returns the path of the list file within a tar archive, None if the file isn't
+    /// an archive
+    fn archive_member(&self) -> Option<&String> {
+        match self {
+            Compression::TarGz(member)
+            | Compression::TarZstd(member)
+            | Compression::TarXz(member)
+            | Compression::Tar(member) => Some(member),
+            Compression::Gz
+            | Compression::Zstd
+            | Compression::Bzip2
+            | Compression::Xz
+            | Compression::Zip(_) => None,
+        }
+    }
+}
+
+/// returns true if the path of a list file is a glob pattern matching several list
+/// files, e.g. `lists/*.txt`
+///
+/// * `path`: the configured path of the list file
+pub fn is_pattern(path: &str) -> bool {
+    path.contains(['*', '?', '['])
+}
+
+/// Decoder decompresses a file according to its compression, or the body of a
+/// response according to its Content-Encoding
+#[derive(Debug)]
+pub enum Decoder<R = BufReader<File>> {
+    Gz(GzipDecoder<R>),
+    Zstd(ZstdDecoder<R>),
+    Bzip2(BzDecoder<R>),
+    Xz(XzDecoder<R>),
+    /// a deflated member of a zip archive
+    Deflate(DeflateDecoder<R>),
+    /// an uncompressed tar archive or member of a zip archive
+    Stored(R),
+}
+
+impl<R: AsyncBufRead> Decoder<R> {
+    /// Initialize a new Decoder
+    ///
+    /// * `compression`: the compression of the file
+    /// * `reader`: the reader of the compressed file
+    pub fn new(compression: &Compression, reader: R) -> Self {
+        match compression {
+            Compression::Gz | Compression::TarGz(_) => Decoder::Gz(GzipDecoder::new(reader)),
+            Compression::Zstd | Compression::TarZstd(_) => Decoder::Zstd(ZstdDecoder::new(reader)),
+            Compression::Bzip2 => Decoder::Bzip2(BzDecoder::new(reader)),
+            Compression::Xz | Compression::TarXz(_) => Decoder::Xz(XzDecoder::new(reader)),
+            Compression::Tar(_) => Decoder::Stored(reader),
+            // the file has to be positioned at the member by zip_member
+            Compression::Zip(_) => Decoder::Stored(reader),
+        }
+    }
+
+    /// returns the reader of the compressed data
+    pub fn get_mut(&mut self) -> &mut R {
+        match self {
+            Decoder::Gz(decoder) => decoder.get_mut(),
+            Decoder::Zstd(decoder) => decoder.get_mut(),
+            Decoder::Bzip2(decoder) => decoder.get_mut(),
+            Decoder::Xz(decoder) => decoder.get_mut(),
+            Decoder::Deflate(decoder) => decoder.get_mut(),
+            Decoder::Stored(reader) => reader,
+        }
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncRead for Decoder<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Decoder::Gz(decoder) => Pin::new(decoder).poll_read(cx, buf),
+            Decoder::Zstd(decoder) => Pin::new(decoder).poll_read(cx, buf),
+            Decoder::Bzip2(decoder) => Pin::new(decoder).poll_read(cx, buf),
+            Decoder::Xz(decoder) => Pin::new(decoder).poll_read(cx, buf),
+            Decoder::Deflate(decoder) => Pin::new(decoder).poll_read(cx, buf),
+            Decoder::Stored(file) => Pin::new(file).poll_read(cx, buf),
+        }
+    }
+}
+
+/// seconds an archive may be scanned for the list file if no timeout was configured
+pub const DEFAULT_SCAN_TIMEOUT: u64 = 300;
+
 #[derive(Debug)]
 pub enum Handle {
     File(BufReader<File>),
-    Gz(GzipDecoder<BufReader<File>>),
-    TarGz(Entry<Archive<GzipDecoder<BufReader<File>>>>),
+    /// a compressed list file
+    Decoded(Decoder),
+    /// a list file within a compressed tar archive
+    Tar(Entry<Archive<Decoder>>),
+    /// a list file within a tar archive located by the member index or within a zip
+    /// archive
+    Member(Take<Decoder>),
+    /// the list files within a tar archive matching a pattern
+    Matches(MatchingEntries),
+}
+
+/// MatchingEntries reads the list files within a tar archive matching a glob pattern
+/// one after another as a single list
+pub struct MatchingEntries {
+    /// the entries of the archive not scanned yet
+    entries: Entries<Decoder>,
+    /// the pattern the paths of the list files match
+    pattern: glob::Pattern,
+    /// the list file currently read
+    current: Option<Entry<Archive<Decoder>>>,
+}
+
+impl std::fmt::Debug for MatchingEntries {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MatchingEntries")
+            .field("pattern", &self.pattern)
+            .finish_non_exhaustive()
+    }
+}
+
+impl MatchingEntries {
+    /// scans the archive for the next file matching the pattern
+    async fn next_match(&mut self) -> anyhow::Result<Option<Entry<Archive<Decoder>>>> {
+        // a `*` doesn't match the separator, so `lists/*.txt` skips sub directories
+        let options = glob::MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+        while let Some(entry_result) = self.entries.next().await {
+            let entry = entry_result?;
+            if entry.header().entry_type().is_file()
+                && self.pattern.matches_path_with(&entry.path()?, options)
+            {
+                return Ok(Some(entry));
+            }
+        }
+        Ok(None)
+    }
+
+    /// returns the list file currently read, the next matching one if the current one
+    /// is finished and None if there is no list file left
+    async fn current(&mut self) -> anyhow::Result<Option<&mut Entry<Archive<Decoder>>>> {
+        if self.current.is_none() {
+            self.current = self.next_match().await?;
+        }
+        Ok(self.current.as_mut())
+    }
+}
+
+/// MemberIndex records the position of the list file within a tar archive, so the
+/// archive's headers don't have to be scanned again as long as the archive is unchanged
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+struct MemberIndex {
+    /// the path of the list file within the archive
+    member: String,
+    /// length of the archive the index was created for
+    archive_len: u64,
+    /// modification time of the archive the index was created for
+    archive_modified: u64,
+    /// offset of the list file's content within the uncompressed archive
+    offset: u64,
+    /// length of the list file
+    size: u64,
+}
+
+impl MemberIndex {
+    /// reads the index and returns it if it belongs to the member of the archive
+    ///
+    /// * `index_path`: the file system path of the index
+    /// * `member`: the path of the list file within the archive
+    /// * `archive_meta`: the metadata of the archive
+    async fn load(
+        index_path: &Path,
+        member: &str,
+        archive_meta: &std::fs::Metadata,
+    ) -> Option<Self> {
+        let contents = fs::read_to_string(index_path).await.ok()?;
+        let index: Self = serde_json::from_str(&contents).ok()?;
+        index.matches(member, archive_meta).then_some(index)
+    }
+
+    /// returns true if the index belongs to the member of the archive
+    ///
+    /// * `member`: the path of the list file within the archive
+    /// * `archive_meta`: the metadata of the archive
+    fn matches(&self, member: &str, archive_meta: &std::fs::Metadata) -> bool {
+        self.member == member
+            && self.archive_len == archive_meta.len()
+            && Some(self.archive_modified) == modified_nanos(archive_meta)
+    }
+}
+
+/// returns the modification time of a file in nanoseconds since the unix epoch
+///
+/// * `meta`: the metadata of the file
+fn modified_nanos(meta: &std::fs::Metadata) -> Option<u64> {
+    let modified = meta.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_nanos() as u64)
+}
+
+/// returns the entry of the archive with the given path
+///
+/// * `archive`: the archive to be scanned
+/// * `path_wanted`: the path of the entry within the archive
+async fn find_entry(
+    archive: &mut Archive<Decoder>,
+    path_wanted: &Path,
+) -> anyhow::Result<Option<Entry<Archive<Decoder>>>> {
+    let mut entries = archive.entries()?;
+    while let Some(entry_result) = entries.next().await {
+        if let Ok(entry) = entry_result
+            && let Ok(path) = entry.path()
+            && path == path_wanted
+        {
+            return Ok(Some(entry));
+        }
+    }
+    Ok(None)
+}
+
+/// returns the list file within a zip archive, which is located by the archive's
+/// central directory instead of scanning the archive
+///
+/// * `file`: the zip archive
+/// * `member`: the path of the list file within the archive
+async fn zip_member(mut file: File, member: &str) -> anyhow::Result<Take<Decoder>> {
+    let std_file = file.try_clone().await?.into_std().await;
+    let name = member.to_string();
+    let (data_start, method, size) = tokio::task::spawn_blocking(move || -> anyhow::Result<_> {
+        let mut archive = zip::ZipArchive::new(std_file)?;
+        let entry = archive
+            .by_name(&name)
+            .map_err(|_| anyhow::anyhow!("specified list file not found in archive"))?;
+        Ok((entry.data_start(), entry.compression(), entry.size()))
+    })
+    .await??;
+    file.seek(SeekFrom::Start(data_start)).await?;
+    let decoder = match method {
+        zip::CompressionMethod::Stored => Decoder::Stored(BufReader::new(file)),
+        zip::CompressionMethod::Deflated => {
+            Decoder::Deflate(DeflateDecoder::new(BufReader::new(file)))
+        }
+        method => {
+            return Err(anyhow::anyhow!(
+                "{} is compressed by the unsupported method {:?}",
+                member,
+                method
+            ))
+        }
+    };
+    Ok(decoder.take(size))
 }
 
 /// FileInput reads data from a File
@@ -35,6 +328,18 @@ pub struct FileInput {
     path: PathBuf,
     /// the file handle
     handle: Option<Handle>,
+    /// maximum time to scan a tar archive for the list file
+    scan_timeout: Duration,
+    /// remember the position of the list file within a tar archive
+    member_index: bool,
+    /// position of the list file within a tar archive found by the last scan
+    member: Option<MemberIndex>,
+    /// number of bytes read from the file at once
+    read_size: usize,
+    /// frames the data into whole lines
+    lines: LineBuffer,
+    /// hand on the data in blocks as read instead of framing it into lines
+    unframed: bool,
 }
 
 impl FileInput {
@@ -47,11 +352,50 @@ impl FileInput {
             compression,
             path,
             handle: None,
+            scan_timeout: Duration::from_secs(DEFAULT_SCAN_TIMEOUT),
+            member_index: false,
+            member: None,
+            read_size: DEFAULT_READ_SIZE,
+            lines: LineBuffer::default(),
+            unframed: false,
         }
     }
 
+    /// sets how the list file is looked up within a tar archive
+    ///
+    /// * `scan_timeout`: maximum time to scan the archive for the list file
+    /// * `member_index`: remember the position of the list file for subsequent reads
+    pub fn with_archive_settings(mut self, scan_timeout: Duration, member_index: bool) -> Self {
+        self.scan_timeout = scan_timeout;
+        self.member_index = member_index;
+        self
+    }
+
+    /// sets the number of bytes read from the file at once
+    ///
+    /// * `read_size`: number of bytes read at once
+    pub fn with_read_size(mut self, read_size: usize) -> Self {
+        self.read_size = read_size;
+        self.lines = LineBuffer::with_read_size(read_size);
+        self
+    }
+
+    /// reads an uncompressed file in blocks as they are instead of lines, so a file is
+    /// copied unchanged, e.g. a compressed list file which is decoded later on
+    pub fn unframed(mut self) -> Self {
+        self.unframed = true;
+        self
+    }
+
+    /// returns the file system path of the member index belonging to the file
+    fn index_path(&self) -> PathBuf {
+        let file_name = self.path.file_name().unwrap_or_default().to_string_lossy();
+        self.path.with_file_name(format!(".{}.index", file_name))
+    }
+
     /// initializes the file handle according to the specified compression format
     async fn init_handle(&mut self) -> anyhow::Result<()> {
+        self.lines.clear();
         let f = File::open(self.path.clone()).await.with_context(|| {
             format!(
                 "unable to open file {}",
@@ -62,93 +406,166 @@ impl FileInput {
                     .unwrap_or_default()
             )
         })?;
-        match &self.compression {
-            Some(Compression::Gz) => {
-                let gz = GzipDecoder::new(BufReader::new(f));
-                self.handle = Some(Handle::Gz(gz));
+        let compression = match &self.compression {
+            Some(compression) => compression,
+            None => {
+                self.handle = Some(Handle::File(BufReader::with_capacity(self.read_size, f)));
+                return Ok(());
             }
-            Some(Compression::TarGz(wanted_path_str)) => {
-                let gz = GzipDecoder::new(BufReader::new(f));
-                let mut archive = Archive::new(gz);
-
-                let path_wanted = Path::new(wanted_path_str);
-                let mut entries = archive.entries()?;
-                while let Some(entry_result) = entries.next().await {
-                    if let Ok(entry) = entry_result
-                        && let Ok(path) = entry.path()
-                        && path == path_wanted
-                    {
-                        self.handle = Some(Handle::TarGz(entry));
-                        break;
+        };
+        if let Compression::Zip(member) = compression {
+            self.handle = Some(Handle::Member(zip_member(f, member).await?));
+            return Ok(());
+        }
+        match compression.archive_member() {
+            None => {
+                self.handle = Some(Handle::Decoded(Decoder::new(
+                    compression,
+                    BufReader::new(f),
+                )))
+            }
+            Some(pattern) if is_pattern(pattern) => {
+                let mut archive = Archive::new(Decoder::new(compression, BufReader::new(f)));
+                let mut matches = MatchingEntries {
+                    entries: archive.entries()?,
+                    pattern: glob::Pattern::new(pattern)
+                        .with_context(|| format!("invalid archive list file {}", pattern))?,
+                    current: None,
+                };
+                let first = tokio::time::timeout(self.scan_timeout, matches.next_match())
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "scanning the archive for {} timed out after {} seconds",
+                            pattern,
+                            self.scan_timeout.as_secs()
+                        )
+                    })??
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("no list file matching {} in archive", pattern)
+                    })?;
+                matches.current = Some(first);
+                self.handle = Some(Handle::Matches(matches));
+            }
+            Some(wanted_path_str) => {
+                let meta = f.metadata().await?;
+                let index_path = self.index_path();
+                // a known position is reached by skipping the decompressed bytes instead
+                // of parsing every header in front of the list file
+                let known_member = match &self.member {
+                    Some(m) if m.matches(wanted_path_str, &meta) => Some(m.clone()),
+                    _ if self.member_index => {
+                        MemberIndex::load(&index_path, wanted_path_str, &meta).await
                     }
+                    _ => None,
+                };
+                if let Some(index) = known_member {
+                    let mut decoder = Decoder::new(compression, BufReader::new(f));
+                    tokio::io::copy(
+                        &mut (&mut decoder).take(index.offset),
+                        &mut tokio::io::sink(),
+                    )
+                    .await?;
+                    self.handle = Some(Handle::Member(decoder.take(index.size)));
+                    return Ok(());
                 }
-                if self.handle.is_none() {
-                    return Err(anyhow::anyhow!("specified list file not found in archive"));
+
+                let mut archive = Archive::new(Decoder::new(compression, BufReader::new(f)));
+                let path_wanted = Path::new(wanted_path_str);
+                let entry =
+                    tokio::time::timeout(self.scan_timeout, find_entry(&mut archive, path_wanted))
+                        .await
+                        .with_context(|| {
+                            format!(
+                                "scanning the archive for {} timed out after {} seconds",
+                                wanted_path_str,
+                                self.scan_timeout.as_secs()
+                            )
+                        })??
+                        .ok_or_else(|| {
+                            anyhow::anyhow!("specified list file not found in archive")
+                        })?;
+
+                let index = MemberIndex {
+                    member: wanted_path_str.clone(),
+                    archive_len: meta.len(),
+                    archive_modified: modified_nanos(&meta).unwrap_or_default(),
+                    offset: entry.raw_file_position(),
+                    size: entry.header().entry_size()?,
+                };
+                if self.member_index
+                    && let Err(e) = fs::write(&index_path, serde_json::to_string(&index)?).await
+                {
+                    warn!("could not write archive index {:?}: {}", index_path, e);
                 }
+                // a reset seeks the list file without scanning the archive again
+                self.member = Some(index);
+                self.handle = Some(Handle::Tar(entry));
             }
-            None => self.handle = Some(Handle::File(BufReader::new(f))),
         }
         Ok(())
     }
+
+    /// returns true if the path points to a FIFO or a character device which can't
+    /// be reopened or seeked
+    async fn is_stream(&self) -> bool {
+        match fs::metadata(&self.path).await {
+            Ok(meta) => platform::is_stream(&meta),
+            Err(_) => false,
+        }
+    }
 }
 
 #[async_trait]
 impl Input for FileInput {
-    async fn chunk(&mut self) -> anyhow::Result<Option<Vec<u8>>> {
-        /// inner function reading bytes until the next newline character
-        ///
-        /// * `archive`: the file handle to read from
-        /// * `vec_buf`: the target buffer containing the line
-        async fn read_bytes_till_newline(
-            archive: &mut (impl AsyncRead + Unpin),
-            mut vec_buf: Vec<u8>,
-        ) -> anyhow::Result<Option<Vec<u8>>> {
-            loop {
-                let mut byte_buf = Vec::with_capacity(1);
-                let n = archive.take(1).read_to_end(&mut byte_buf).await;
-                match n {
-                    Ok(n) if n > 0 => {
-                        if let Some(b) = byte_buf.last() && b == &10 {
-                                return Ok(Some(vec_buf));
-                            }
-                        vec_buf.extend(byte_buf);
-                        if vec_buf.len() >= vec_buf.capacity() {
-                            return Err(anyhow::anyhow!("Error reading chunk from file: line length exceeds buffer capacity"));
-                        }
-                    }
-                    Ok(n) if n > vec_buf.len() => {
-                        return Err(anyhow::anyhow!("Error reading chunk from file:  chunk exceedes maximum line length of {} bytes", vec_buf.len()));
-                    }
-                    Err(e) => return Err(anyhow::anyhow!("Error reading chunk from file: {}", e)),
-                    _ => return Ok(None),
-                }
-            }
-        }
-
-        // read buffer size for a single line
-        const BUF_SIZE: usize = 1024;
-
+    async fn chunk(&mut self) -> anyhow::Result<Option<Bytes>> {
         if self.handle.is_none() {
             self.init_handle().await?;
         }
-        let mut str_buf = String::new();
-        let vec_buf = Vec::with_capacity(BUF_SIZE);
         // handle can be safely unwrapped here since it's initialized at the beginning of the function
         match self.handle.as_mut().unwrap() {
-            Handle::File(file) => match file.read_line(&mut str_buf).await {
-                Ok(n) if n > 0 => Ok(Some(str_buf.as_bytes().to_vec())),
-                Ok(n) if n == 0 => Ok(None),
-                Ok(_) => Ok(None),
-                Err(e) => Err(anyhow::anyhow!("Error reading line from file: {}", e)),
-            },
-            Handle::Gz(archive) => read_bytes_till_newline(archive, vec_buf).await,
-            Handle::TarGz(archive) => read_bytes_till_newline(archive, vec_buf).await,
+            Handle::File(file) if self.unframed => {
+                let mut block = BytesMut::with_capacity(self.read_size);
+                let n = file
+                    .read_buf(&mut block)
+                    .await
+                    .with_context(|| "Error reading chunk from file")?;
+                Ok((n > 0).then(|| block.freeze()))
+            }
+            // the data is read in blocks, which are framed into whole lines
+            Handle::File(file) => self.lines.read_line(file).await,
+            Handle::Decoded(decoder) => self.lines.read_line(decoder).await,
+            Handle::Tar(archive) => self.lines.read_line(archive).await,
+            Handle::Member(member) => self.lines.read_line(member).await,
+            Handle::Matches(matches) => {
+                while let Some(entry) = matches.current().await? {
+                    if let Some(line) = self.lines.read_line(entry).await? {
+                        return Ok(Some(line));
+                    }
+                    // the list file is finished, continue with the next matching one
+                    matches.current = None;
+                }
+                Ok(None)
+            }
         }
     }
 
     /// reinitialize the file handle and start reading from zero
     async fn reset(&mut self) -> anyhow::Result<()> {
         if self.handle.is_some() {
+            // data read from a stream is gone and reopening would wait for a new writer
+            if self.is_stream().await {
+                return Err(anyhow::anyhow!(
+                    "file {} is a stream and can't be reset",
+                    self.path.to_str().unwrap_or("<no-name>")
+                ));
+            }
+            // uncompressed files are rewound, compressed ones have to be decoded again
+            if let Some(Handle::File(file)) = self.handle.as_mut() {
+                file.seek(SeekFrom::Start(0)).await?;
+                self.lines.clear();
+                return Ok(());
+            }
             self.handle.take();
         }
         self.init_handle().await?;
@@ -157,6 +574,12 @@ impl Input for FileInput {
 
     /// get the file length from file metadata
     async fn len(&mut self) -> anyhow::Result<u64> {
+        if self.is_stream().await {
+            return Err(anyhow::anyhow!(
+                "file {} is a stream and has no length",
+                self.path.to_str().unwrap_or("<no-name>")
+            ));
+        }
         let content_len = fs::metadata(&self.path)
             .await
             .with_context(|| {
@@ -169,3 +592,356 @@ impl Input for FileInput {
         Ok(content_len)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{input::lines::MAX_LINE_LEN, tests::helper::cache_file_creator::TEST_CACHE};
+
+    use super::*;
+
+    #[test]
+    fn test_from_path() {
+        assert_eq!(
+            Compression::from_path("/lists/hosts.gz"),
+            Some(Compression::Gz)
+        );
+        assert_eq!(
+            Compression::from_path("hosts.TXT.ZST"),
+            Some(Compression::Zstd)
+        );
+        assert_eq!(
+            Compression::from_path("hosts.bz2"),
+            Some(Compression::Bzip2)
+        );
+        assert_eq!(Compression::from_path("hosts.xz"), Some(Compression::Xz));
+        // archives need the path of the list file within them
+        assert_eq!(Compression::from_path("lists.tar.gz"), None);
+        assert_eq!(Compression::from_path("lists.tgz"), None);
+        assert_eq!(Compression::from_path("hosts.txt"), None);
+        assert_eq!(Compression::from_path("/hosts"), None);
+    }
+
+    #[tokio::test]
+    async fn test_member_index() {
+        use async_compression::tokio::write::GzipEncoder;
+        use tokio::io::AsyncWriteExt;
+
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_member_index");
+        std::fs::create_dir_all(&dir).unwrap();
+        let archive_path = dir.join("archive");
+        std::fs::remove_file(dir.join(".archive.index")).ok();
+
+        // an archive with the list file behind another member
+        let mut builder =
+            tokio_tar::Builder::new(GzipEncoder::new(File::create(&archive_path).await.unwrap()));
+        for (path, data) in [
+            ("other/list", "other.domain\n"),
+            ("wanted/list", "one.domain\ntwo.domain\n"),
+        ] {
+            let mut header = tokio_tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, data.as_bytes())
+                .await
+                .unwrap();
+        }
+        let mut encoder = builder.into_inner().await.unwrap();
+        encoder.shutdown().await.unwrap();
+
+        let read_all = |input: FileInput| async move {
+            let mut input = input;
+            let mut got = vec![];
+            while let Some(chunk) = input.chunk().await.unwrap() {
+                got.push(String::from_utf8(chunk.to_vec()).unwrap());
+            }
+            (got, input)
+        };
+        let compression = Some(Compression::TarGz("wanted/list".to_string()));
+        let new_input = || {
+            FileInput::new(archive_path.clone(), compression.clone())
+                .with_archive_settings(Duration::from_secs(10), true)
+        };
+
+        // the first read scans the archive and writes the index
+        let (got, input) = read_all(new_input()).await;
+        assert_eq!(got, vec!["one.domain", "two.domain"]);
+        assert!(matches!(input.handle, Some(Handle::Tar(_))));
+
+        // the second read seeks the indexed position
+        let (got, input) = read_all(new_input()).await;
+        assert_eq!(got, vec!["one.domain", "two.domain"]);
+        assert!(matches!(input.handle, Some(Handle::Member(_))));
+
+        // a reset seeks the list file found by the previous scan even without index
+        let input = FileInput::new(archive_path.clone(), compression.clone());
+        let (_, mut input) = read_all(input).await;
+        input.reset().await.unwrap();
+        assert!(matches!(input.handle, Some(Handle::Member(_))));
+        let (got, _) = read_all(input).await;
+        assert_eq!(got, vec!["one.domain", "two.domain"]);
+    }
+
+    #[tokio::test]
+    async fn test_matching_members() {
+        use async_compression::tokio::write::GzipEncoder;
+        use tokio::io::AsyncWriteExt;
+
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_matching_members");
+        std::fs::create_dir_all(&dir).unwrap();
+        let archive_path = dir.join("archive.tar.gz");
+        let mut builder =
+            tokio_tar::Builder::new(GzipEncoder::new(File::create(&archive_path).await.unwrap()));
+        for (path, data) in [
+            ("lists/a.txt", "a.domain\n"),
+            ("other/b.txt", "other.domain\n"),
+            ("lists/sub/c.txt", "sub.domain\n"),
+            ("lists/d.txt", "d1.domain\nd2.domain\n"),
+        ] {
+            let mut header = tokio_tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, data.as_bytes())
+                .await
+                .unwrap();
+        }
+        let mut encoder = builder.into_inner().await.unwrap();
+        encoder.shutdown().await.unwrap();
+
+        let compression = Some(Compression::TarGz("lists/*.txt".to_string()));
+        let mut input = FileInput::new(archive_path.clone(), compression);
+        for _ in 0..2 {
+            let mut got = vec![];
+            while let Some(chunk) = input.chunk().await.unwrap() {
+                got.push(String::from_utf8(chunk.to_vec()).unwrap());
+            }
+            assert_eq!(got, vec!["a.domain", "d1.domain", "d2.domain"]);
+            input.reset().await.unwrap();
+        }
+
+        let compression = Some(Compression::TarGz("missing/*".to_string()));
+        let mut input = FileInput::new(archive_path, compression);
+        assert!(input.chunk().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_plain_tar() {
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_plain_tar");
+        std::fs::create_dir_all(&dir).unwrap();
+        let archive_path = dir.join("archive.tar");
+        let mut builder = tokio_tar::Builder::new(File::create(&archive_path).await.unwrap());
+        for (path, data) in [
+            ("other/list", "other.domain\n"),
+            ("wanted/list", "one.domain\ntwo.domain\n"),
+        ] {
+            let mut header = tokio_tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, data.as_bytes())
+                .await
+                .unwrap();
+        }
+        builder.into_inner().await.unwrap();
+
+        let compression = Some(Compression::Tar("wanted/list".to_string()));
+        let mut input = FileInput::new(archive_path, compression);
+        let mut got = vec![];
+        while let Some(chunk) = input.chunk().await.unwrap() {
+            got.push(String::from_utf8(chunk.to_vec()).unwrap());
+        }
+        assert_eq!(got, vec!["one.domain", "two.domain"]);
+    }
+
+    #[tokio::test]
+    async fn test_zip_member() {
+        use std::io::Write;
+        use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_zip_member");
+        std::fs::create_dir_all(&dir).unwrap();
+        let archive_path = dir.join("archive.zip");
+        let mut writer = ZipWriter::new(std::fs::File::create(&archive_path).unwrap());
+        for (path, method) in [
+            ("stored/list", CompressionMethod::Stored),
+            ("deflated/list", CompressionMethod::Deflated),
+        ] {
+            let options = FileOptions::default().compression_method(method);
+            writer.start_file(path, options).unwrap();
+            writer.write_all(b"one.domain\ntwo.domain\n").unwrap();
+        }
+        writer.finish().unwrap();
+
+        for member in ["stored/list", "deflated/list"] {
+            let compression = Some(Compression::Zip(member.to_string()));
+            let mut input = FileInput::new(archive_path.clone(), compression);
+            let first = input.chunk().await.unwrap().unwrap();
+            assert_eq!(String::from_utf8(first.to_vec()).unwrap(), "one.domain");
+            input.reset().await.unwrap();
+            let mut got = vec![];
+            while let Some(chunk) = input.chunk().await.unwrap() {
+                got.push(String::from_utf8(chunk.to_vec()).unwrap());
+            }
+            assert_eq!(got, vec!["one.domain", "two.domain"]);
+        }
+
+        let compression = Some(Compression::Zip("missing/list".to_string()));
+        let mut input = FileInput::new(archive_path, compression);
+        assert!(input.chunk().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_reset() {
+        use async_compression::tokio::write::{BzEncoder, GzipEncoder, XzEncoder, ZstdEncoder};
+        use tokio::io::AsyncWriteExt;
+
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_reset");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("list");
+        std::fs::write(&path, "one.domain\ntwo.domain\n").unwrap();
+
+        let gz_path = dir.join("list.gz");
+        let mut gz = GzipEncoder::new(File::create(&gz_path).await.unwrap());
+        gz.write_all(b"one.domain\ntwo.domain\n").await.unwrap();
+        gz.shutdown().await.unwrap();
+
+        let zst_path = dir.join("list.zst");
+        let mut zst = ZstdEncoder::new(File::create(&zst_path).await.unwrap());
+        zst.write_all(b"one.domain\ntwo.domain\n").await.unwrap();
+        zst.shutdown().await.unwrap();
+
+        let bz2_path = dir.join("list.bz2");
+        let mut bz2 = BzEncoder::new(File::create(&bz2_path).await.unwrap());
+        bz2.write_all(b"one.domain\ntwo.domain\n").await.unwrap();
+        bz2.shutdown().await.unwrap();
+
+        let xz_path = dir.join("list.xz");
+        let mut xz = XzEncoder::new(File::create(&xz_path).await.unwrap());
+        xz.write_all(b"one.domain\ntwo.domain\n").await.unwrap();
+        xz.shutdown().await.unwrap();
+
+        for (path, compression) in [
+            (path, None),
+            (gz_path, Some(Compression::Gz)),
+            (zst_path, Some(Compression::Zstd)),
+            (bz2_path, Some(Compression::Bzip2)),
+            (xz_path, Some(Compression::Xz)),
+        ] {
+            let mut input = FileInput::new(path, compression);
+            // plain files keep the newline, decompressed lines are split at it
+            let first = input.chunk().await.unwrap().unwrap();
+            assert_eq!(
+                String::from_utf8(first.to_vec()).unwrap().trim(),
+                "one.domain"
+            );
+            input.reset().await.unwrap();
+            let mut got = vec![];
+            while let Some(chunk) = input.chunk().await.unwrap() {
+                got.push(
+                    String::from_utf8(chunk.to_vec())
+                        .unwrap()
+                        .trim()
+                        .to_string(),
+                );
+            }
+            assert_eq!(got, vec!["one.domain", "two.domain"]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_whole_lines() {
+        use async_compression::tokio::write::GzipEncoder;
+        use tokio::io::AsyncWriteExt;
+
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_whole_lines");
+        std::fs::create_dir_all(&dir).unwrap();
+        // a line longer than the blocks read and a last line without newline
+        let long = format!("{}.domain", "a".repeat(10_000));
+        let gz_path = dir.join("list.gz");
+        let mut gz = GzipEncoder::new(File::create(&gz_path).await.unwrap());
+        gz.write_all(format!("one.domain\n{}\nlast.domain", long).as_bytes())
+            .await
+            .unwrap();
+        gz.shutdown().await.unwrap();
+
+        let mut input = FileInput::new(gz_path, Some(Compression::Gz)).with_read_size(1024);
+        let mut got = vec![];
+        while let Some(chunk) = input.chunk().await.unwrap() {
+            got.push(String::from_utf8(chunk.to_vec()).unwrap());
+        }
+        assert_eq!(got, vec!["one.domain", &long, "last.domain"]);
+    }
+
+    #[tokio::test]
+    async fn test_plain_lines() {
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_plain_lines");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("list");
+        std::fs::write(&path, "one.domain\r\ntwo.domain\nlast.domain").unwrap();
+
+        // plain files are framed like compressed ones, also after a reset
+        let mut input = FileInput::new(path.clone(), None).with_read_size(4);
+        for _ in 0..2 {
+            let mut got = vec![];
+            while let Some(chunk) = input.chunk().await.unwrap() {
+                got.push(String::from_utf8(chunk.to_vec()).unwrap());
+            }
+            assert_eq!(got, vec!["one.domain\r", "two.domain", "last.domain"]);
+            input.reset().await.unwrap();
+        }
+
+        // copied files are handed on unchanged
+        let mut input = FileInput::new(path.clone(), None).unframed();
+        let mut got = vec![];
+        while let Some(chunk) = input.chunk().await.unwrap() {
+            got.extend_from_slice(&chunk);
+        }
+        assert_eq!(got, std::fs::read(&path).unwrap());
+
+        // lines of binary data are bounded
+        std::fs::write(&path, vec![b'a'; MAX_LINE_LEN + DEFAULT_READ_SIZE]).unwrap();
+        let mut input = FileInput::new(path, None);
+        assert!(input.chunk().await.is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_fifo_input() {
+        use nix::{sys::stat::Mode, unistd::mkfifo};
+
+        let mut fifo_path = PathBuf::from(TEST_CACHE);
+        fifo_path.push("test_fifo_input");
+        std::fs::create_dir_all(&fifo_path).unwrap();
+        fifo_path.push("fifo");
+        std::fs::remove_file(&fifo_path).ok();
+        mkfifo(&fifo_path, Mode::S_IRWXU).unwrap();
+
+        // another process streams entries into the FIFO
+        let writer_path = fifo_path.clone();
+        let writer = tokio::task::spawn_blocking(move || {
+            std::fs::write(writer_path, "one.domain\ntwo.domain\n").unwrap();
+        });
+
+        let mut input = FileInput::new(fifo_path, None);
+        let mut got = vec![];
+        while let Some(chunk) = input.chunk().await.unwrap() {
+            got.push(String::from_utf8(chunk.to_vec()).unwrap());
+        }
+        writer.await.unwrap();
+
+        assert_eq!(got, vec!["one.domain", "two.domain"]);
+        assert!(input.len().await.is_err());
+        assert!(input.reset().await.is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    net::IpAddr,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use crate::{
+    filter_list::ListFormat,
+    input::{
+        hash_prefix::HashPrefixParser, json::JsonParser, maltrail::TrailParser, rpz::RpzParser,
+        suricata::RuleParser, xml::XmlParser, zone::ZoneParser, Input,
+    },
+    output::lint::is_valid_hostname,
+};
+
+/// IndicatorParser reads the indicators from the lines of a list in a specific format
+pub trait IndicatorParser {
+    /// returns the indicators found in a line
+    ///
+    /// * `line`: a line of the list
+    fn indicators(&mut self, line: &str) -> Vec<String>;
+
+    /// returns the indicators left after the last line, e.g. of a document which can
+    /// only be parsed as a whole, and keeps state for the next run
+    fn finish(&mut self) -> anyhow::Result<Vec<String>> {
+        Ok(vec![])
+    }
+}
+
+/// returns the parser for a list format, None if the lines are read as they are
+///
+/// * `format`: the format of the list
+/// * `path`: the file system path the indicators are written to
+/// * `entries_dir`: the directory of the entry files of all lists
+fn parser(
+    format: &ListFormat,
+    path: &Path,
+    entries_dir: &Path,
+) -> Option<Box<dyn IndicatorParser + Send>> {
+    match format {
+        ListFormat::Lines => None,
+        ListFormat::Zone { strip_origin } => Some(Box::new(ZoneParser::new(*strip_origin))),
+        ListFormat::Rpz => Some(Box::new(RpzParser::default())),
+        ListFormat::Maltrail => Some(Box::new(TrailParser)),
+        ListFormat::Suricata => Some(Box::new(RuleParser::default())),
+        ListFormat::Json { path } => Some(Box::new(JsonParser::new(path))),
+        ListFormat::Xml { path } => Some(Box::new(XmlParser::new(path))),
+        ListFormat::HashPrefix { salt, suffix } => Some(Box::new(HashPrefixParser::new(
+            salt,
+            suffix,
+            PathBuf::from(format!("{}.hashes", path.display())),
+            entries_dir,
+        ))),
+    }
+}
+
+/// returns the normalized indicator if the value is a domain, an IP address or a
+/// network in CIDR notation
+///
+/// * `value`: the candidate read from a list
+pub fn indicator(value: &str) -> Option<String> {
+    let value = value.trim().trim_matches('.').to_lowercase();
+    let is_network = |v: &str| match v.split_once('/') {
+        Some((ip, prefix)) => ip.parse::<IpAddr>().is_ok() && prefix.parse::<u8>().is_ok(),
+        None => v.parse::<IpAddr>().is_ok(),
+    };
+    // a single label is rather a word than a domain
+    // the cheaper check of the host name comes first since most indicators are domains
+    ((value.contains('.') && is_valid_hostname(&value)) || is_network(&value)).then_some(value)
+}
+
+/// writes the indicators of a list read from the input to a file, one indicator per
+/// line, and returns the number of indicators
+///
+/// * `input`: the list
+/// * `path`: the file system path the indicators are written to
+/// * `format`: the format of the list
+/// * `entries_dir`: the directory of the entry files of all lists
+pub async fn convert(
+    input: &mut (dyn Input + Send),
+    path: &Path,
+    format: &ListFormat,
+    entries_dir: &Path,
+) -> anyhow::Result<usize> {
+    let mut parser = match parser(format, path, entries_dir) {
+        Some(parser) => parser,
+        None => return Err(anyhow::anyhow!("{:?} lists need no conversion", format)),
+    };
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| "could not create indicator directory")?;
+    }
+    let mut writer =
+        BufWriter::new(File::create(path).with_context(|| format!("could not create {:?}", path))?);
+    let mut count = 0;
+    let mut write = |indicators: Vec<String>| -> anyhow::Result<()> {
+        for indicator in indicators {
+            writer.write_all(indicator.as_bytes())?;
+            writer.write_all(b"\n")?;
+            count += 1;
+        }
+        Ok(())
+    };
+    while let Some(chunk) = input.chunk().await? {
+        let chunk = String::from_utf8_lossy(&chunk);
+        for line in chunk.lines() {
+            write(parser.indicators(line))?;
+        }
+    }
+    write(parser.finish()?)?;
+    writer.flush()?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_indicator() {
+        assert_eq!(indicator(".Evil.COM."), Some("evil.com".to_string()));
+        assert_eq!(indicator("192.0.2.1"), Some("192.0.2.1".to_string()));
+        assert_eq!(indicator("192.0.2.0/24"), Some("192.0.2.0/24".to_string()));
+        assert_eq!(indicator("2001:db8::1"), Some("2001:db8::1".to_string()));
+        assert_eq!(indicator("localhost"), None);
+        assert_eq!(indicator("not a domain"), None);
+        assert_eq!(indicator("192.0.2.0/x"), None);
+    }
+}
@@ -0,0 +1,489 @@
+use std::{
+    fs,
+    net::{IpAddr, SocketAddr},
+};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{
+        AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt,
+        BufReader,
+    },
+    net::{TcpListener, TcpStream},
+};
+
+use crate::input::Input;
+
+/// port of the control connection if none is part of the source
+const DEFAULT_PORT: u16 = 21;
+/// user logging in if none is configured
+const ANONYMOUS_USER: &str = "anonymous";
+/// bytes read from the data connection at once
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Stream is a plain or TLS protected connection to the server
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send + Sync {}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send + Sync> Stream for S {}
+
+/// FtpSettings describe how the server is accessed
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FtpSettings {
+    /// user logging in, defaults to anonymous
+    pub user: Option<String>,
+    /// file containing the password of the user
+    pub password_file: Option<String>,
+    /// protects the control and data connections by explicit TLS (AUTH TLS)
+    #[serde(default)]
+    pub tls: bool,
+    /// the server connects to harvester for the transfer instead of passive mode
+    #[serde(default)]
+    pub active: bool,
+}
+
+/// Session is a logged in control connection
+struct Session {
+    control: BufReader<Box<dyn Stream>>,
+    /// address of the server the data connections are opened to in passive mode
+    peer: IpAddr,
+    /// address of harvester the server connects to in active mode
+    local: IpAddr,
+}
+
+/// FtpInput reads a file from an FTP server, optionally protected by TLS
+pub struct FtpInput {
+    /// the host and port of the server
+    addr: String,
+    path: String,
+    settings: FtpSettings,
+    /// the control connection of the running transfer
+    session: Option<Session>,
+    data: Option<Box<dyn Stream>>,
+}
+
+impl std::fmt::Debug for FtpInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FtpInput")
+            .field("addr", &self.addr)
+            .field("path", &self.path)
+            .field("settings", &self.settings)
+            .finish()
+    }
+}
+
+impl FtpInput {
+    /// Initialize a new FtpInput
+    ///
+    /// * `source`: the location of the file, e.g. `ftp://ftp.example.com/feeds/malware.txt`
+    /// * `settings`: the credentials, TLS and transfer mode
+    pub fn new(source: &str, settings: FtpSettings) -> anyhow::Result<Self> {
+        let (host, path) = source
+            .strip_prefix("ftp://")
+            .and_then(|s| s.split_once('/'))
+            .filter(|(host, path)| !host.is_empty() && !path.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("{} is not an ftp://host/path location", source))?;
+        let addr = match host.rsplit_once(':') {
+            Some((_, port)) if !host.ends_with(']') => {
+                port.parse::<u16>()
+                    .with_context(|| format!("invalid port in {}", source))?;
+                host.to_string()
+            }
+            _ => format!("{}:{}", host, DEFAULT_PORT),
+        };
+        Ok(Self {
+            addr,
+            path: format!("/{}", path),
+            settings,
+            session: None,
+            data: None,
+        })
+    }
+
+    /// returns the host name the TLS certificate of the server is verified against
+    fn host(&self) -> &str {
+        let host = self.addr.rsplit_once(':').map_or(&*self.addr, |s| s.0);
+        host.trim_start_matches('[').trim_end_matches(']')
+    }
+
+    /// wraps a connection to the server in TLS
+    ///
+    /// * `stream`: the control or data connection
+    async fn tls(&self, stream: TcpStream) -> anyhow::Result<Box<dyn Stream>> {
+        let connector = tokio_native_tls::TlsConnector::from(
+            native_tls::TlsConnector::new().with_context(|| "could not initialize TLS")?,
+        );
+        let stream = connector
+            .connect(self.host(), stream)
+            .await
+            .with_context(|| format!("TLS handshake with {} failed", self.addr))?;
+        Ok(Box::new(stream))
+    }
+
+    /// connects to the server and logs in, the transfers are binary
+    async fn login(&self) -> anyhow::Result<Session> {
+        let stream = TcpStream::connect(&self.addr)
+            .await
+            .with_context(|| format!("could not connect to FTP server {}", self.addr))?;
+        let peer = stream.peer_addr()?.ip();
+        let local = stream.local_addr()?.ip();
+        let mut control = BufReader::new(stream);
+        expect(&mut control, None, &[220]).await?;
+        let control: Box<dyn Stream> = match self.settings.tls {
+            true => {
+                expect(&mut control, Some("AUTH TLS"), &[234]).await?;
+                // the server waits for the handshake, so nothing is left in the buffer
+                self.tls(control.into_inner()).await?
+            }
+            false => Box::new(control.into_inner()),
+        };
+        let mut control = BufReader::new(control);
+
+        let user = self.settings.user.as_deref().unwrap_or(ANONYMOUS_USER);
+        let (code, _) = expect(&mut control, Some(&format!("USER {}", user)), &[230, 331]).await?;
+        if code == 331 {
+            let password = match &self.settings.password_file {
+                Some(path) => fs::read_to_string(path)
+                    .with_context(|| format!("could not read FTP password file {}", path))?
+                    .trim_end()
+                    .to_string(),
+                None => "harvester@".to_string(),
+            };
+            expect(&mut control, Some(&format!("PASS {}", password)), &[230])
+                .await
+                .with_context(|| format!("login of {} at {} failed", user, self.addr))?;
+        }
+        if self.settings.tls {
+            expect(&mut control, Some("PBSZ 0"), &[200]).await?;
+            expect(&mut control, Some("PROT P"), &[200]).await?;
+        }
+        expect(&mut control, Some("TYPE I"), &[200]).await?;
+        Ok(Session {
+            control,
+            peer,
+            local,
+        })
+    }
+
+    /// logs in and starts the transfer of the file
+    async fn retrieve(&mut self) -> anyhow::Result<()> {
+        let mut session = self.login().await?;
+        let retr = format!("RETR {}", self.path);
+        let stream = match self.settings.active {
+            true => {
+                let listener = TcpListener::bind((session.local, 0)).await?;
+                let port = port_command(listener.local_addr()?);
+                expect(&mut session.control, Some(&port), &[200]).await?;
+                expect(&mut session.control, Some(&retr), &[125, 150])
+                    .await
+                    .with_context(|| format!("ftp://{}{}", self.addr, self.path))?;
+                listener.accept().await?.0
+            }
+            false => {
+                let port = match expect(&mut session.control, Some("EPSV"), &[229]).await {
+                    Ok((_, text)) => parse_epsv(&text),
+                    // servers without IPv6 support might only know PASV
+                    Err(_) => {
+                        let (_, text) = expect(&mut session.control, Some("PASV"), &[227]).await?;
+                        parse_pasv(&text)
+                    }
+                }
+                .with_context(|| format!("FTP server {} sent no data port", self.addr))?;
+                // the address of the control connection is used, since the address in
+                // the reply of PASV is often the internal one of a server behind NAT
+                let stream = TcpStream::connect((session.peer, port)).await?;
+                expect(&mut session.control, Some(&retr), &[125, 150])
+                    .await
+                    .with_context(|| format!("ftp://{}{}", self.addr, self.path))?;
+                stream
+            }
+        };
+        self.data = Some(match self.settings.tls {
+            true => self.tls(stream).await?,
+            false => Box::new(stream),
+        });
+        self.session = Some(session);
+        Ok(())
+    }
+}
+
+/// sends a command and reads the reply, which has to have one of the expected codes
+///
+/// * `control`: the control connection
+/// * `command`: the command, None only reads the next reply
+/// * `codes`: the expected reply codes
+async fn expect<S: AsyncBufRead + AsyncWrite + Unpin>(
+    control: &mut S,
+    command: Option<&str>,
+    codes: &[u16],
+) -> anyhow::Result<(u16, String)> {
+    if let Some(command) = command {
+        control
+            .write_all(format!("{}\r\n", command).as_bytes())
+            .await?;
+        control.flush().await?;
+    }
+    let (code, text) = read_reply(control).await?;
+    if !codes.contains(&code) {
+        // the password is left out of the message
+        let command = command.map_or("connect", |c| c.split(' ').next().unwrap_or(c));
+        return Err(anyhow::anyhow!(
+            "FTP command {} failed with {} {}",
+            command,
+            code,
+            text
+        ));
+    }
+    Ok((code, text))
+}
+
+/// reads a reply of the server, multi line replies end with a line starting with the
+/// code followed by a space
+///
+/// * `control`: the control connection
+async fn read_reply<S: AsyncBufRead + Unpin>(control: &mut S) -> anyhow::Result<(u16, String)> {
+    let mut text = String::new();
+    let mut code = None;
+    loop {
+        let mut line = String::new();
+        if control.read_line(&mut line).await? == 0 {
+            return Err(anyhow::anyhow!("FTP server closed the connection"));
+        }
+        let line = line.trim_end();
+        let (line_code, rest) = match (line.get(..3), line.get(3..4)) {
+            (Some(c), Some(sep)) => (c.parse::<u16>().ok(), sep),
+            (Some(c), None) => (c.parse::<u16>().ok(), " "),
+            _ => (None, ""),
+        };
+        if code.is_none() {
+            code = line_code;
+            if code.is_none() {
+                return Err(anyhow::anyhow!("malformed FTP reply: {}", line));
+            }
+        }
+        // lines within a multi line reply don't need to start with the code
+        match line_code == code {
+            true => text.push_str(line.get(4..).unwrap_or_default()),
+            false => text.push_str(line.trim_start()),
+        }
+        if line_code == code && rest == " " {
+            return Ok((code.unwrap_or_default(), text));
+        }
+        text.push('\n');
+    }
+}
+
+/// returns the port of the reply to EPSV, e.g. `229 Entering Extended Passive Mode (|||6446|)`
+///
+/// * `text`: the text of the reply
+fn parse_epsv(text: &str) -> Option<u16> {
+    let (_, rest) = text.split_once('(')?;
+    let (inner, _) = rest.split_once(')')?;
+    inner.trim_matches('|').parse().ok()
+}
+
+/// returns the port of the reply to PASV, e.g. `227 Entering Passive Mode (192,168,1,2,25,46)`
+///
+/// * `text`: the text of the reply
+fn parse_pasv(text: &str) -> Option<u16> {
+    let start = text.find(|c: char| c.is_ascii_digit())?;
+    let numbers: Vec<u16> = text[start..]
+        .split(|c: char| !c.is_ascii_digit())
+        .take(6)
+        .map(|n| n.parse().ok())
+        .collect::<Option<_>>()?;
+    match numbers.as_slice() {
+        [_, _, _, _, p1, p2] if *p1 < 256 && *p2 < 256 => Some(p1 * 256 + p2),
+        _ => None,
+    }
+}
+
+/// returns the command announcing the address the server connects to in active mode,
+/// PORT for IPv4 and EPRT for IPv6
+///
+/// * `addr`: the address of the listening socket
+fn port_command(addr: SocketAddr) -> String {
+    match addr {
+        SocketAddr::V4(addr) => {
+            let [a, b, c, d] = addr.ip().octets();
+            format!(
+                "PORT {},{},{},{},{},{}",
+                a,
+                b,
+                c,
+                d,
+                addr.port() >> 8,
+                addr.port() & 0xff
+            )
+        }
+        SocketAddr::V6(addr) => format!("EPRT |2|{}|{}|", addr.ip(), addr.port()),
+    }
+}
+
+#[async_trait]
+impl Input for FtpInput {
+    async fn chunk(&mut self) -> anyhow::Result<Option<Bytes>> {
+        if self.data.is_none() {
+            self.retrieve().await?;
+        }
+        let mut buf = BytesMut::zeroed(CHUNK_SIZE);
+        let n = self
+            .data
+            .as_mut()
+            .unwrap()
+            .read(&mut buf)
+            .await
+            .with_context(|| format!("ftp://{}{}", self.addr, self.path))?;
+        if n == 0 {
+            // the transfer is only complete once the server confirms it
+            if let Some(mut session) = self.session.take() {
+                expect(&mut session.control, None, &[226, 250])
+                    .await
+                    .with_context(|| format!("ftp://{}{}", self.addr, self.path))?;
+                expect(&mut session.control, Some("QUIT"), &[221])
+                    .await
+                    .ok();
+            }
+            return Ok(None);
+        }
+        buf.truncate(n);
+        Ok(Some(buf.freeze()))
+    }
+
+    /// transfers the file again to read it from the beginning
+    async fn reset(&mut self) -> anyhow::Result<()> {
+        self.data = None;
+        self.session = None;
+        Ok(())
+    }
+
+    /// get the file size by the SIZE command of a separate session
+    async fn len(&mut self) -> anyhow::Result<u64> {
+        let mut session = self.login().await?;
+        let (_, text) = expect(
+            &mut session.control,
+            Some(&format!("SIZE {}", self.path)),
+            &[213],
+        )
+        .await
+        .with_context(|| format!("ftp://{}{}", self.addr, self.path))?;
+        expect(&mut session.control, Some("QUIT"), &[221])
+            .await
+            .ok();
+        text.trim()
+            .parse()
+            .with_context(|| format!("invalid size of ftp://{}{}: {}", self.addr, self.path, text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let input = FtpInput::new(
+            "ftp://ftp.example.com/feeds/malware.txt",
+            FtpSettings::default(),
+        )
+        .unwrap();
+        assert_eq!(input.addr, "ftp.example.com:21");
+        assert_eq!(input.path, "/feeds/malware.txt");
+        let input = FtpInput::new("ftp://[::1]:2121/malware.txt", FtpSettings::default()).unwrap();
+        assert_eq!(input.addr, "[::1]:2121");
+        assert_eq!(input.host(), "::1");
+        assert!(FtpInput::new("ftp://ftp.example.com", FtpSettings::default()).is_err());
+        assert!(FtpInput::new("ftp://ftp.example.com:ftp/a", FtpSettings::default()).is_err());
+    }
+
+    #[test]
+    fn test_data_ports() {
+        assert_eq!(
+            parse_epsv("Entering Extended Passive Mode (|||6446|)"),
+            Some(6446)
+        );
+        assert_eq!(
+            parse_pasv("Entering Passive Mode (192,168,1,2,25,46)"),
+            Some(25 * 256 + 46)
+        );
+        assert_eq!(parse_pasv("Entering Passive Mode"), None);
+        assert_eq!(
+            port_command("192.168.1.2:6446".parse().unwrap()),
+            "PORT 192,168,1,2,25,46"
+        );
+        assert_eq!(
+            port_command("[::1]:6446".parse().unwrap()),
+            "EPRT |2|::1|6446|"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_reply() {
+        let mut reply = "230-Welcome\r\n 230 is not the end\r\n230 Logged in\r\n".as_bytes();
+        assert_eq!(
+            read_reply(&mut reply).await.unwrap(),
+            (230, "Welcome\n230 is not the end\nLogged in".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_passive_transfer() {
+        let control = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let data = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let source = format!("ftp://{}/feeds/malware.txt", control.local_addr().unwrap());
+        let data_port = data.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (stream, _) = control.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut commands = vec![];
+            stream.write_all(b"220 ready\r\n").await.unwrap();
+            loop {
+                let mut line = String::new();
+                if stream.read_line(&mut line).await.unwrap() == 0 {
+                    break;
+                }
+                let command = line.trim_end().to_string();
+                let reply = match command.split(' ').next().unwrap() {
+                    "USER" => "331 password required".to_string(),
+                    "PASS" => "230 logged in".to_string(),
+                    "TYPE" => "200 binary".to_string(),
+                    "EPSV" => format!("229 Entering Extended Passive Mode (|||{}|)", data_port),
+                    "RETR" => "150 opening".to_string(),
+                    _ => "221 bye".to_string(),
+                };
+                stream
+                    .write_all(format!("{}\r\n", reply).as_bytes())
+                    .await
+                    .unwrap();
+                if command.starts_with("RETR") {
+                    let (mut data, _) = data.accept().await.unwrap();
+                    data.write_all(b"one.domain\ntwo.domain\n").await.unwrap();
+                    drop(data);
+                    stream.write_all(b"226 complete\r\n").await.unwrap();
+                }
+                commands.push(command);
+            }
+            commands
+        });
+
+        let mut input = FtpInput::new(&source, FtpSettings::default()).unwrap();
+        let mut content = vec![];
+        while let Some(chunk) = input.chunk().await.unwrap() {
+            content.extend_from_slice(&chunk);
+        }
+        assert_eq!(content, b"one.domain\ntwo.domain\n");
+        assert_eq!(
+            server.await.unwrap(),
+            vec![
+                "USER anonymous",
+                "PASS harvester@",
+                "TYPE I",
+                "EPSV",
+                "RETR /feeds/malware.txt",
+                "QUIT"
+            ]
+        );
+    }
+}
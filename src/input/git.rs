@@ -0,0 +1,271 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::AsyncReadExt,
+    process::{Child, ChildStdout, Command},
+};
+
+use crate::input::Input;
+
+/// ref fetched if none is configured, the default branch of the repository
+const DEFAULT_REF: &str = "HEAD";
+/// bytes read from the file at once
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// GitSettings describe which file of the repository is read
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct GitSettings {
+    /// branch, tag or commit, defaults to the default branch
+    #[serde(rename = "ref")]
+    pub reference: Option<String>,
+    /// path of the file within the repository
+    pub path: String,
+}
+
+/// GitInput reads a file of a git repository. Only the requested commit is fetched
+/// into a bare repository, which is kept between the runs.
+#[derive(Debug)]
+pub struct GitInput {
+    /// the URL of the repository
+    url: String,
+    settings: GitSettings,
+    /// the bare repository the commit is fetched into
+    repo_dir: PathBuf,
+    /// the fetched commit
+    commit: Option<String>,
+    child: Option<Child>,
+    stdout: Option<ChildStdout>,
+}
+
+impl GitInput {
+    /// Initialize a new GitInput
+    ///
+    /// * `url`: the URL of the repository, e.g. `https://github.com/org/lists.git`
+    /// * `settings`: the ref and the path of the file
+    /// * `repo_dir`: the directory of the bare repository
+    pub fn new(url: &str, settings: GitSettings, repo_dir: &Path) -> Self {
+        Self {
+            url: url.to_string(),
+            settings,
+            repo_dir: repo_dir.to_path_buf(),
+            commit: None,
+            child: None,
+            stdout: None,
+        }
+    }
+
+    /// returns a git command working on the bare repository, which never prompts for
+    /// credentials
+    ///
+    /// * `args`: the arguments of the command
+    fn git(&self, args: &[&str]) -> Command {
+        let mut command = Command::new("git");
+        command
+            .arg("-C")
+            .arg(&self.repo_dir)
+            .args(args)
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .stdin(Stdio::null())
+            .kill_on_drop(true);
+        command
+    }
+
+    /// runs a git command and returns its standard output
+    ///
+    /// * `args`: the arguments of the command
+    async fn run(&self, args: &[&str]) -> anyhow::Result<String> {
+        let output = self
+            .git(args)
+            .output()
+            .await
+            .with_context(|| "could not execute git")?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "git {} failed: {}",
+                args.first().unwrap_or(&""),
+                String::from_utf8_lossy(&output.stderr).trim_end()
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .trim_end()
+            .to_string())
+    }
+
+    /// fetches the commit of the configured ref without its history, once per input
+    async fn fetch(&mut self) -> anyhow::Result<()> {
+        if self.commit.is_some() {
+            return Ok(());
+        }
+        if !self.repo_dir.join("HEAD").exists() {
+            let status = Command::new("git")
+                .args(["init", "--quiet", "--bare"])
+                .arg(&self.repo_dir)
+                .status()
+                .await
+                .with_context(|| "could not execute git")?;
+            if !status.success() {
+                return Err(anyhow::anyhow!(
+                    "could not create repository {:?}",
+                    self.repo_dir
+                ));
+            }
+        }
+        let reference = self.settings.reference.as_deref().unwrap_or(DEFAULT_REF);
+        self.run(&[
+            "fetch",
+            "--quiet",
+            "--depth",
+            "1",
+            self.url.as_str(),
+            reference,
+        ])
+        .await
+        .with_context(|| format!("could not fetch {} of {}", reference, self.url))?;
+        let commit = self.run(&["rev-parse", "FETCH_HEAD"]).await?;
+        info!("{}: fetched {} at commit {}", self.url, reference, commit);
+        self.commit = Some(commit);
+        Ok(())
+    }
+
+    /// returns the object name of the file in the fetched commit
+    fn object(&self) -> String {
+        format!(
+            "{}:{}",
+            self.commit.as_deref().unwrap_or(DEFAULT_REF),
+            self.settings.path.trim_start_matches('/')
+        )
+    }
+
+    /// starts streaming the file of the fetched commit
+    async fn spawn(&mut self) -> anyhow::Result<()> {
+        self.fetch().await?;
+        let mut child = self
+            .git(&["cat-file", "blob", &self.object()])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| "could not execute git")?;
+        self.stdout = child.stdout.take();
+        self.child = Some(child);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Input for GitInput {
+    async fn chunk(&mut self) -> anyhow::Result<Option<Bytes>> {
+        if self.stdout.is_none() {
+            self.spawn().await?;
+        }
+        let mut buf = BytesMut::zeroed(CHUNK_SIZE);
+        let n = self
+            .stdout
+            .as_mut()
+            .with_context(|| "git has no stdout")?
+            .read(&mut buf)
+            .await?;
+        if n == 0 {
+            let status = self.child.as_mut().unwrap().wait().await?;
+            if !status.success() {
+                return Err(anyhow::anyhow!(
+                    "{} has no file {}",
+                    self.url,
+                    self.object()
+                ));
+            }
+            return Ok(None);
+        }
+        buf.truncate(n);
+        Ok(Some(buf.freeze()))
+    }
+
+    /// reads the file of the fetched commit again
+    async fn reset(&mut self) -> anyhow::Result<()> {
+        self.stdout = None;
+        self.child = None;
+        Ok(())
+    }
+
+    /// get the file size from the object in the fetched commit
+    async fn len(&mut self) -> anyhow::Result<u64> {
+        self.fetch().await?;
+        let object = self.object();
+        let size = self
+            .run(&["cat-file", "-s", &object])
+            .await
+            .with_context(|| format!("{} has no file {}", self.url, object))?;
+        size.parse()
+            .with_context(|| format!("invalid size of {}: {}", object, size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::tests::helper::cache_file_creator::TEST_CACHE;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_git_input() {
+        let dir = PathBuf::from(TEST_CACHE).join("test_git_input");
+        fs::remove_dir_all(&dir).ok();
+        let origin = dir.join("origin");
+        fs::create_dir_all(origin.join("lists")).unwrap();
+        fs::write(origin.join("lists/malware.txt"), "one.domain\ntwo.domain\n").unwrap();
+        for args in [
+            vec!["init", "--quiet"],
+            vec!["add", "."],
+            vec![
+                "-c",
+                "user.name=harvester",
+                "-c",
+                "user.email=harvester@localhost",
+                "commit",
+                "--quiet",
+                "--message",
+                "lists",
+            ],
+        ] {
+            let status = Command::new("git")
+                .arg("-C")
+                .arg(&origin)
+                .args(args)
+                .status()
+                .await
+                .unwrap();
+            assert!(status.success());
+        }
+
+        let url = format!("file://{}", fs::canonicalize(&origin).unwrap().display());
+        let settings = GitSettings {
+            reference: None,
+            path: "/lists/malware.txt".to_string(),
+        };
+        let mut input = GitInput::new(&url, settings, &dir.join("cache"));
+        assert_eq!(input.len().await.unwrap(), 22);
+        for _ in 0..2 {
+            let mut content = vec![];
+            while let Some(chunk) = input.chunk().await.unwrap() {
+                content.extend_from_slice(&chunk);
+            }
+            assert_eq!(content, b"one.domain\ntwo.domain\n");
+            input.reset().await.unwrap();
+        }
+
+        let settings = GitSettings {
+            reference: None,
+            path: "missing.txt".to_string(),
+        };
+        let mut input = GitInput::new(&url, settings, &dir.join("cache"));
+        assert!(input.chunk().await.is_err());
+    }
+}
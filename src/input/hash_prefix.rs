@@ -0,0 +1,215 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use crate::{input::format::IndicatorParser, output::hashed::hash_entry};
+
+/// the shortest prefix accepted, 4 bytes like the prefixes of Safe Browsing lists
+const MIN_PREFIX_LEN: usize = 8;
+/// the length of a hex encoded SHA-256 digest
+const FULL_HASH_LEN: usize = 64;
+
+/// HashPrefixParser resolves the SHA-256 hash prefixes of a list to the domains they
+/// were computed from. Candidates are the entries of all other lists and the domains
+/// resolved in earlier runs, which are kept in a full-hash cache.
+pub struct HashPrefixParser {
+    salt: String,
+    suffix: String,
+    /// full hashes of the candidates ordered by hash
+    hashes: Vec<(String, String)>,
+    /// file the resolved full hashes are kept in
+    cache_path: PathBuf,
+    /// full hashes resolved in this run
+    resolved: BTreeMap<String, String>,
+    /// number of prefixes read and resolved in this run
+    prefixes: usize,
+    unresolved: usize,
+}
+
+impl HashPrefixParser {
+    /// Initialize a new HashPrefixParser
+    ///
+    /// * `salt`: the salt prepended to the domains before hashing
+    /// * `suffix`: the suffix appended to the domains before hashing, e.g. `/` for the
+    ///   host expressions of Safe Browsing
+    /// * `cache_path`: the file the resolved full hashes are kept in
+    /// * `entries_dir`: the directory of the entry files of all lists
+    pub fn new(salt: &str, suffix: &str, cache_path: PathBuf, entries_dir: &Path) -> Self {
+        let hash = |domain: &str| hash_entry(salt, &format!("{}{}", domain, suffix));
+        // a damaged cache only costs the prefixes whose candidates are gone
+        let cache: BTreeMap<String, String> = fs::read_to_string(&cache_path)
+            .ok()
+            .and_then(|c| serde_json::from_str(&c).ok())
+            .unwrap_or_default();
+        // entries hashed with another salt or suffix are outdated
+        let mut hashes: Vec<(String, String)> = cache
+            .into_iter()
+            .filter(|(full_hash, domain)| hash(domain) == *full_hash)
+            .collect();
+        // the own entries would keep resolved domains alive after they left the list
+        let list_id = cache_path.file_stem().map(|s| s.to_os_string());
+        for entry in fs::read_dir(entries_dir).into_iter().flatten().flatten() {
+            if Some(entry.file_name()) == list_id || !entry.path().is_file() {
+                continue;
+            }
+            match candidates(&entry.path()) {
+                Ok(domains) => hashes.extend(domains.into_iter().map(|d| (hash(&d), d))),
+                Err(e) => warn!("{:#}", e),
+            }
+        }
+        hashes.sort();
+        hashes.dedup();
+        Self {
+            salt: salt.to_string(),
+            suffix: suffix.to_string(),
+            hashes,
+            cache_path,
+            resolved: BTreeMap::new(),
+            prefixes: 0,
+            unresolved: 0,
+        }
+    }
+
+    /// returns the candidates whose full hashes start with the prefix
+    ///
+    /// * `prefix`: the lowercase hex encoded prefix
+    fn matches(&self, prefix: &str) -> &[(String, String)] {
+        let start = self.hashes.partition_point(|(h, _)| h.as_str() < prefix);
+        let len = self.hashes[start..]
+            .iter()
+            .take_while(|(h, _)| h.starts_with(prefix))
+            .count();
+        &self.hashes[start..start + len]
+    }
+}
+
+impl IndicatorParser for HashPrefixParser {
+    fn indicators(&mut self, line: &str) -> Vec<String> {
+        let prefix = match hash_prefix(line) {
+            Some(prefix) => prefix,
+            None => return vec![],
+        };
+        self.prefixes += 1;
+        let (full_hash, domain) = match self.matches(&prefix) {
+            [candidate] => candidate.clone(),
+            matches => {
+                // a short prefix may collide, guessing could block an innocent domain
+                if matches.len() > 1 {
+                    debug!("{} matches {} candidates", prefix, matches.len());
+                }
+                self.unresolved += 1;
+                return vec![];
+            }
+        };
+        self.resolved.insert(full_hash, domain.clone());
+        vec![domain]
+    }
+
+    /// writes the resolved full hashes to the cache file
+    fn finish(&mut self) -> anyhow::Result<Vec<String>> {
+        info!(
+            "{} of {} hash prefixes resolved (salt {:?}, suffix {:?})",
+            self.prefixes - self.unresolved,
+            self.prefixes,
+            self.salt,
+            self.suffix
+        );
+        if let Some(parent) = self.cache_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp_path = self.cache_path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_string(&self.resolved)?)
+            .with_context(|| format!("could not write {:?}", tmp_path))?;
+        fs::rename(&tmp_path, &self.cache_path)
+            .with_context(|| format!("could not write {:?}", self.cache_path))?;
+        Ok(vec![])
+    }
+}
+
+/// returns the lowercase hash prefix of a line, the prefix may be followed by further
+/// fields separated by white space, a colon or a comma
+///
+/// * `line`: a line of the list
+fn hash_prefix(line: &str) -> Option<String> {
+    let prefix = line
+        .trim()
+        .split(|c: char| c.is_whitespace() || c == ':' || c == ',')
+        .next()?
+        .to_lowercase();
+    ((MIN_PREFIX_LEN..=FULL_HASH_LEN).contains(&prefix.len())
+        && prefix.bytes().all(|b| b.is_ascii_hexdigit()))
+    .then_some(prefix)
+}
+
+/// returns the entries of an entry file which can be domains
+///
+/// * `path`: the file system path of the entry file
+fn candidates(path: &Path) -> anyhow::Result<Vec<String>> {
+    let file = File::open(path).with_context(|| format!("could not open {:?}", path))?;
+    let mut domains = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.contains('.') {
+            domains.push(line);
+        }
+    }
+    Ok(domains)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::helper::cache_file_creator::TEST_CACHE;
+
+    use super::*;
+
+    #[test]
+    fn test_hash_prefix() {
+        let full = hash_entry("", "example.com/");
+        assert_eq!(hash_prefix(&full[..8]), Some(full[..8].to_string()));
+        assert_eq!(
+            hash_prefix(&format!("{}:32:malware", full[..8].to_uppercase())),
+            Some(full[..8].to_string())
+        );
+        assert_eq!(hash_prefix(&full), Some(full.clone()));
+        assert_eq!(hash_prefix("abc"), None);
+        assert_eq!(hash_prefix("# comment"), None);
+        assert_eq!(hash_prefix("example.com"), None);
+    }
+
+    #[test]
+    fn test_resolve_prefixes() {
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_resolve_prefixes");
+        let entries_dir = dir.join("entries");
+        fs::create_dir_all(&entries_dir).unwrap();
+        fs::write(entries_dir.join("other"), "evil.com\nbad.org\n").unwrap();
+        let cache_path = dir.join("hashed.hashes");
+        fs::remove_file(&cache_path).ok();
+
+        let prefix = |domain: &str| hash_entry("salt", &format!("{}/", domain))[..8].to_string();
+        let list = format!(
+            "{}\n{}\n{}\n",
+            prefix("evil.com"),
+            prefix("unknown.net"),
+            "# comment"
+        );
+        let mut parser = HashPrefixParser::new("salt", "/", cache_path.clone(), &entries_dir);
+        let got: Vec<String> = list.lines().flat_map(|l| parser.indicators(l)).collect();
+        assert_eq!(got, vec!["evil.com"]);
+        parser.finish().unwrap();
+
+        // the cache resolves the prefix after its candidate is gone
+        fs::remove_file(entries_dir.join("other")).unwrap();
+        let mut parser = HashPrefixParser::new("salt", "/", cache_path.clone(), &entries_dir);
+        assert_eq!(parser.indicators(&prefix("evil.com")), vec!["evil.com"]);
+        assert!(parser.indicators(&prefix("bad.org")).is_empty());
+        // a new salt invalidates the cache
+        let mut parser = HashPrefixParser::new("pepper", "/", cache_path, &entries_dir);
+        assert!(parser.indicators(&prefix("evil.com")).is_empty());
+    }
+}
@@ -0,0 +1,184 @@
+use anyhow::Context;
+use serde_json::Value;
+
+use crate::input::format::IndicatorParser;
+
+/// JsonParser reads the indicators selected by a JSONPath from a JSON document or from
+/// every document of a JSON Lines file
+#[derive(Debug)]
+pub struct JsonParser {
+    path: String,
+    /// the lines read so far, the document is parsed once it's complete
+    content: String,
+}
+
+impl JsonParser {
+    /// Initialize a new JsonParser
+    ///
+    /// * `path`: the JSONPath selecting the indicators
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            content: String::new(),
+        }
+    }
+
+    /// returns the indicators selected in a document
+    ///
+    /// * `document`: the parsed document
+    fn select(&self, document: &Value) -> anyhow::Result<Vec<String>> {
+        Ok(json_path(document, &self.path)?
+            .into_iter()
+            .filter_map(scalar)
+            .collect())
+    }
+}
+
+impl IndicatorParser for JsonParser {
+    fn indicators(&mut self, line: &str) -> Vec<String> {
+        self.content.push_str(line);
+        self.content.push('\n');
+        vec![]
+    }
+
+    /// parses the content as one document or as JSON Lines
+    fn finish(&mut self) -> anyhow::Result<Vec<String>> {
+        let content = std::mem::take(&mut self.content);
+        if let Ok(document) = serde_json::from_str::<Value>(&content) {
+            return self.select(&document);
+        }
+        let mut indicators = vec![];
+        for (i, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let document: Value = serde_json::from_str(line)
+                .with_context(|| format!("line {} is no JSON document", i + 1))?;
+            indicators.extend(self.select(&document)?);
+        }
+        Ok(indicators)
+    }
+}
+
+/// returns a string or number as string
+///
+/// * `value`: a JSON value
+pub fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// returns the values selected by a JSONPath, supported are child names (`.name`,
+/// `['name']`), array indexes (`[0]`) and wildcards (`.*`, `[*]`)
+///
+/// * `value`: the JSON document
+/// * `path`: the JSONPath starting with `$`
+pub fn json_path<'a>(value: &'a Value, path: &str) -> anyhow::Result<Vec<&'a Value>> {
+    let mut rest = path
+        .trim()
+        .strip_prefix('$')
+        .with_context(|| format!("JSONPath {} doesn't start with $", path))?;
+    let mut selected = vec![value];
+    while !rest.is_empty() {
+        let (selector, tail) = if let Some(tail) = rest.strip_prefix("..") {
+            return Err(anyhow::anyhow!(
+                "JSONPath {}: recursive descent at ..{} is not supported",
+                path,
+                tail
+            ));
+        } else if let Some(tail) = rest.strip_prefix('.') {
+            let end = tail.find(['.', '[']).unwrap_or(tail.len());
+            (&tail[..end], &tail[end..])
+        } else if let Some(tail) = rest.strip_prefix('[') {
+            let end = tail
+                .find(']')
+                .with_context(|| format!("JSONPath {} misses a ]", path))?;
+            (&tail[..end], &tail[end + 1..])
+        } else {
+            return Err(anyhow::anyhow!(
+                "JSONPath {} is malformed at {}",
+                path,
+                rest
+            ));
+        };
+        let name = selector.trim_matches(|c| c == '\'' || c == '"');
+        selected = selected
+            .into_iter()
+            .flat_map(|v| -> Vec<&Value> {
+                match (selector, v) {
+                    ("*", Value::Array(items)) => items.iter().collect(),
+                    ("*", Value::Object(map)) => map.values().collect(),
+                    (_, Value::Array(items)) => selector
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|i| items.get(i))
+                        .into_iter()
+                        .collect(),
+                    (_, Value::Object(map)) => map.get(name).into_iter().collect(),
+                    _ => vec![],
+                }
+            })
+            .collect();
+        rest = tail;
+    }
+    Ok(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_json_path() {
+        let doc = json!({"data": [{"domain": "one.domain"}, {"domain": "two.domain", "ip": 1}],
+                         "meta": {"next": "abc"}});
+        let strings = |path: &str| -> Vec<String> {
+            json_path(&doc, path)
+                .unwrap()
+                .into_iter()
+                .filter_map(scalar)
+                .collect()
+        };
+        assert_eq!(
+            strings("$.data[*].domain"),
+            vec!["one.domain", "two.domain"]
+        );
+        assert_eq!(strings("$['data'][1].domain"), vec!["two.domain"]);
+        assert_eq!(strings("$.data[*].ip"), vec!["1"]);
+        assert_eq!(strings("$.meta.*"), vec!["abc"]);
+        assert!(strings("$.missing[0]").is_empty());
+        assert!(json_path(&doc, "data").is_err());
+        assert!(json_path(&doc, "$..domain").is_err());
+    }
+
+    #[test]
+    fn test_json_parser() {
+        let mut parser = JsonParser::new("$.data[*].attributes.value");
+        let document = r#"{"data": [
+  {"type": "domain", "attributes": {"value": "one.domain"}},
+  {"type": "ip", "attributes": {"value": "192.0.2.1"}}
+]}"#;
+        for line in document.lines() {
+            assert!(parser.indicators(line).is_empty());
+        }
+        assert_eq!(parser.finish().unwrap(), vec!["one.domain", "192.0.2.1"]);
+
+        let mut parser = JsonParser::new("$.domain");
+        for line in [
+            "{\"domain\": \"one.domain\"}",
+            "",
+            "{\"domain\": \"two.domain\"}",
+        ] {
+            parser.indicators(line);
+        }
+        assert_eq!(parser.finish().unwrap(), vec!["one.domain", "two.domain"]);
+
+        parser.indicators("not json");
+        assert!(parser.finish().is_err());
+    }
+}
@@ -0,0 +1,133 @@
+use anyhow::Context;
+use bytes::{Bytes, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// longest line a list may contain, longer ones are most likely binary data
+pub const MAX_LINE_LEN: usize = 64 * 1024;
+/// default number of bytes read from a source at once
+pub const DEFAULT_READ_SIZE: usize = 64 * 1024;
+
+/// LineBuffer frames the data read from a source into whole lines, no matter where
+/// the reads split the data, so no entry is cut in two. The lines share the buffer
+/// the data was read into instead of being copied.
+#[derive(Debug)]
+pub struct LineBuffer {
+    buf: BytesMut,
+    /// number of bytes read from a source at once
+    read_size: usize,
+}
+
+impl Default for LineBuffer {
+    fn default() -> Self {
+        Self::with_read_size(DEFAULT_READ_SIZE)
+    }
+}
+
+impl LineBuffer {
+    /// creates an empty line buffer
+    ///
+    /// * `read_size`: number of bytes read from a source at once
+    pub fn with_read_size(read_size: usize) -> Self {
+        Self {
+            buf: BytesMut::new(),
+            read_size: read_size.max(1),
+        }
+    }
+
+    /// returns the next complete line without its line break, None if the buffered
+    /// data doesn't contain one
+    fn next_line(&mut self) -> anyhow::Result<Option<Bytes>> {
+        // memchr scans a whole vector register of bytes per step
+        match memchr::memchr(b'\n', &self.buf) {
+            Some(pos) => {
+                let mut line = self.buf.split_to(pos + 1);
+                line.truncate(pos);
+                Ok(Some(line.freeze()))
+            }
+            None if self.buf.len() > MAX_LINE_LEN => Err(anyhow::anyhow!(
+                "Error reading chunk from file: line length exceeds {} bytes",
+                MAX_LINE_LEN
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// returns the rest of the data once the source is exhausted, which is the last
+    /// line if it lacks a line break
+    fn finish(&mut self) -> Option<Bytes> {
+        (!self.buf.is_empty()).then(|| self.buf.split().freeze())
+    }
+
+    /// discards the buffered data, e.g. when the source is read from the start again
+    pub fn clear(&mut self) {
+        self.buf.clear();
+    }
+
+    /// returns the next whole line of a reader, None at its end
+    ///
+    /// * `reader`: the source of the data
+    pub async fn read_line(
+        &mut self,
+        reader: &mut (impl AsyncRead + Unpin),
+    ) -> anyhow::Result<Option<Bytes>> {
+        loop {
+            if let Some(line) = self.next_line()? {
+                return Ok(Some(line));
+            }
+            // the data is read right behind the buffered rest of the last line
+            self.buf.reserve(self.read_size);
+            let n = (&mut *reader)
+                .take(self.read_size as u64)
+                .read_buf(&mut self.buf)
+                .await
+                .with_context(|| "Error reading chunk from file")?;
+            if n == 0 {
+                return Ok(self.finish());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// returns all lines of the data read in blocks of the size
+    async fn read_lines(data: &[u8], read_size: usize) -> anyhow::Result<Vec<String>> {
+        let mut reader = data;
+        let mut lines = LineBuffer::with_read_size(read_size);
+        let mut read = vec![];
+        while let Some(line) = lines.read_line(&mut reader).await? {
+            read.push(String::from_utf8(line.to_vec())?);
+        }
+        Ok(read)
+    }
+
+    #[tokio::test]
+    async fn test_read_line() {
+        // a line longer than a read and a last line without line break
+        let long = "a".repeat(DEFAULT_READ_SIZE + 10);
+        let data = format!("one.domain\n{}\n\nlast.domain", long);
+        assert_eq!(
+            read_lines(data.as_bytes(), DEFAULT_READ_SIZE)
+                .await
+                .unwrap(),
+            vec!["one.domain", &long, "", "last.domain"]
+        );
+
+        let data = vec![b'a'; MAX_LINE_LEN + DEFAULT_READ_SIZE];
+        assert!(read_lines(&data, DEFAULT_READ_SIZE).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_split_chunks() {
+        // reads of a few bytes split nearly every line
+        let data = b"one.domain\ntwo.domain\n\nthree";
+        for read_size in [1, 3, 4, 64] {
+            assert_eq!(
+                read_lines(data, read_size).await.unwrap(),
+                vec!["one.domain", "two.domain", "", "three"]
+            );
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::BTreeSet;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+
+use crate::{input::Input, output::lint::is_valid_hostname};
+
+/// neighbouring keys on a QWERTY keyboard
+const KEYBOARD: [(char, &str); 36] = [
+    ('1', "2q"),
+    ('2', "13wq"),
+    ('3', "24ew"),
+    ('4', "35re"),
+    ('5', "46tr"),
+    ('6', "57yt"),
+    ('7', "68uy"),
+    ('8', "79iu"),
+    ('9', "80oi"),
+    ('0', "9po"),
+    ('q', "12wa"),
+    ('w', "3esaq2"),
+    ('e', "4rdsw3"),
+    ('r', "5tfde4"),
+    ('t', "6ygfr5"),
+    ('y', "7uhgt6"),
+    ('u', "8ijhy7"),
+    ('i', "9okju8"),
+    ('o', "0plki9"),
+    ('p', "lo0"),
+    ('a', "qwsz"),
+    ('s', "edxzaw"),
+    ('d', "rfcxse"),
+    ('f', "tgvcdr"),
+    ('g', "yhbvft"),
+    ('h', "ujnbgy"),
+    ('j', "ikmnhu"),
+    ('k', "olmji"),
+    ('l', "kop"),
+    ('z', "asx"),
+    ('x', "zsdc"),
+    ('c', "xdfv"),
+    ('v', "cfgb"),
+    ('b', "vghn"),
+    ('n', "bhjm"),
+    ('m', "njk"),
+];
+
+/// character sequences looking alike in ASCII
+const HOMOGLYPHS: [(&str, &str); 16] = [
+    ("o", "0"),
+    ("0", "o"),
+    ("l", "1"),
+    ("l", "i"),
+    ("i", "1"),
+    ("i", "l"),
+    ("1", "l"),
+    ("m", "rn"),
+    ("rn", "m"),
+    ("w", "vv"),
+    ("vv", "w"),
+    ("d", "cl"),
+    ("cl", "d"),
+    ("g", "q"),
+    ("q", "g"),
+    ("e", "3"),
+];
+
+const VOWELS: &str = "aeiou";
+
+/// returns the lookalike permutations of a domain, only the labels before the top
+/// level domain are changed
+///
+/// * `domain`: the seed domain
+pub fn permutations(domain: &str) -> BTreeSet<String> {
+    let domain = domain.trim().trim_end_matches('.').to_lowercase();
+    let (name, tld) = match domain.rsplit_once('.') {
+        Some(split) => split,
+        None => return BTreeSet::new(),
+    };
+    let chars: Vec<char> = name.chars().collect();
+    let mut names: BTreeSet<String> = BTreeSet::new();
+    let join = |parts: &[&[char]]| parts.iter().flat_map(|p| p.iter()).collect::<String>();
+
+    for i in 0..chars.len() {
+        let (before, c, after) = (&chars[..i], chars[i], &chars[i + 1..]);
+        // omission and repetition
+        names.insert(join(&[before, after]));
+        names.insert(join(&[before, &[c, c], after]));
+        // transposition
+        if i + 1 < chars.len() {
+            names.insert(join(&[before, &[chars[i + 1], c], &chars[i + 2..]]));
+        }
+        // hyphenation
+        if i > 0 {
+            names.insert(join(&[before, &['-'], &chars[i..]]));
+        }
+        // replacement and insertion of neighbouring keys
+        if let Some((_, keys)) = KEYBOARD.iter().find(|(k, _)| *k == c) {
+            for key in keys.chars() {
+                names.insert(join(&[before, &[key], after]));
+                names.insert(join(&[before, &[key, c], after]));
+                names.insert(join(&[before, &[c, key], after]));
+            }
+        }
+        // vowel swap
+        if VOWELS.contains(c) {
+            for vowel in VOWELS.chars().filter(|v| *v != c) {
+                names.insert(join(&[before, &[vowel], after]));
+            }
+        }
+        // bitsquatting
+        for bit in 0..8 {
+            let flipped = (c as u8 ^ (1 << bit)) as char;
+            if flipped.is_ascii_lowercase() || flipped.is_ascii_digit() {
+                names.insert(join(&[before, &[flipped], after]));
+            }
+        }
+    }
+    // homoglyphs
+    for (from, to) in HOMOGLYPHS {
+        for (i, _) in name.match_indices(from) {
+            names.insert(format!("{}{}{}", &name[..i], to, &name[i + from.len()..]));
+        }
+    }
+    // addition
+    for c in 'a'..='z' {
+        names.insert(format!("{}{}", name, c));
+    }
+
+    names
+        .into_iter()
+        .map(|n| format!("{}.{}", n, tld))
+        .filter(|d| *d != domain && is_valid_hostname(d))
+        .filter(|d| !d.split('.').any(|l| l.starts_with('-') || l.ends_with('-')))
+        .collect()
+}
+
+/// LookalikeInput generates the lookalike permutations of brand domains, e.g. typo
+/// squatting and homoglyph domains, one domain per line
+#[derive(Debug)]
+pub struct LookalikeInput {
+    lines: Vec<String>,
+    position: usize,
+}
+
+impl LookalikeInput {
+    /// Initialize a new LookalikeInput
+    ///
+    /// * `seeds`: the seed domains separated by commas or white space
+    pub fn new(seeds: &str) -> Self {
+        let domains: BTreeSet<String> = seeds
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|s| !s.is_empty())
+            .flat_map(permutations)
+            .collect();
+        Self {
+            lines: domains.into_iter().map(|d| d + "\n").collect(),
+            position: 0,
+        }
+    }
+}
+
+#[async_trait]
+impl Input for LookalikeInput {
+    async fn chunk(&mut self) -> anyhow::Result<Option<Bytes>> {
+        let line = self
+            .lines
+            .get(self.position)
+            .map(|l| Bytes::copy_from_slice(l.as_bytes()));
+        self.position += 1;
+        Ok(line)
+    }
+
+    async fn reset(&mut self) -> anyhow::Result<()> {
+        self.position = 0;
+        Ok(())
+    }
+
+    /// the permutations are deterministic, so their length tells if they changed
+    async fn len(&mut self) -> anyhow::Result<u64> {
+        Ok(self.lines.iter().map(|l| l.len() as u64).sum())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permutations() {
+        let domains = permutations("Example.com.");
+        for expected in [
+            "exmple.com",
+            "exaample.com",
+            "examlpe.com",
+            "ex-ample.com",
+            "exanple.com",
+            "examp1e.com",
+            "exampie.com",
+            "examplem.com",
+            "examplea.com",
+            "exampke.com",
+            "ixample.com",
+        ] {
+            assert!(domains.contains(expected), "{} is missing", expected);
+        }
+        assert!(!domains.contains("example.com"));
+        assert!(domains.iter().all(|d| is_valid_hostname(d)));
+        assert!(domains.iter().all(|d| d.ends_with(".com")));
+        assert!(permutations("localhost").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_lookalike_input() {
+        let mut input = LookalikeInput::new("ab.com, cd.org");
+        let len = input.len().await.unwrap();
+        let mut read = 0;
+        while let Some(chunk) = input.chunk().await.unwrap() {
+            assert!(chunk.ends_with(b"\n"));
+            read += chunk.len() as u64;
+        }
+        assert_eq!(read, len);
+        input.reset().await.unwrap();
+        assert!(input.chunk().await.unwrap().is_some());
+    }
+}
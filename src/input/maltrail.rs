@@ -0,0 +1,81 @@
+use std::net::IpAddr;
+
+use crate::input::format::{indicator, IndicatorParser};
+
+/// TrailParser reads the trails of Maltrail trail files, i.e. the static trails
+/// (`domain`, `ip`, `ip:port` or `domain/path` per line) and the `trails.csv` of a
+/// Maltrail installation (`trail,info,reference`)
+#[derive(Debug, Default)]
+pub struct TrailParser;
+
+impl IndicatorParser for TrailParser {
+    fn indicators(&mut self, line: &str) -> Vec<String> {
+        trail(line).into_iter().collect()
+    }
+}
+
+/// returns the domain or address a trail refers to
+///
+/// * `line`: a line of a trail file
+fn trail(line: &str) -> Option<String> {
+    let line = line.split('#').next()?.trim();
+    let trail = line.split(',').next()?.trim().trim_matches('"');
+    let trail = trail
+        .strip_prefix("http://")
+        .or_else(|| trail.strip_prefix("https://"))
+        .unwrap_or(trail);
+    // networks keep their prefix length, URL trails are reduced to their host
+    if let Some(network) = indicator(trail).filter(|_| trail.contains('/')) {
+        return Some(network);
+    }
+    let host = trail.split('/').next()?;
+    let host = match host.rsplit_once(':') {
+        Some((address, port)) if address.parse::<IpAddr>().is_ok() || !address.contains(':') => {
+            port.parse::<u16>().ok().map(|_| address)?
+        }
+        _ => host,
+    };
+    indicator(host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trail() {
+        let trails = r#"# Copyright (c) 2014-2022 Maltrail developers
+# Reference: https://example.org/report
+
+evil-domain.com
+sub.Evil-Domain.com # comment
+192.0.2.1
+192.0.2.2:8080
+198.51.100.0/24
+evil.org/gate.php
+http://evil.net:8080/panel/
+2001:db8::1
+"bad.trail","malware","(static)"
+bad.example,malware (static),example.org
+"#;
+        let got: Vec<String> = trails
+            .lines()
+            .flat_map(|l| TrailParser.indicators(l))
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                "evil-domain.com",
+                "sub.evil-domain.com",
+                "192.0.2.1",
+                "192.0.2.2",
+                "198.51.100.0/24",
+                "evil.org",
+                "evil.net",
+                "2001:db8::1",
+                "bad.trail",
+                "bad.example"
+            ]
+        );
+    }
+}
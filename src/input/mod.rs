@@ -1,13 +1,39 @@
+pub(crate) mod api;
+pub(crate) mod axfr;
+pub(crate) mod azure;
+pub(crate) mod command;
+pub(crate) mod dir;
+pub(crate) mod empty;
 pub(crate) mod file;
+pub(crate) mod format;
+pub(crate) mod ftp;
+pub(crate) mod gcs;
+pub(crate) mod git;
+pub(crate) mod hash_prefix;
+pub(crate) mod json;
+pub(crate) mod lines;
+pub(crate) mod lookalike;
+pub(crate) mod maltrail;
+pub(crate) mod rpz;
+pub(crate) mod s3;
+pub(crate) mod sftp;
+pub(crate) mod source;
+pub(crate) mod suricata;
 pub(crate) mod url;
+pub(crate) mod xml;
+pub(crate) mod zone;
 
 use async_trait::async_trait;
+use bytes::Bytes;
 
 /// Input is the trait all input sources must implement
 #[async_trait]
 pub trait Input {
-    /// input sources are supposed to provide the data chunk wise
-    async fn chunk(&mut self) -> anyhow::Result<Option<Vec<u8>>>;
+    /// input sources are supposed to provide the data chunk wise. Inputs the entries
+    /// are extracted from return whole lines without their line break, so no entry is
+    /// split between chunks. Chunks are shared buffers, so inputs hand on the data they received without
+    /// copying it.
+    async fn chunk(&mut self) -> anyhow::Result<Option<Bytes>>;
 
     /// start reading from the beginning
     async fn reset(&mut self) -> anyhow::Result<()>;
@@ -0,0 +1,108 @@
+use crate::input::{format::IndicatorParser, zone::ZoneParser};
+
+/// CNAME targets of policy records letting the name through instead of blocking it
+const NON_BLOCKING_ACTIONS: [&str; 2] = ["rpz-passthru.", "rpz-tcp-only."];
+
+/// RpzParser extracts the blocked domains from a response policy zone file in the
+/// format used by BIND. Names answered with NXDOMAIN (`CNAME .`) or NODATA
+/// (`CNAME *.`), dropped or rewritten to local data are blocked, passthru rules aren't.
+/// Triggers on response, client and name server addresses and on name server names
+/// are skipped, wildcard triggers yield the domain they cover.
+#[derive(Debug)]
+pub struct RpzParser {
+    zone: ZoneParser,
+    /// the last domain returned, so a name and its wildcard yield it once
+    last_domain: Option<String>,
+}
+
+impl Default for RpzParser {
+    fn default() -> Self {
+        Self {
+            zone: ZoneParser::new(true),
+            last_domain: None,
+        }
+    }
+}
+
+impl RpzParser {
+    /// returns the domain blocked by the record starting at the line, None if the
+    /// line doesn't start a blocking record or its domain was returned just before
+    ///
+    /// * `line`: a line of the zone file
+    pub fn parse_line(&mut self, line: &str) -> Option<String> {
+        let record = self.zone.parse_record(line)?;
+        // the triggers other than query names end with a label like rpz-ip
+        if record.name.rsplit('.').next()?.starts_with("rpz-") {
+            return None;
+        }
+        let action = record.data.first().map(|a| a.to_lowercase());
+        if record.record_type == "CNAME"
+            && action.is_some_and(|a| NON_BLOCKING_ACTIONS.contains(&a.as_str()))
+        {
+            return None;
+        }
+
+        let domain = record.name.strip_prefix("*.").unwrap_or(&record.name);
+        if self.last_domain.as_deref() == Some(domain) {
+            return None;
+        }
+        self.last_domain = Some(domain.to_string());
+        Some(domain.to_string())
+    }
+}
+
+impl IndicatorParser for RpzParser {
+    fn indicators(&mut self, line: &str) -> Vec<String> {
+        self.parse_line(line).into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RPZ: &str = r#"$TTL 300
+$ORIGIN rpz.local.
+@   IN SOA localhost. root.localhost. (
+        2023010101 3600 600 86400 300 )
+    IN NS localhost.
+bad.com             CNAME .
+*.bad.com           CNAME .
+nodata.example      CNAME *.
+*.wild.example      CNAME .
+good.bad.com        CNAME rpz-passthru.
+slow.example        CNAME rpz-tcp-only.
+dropped.example     CNAME rpz-drop.
+walled.example      CNAME garden.example.
+local.example       A 10.0.0.1
+                    AAAA ::1
+32.1.0.0.127.rpz-ip CNAME .
+ns.evil.rpz-nsdname CNAME .
+"#;
+
+    #[test]
+    fn test_parse_rpz() {
+        let mut parser = RpzParser::default();
+        let domains: Vec<String> = RPZ.lines().filter_map(|l| parser.parse_line(l)).collect();
+        assert_eq!(
+            domains,
+            vec![
+                "bad.com",
+                "nodata.example",
+                "wild.example",
+                "dropped.example",
+                "walled.example",
+                "local.example"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dumped_rpz() {
+        // zones dumped by a server use absolute names without $ORIGIN
+        let rpz = "rpz.local. 300 IN SOA localhost. root.localhost. 1 3600 600 86400 300\nrpz.local. 300 IN NS localhost.\nBad.com.rpz.local. 300 IN CNAME .\n*.bad.com.rpz.local. 300 IN CNAME .\n";
+        let mut parser = RpzParser::default();
+        let domains: Vec<String> = rpz.lines().flat_map(|l| parser.indicators(l)).collect();
+        assert_eq!(domains, vec!["bad.com"]);
+    }
+}
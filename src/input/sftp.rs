@@ -0,0 +1,293 @@
+use std::{
+    env, fs,
+    io::Read,
+    net::TcpStream,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use serde::{Deserialize, Serialize};
+use ssh2::{CheckResult, KnownHostFileKind, Session};
+
+use crate::input::Input;
+
+/// port of the SSH server if none is part of the source
+const DEFAULT_PORT: u16 = 22;
+/// bytes read from the file at once
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// SftpSettings describe how the SSH server is accessed, without key and password the
+/// keys of the SSH agent are tried
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SftpSettings {
+    /// user logging in, defaults to `USER`
+    pub user: Option<String>,
+    /// private key of the user
+    pub key_file: Option<String>,
+    /// file containing the passphrase of the private key
+    pub passphrase_file: Option<String>,
+    /// file containing the password of the user
+    pub password_file: Option<String>,
+    /// known hosts the key of the server is verified against, defaults to
+    /// `~/.ssh/known_hosts`
+    pub known_hosts_file: Option<String>,
+}
+
+/// Transfer is an authenticated session reading the file
+struct Transfer {
+    /// the file keeps the channel of the session open
+    file: ssh2::File,
+    _session: Session,
+}
+
+/// SftpInput reads a file from an SSH server by SFTP
+pub struct SftpInput {
+    host: String,
+    port: u16,
+    path: String,
+    settings: SftpSettings,
+    transfer: Option<Transfer>,
+}
+
+impl std::fmt::Debug for SftpInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SftpInput")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("path", &self.path)
+            .field("settings", &self.settings)
+            .finish()
+    }
+}
+
+impl SftpInput {
+    /// Initialize a new SftpInput
+    ///
+    /// * `source`: the location of the file, e.g. `sftp://partner.example.com/feeds/malware.txt`,
+    ///   paths starting with `/~/` are relative to the home directory
+    /// * `settings`: the user and its credentials
+    pub fn new(source: &str, settings: SftpSettings) -> anyhow::Result<Self> {
+        let (authority, path) = source
+            .strip_prefix("sftp://")
+            .and_then(|s| s.split_once('/'))
+            .filter(|(authority, path)| !authority.is_empty() && !path.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("{} is not an sftp://host/path location", source))?;
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !authority.ends_with(']') => (
+                host,
+                port.parse()
+                    .with_context(|| format!("invalid port in {}", source))?,
+            ),
+            _ => (authority, DEFAULT_PORT),
+        };
+        let path = match path.strip_prefix("~/") {
+            Some(relative) => relative.to_string(),
+            None => format!("/{}", path),
+        };
+        Ok(Self {
+            host: host
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .to_string(),
+            port,
+            path,
+            settings,
+            transfer: None,
+        })
+    }
+
+    /// returns the location of the file for messages
+    fn location(&self) -> String {
+        match self.path.starts_with('/') {
+            true => format!("sftp://{}:{}{}", self.host, self.port, self.path),
+            false => format!("sftp://{}:{}/~/{}", self.host, self.port, self.path),
+        }
+    }
+
+    /// connects to the server, verifies its key and authenticates the user
+    fn connect(host: &str, port: u16, settings: &SftpSettings) -> anyhow::Result<Session> {
+        let tcp = TcpStream::connect((host, port))
+            .with_context(|| format!("could not connect to SSH server {}:{}", host, port))?;
+        let mut session = Session::new()?;
+        session.set_tcp_stream(tcp);
+        session
+            .handshake()
+            .with_context(|| format!("SSH handshake with {} failed", host))?;
+        verify_host_key(&session, host, port, settings)?;
+
+        let user = match &settings.user {
+            Some(user) => user.clone(),
+            None => env::var("USER").with_context(|| "no SFTP user configured")?,
+        };
+        if let Some(key_file) = &settings.key_file {
+            let passphrase = settings
+                .passphrase_file
+                .as_ref()
+                .map(|path| read_secret(path))
+                .transpose()?;
+            session
+                .userauth_pubkey_file(&user, None, Path::new(key_file), passphrase.as_deref())
+                .with_context(|| {
+                    format!("key {} of {} was rejected by {}", key_file, user, host)
+                })?;
+        } else if let Some(password_file) = &settings.password_file {
+            session
+                .userauth_password(&user, &read_secret(password_file)?)
+                .with_context(|| format!("password of {} was rejected by {}", user, host))?;
+        } else {
+            session
+                .userauth_agent(&user)
+                .with_context(|| format!("no key of the SSH agent was accepted for {}", user))?;
+        }
+        Ok(session)
+    }
+
+    /// opens the file in a new session
+    fn open(
+        host: &str,
+        port: u16,
+        path: &str,
+        settings: &SftpSettings,
+    ) -> anyhow::Result<Transfer> {
+        let session = Self::connect(host, port, settings)?;
+        let file = session.sftp()?.open(Path::new(path))?;
+        Ok(Transfer {
+            file,
+            _session: session,
+        })
+    }
+}
+
+/// returns the content of a file containing a secret without the trailing new line
+///
+/// * `path`: the path of the file
+fn read_secret(path: &str) -> anyhow::Result<String> {
+    Ok(fs::read_to_string(path)
+        .with_context(|| format!("could not read {}", path))?
+        .trim_end()
+        .to_string())
+}
+
+/// checks the key of the server against the known hosts, unknown and changed keys are
+/// rejected
+///
+/// * `session`: the session after the handshake
+/// * `host`: the host name of the server
+/// * `port`: the port of the server
+/// * `settings`: the configured known hosts file
+fn verify_host_key(
+    session: &Session,
+    host: &str,
+    port: u16,
+    settings: &SftpSettings,
+) -> anyhow::Result<()> {
+    let known_hosts_file = match &settings.known_hosts_file {
+        Some(path) => PathBuf::from(path),
+        None => PathBuf::from(env::var("HOME").with_context(|| "no known hosts file found")?)
+            .join(".ssh")
+            .join("known_hosts"),
+    };
+    let mut known_hosts = session.known_hosts()?;
+    known_hosts
+        .read_file(&known_hosts_file, KnownHostFileKind::OpenSSH)
+        .with_context(|| format!("could not read known hosts {:?}", known_hosts_file))?;
+    let (key, _) = session
+        .host_key()
+        .with_context(|| format!("{} sent no host key", host))?;
+    match known_hosts.check_port(host, port, key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::Mismatch => Err(anyhow::anyhow!(
+            "host key of {} doesn't match {:?}",
+            host,
+            known_hosts_file
+        )),
+        CheckResult::NotFound | CheckResult::Failure => Err(anyhow::anyhow!(
+            "host key of {} isn't in {:?}",
+            host,
+            known_hosts_file
+        )),
+    }
+}
+
+#[async_trait]
+impl Input for SftpInput {
+    /// reads the next chunk of the file, the blocking SSH calls run on the blocking
+    /// threads of the runtime
+    async fn chunk(&mut self) -> anyhow::Result<Option<Bytes>> {
+        let transfer = self.transfer.take();
+        let (host, port, path) = (self.host.clone(), self.port, self.path.clone());
+        let settings = self.settings.clone();
+        let (transfer, chunk) = tokio::task::spawn_blocking(move || {
+            let mut transfer = match transfer {
+                Some(transfer) => transfer,
+                None => match Self::open(&host, port, &path, &settings) {
+                    Ok(transfer) => transfer,
+                    Err(e) => return (None, Err(e)),
+                },
+            };
+            let mut buf = BytesMut::zeroed(CHUNK_SIZE);
+            let chunk = transfer.file.read(&mut buf).map(|n| {
+                buf.truncate(n);
+                buf
+            });
+            (Some(transfer), chunk.map_err(anyhow::Error::from))
+        })
+        .await?;
+        let chunk = chunk.with_context(|| self.location())?;
+        if chunk.is_empty() {
+            // the session is closed once the file was read
+            return Ok(None);
+        }
+        self.transfer = transfer;
+        Ok(Some(chunk.freeze()))
+    }
+
+    /// opens the file again to read it from the beginning
+    async fn reset(&mut self) -> anyhow::Result<()> {
+        self.transfer = None;
+        Ok(())
+    }
+
+    /// get the file size from its attributes
+    async fn len(&mut self) -> anyhow::Result<u64> {
+        let (host, port, path) = (self.host.clone(), self.port, self.path.clone());
+        let settings = self.settings.clone();
+        let stat = tokio::task::spawn_blocking(move || {
+            let session = Self::connect(&host, port, &settings)?;
+            let stat = session.sftp()?.stat(Path::new(&path))?;
+            Ok::<_, anyhow::Error>(stat)
+        })
+        .await?
+        .with_context(|| self.location())?;
+        stat.size
+            .with_context(|| format!("{} has no size", self.location()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let input = SftpInput::new(
+            "sftp://partner.example.com/feeds/malware.txt",
+            SftpSettings::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            (input.host.as_str(), input.port, input.path.as_str()),
+            ("partner.example.com", 22, "/feeds/malware.txt")
+        );
+        let input =
+            SftpInput::new("sftp://[::1]:2222/~/malware.txt", SftpSettings::default()).unwrap();
+        assert_eq!(
+            (input.host.as_str(), input.port, input.path.as_str()),
+            ("::1", 2222, "malware.txt")
+        );
+        assert!(SftpInput::new("sftp://partner.example.com", SftpSettings::default()).is_err());
+        assert!(SftpInput::new("ftp://partner.example.com/a", SftpSettings::default()).is_err());
+    }
+}
@@ -0,0 +1,137 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use crate::input::{
+    api::ApiInput, axfr::AxfrInput, azure::AzureInput, command::CommandInput, dir::DirInput,
+    file::FileInput, ftp::FtpInput, gcs::GcsInput, git::GitInput, lookalike::LookalikeInput,
+    s3::S3Input, sftp::SftpInput, url::UrlInput, Input,
+};
+
+/// SourceType determines where a filter list is read from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum SourceType {
+    /// the source is an URL to be downloaded
+    #[default]
+    Url,
+    /// the source is a command line whose standard output is read
+    Command,
+    /// the source is a path on the local file system, FIFOs and character devices
+    /// are supported as well
+    File,
+    /// the source lists brand domains separated by commas, their lookalike
+    /// permutations are generated
+    Lookalike,
+    /// the source is the URL of a JSON API whose pages are requested according to the
+    /// api settings of the list
+    Api,
+    /// the source is a directory whose files are read one after another in the order
+    /// of their paths
+    Dir,
+    /// the source is the location of an object in an S3 bucket, e.g.
+    /// `s3://bucket/lists/malware.txt`
+    S3,
+    /// the source is the location of an object in a Google Cloud Storage bucket, e.g.
+    /// `gs://bucket/lists/malware.txt`
+    Gcs,
+    /// the source is the URL of a blob in an Azure Blob Storage container, e.g.
+    /// `https://account.blob.core.windows.net/container/lists/malware.txt`
+    Azure,
+    /// the source is the location of a file on an FTP server, e.g.
+    /// `ftp://ftp.example.com/feeds/malware.txt`
+    Ftp,
+    /// the source is the location of a file on an SSH server, e.g.
+    /// `sftp://partner.example.com/feeds/malware.txt`
+    Sftp,
+    /// the source is the URL of a git repository, e.g.
+    /// `https://github.com/org/lists.git`
+    Git,
+    /// the source is a DNS server and the zone transferred from it, e.g.
+    /// `axfr://ns1.example.com/rpz.local`
+    Axfr,
+}
+
+impl SourceType {
+    /// returns true if the content length of the source is known before reading it
+    pub fn has_len(&self) -> bool {
+        !matches!(
+            self,
+            SourceType::Command | SourceType::Api | SourceType::Axfr
+        )
+    }
+}
+
+/// SourceInput wraps the input types a filter list can be read from
+#[derive(Debug)]
+pub enum SourceInput {
+    Url(UrlInput),
+    Command(CommandInput),
+    File(FileInput),
+    Lookalike(LookalikeInput),
+    Api(ApiInput),
+    Dir(DirInput),
+    S3(S3Input),
+    Gcs(GcsInput),
+    Azure(AzureInput),
+    Ftp(FtpInput),
+    Sftp(SftpInput),
+    Git(GitInput),
+    Axfr(AxfrInput),
+}
+
+#[async_trait]
+impl Input for SourceInput {
+    async fn chunk(&mut self) -> anyhow::Result<Option<Bytes>> {
+        match self {
+            SourceInput::Url(input) => input.chunk().await,
+            SourceInput::Command(input) => input.chunk().await,
+            SourceInput::File(input) => input.chunk().await,
+            SourceInput::Lookalike(input) => input.chunk().await,
+            SourceInput::Api(input) => input.chunk().await,
+            SourceInput::Dir(input) => input.chunk().await,
+            SourceInput::S3(input) => input.chunk().await,
+            SourceInput::Gcs(input) => input.chunk().await,
+            SourceInput::Azure(input) => input.chunk().await,
+            SourceInput::Ftp(input) => input.chunk().await,
+            SourceInput::Sftp(input) => input.chunk().await,
+            SourceInput::Git(input) => input.chunk().await,
+            SourceInput::Axfr(input) => input.chunk().await,
+        }
+    }
+
+    async fn reset(&mut self) -> anyhow::Result<()> {
+        match self {
+            SourceInput::Url(input) => input.reset().await,
+            SourceInput::Command(input) => input.reset().await,
+            SourceInput::File(input) => input.reset().await,
+            SourceInput::Lookalike(input) => input.reset().await,
+            SourceInput::Api(input) => input.reset().await,
+            SourceInput::Dir(input) => input.reset().await,
+            SourceInput::S3(input) => input.reset().await,
+            SourceInput::Gcs(input) => input.reset().await,
+            SourceInput::Azure(input) => input.reset().await,
+            SourceInput::Ftp(input) => input.reset().await,
+            SourceInput::Sftp(input) => input.reset().await,
+            SourceInput::Git(input) => input.reset().await,
+            SourceInput::Axfr(input) => input.reset().await,
+        }
+    }
+
+    async fn len(&mut self) -> anyhow::Result<u64> {
+        match self {
+            SourceInput::Url(input) => input.len().await,
+            SourceInput::Command(input) => input.len().await,
+            SourceInput::File(input) => input.len().await,
+            SourceInput::Lookalike(input) => input.len().await,
+            SourceInput::Api(input) => input.len().await,
+            SourceInput::Dir(input) => input.len().await,
+            SourceInput::S3(input) => input.len().await,
+            SourceInput::Gcs(input) => input.len().await,
+            SourceInput::Azure(input) => input.len().await,
+            SourceInput::Ftp(input) => input.len().await,
+            SourceInput::Sftp(input) => input.len().await,
+            SourceInput::Git(input) => input.len().await,
+            SourceInput::Axfr(input) => input.len().await,
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use crate::input::format::{indicator, IndicatorParser};
+
+/// sticky buffers whose following contents are host names
+const HOST_BUFFERS: [&str; 6] = [
+    "dns.query",
+    "dns_query",
+    "tls.sni",
+    "tls_sni",
+    "http.host",
+    "quic.sni",
+];
+/// content modifiers of the legacy syntax making the preceding content a host name
+const HOST_MODIFIERS: [&str; 1] = ["http_host"];
+/// buffers without a dot in their name which end a host buffer
+const OTHER_BUFFERS: [&str; 2] = ["pkt_data", "file_data"];
+
+/// RuleParser reads the indicators of Suricata and Snort rulesets: the addresses in
+/// rule headers, the contents of host name buffers (DNS queries, TLS SNI, HTTP hosts)
+/// and DNS names matched in their wire format
+#[derive(Debug, Default)]
+pub struct RuleParser {
+    /// a rule continued on the next line
+    pending: String,
+}
+
+impl IndicatorParser for RuleParser {
+    fn indicators(&mut self, line: &str) -> Vec<String> {
+        let line = line.trim();
+        // rules may be continued on the next line by a trailing backslash
+        if let Some(part) = line.strip_suffix('\\') {
+            self.pending.push_str(part);
+            return vec![];
+        }
+        let rule = std::mem::take(&mut self.pending) + line;
+        rule_indicators(&rule)
+    }
+}
+
+/// returns the indicators of a rule
+///
+/// * `rule`: a complete rule
+fn rule_indicators(rule: &str) -> Vec<String> {
+    let rule = rule.trim();
+    // disabled rules are commented out
+    if rule.starts_with('#') {
+        return vec![];
+    }
+    let (header, options) = match rule.split_once('(') {
+        Some((header, options)) => (header, options.rsplit_once(')').map_or(options, |o| o.0)),
+        None => return vec![],
+    };
+    let mut indicators = vec![];
+    // action protocol source source_port direction destination destination_port
+    let fields: Vec<&str> = header.split_whitespace().collect();
+    if fields.len() == 7 {
+        for addresses in [fields[2], fields[5]] {
+            indicators.extend(
+                addresses
+                    .split(',')
+                    .map(|a| a.trim_matches(|c| c == '[' || c == ']'))
+                    .filter(|a| !a.starts_with('!'))
+                    .filter_map(indicator),
+            );
+        }
+    }
+
+    let mut host_buffer = false;
+    let mut last_content: Option<Vec<u8>> = None;
+    for option in split_options(options) {
+        let (keyword, value) = match option.split_once(':') {
+            Some((keyword, value)) => (keyword.trim(), Some(value.trim())),
+            None => (option.trim(), None),
+        };
+        match (keyword, value) {
+            // negated contents match everything but the host
+            ("content", Some(value)) if !value.starts_with('!') => {
+                let content = decode_content(value);
+                match host_buffer {
+                    true => indicators.extend(host(&content)),
+                    false => indicators.extend(dns_name(&content)),
+                }
+                last_content = Some(content);
+            }
+            (keyword, None) if HOST_MODIFIERS.contains(&keyword) => {
+                indicators.extend(last_content.as_deref().and_then(host));
+            }
+            (keyword, None) if HOST_BUFFERS.contains(&keyword) => host_buffer = true,
+            (keyword, None) if keyword.contains('.') || OTHER_BUFFERS.contains(&keyword) => {
+                host_buffer = false
+            }
+            _ => {}
+        }
+    }
+    let mut unique = vec![];
+    for indicator in indicators {
+        if !unique.contains(&indicator) {
+            unique.push(indicator);
+        }
+    }
+    unique
+}
+
+/// splits the options of a rule at the semicolons outside of quoted strings
+///
+/// * `options`: the options of a rule without the enclosing parentheses
+fn split_options(options: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+    for (i, c) in options.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                parts.push(&options[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&options[start..]);
+    parts.into_iter().filter(|p| !p.trim().is_empty()).collect()
+}
+
+/// returns the bytes matched by a content, i.e. the quoted string with its escaped
+/// characters and hex encoded bytes between pipes
+///
+/// * `value`: the value of the content option
+fn decode_content(value: &str) -> Vec<u8> {
+    let value = value.trim();
+    let value = value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value);
+    let mut bytes = vec![];
+    let (mut hex, mut escaped) = (false, false);
+    let mut digits = String::new();
+    for c in value.chars() {
+        match c {
+            _ if escaped => {
+                escaped = false;
+                bytes.extend(c.to_string().as_bytes());
+            }
+            '\\' => escaped = true,
+            '|' => hex = !hex,
+            _ if hex => {
+                if c.is_ascii_hexdigit() {
+                    digits.push(c);
+                }
+                if digits.len() == 2 {
+                    bytes.extend(u8::from_str_radix(&digits, 16).ok());
+                    digits.clear();
+                }
+            }
+            _ => bytes.extend(c.to_string().as_bytes()),
+        }
+    }
+    bytes
+}
+
+/// returns the indicator of a content matched against a host name buffer
+///
+/// * `content`: the decoded content
+fn host(content: &[u8]) -> Option<String> {
+    indicator(std::str::from_utf8(content).ok()?)
+}
+
+/// returns the name of a content matching a DNS name in its wire format, e.g.
+/// `|07|example|03|com|00|` of Snort rules
+///
+/// * `content`: the decoded content
+fn dns_name(content: &[u8]) -> Option<String> {
+    let mut labels = vec![];
+    let mut rest = content;
+    while let Some((&len, tail)) = rest.split_first() {
+        let len = len as usize;
+        if len == 0 {
+            break;
+        }
+        if len > 63 || tail.len() < len {
+            return None;
+        }
+        labels.push(std::str::from_utf8(&tail[..len]).ok()?);
+        rest = &tail[len..];
+    }
+    if labels.len() < 2 {
+        return None;
+    }
+    indicator(&labels.join("."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rule_indicators() {
+        let rules = r#"# Emerging Threats
+alert dns $HOME_NET any -> any any (msg:"ET DNS Query"; dns.query; content:"evil.com"; nocase; bsize:8; sid:1;)
+alert tls $HOME_NET any -> $EXTERNAL_NET any (msg:"SNI"; tls.sni; content:".bad-sni.net"; endswith; sid:2;)
+alert http $HOME_NET any -> $EXTERNAL_NET any (msg:"legacy"; content:"host.example"; http_host; content:"/gate.php"; http_uri; sid:3;)
+alert udp $HOME_NET any -> any 53 (msg:"snort dns"; content:"|07|example|03|org|00|"; nocase; sid:4;)
+alert ip [192.0.2.1,198.51.100.0/24,!192.0.2.2] any -> $HOME_NET any (msg:"CINS"; sid:5;)
+# alert dns any any -> any any (msg:"disabled"; dns.query; content:"disabled.com"; sid:6;)
+alert dns $HOME_NET any -> any any (msg:"semi\; colon"; dns_query; \
+    content:"continued.org"; sid:7;)
+alert http any any -> any any (msg:"uri"; http.uri; content:"not.a.host"; sid:8;)
+alert dns any any -> any any (msg:"negated"; dns.query; content:!"good.com"; sid:9;)
+"#;
+        let mut parser = RuleParser::default();
+        let got: Vec<String> = rules.lines().flat_map(|l| parser.indicators(l)).collect();
+        assert_eq!(
+            got,
+            vec![
+                "evil.com",
+                "bad-sni.net",
+                "host.example",
+                "example.org",
+                "192.0.2.1",
+                "198.51.100.0/24",
+                "continued.org"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_content() {
+        assert_eq!(decode_content(r#""a|2e 62|\"c""#), b"a.b\"c".to_vec());
+        assert_eq!(
+            dns_name(b"\x03www\x07example\x03com\x00"),
+            Some("www.example.com".to_string())
+        );
+        assert_eq!(dns_name(b"/gate.php"), None);
+        assert_eq!(dns_name(b"GET"), None);
+    }
+}
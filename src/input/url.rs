@@ -1,13 +1,658 @@
-use crate::input::Input;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::PathBuf,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context as TaskContext, Poll},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    input::{
+        file::{Compression, Decoder},
+        lines::DEFAULT_READ_SIZE,
+        Input,
+    },
+    schedule::random_delay,
+};
 use anyhow::Context;
 use async_trait::async_trait;
-use reqwest::{header::CONTENT_LENGTH, Response, StatusCode, Url};
+use bytes::{Buf, Bytes};
+use futures::FutureExt;
+use reqwest::{
+    header::{
+        HeaderMap, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE, ETAG, IF_MODIFIED_SINCE,
+        IF_NONE_MATCH, IF_RANGE, LAST_MODIFIED, RANGE,
+    },
+    redirect, Client, Identity, Proxy, RequestBuilder, Response, StatusCode, Url,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncReadExt, ReadBuf};
+
+/// proxy setting bypassing all proxies including the ones of the environment
+pub const DIRECT_PROXY: &str = "direct";
+/// times a failed request is repeated if none is configured
+pub const DEFAULT_RETRIES: u32 = 3;
+/// the exponential backoff doesn't grow beyond 2^MAX_BACKOFF_EXPONENT seconds
+const MAX_BACKOFF_EXPONENT: u32 = 6;
+/// seconds establishing a connection may take if no timeout is configured
+pub const DEFAULT_CONNECT_TIMEOUT: u64 = 30;
+/// seconds a server may stay silent if no timeout is configured
+pub const DEFAULT_READ_TIMEOUT: u64 = 120;
+/// redirects a request may follow if no maximum is configured
+pub const DEFAULT_MAX_REDIRECTS: usize = 10;
+
+/// returns the time to wait before the retry of a request, half of it is random so
+/// the retries of several lists don't hit a server at once
+///
+/// * `attempt`: the number of retries made before, starting with zero
+fn backoff(attempt: u32) -> Duration {
+    let secs = 1 << attempt.min(MAX_BACKOFF_EXPONENT);
+    Duration::from_secs(secs) / 2 + random_delay(secs) / 2
+}
+
+/// returns true if repeating the request may succeed
+///
+/// * `result`: the outcome of a request
+fn is_transient(result: &reqwest::Result<Response>) -> bool {
+    match result {
+        Ok(response) => {
+            response.status().is_server_error()
+                || response.status() == StatusCode::TOO_MANY_REQUESTS
+        }
+        Err(e) => e.is_connect() || e.is_timeout() || e.is_request() || e.is_body(),
+    }
+}
+
+/// Timeouts limit how long the requests of an URL may take, so a hung server doesn't
+/// stall the download
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct Timeouts {
+    /// seconds establishing a connection may take, defaults to 30
+    pub connect: Option<u64>,
+    /// seconds waiting for the response or the next part of the body may take,
+    /// defaults to 120
+    pub read: Option<u64>,
+    /// seconds a request including its body may take, unlimited by default
+    pub total: Option<u64>,
+}
+
+impl Timeouts {
+    /// returns the timeouts with the unset ones taken from others
+    ///
+    /// * `other`: the timeouts used as fallback, e.g. the global ones
+    pub fn or(self, other: Timeouts) -> Self {
+        Self {
+            connect: self.connect.or(other.connect),
+            read: self.read.or(other.read),
+            total: self.total.or(other.total),
+        }
+    }
+
+    /// returns the configured connect timeout or the default one
+    pub fn connect(&self) -> Duration {
+        Duration::from_secs(self.connect.unwrap_or(DEFAULT_CONNECT_TIMEOUT))
+    }
+
+    /// returns the configured read timeout or the default one
+    pub fn read(&self) -> Duration {
+        Duration::from_secs(self.read.unwrap_or(DEFAULT_READ_TIMEOUT))
+    }
+}
+
+/// Redirects limit the redirects the requests of an URL follow, e.g. from a feed to
+/// its CDN
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct Redirects {
+    /// number of redirects a request may follow, defaults to 10
+    pub max: Option<usize>,
+    /// false refuses redirects to another scheme, host or port than the one of the
+    /// list, defaults to false if the requests carry credentials and true otherwise
+    pub cross_origin: Option<bool>,
+}
+
+impl Redirects {
+    /// returns the redirects with the unset settings taken from others
+    ///
+    /// * `other`: the settings used as fallback, e.g. the global ones
+    pub fn or(self, other: Redirects) -> Self {
+        Self {
+            max: self.max.or(other.max),
+            cross_origin: self.cross_origin.or(other.cross_origin),
+        }
+    }
+
+    /// returns the redirect policy of a client
+    ///
+    /// * `credentials`: true if the requests carry configured headers or credentials,
+    ///   which would be sent to every origin redirected to
+    fn policy(&self, credentials: bool) -> redirect::Policy {
+        let max = self.max.unwrap_or(DEFAULT_MAX_REDIRECTS);
+        let cross_origin = self.cross_origin.unwrap_or(!credentials);
+        redirect::Policy::custom(move |attempt| {
+            let origin = attempt.previous()[0].origin();
+            if attempt.previous().len() > max {
+                let message = format!("more than {} redirects", max);
+                attempt.error(message)
+            } else if !cross_origin && attempt.url().origin() != origin {
+                let message = format!("refused cross-origin redirect to {}", attempt.url());
+                attempt.error(message)
+            } else {
+                attempt.follow()
+            }
+        })
+    }
+}
+
+/// ClientIdentity is the client certificate a list authenticates with by mutual TLS,
+/// e.g. to enterprise feeds
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct ClientIdentity {
+    /// PEM file containing the certificate chain or a PKCS#12 archive containing the
+    /// certificate chain and the private key
+    pub cert_file: String,
+    /// PEM file containing the PKCS#8 private key of a PEM certificate chain
+    pub key_file: Option<String>,
+    /// file containing the password of a PKCS#12 archive
+    pub password_file: Option<String>,
+}
+
+impl ClientIdentity {
+    /// reads the certificate chain and the private key, a key file marks the
+    /// certificate file as PEM, otherwise it's read as PKCS#12 archive
+    fn load(&self) -> anyhow::Result<Identity> {
+        let cert = fs::read(&self.cert_file)
+            .with_context(|| format!("could not read {}", self.cert_file))?;
+        match &self.key_file {
+            Some(key_file) => {
+                let key =
+                    fs::read(key_file).with_context(|| format!("could not read {}", key_file))?;
+                Identity::from_pkcs8_pem(&cert, &key)
+            }
+            None => {
+                let password = match &self.password_file {
+                    Some(path) => read_secret(path)?,
+                    None => String::new(),
+                };
+                Identity::from_pkcs12_der(&cert, &password)
+            }
+        }
+        .with_context(|| format!("invalid client certificate {}", self.cert_file))
+    }
+}
+
+/// RateLimit caps the requests and the bandwidth of the downloads from a host, so
+/// several lists from it don't trip its abuse protection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RateLimit {
+    /// number of requests per minute, which are spread evenly over the minute
+    pub requests_per_minute: Option<u32>,
+    /// number of bytes per second all downloads from the host may read together
+    pub bytes_per_second: Option<u64>,
+}
+
+/// TokenBucket hands out tokens at a constant rate up to its capacity. Tokens taken
+/// beyond the available ones are owed, so the next takers wait for them too.
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    /// tokens added per second
+    rate: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// Initialize a new full TokenBucket
+    ///
+    /// * `capacity`: the maximum number of tokens
+    /// * `rate`: tokens added per second
+    fn new(capacity: f64, rate: f64) -> Self {
+        Self {
+            capacity,
+            rate,
+            tokens: capacity,
+            updated: Instant::now(),
+        }
+    }
+
+    /// takes tokens and returns the time to wait until they are available
+    ///
+    /// * `amount`: the number of tokens taken
+    fn take(&mut self, amount: f64) -> Duration {
+        let now = Instant::now();
+        let refill = now.duration_since(self.updated).as_secs_f64() * self.rate;
+        self.tokens = (self.tokens + refill).min(self.capacity) - amount;
+        self.updated = now;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
+/// HostLimiter enforces the RateLimit of a host on all lists downloaded from it
+#[derive(Debug)]
+struct HostLimiter {
+    requests: Option<Mutex<TokenBucket>>,
+    bytes: Option<Mutex<TokenBucket>>,
+}
+
+impl HostLimiter {
+    /// Initialize a new HostLimiter
+    ///
+    /// * `limit`: the rate limit of the host
+    fn new(limit: RateLimit) -> Self {
+        Self {
+            requests: limit
+                .requests_per_minute
+                .filter(|&r| r > 0)
+                .map(|r| Mutex::new(TokenBucket::new(1.0, r as f64 / 60.0))),
+            // a second's worth of bytes may be read at once
+            bytes: limit
+                .bytes_per_second
+                .filter(|&b| b > 0)
+                .map(|b| Mutex::new(TokenBucket::new(b as f64, b as f64))),
+        }
+    }
+
+    /// waits until another request may be sent to the host
+    async fn request(&self) {
+        if let Some(bucket) = &self.requests {
+            let delay = bucket.lock().unwrap().take(1.0);
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// waits until the bandwidth of the host allows reading the bytes
+    ///
+    /// * `len`: the number of bytes read
+    async fn read(&self, len: usize) {
+        if let Some(bucket) = &self.bytes {
+            let delay = bucket.lock().unwrap().take(len as f64);
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+/// ClientSettings configure the HTTP client all lists with an URL source share
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ClientSettings {
+    /// maximum number of idle connections kept open per host, unlimited by default
+    pub pool_size: Option<usize>,
+    /// seconds an idle connection is kept open, defaults to 90
+    pub pool_idle_timeout: Option<u64>,
+    /// true speaks HTTP/2 without negotiation, e.g. to cleartext servers, false
+    /// restricts the client to HTTP/1.1. HTTPS servers negotiate it by default.
+    pub http2: Option<bool>,
+    /// the User-Agent header of the requests, defaults to harvester/<version>
+    pub user_agent: Option<String>,
+    /// rate limits of the downloads by host name
+    #[serde(default)]
+    pub rate_limits: BTreeMap<String, RateLimit>,
+}
+
+/// HttpClients share the connections of the lists with an URL source. Lists sending
+/// their requests through the same proxy within the same timeouts and redirect limits
+/// with the same client certificate use one client.
+#[derive(Debug, Default)]
+pub struct HttpClients {
+    settings: ClientSettings,
+    clients: HashMap<ClientKey, Client>,
+    /// limiters of the hosts with a rate limit, shared by their lists
+    limiters: HashMap<String, Arc<HostLimiter>>,
+}
+
+/// the settings of a list which need a client of their own, the flag tells if the
+/// requests carry credentials
+type ClientKey = (
+    Option<String>,
+    Timeouts,
+    Redirects,
+    bool,
+    Option<ClientIdentity>,
+);
+
+impl HttpClients {
+    /// Initialize new HttpClients
+    ///
+    /// * `settings`: the configuration of the clients
+    pub fn new(settings: ClientSettings) -> Self {
+        Self {
+            settings,
+            clients: HashMap::new(),
+            limiters: HashMap::new(),
+        }
+    }
+
+    /// returns the limiter of the host of an URL if it has a rate limit
+    ///
+    /// * `url`: the URL of a list
+    fn limiter(&mut self, url: &Url) -> Option<Arc<HostLimiter>> {
+        let host = url.host_str()?;
+        let limit = self.settings.rate_limits.get(host)?;
+        Some(
+            self.limiters
+                .entry(host.to_string())
+                .or_insert_with(|| Arc::new(HostLimiter::new(*limit)))
+                .clone(),
+        )
+    }
+
+    /// returns the client sending the requests of a list, the client is created
+    /// when a list needs it first
+    ///
+    /// * `http`: the proxy, timeouts, redirect limits and client certificate of the list
+    pub fn client(&mut self, http: &HttpSettings) -> anyhow::Result<Client> {
+        let key = (
+            http.proxy.clone(),
+            http.timeouts,
+            http.redirects,
+            http.has_credentials(),
+            http.identity.clone(),
+        );
+        if let Some(client) = self.clients.get(&key) {
+            // a clone shares the connection pool
+            return Ok(client.clone());
+        }
+        let user_agent = self
+            .settings
+            .user_agent
+            .clone()
+            .unwrap_or_else(|| format!("harvester/{}", env!("CARGO_PKG_VERSION")));
+        let mut builder = Client::builder()
+            .user_agent(user_agent)
+            .connect_timeout(http.timeouts.connect())
+            .redirect(http.redirects.policy(http.has_credentials()));
+        if let Some(pool_size) = self.settings.pool_size {
+            builder = builder.pool_max_idle_per_host(pool_size);
+        }
+        if let Some(idle_timeout) = self.settings.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(Duration::from_secs(idle_timeout));
+        }
+        match self.settings.http2 {
+            Some(true) => builder = builder.http2_prior_knowledge(),
+            Some(false) => builder = builder.http1_only(),
+            None => {}
+        }
+        if let Some(total) = http.timeouts.total {
+            builder = builder.timeout(Duration::from_secs(total));
+        }
+        match http.proxy.as_deref() {
+            Some(DIRECT_PROXY) => builder = builder.no_proxy(),
+            Some(proxy) => {
+                let proxy =
+                    Proxy::all(proxy).with_context(|| format!("invalid proxy {}", proxy))?;
+                builder = builder.proxy(proxy);
+            }
+            // reqwest uses the proxies of HTTP_PROXY, HTTPS_PROXY and ALL_PROXY
+            None => {}
+        }
+        if let Some(identity) = &http.identity {
+            builder = builder.identity(identity.load()?);
+        }
+        let client = builder.build()?;
+        self.clients.insert(key, client.clone());
+        Ok(client)
+    }
+}
+
+/// HttpSettings describe the headers and credentials sent with the requests of an URL
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct HttpSettings {
+    /// additional request headers
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// additional request headers whose values are read from files, e.g. API keys
+    #[serde(default)]
+    pub header_files: BTreeMap<String, String>,
+    /// file containing a token sent as bearer token
+    pub token_file: Option<String>,
+    /// user name sent by basic authentication
+    pub username: Option<String>,
+    /// file containing the password sent by basic authentication
+    pub password_file: Option<String>,
+    /// client certificate presented to servers requiring mutual TLS
+    pub identity: Option<ClientIdentity>,
+    /// URL of the HTTP(S) or SOCKS5 proxy the requests are sent through, `direct`
+    /// bypasses the proxies of the environment
+    pub proxy: Option<String>,
+    /// timeouts of the requests, unset ones fall back to the global timeouts
+    #[serde(default)]
+    pub timeouts: Timeouts,
+    /// limits of the redirects the requests follow, unset ones fall back to the
+    /// global limits
+    #[serde(default)]
+    pub redirects: Redirects,
+}
+
+/// returns the content of a file containing a secret without the trailing new line
+///
+/// * `path`: the path of the file
+fn read_secret(path: &str) -> anyhow::Result<String> {
+    Ok(fs::read_to_string(path)
+        .with_context(|| format!("could not read {}", path))?
+        .trim_end()
+        .to_string())
+}
+
+impl HttpSettings {
+    /// returns true if the requests carry configured headers or credentials
+    fn has_credentials(&self) -> bool {
+        !self.headers.is_empty()
+            || !self.header_files.is_empty()
+            || self.token_file.is_some()
+            || self.username.is_some()
+    }
+
+    /// adds the headers and credentials to a request, the files are read for every
+    /// request so rotated secrets are picked up
+    ///
+    /// * `request`: the request to an URL
+    fn apply(&self, mut request: RequestBuilder) -> anyhow::Result<RequestBuilder> {
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        for (name, path) in &self.header_files {
+            request = request.header(name, read_secret(path)?);
+        }
+        if let Some(token_file) = &self.token_file {
+            request = request.bearer_auth(read_secret(token_file)?);
+        }
+        if let Some(username) = &self.username {
+            let password = self.password_file.as_deref().map(read_secret).transpose()?;
+            request = request.basic_auth(username, password);
+        }
+        Ok(request)
+    }
+}
+
+/// Validators identify the version of a download to the server
+#[derive(Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+struct Validators {
+    /// the URL the validators were sent for
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl Validators {
+    /// returns the validators sent with a response
+    ///
+    /// * `url`: the requested URL
+    /// * `headers`: the headers of the response
+    fn from_headers(url: &Url, headers: &HeaderMap) -> Self {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string())
+        };
+        Self {
+            url: url.to_string(),
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        }
+    }
+
+    /// whether the server sent anything a conditional request can be made with
+    fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+/// ChunkReader hands the parts of a response body received so far to the decoder
+/// of its Content-Encoding. It is pending while the received parts are read, the
+/// decoder is polled again once the next part arrived instead of being woken.
+#[derive(Debug, Default)]
+struct ChunkReader {
+    /// the received part of the body not read by the decoder yet
+    chunk: Bytes,
+    /// whether the whole body was received
+    finished: bool,
+}
+
+impl ChunkReader {
+    /// adds the next part of the body
+    ///
+    /// * `chunk`: the part of the body received
+    fn push(&mut self, chunk: Bytes) {
+        self.chunk = match self.chunk.is_empty() {
+            true => chunk,
+            false => Bytes::from([self.chunk.as_ref(), chunk.as_ref()].concat()),
+        };
+    }
+}
+
+impl AsyncRead for ChunkReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut TaskContext<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let reader = self.get_mut();
+        if reader.chunk.is_empty() && !reader.finished {
+            return Poll::Pending;
+        }
+        let n = reader.chunk.len().min(buf.remaining());
+        buf.put_slice(&reader.chunk.split_to(n));
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncBufRead for ChunkReader {
+    fn poll_fill_buf(self: Pin<&mut Self>, _cx: &mut TaskContext<'_>) -> Poll<io::Result<&[u8]>> {
+        let reader = self.get_mut();
+        if reader.chunk.is_empty() && !reader.finished {
+            return Poll::Pending;
+        }
+        Poll::Ready(Ok(&reader.chunk))
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        self.get_mut().chunk.advance(amt);
+    }
+}
+
+/// ContentDecoder decodes the body of a response sent with a Content-Encoding by
+/// the decoders of compressed list files
+#[derive(Debug)]
+struct ContentDecoder {
+    /// the compression of a file encoded like the body
+    compression: Compression,
+    decoder: Decoder<ChunkReader>,
+    /// whether the encoded stream ended
+    ended: bool,
+}
+
+impl ContentDecoder {
+    /// returns the decoder of a Content-Encoding, None if the body isn't encoded
+    ///
+    /// * `encoding`: the value of the Content-Encoding header
+    fn new(encoding: &str) -> anyhow::Result<Option<Self>> {
+        let compression = match encoding.trim().to_lowercase().as_str() {
+            "" | "identity" => return Ok(None),
+            "gzip" | "x-gzip" => Compression::Gz,
+            "zstd" => Compression::Zstd,
+            "bzip2" | "x-bzip2" => Compression::Bzip2,
+            "xz" | "x-xz" => Compression::Xz,
+            _ => return Err(anyhow::anyhow!("unsupported Content-Encoding {}", encoding)),
+        };
+        Ok(Some(Self {
+            decoder: Decoder::new(&compression, ChunkReader::default()),
+            compression,
+            ended: false,
+        }))
+    }
+
+    /// returns the bytes decoded from a part of the body
+    ///
+    /// * `chunk`: the next part of the encoded body
+    fn decode(&mut self, chunk: Bytes) -> anyhow::Result<Vec<u8>> {
+        self.decoder.get_mut().push(chunk);
+        self.read_decoded()
+    }
+
+    /// fails if the body was truncated. The stream of a complete body ends with its
+    /// last part already, not all decoders tell a truncated one at the end of the body.
+    fn finish(&mut self) -> anyhow::Result<()> {
+        if self.ended {
+            return Ok(());
+        }
+        self.decoder.get_mut().finished = true;
+        self.read_decoded()?;
+        Err(anyhow::anyhow!(
+            "the body ends within its {:?} encoding",
+            self.compression
+        ))
+    }
+
+    /// reads what the decoder can decode from the parts of the body received so far
+    fn read_decoded(&mut self) -> anyhow::Result<Vec<u8>> {
+        let mut decoded = vec![];
+        let mut buf = vec![0; DEFAULT_READ_SIZE];
+        while let Some(n) = self.decoder.read(&mut buf).now_or_never() {
+            match n? {
+                0 => {
+                    self.ended = true;
+                    break;
+                }
+                n => decoded.extend_from_slice(&buf[..n]),
+            }
+        }
+        Ok(decoded)
+    }
+}
 
 /// UrlInput downloads data from an Url
 #[derive(Debug)]
 pub struct UrlInput {
     pub url: Url,
+    client: Client,
     response: Option<reqwest::Response>,
+    /// bytes of the current response read so far
+    position: u64,
+    /// times a failed request is repeated
+    retries: u32,
+    /// time the response or the next part of the body is waited for
+    read_timeout: Duration,
+    /// headers and credentials sent with every request
+    http: HttpSettings,
+    /// rate limit shared with the other lists of the host
+    limiter: Option<Arc<HostLimiter>>,
+    /// file the validators of the last complete download are kept in
+    validators_path: Option<PathBuf>,
+    /// the validators of the current response
+    validators: Validators,
+    /// compression of the list file, a body encoded the same way is kept as is
+    compression: Option<Compression>,
+    /// decoder of the Content-Encoding of the current response
+    decoder: Option<ContentDecoder>,
+    /// digest of the body read so far if the response has no validators
+    digest: Option<Sha256>,
 }
 
 impl UrlInput {
@@ -17,14 +662,282 @@ impl UrlInput {
     pub fn new(url: Url) -> Self {
         Self {
             url,
+            client: Client::builder()
+                .connect_timeout(Timeouts::default().connect())
+                .build()
+                .expect("could not create an HTTP client"),
             response: None,
+            position: 0,
+            retries: 0,
+            read_timeout: Timeouts::default().read(),
+            http: HttpSettings::default(),
+            limiter: None,
+            validators_path: None,
+            validators: Validators::default(),
+            compression: None,
+            decoder: None,
+            digest: None,
+        }
+    }
+
+    /// keeps bodies whose Content-Encoding matches the compression of the list file
+    /// encoded, so they are decompressed once by the extraction. Other encodings
+    /// are decoded while downloading.
+    ///
+    /// * `compression`: the compression of the list file
+    pub fn with_compression(mut self, compression: Option<Compression>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// repeats requests failing by connection errors and server errors, waiting
+    /// exponentially longer between the attempts
+    ///
+    /// * `retries`: times a failed request is repeated
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// sends the headers and credentials with every request through the configured
+    /// proxy within the configured timeouts and the rate limit of the host
+    ///
+    /// * `http`: the headers, credentials, proxy and timeouts
+    /// * `clients`: the clients and rate limits shared with the other lists
+    pub fn with_http(
+        mut self,
+        http: HttpSettings,
+        clients: &mut HttpClients,
+    ) -> anyhow::Result<Self> {
+        self.client = clients.client(&http)?;
+        self.limiter = clients.limiter(&self.url);
+        self.read_timeout = http.timeouts.read();
+        self.http = http;
+        Ok(self)
+    }
+
+    /// sends the request until it doesn't fail transiently or the retries are used
+    /// up, the response of the last attempt is returned
+    ///
+    /// * `build`: builds the request with the client
+    async fn send(&self, build: impl Fn(&Client) -> RequestBuilder) -> anyhow::Result<Response> {
+        let mut attempt = 0;
+        loop {
+            let request = self
+                .http
+                .apply(build(&self.client))
+                .with_context(|| format!("{}", self.url))?;
+            if let Some(limiter) = &self.limiter {
+                limiter.request().await;
+            }
+            // a server accepting the connection but never answering counts as failed
+            let result = tokio::time::timeout(self.read_timeout, request.send()).await;
+            let transient = result.as_ref().map_or(true, is_transient);
+            if attempt >= self.retries || !transient {
+                return match result {
+                    Ok(result) => result.with_context(|| format!("{}", self.url)),
+                    Err(_) => Err(anyhow::anyhow!(
+                        "{}: no response within {}s",
+                        self.url,
+                        self.read_timeout.as_secs()
+                    )),
+                };
+            }
+            let delay = backoff(attempt);
+            let reason = match &result {
+                Ok(Ok(response)) => format!("status code {}", response.status()),
+                Ok(Err(e)) => e.to_string(),
+                Err(_) => format!("no response within {}s", self.read_timeout.as_secs()),
+            };
+            warn!(
+                "{}: {}, retrying in {:.1}s",
+                self.url,
+                reason,
+                delay.as_secs_f64()
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// requests the rest of an interrupted body, the version of the list must not
+    /// have changed in between. Returns the part of the body read beyond the
+    /// interruption while checking that.
+    async fn resume(&mut self) -> anyhow::Result<Option<Bytes>> {
+        let position = self.position;
+        // If-Range accepts either validator, the server sends the whole body otherwise
+        let validator = match (&self.validators.etag, &self.validators.last_modified) {
+            (Some(etag), _) => etag.clone(),
+            (None, Some(last_modified)) => last_modified.clone(),
+            (None, None) => return self.restart().await,
+        };
+        let response = self
+            .send(|client| {
+                client
+                    .get(self.url.clone())
+                    .header(RANGE, format!("bytes={}-", position))
+                    .header(IF_RANGE, &validator)
+            })
+            .await?;
+        let resumed = match response.status() {
+            StatusCode::OK => position == 0,
+            StatusCode::PARTIAL_CONTENT => response
+                .headers()
+                .get(CONTENT_RANGE)
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|v| v.starts_with(&format!("bytes {}-", position))),
+            _ => false,
+        };
+        if !resumed {
+            return Err(anyhow::anyhow!(
+                "status code {}: {} can't be resumed at byte {}",
+                response.status(),
+                self.url,
+                position
+            ));
+        }
+        self.response = Some(response);
+        Ok(None)
+    }
+
+    /// requests an interrupted body without validators again from zero. The part
+    /// read before is skipped, it must be the same as in the interrupted response.
+    async fn restart(&mut self) -> anyhow::Result<Option<Bytes>> {
+        let position = self.position;
+        let read = match &self.digest {
+            Some(digest) => digest.clone().finalize(),
+            None => return Err(anyhow::anyhow!("{} can't be restarted", self.url)),
+        };
+        let response = self.send(|client| client.get(self.url.clone())).await?;
+        if response.status() != StatusCode::OK {
+            return Err(anyhow::anyhow!(
+                "status code {}: {} can't be restarted",
+                response.status(),
+                self.url
+            ));
+        }
+        let response = self.response.insert(response);
+        let mut digest = Sha256::new();
+        let mut skipped = 0;
+        let mut rest = None;
+        while skipped < position {
+            let chunk = tokio::time::timeout(self.read_timeout, response.chunk())
+                .await
+                .map_err(|_| {
+                    anyhow::anyhow!("no data received within {}s", self.read_timeout.as_secs())
+                })??;
+            let chunk = match chunk {
+                Some(chunk) => chunk,
+                None => break,
+            };
+            let needed = (position - skipped) as usize;
+            if chunk.len() > needed {
+                digest.update(&chunk[..needed]);
+                skipped = position;
+                rest = Some(chunk.slice(needed..));
+            } else {
+                digest.update(&chunk);
+                skipped += chunk.len() as u64;
+            }
+        }
+        if skipped < position || digest.clone().finalize() != read {
+            return Err(anyhow::anyhow!(
+                "{} changed while being downloaded",
+                self.url
+            ));
+        }
+        self.digest = Some(digest);
+        Ok(rest)
+    }
+
+    /// keeps the ETag and Last-Modified of complete downloads, so the next run can
+    /// ask the server whether the list changed
+    ///
+    /// * `path`: the file the validators are kept in
+    pub fn with_validators(mut self, path: PathBuf) -> Self {
+        self.validators_path = Some(path);
+        self
+    }
+
+    /// asks the server by a conditional request whether the list changed since the
+    /// last complete download, returns None if no validators of it are known. The
+    /// body of a changed list is read by the following chunks.
+    pub async fn is_modified(&mut self) -> anyhow::Result<Option<bool>> {
+        let stored: Validators = match self
+            .validators_path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|content| serde_json::from_str::<Validators>(&content).ok())
+        {
+            // the validators of a former source don't apply
+            Some(v) if v.url == self.url.as_str() && !v.is_empty() => v,
+            _ => return Ok(None),
+        };
+        let response = self
+            .send(|client| {
+                let mut request = client.get(self.url.clone());
+                if let Some(etag) = &stored.etag {
+                    request = request.header(IF_NONE_MATCH, etag);
+                }
+                if let Some(last_modified) = &stored.last_modified {
+                    request = request.header(IF_MODIFIED_SINCE, last_modified);
+                }
+                request
+            })
+            .await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(Some(false));
+        }
+        self.set_response(response)?;
+        Ok(Some(true))
+    }
+
+    /// keeps the response the body is read from, its validators and the decoder of
+    /// its Content-Encoding
+    ///
+    /// * `response`: the response to a request of the URL
+    fn set_response(&mut self, response: Response) -> anyhow::Result<()> {
+        let encoding = response
+            .headers()
+            .get(CONTENT_ENCODING)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        self.decoder = ContentDecoder::new(encoding)
+            .with_context(|| format!("{}", self.url))?
+            .filter(|d| self.compression.as_ref() != Some(&d.compression));
+        self.validators = Validators::from_headers(&self.url, response.headers());
+        // a body without validators is checked by its digest when it's restarted
+        self.digest = self.validators.is_empty().then(Sha256::new);
+        self.response = Some(response);
+        self.position = 0;
+        Ok(())
+    }
+
+    /// writes the validators of the completely read response for the next run
+    fn save_validators(&self) -> anyhow::Result<()> {
+        let path = match &self.validators_path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if self.validators.is_empty() {
+            // validators of an older version must not mark this one as unchanged
+            return match fs::remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    Err(e).with_context(|| format!("could not remove {:?}", path))
+                }
+                _ => Ok(()),
+            };
         }
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_string(&self.validators)?)
+            .with_context(|| format!("could not write {:?}", tmp_path))?;
+        fs::rename(&tmp_path, path).with_context(|| format!("could not write {:?}", path))?;
+        Ok(())
     }
 
     /// perform a head request and return the response
     pub async fn head_request(&self) -> anyhow::Result<Response> {
-        let cli = reqwest::Client::new();
-        let header = cli.head(self.url.clone()).send().await?;
+        let header = self.send(|client| client.head(self.url.clone())).await?;
         let status_code = header.status();
         if status_code != StatusCode::OK {
             return Err(anyhow::anyhow!("status code {}: {}", status_code, self.url,))
@@ -36,32 +949,87 @@ impl UrlInput {
 
 #[async_trait]
 impl Input for UrlInput {
-    async fn chunk(&mut self) -> anyhow::Result<Option<Vec<u8>>> {
+    async fn chunk(&mut self) -> anyhow::Result<Option<Bytes>> {
         if self.response.is_none() {
-            self.response = Some(reqwest::get(self.url.clone()).await?);
+            let response = self.send(|client| client.get(self.url.clone())).await?;
+            self.set_response(response)?;
         }
 
+        // a reset asks for the range from zero which servers may answer partially
         let status_code = self.response.as_ref().unwrap().status();
-        if status_code != StatusCode::OK {
+        if status_code != StatusCode::OK && status_code != StatusCode::PARTIAL_CONTENT {
             return Err(anyhow::anyhow!("status code {}: {}", status_code, self.url,))
                 .with_context(|| format!("{}", self.url));
         }
 
-        match self.response.as_mut().unwrap().chunk().await {
-            Ok(Some(r)) => {
-                let r = r.to_vec();
-                Ok(Some(r))
+        let mut attempt = 0;
+        let mut rest = None;
+        loop {
+            let result = match rest.take() {
+                Some(rest) => Ok(Some(rest)),
+                None => {
+                    let chunk = self.response.as_mut().unwrap().chunk();
+                    match tokio::time::timeout(self.read_timeout, chunk).await {
+                        Ok(result) => result.map_err(anyhow::Error::from),
+                        Err(_) => Err(anyhow::anyhow!(
+                            "no data received within {}s",
+                            self.read_timeout.as_secs()
+                        )),
+                    }
+                }
+            };
+            match result {
+                Ok(Some(r)) => {
+                    if let Some(limiter) = &self.limiter {
+                        limiter.read(r.len()).await;
+                    }
+                    // a resumed body continues at the position of the encoded bytes
+                    self.position += r.len() as u64;
+                    if let Some(digest) = &mut self.digest {
+                        digest.update(&r);
+                    }
+                    return match &mut self.decoder {
+                        Some(decoder) => Ok(Some(Bytes::from(
+                            decoder.decode(r).with_context(|| format!("{}", self.url))?,
+                        ))),
+                        // the body is handed on in the buffers it was received in
+                        None => Ok(Some(r)),
+                    };
+                }
+                Ok(None) => {
+                    if let Some(mut decoder) = self.decoder.take() {
+                        decoder.finish().with_context(|| format!("{}", self.url))?;
+                    }
+                    self.save_validators()?;
+                    return Ok(None);
+                }
+                // a connection lost within the body is continued where it broke off
+                Err(e) if attempt < self.retries => {
+                    let delay = backoff(attempt);
+                    warn!(
+                        "{}: {} after {} bytes, retrying in {:.1}s",
+                        self.url,
+                        e,
+                        self.position,
+                        delay.as_secs_f64()
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                    rest = self.resume().await?;
+                }
+                Err(e) => return Err(e).with_context(|| format!("{}", self.url)),
             }
-            Ok(None) => Ok(None),
-            Err(e) => Err(anyhow::anyhow!(e)).with_context(|| format!("{}", self.url)),
         }
     }
 
     /// download again to read request body from zero
     async fn reset(&mut self) -> anyhow::Result<()> {
-        if self.response.is_none() {
-            self.response = Some(reqwest::get(self.url.clone()).await?);
-        }
+        // the body of the previous response is consumed and can't be rewound
+        self.response.take();
+        let response = self
+            .send(|client| client.get(self.url.clone()).header(RANGE, "bytes=0-"))
+            .await?;
+        self.set_response(response)?;
         Ok(())
     }
 
@@ -84,3 +1052,668 @@ impl Input for UrlInput {
         Ok(content_length)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::helper::cache_file_creator::TEST_CACHE;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        task::JoinHandle,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reset() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/list", listener.local_addr().unwrap())).unwrap();
+        let server = tokio::spawn(async move {
+            let mut requests = vec![];
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 1024];
+                let n = stream.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+                let body = "one.domain\n";
+                let response = match request.contains("range: bytes=0-") {
+                    true => format!(
+                        "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\nContent-Range: bytes 0-{}/{}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body.len() - 1,
+                        body.len(),
+                        body
+                    ),
+                    false => format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    ),
+                };
+                stream.write_all(response.as_bytes()).await.unwrap();
+                stream.shutdown().await.unwrap();
+                requests.push(request);
+            }
+            requests
+        });
+
+        let mut input = UrlInput::new(url);
+        assert_eq!(input.chunk().await.unwrap().unwrap(), &b"one.domain\n"[..]);
+        assert_eq!(input.chunk().await.unwrap(), None);
+        input.reset().await.unwrap();
+        assert_eq!(input.chunk().await.unwrap().unwrap(), &b"one.domain\n"[..]);
+        assert_eq!(input.chunk().await.unwrap(), None);
+
+        let requests = server.await.unwrap();
+        assert!(!requests[0].contains("range:"));
+        assert!(requests[1].contains("range: bytes=0-"));
+    }
+
+    #[tokio::test]
+    async fn test_http_settings() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/list", listener.local_addr().unwrap())).unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let n = stream.read(&mut buf).await.unwrap();
+            let response = "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+            stream.write_all(response.as_bytes()).await.unwrap();
+            stream.shutdown().await.unwrap();
+            String::from_utf8_lossy(&buf[..n]).to_lowercase()
+        });
+
+        let dir = PathBuf::from(TEST_CACHE).join("test_http_settings");
+        fs::create_dir_all(&dir).unwrap();
+        let key_file = dir.join("api_key");
+        fs::write(&key_file, "secret-key\n").unwrap();
+        let password_file = dir.join("password");
+        fs::write(&password_file, "pass\n").unwrap();
+        let http = HttpSettings {
+            headers: BTreeMap::from([("Accept".to_string(), "text/plain".to_string())]),
+            header_files: BTreeMap::from([(
+                "X-Api-Key".to_string(),
+                key_file.to_string_lossy().to_string(),
+            )]),
+            username: Some("user".to_string()),
+            password_file: Some(password_file.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let mut input = UrlInput::new(url)
+            .with_http(http, &mut HttpClients::default())
+            .unwrap();
+        assert_eq!(input.chunk().await.unwrap(), None);
+
+        let request = server.await.unwrap();
+        assert!(request.contains("accept: text/plain"));
+        assert!(request.contains("x-api-key: secret-key\r\n"));
+        // user:pass
+        assert!(request.contains("authorization: basic dxnlcjpwyxnz"));
+
+        let http = HttpSettings {
+            token_file: Some(dir.join("missing").to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let mut input = UrlInput::new(Url::parse("http://127.0.0.1:1/").unwrap())
+            .with_http(http, &mut HttpClients::default())
+            .unwrap();
+        assert!(input.chunk().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_proxy() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let n = stream.read(&mut buf).await.unwrap();
+            let body = "one.domain\n";
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+            stream.shutdown().await.unwrap();
+            String::from_utf8_lossy(&buf[..n]).to_string()
+        });
+
+        let http = HttpSettings {
+            proxy: Some(proxy),
+            ..Default::default()
+        };
+        let url = Url::parse("http://lists.example/list").unwrap();
+        let mut input = UrlInput::new(url)
+            .with_http(http, &mut HttpClients::default())
+            .unwrap();
+        assert_eq!(input.chunk().await.unwrap().unwrap(), &b"one.domain\n"[..]);
+
+        // the proxy receives the absolute URL
+        let request = server.await.unwrap();
+        assert!(request.starts_with("GET http://lists.example/list HTTP/1.1"));
+
+        let http = HttpSettings {
+            proxy: Some("no proxy".to_string()),
+            ..Default::default()
+        };
+        assert!(UrlInput::new(Url::parse("http://lists.example/").unwrap())
+            .with_http(http, &mut HttpClients::default())
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_http_clients() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/list", listener.local_addr().unwrap())).unwrap();
+        let server = tokio::spawn(async move {
+            // both requests arrive on the same connection
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut requests = vec![];
+            for _ in 0..2 {
+                let mut buf = [0; 1024];
+                let n = stream.read(&mut buf).await.unwrap();
+                requests.push(String::from_utf8_lossy(&buf[..n]).to_lowercase());
+                let response = "HTTP/1.1 200 OK\r\nContent-Length: 11\r\n\r\none.domain\n";
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+
+        let mut clients = HttpClients::new(ClientSettings {
+            user_agent: Some("test-agent".to_string()),
+            ..Default::default()
+        });
+        for _ in 0..2 {
+            let mut input = UrlInput::new(url.clone())
+                .with_http(HttpSettings::default(), &mut clients)
+                .unwrap();
+            while input.chunk().await.unwrap().is_some() {}
+        }
+        assert_eq!(clients.clients.len(), 1);
+        let requests = server.await.unwrap();
+        assert!(requests[1].contains("user-agent: test-agent"));
+
+        // lists with another proxy get a client of their own
+        let http = HttpSettings {
+            proxy: Some(DIRECT_PROXY.to_string()),
+            ..Default::default()
+        };
+        clients.client(&http).unwrap();
+        assert_eq!(clients.clients.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_timeouts() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/list", listener.local_addr().unwrap())).unwrap();
+        let server = tokio::spawn(async move {
+            let mut streams = vec![];
+            for i in 0..2 {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 1024];
+                let _request = stream.read(&mut buf).await.unwrap();
+                // the first request is never answered, the second body stalls
+                if i == 1 {
+                    let response = "HTTP/1.1 200 OK\r\nContent-Length: 22\r\n\r\none.domain\n";
+                    stream.write_all(response.as_bytes()).await.unwrap();
+                }
+                streams.push(stream);
+            }
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        });
+
+        let http = HttpSettings {
+            timeouts: Timeouts {
+                read: Some(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut input = UrlInput::new(url.clone())
+            .with_http(http.clone(), &mut HttpClients::default())
+            .unwrap();
+        let err = input.chunk().await.unwrap_err();
+        assert!(err.to_string().contains("no response within 1s"));
+
+        let mut input = UrlInput::new(url)
+            .with_http(http, &mut HttpClients::default())
+            .unwrap();
+        assert_eq!(input.chunk().await.unwrap().unwrap(), &b"one.domain\n"[..]);
+        let err = input.chunk().await.unwrap_err();
+        assert!(format!("{:#}", err).contains("no data received within 1s"));
+        server.abort();
+
+        let timeouts = Timeouts {
+            connect: Some(5),
+            ..Default::default()
+        }
+        .or(Timeouts {
+            connect: Some(10),
+            total: Some(600),
+            ..Default::default()
+        });
+        assert_eq!(timeouts.connect(), Duration::from_secs(5));
+        assert_eq!(timeouts.read(), Duration::from_secs(DEFAULT_READ_TIMEOUT));
+        assert_eq!(timeouts.total, Some(600));
+    }
+
+    #[tokio::test]
+    async fn test_redirects() {
+        // the feed redirects to a CDN on another port, which is another origin
+        let cdn = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let feed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/list", feed.local_addr().unwrap())).unwrap();
+        let cdn_addr = cdn.local_addr().unwrap();
+        let responses = [
+            (
+                cdn,
+                "HTTP/1.1 200 OK\r\nContent-Length: 11\r\nConnection: close\r\n\r\none.domain\n"
+                    .to_string(),
+            ),
+            (
+                feed,
+                format!(
+                    "HTTP/1.1 302 Found\r\nLocation: http://{}/list\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    cdn_addr
+                ),
+            ),
+        ];
+        let servers: Vec<_> = responses
+            .into_iter()
+            .map(|(listener, response)| {
+                tokio::spawn(async move {
+                    loop {
+                        let (mut stream, _) = listener.accept().await.unwrap();
+                        let mut buf = [0; 1024];
+                        let _request = stream.read(&mut buf).await.unwrap();
+                        stream.write_all(response.as_bytes()).await.unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        let mut input = UrlInput::new(url.clone())
+            .with_http(HttpSettings::default(), &mut HttpClients::default())
+            .unwrap();
+        assert_eq!(input.chunk().await.unwrap().unwrap(), &b"one.domain\n"[..]);
+
+        for (redirects, message) in [
+            (
+                Redirects {
+                    cross_origin: Some(false),
+                    ..Default::default()
+                },
+                "refused cross-origin redirect",
+            ),
+            (
+                Redirects {
+                    max: Some(0),
+                    ..Default::default()
+                },
+                "more than 0 redirects",
+            ),
+        ] {
+            let http = HttpSettings {
+                redirects,
+                ..Default::default()
+            };
+            let mut input = UrlInput::new(url.clone())
+                .with_http(http, &mut HttpClients::default())
+                .unwrap();
+            let err = input.chunk().await.unwrap_err();
+            assert!(format!("{:#}", err).contains(message), "{:#}", err);
+        }
+
+        // configured headers are only sent to another origin if it's allowed explicitly
+        let headers = BTreeMap::from([("X-Api-Key".to_string(), "secret".to_string())]);
+        let http = HttpSettings {
+            headers: headers.clone(),
+            ..Default::default()
+        };
+        let mut input = UrlInput::new(url.clone())
+            .with_http(http, &mut HttpClients::default())
+            .unwrap();
+        let err = input.chunk().await.unwrap_err();
+        assert!(format!("{:#}", err).contains("refused cross-origin redirect"));
+        let http = HttpSettings {
+            headers,
+            redirects: Redirects {
+                cross_origin: Some(true),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut input = UrlInput::new(url.clone())
+            .with_http(http, &mut HttpClients::default())
+            .unwrap();
+        assert_eq!(input.chunk().await.unwrap().unwrap(), &b"one.domain\n"[..]);
+        servers.iter().for_each(|s| s.abort());
+    }
+
+    #[test]
+    fn test_client_identity() {
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_client_identity");
+        fs::create_dir_all(&dir).unwrap();
+        let cert_file = dir.join("client.pem");
+        fs::write(
+            &cert_file,
+            "-----BEGIN CERTIFICATE-----\n-----END CERTIFICATE-----\n",
+        )
+        .unwrap();
+        let identity = ClientIdentity {
+            cert_file: cert_file.to_string_lossy().to_string(),
+            key_file: Some("/nonexistent/client.key".to_string()),
+            password_file: None,
+        };
+        let http = HttpSettings {
+            identity: Some(identity.clone()),
+            ..Default::default()
+        };
+        let err = HttpClients::default().client(&http).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("could not read /nonexistent/client.key"));
+
+        // without a key file the certificate is read as PKCS#12 archive
+        let http = HttpSettings {
+            identity: Some(ClientIdentity {
+                key_file: None,
+                ..identity
+            }),
+            ..Default::default()
+        };
+        let err = HttpClients::default().client(&http).unwrap_err();
+        assert!(err.to_string().contains("invalid client certificate"));
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let mut bucket = TokenBucket::new(10.0, 10.0);
+        assert_eq!(bucket.take(10.0), Duration::ZERO);
+        // the owed tokens delay the following takers as well
+        let delay = bucket.take(5.0);
+        assert!(delay > Duration::from_millis(400) && delay <= Duration::from_millis(500));
+        let delay = bucket.take(5.0);
+        assert!(delay > Duration::from_millis(900) && delay <= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limits() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/list", listener.local_addr().unwrap())).unwrap();
+        let server = tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 1024];
+                let _request = stream.read(&mut buf).await.unwrap();
+                let response =
+                    "HTTP/1.1 200 OK\r\nContent-Length: 11\r\nConnection: close\r\n\r\none.domain\n";
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        // the lists of the host share its limit of one request per half second
+        let mut clients = HttpClients::new(ClientSettings {
+            rate_limits: BTreeMap::from([(
+                "127.0.0.1".to_string(),
+                RateLimit {
+                    requests_per_minute: Some(120),
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        });
+        let start = Instant::now();
+        for _ in 0..3 {
+            let mut input = UrlInput::new(url.clone())
+                .with_http(HttpSettings::default(), &mut clients)
+                .unwrap();
+            while input.chunk().await.unwrap().is_some() {}
+        }
+        assert!(start.elapsed() >= Duration::from_secs(1));
+        assert_eq!(clients.limiters.len(), 1);
+
+        // other hosts aren't limited
+        let other = Url::parse("http://localhost/list").unwrap();
+        assert!(clients.limiter(&other).is_none());
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_content_encoding() {
+        use async_compression::tokio::write::GzipEncoder;
+
+        let mut encoder = GzipEncoder::new(vec![]);
+        encoder
+            .write_all(b"one.domain\ntwo.domain\n")
+            .await
+            .unwrap();
+        encoder.shutdown().await.unwrap();
+        let body = encoder.into_inner();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/list", listener.local_addr().unwrap())).unwrap();
+        let server_body = body.clone();
+        let server = tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 1024];
+                let _request = stream.read(&mut buf).await.unwrap();
+                let header = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    server_body.len()
+                );
+                stream.write_all(header.as_bytes()).await.unwrap();
+                stream.write_all(&server_body).await.unwrap();
+            }
+        });
+
+        let read_all = |mut input: UrlInput| async move {
+            let mut content = vec![];
+            while let Some(chunk) = input.chunk().await.unwrap() {
+                content.extend_from_slice(&chunk);
+            }
+            content
+        };
+        let input = UrlInput::new(url.clone());
+        assert_eq!(read_all(input).await, b"one.domain\ntwo.domain\n");
+
+        // a gzipped list file is decompressed by the extraction
+        let input = UrlInput::new(url).with_compression(Some(Compression::Gz));
+        assert_eq!(read_all(input).await, body);
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_content_decoder() {
+        use async_compression::tokio::write::{BzEncoder, GzipEncoder, XzEncoder, ZstdEncoder};
+
+        let content = "one.domain\ntwo.domain\n".repeat(100);
+        let mut gzip = GzipEncoder::new(vec![]);
+        gzip.write_all(content.as_bytes()).await.unwrap();
+        gzip.shutdown().await.unwrap();
+        let mut zstd = ZstdEncoder::new(vec![]);
+        zstd.write_all(content.as_bytes()).await.unwrap();
+        zstd.shutdown().await.unwrap();
+        let mut bzip2 = BzEncoder::new(vec![]);
+        bzip2.write_all(content.as_bytes()).await.unwrap();
+        bzip2.shutdown().await.unwrap();
+        let mut xz = XzEncoder::new(vec![]);
+        xz.write_all(content.as_bytes()).await.unwrap();
+        xz.shutdown().await.unwrap();
+
+        for (encoding, body) in [
+            ("gzip", gzip.into_inner()),
+            ("zstd", zstd.into_inner()),
+            ("bzip2", bzip2.into_inner()),
+            ("xz", xz.into_inner()),
+        ] {
+            // the body is decoded while its parts arrive
+            let mut decoder = ContentDecoder::new(encoding).unwrap().unwrap();
+            let mut decoded = vec![];
+            for part in body.chunks(7) {
+                decoded.extend(decoder.decode(Bytes::copy_from_slice(part)).unwrap());
+            }
+            decoder.finish().unwrap();
+            assert_eq!(decoded, content.as_bytes());
+
+            // a truncated body fails
+            let mut decoder = ContentDecoder::new(encoding).unwrap().unwrap();
+            decoder
+                .decode(Bytes::copy_from_slice(&body[..body.len() / 2]))
+                .unwrap();
+            assert!(decoder.finish().is_err());
+        }
+        assert!(ContentDecoder::new("br").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_retries() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/list", listener.local_addr().unwrap())).unwrap();
+        let server = tokio::spawn(async move {
+            let mut requests = vec![];
+            for i in 0..3 {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 1024];
+                let n = stream.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+                let response = match i {
+                    0 => "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+                    // the connection breaks off after the first line
+                    1 => "HTTP/1.1 200 OK\r\nContent-Length: 22\r\nETag: \"v1\"\r\nConnection: close\r\n\r\none.domain\n".to_string(),
+                    _ => "HTTP/1.1 206 Partial Content\r\nContent-Length: 11\r\nContent-Range: bytes 11-21/22\r\nConnection: close\r\n\r\ntwo.domain\n".to_string(),
+                };
+                stream.write_all(response.as_bytes()).await.unwrap();
+                stream.shutdown().await.unwrap();
+                requests.push(request);
+            }
+            requests
+        });
+
+        let mut input = UrlInput::new(url).with_retries(2);
+        let mut content = vec![];
+        while let Some(chunk) = input.chunk().await.unwrap() {
+            content.extend_from_slice(&chunk);
+        }
+        assert_eq!(content, b"one.domain\ntwo.domain\n");
+
+        let requests = server.await.unwrap();
+        assert!(requests[2].contains("range: bytes=11-"));
+        assert!(requests[2].contains("if-range: \"v1\""));
+        assert!(backoff(0) >= Duration::from_millis(500) && backoff(0) <= Duration::from_secs(1));
+        assert!(backoff(20) <= Duration::from_secs(64));
+    }
+
+    /// serves the responses one per connection and returns the requests
+    async fn serve(responses: Vec<&'static str>) -> (Url, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/list", listener.local_addr().unwrap())).unwrap();
+        let server = tokio::spawn(async move {
+            let mut requests = vec![];
+            for response in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 1024];
+                let n = stream.read(&mut buf).await.unwrap();
+                requests.push(String::from_utf8_lossy(&buf[..n]).to_lowercase());
+                stream.write_all(response.as_bytes()).await.unwrap();
+                stream.shutdown().await.unwrap();
+            }
+            requests
+        });
+        (url, server)
+    }
+
+    #[tokio::test]
+    async fn test_resume_without_etag() {
+        let read_all = |mut input: UrlInput| async move {
+            let mut content = vec![];
+            while let Some(chunk) = input.chunk().await.unwrap() {
+                content.extend_from_slice(&chunk);
+            }
+            content
+        };
+        // Last-Modified validates the range if there is no ETag
+        let (url, server) = serve(vec![
+            "HTTP/1.1 200 OK\r\nContent-Length: 22\r\nLast-Modified: Tue, 01 Oct 2024 10:00:00 GMT\r\nConnection: close\r\n\r\none.domain\n",
+            "HTTP/1.1 206 Partial Content\r\nContent-Length: 11\r\nContent-Range: bytes 11-21/22\r\nConnection: close\r\n\r\ntwo.domain\n",
+        ])
+        .await;
+        let input = UrlInput::new(url).with_retries(1);
+        assert_eq!(read_all(input).await, b"one.domain\ntwo.domain\n");
+        let requests = server.await.unwrap();
+        assert!(requests[1].contains("range: bytes=11-"));
+        assert!(requests[1].contains("if-range: tue, 01 oct 2024 10:00:00 gmt"));
+
+        // without validators the list is requested from zero again
+        let (url, server) = serve(vec![
+            "HTTP/1.1 200 OK\r\nContent-Length: 22\r\nConnection: close\r\n\r\none.domain\n",
+            "HTTP/1.1 200 OK\r\nContent-Length: 22\r\nConnection: close\r\n\r\none.domain\ntwo.domain\n",
+        ])
+        .await;
+        let input = UrlInput::new(url).with_retries(1);
+        assert_eq!(read_all(input).await, b"one.domain\ntwo.domain\n");
+        let requests = server.await.unwrap();
+        assert!(!requests[1].contains("range:"));
+
+        // a list which changed in between can't be continued
+        let (url, server) = serve(vec![
+            "HTTP/1.1 200 OK\r\nContent-Length: 22\r\nConnection: close\r\n\r\none.domain\n",
+            "HTTP/1.1 200 OK\r\nContent-Length: 22\r\nConnection: close\r\n\r\nnew.domain\ntwo.domain\n",
+        ])
+        .await;
+        let mut input = UrlInput::new(url).with_retries(1);
+        assert_eq!(input.chunk().await.unwrap().unwrap(), "one.domain\n");
+        let err = input.chunk().await.unwrap_err();
+        assert!(format!("{:#}", err).contains("changed while being downloaded"));
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_is_modified() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/list", listener.local_addr().unwrap())).unwrap();
+        let server = tokio::spawn(async move {
+            let mut requests = vec![];
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 1024];
+                let n = stream.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+                let body = "one.domain\n";
+                let response = match request.contains("if-none-match: \"v1\"") {
+                    true => "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n".to_string(),
+                    false => format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nETag: \"v1\"\r\nLast-Modified: Wed, 14 Oct 2026 07:28:00 GMT\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    ),
+                };
+                stream.write_all(response.as_bytes()).await.unwrap();
+                stream.shutdown().await.unwrap();
+                requests.push(request);
+            }
+            requests
+        });
+
+        let mut path = PathBuf::from(TEST_CACHE);
+        path.push("test_is_modified");
+        fs::create_dir_all(&path).unwrap();
+        path.push(".list.validators");
+        fs::remove_file(&path).ok();
+
+        let mut input = UrlInput::new(url.clone()).with_validators(path.clone());
+        assert_eq!(input.is_modified().await.unwrap(), None);
+        assert_eq!(input.chunk().await.unwrap().unwrap(), &b"one.domain\n"[..]);
+        assert_eq!(input.chunk().await.unwrap(), None);
+
+        let mut input = UrlInput::new(url).with_validators(path);
+        assert_eq!(input.is_modified().await.unwrap(), Some(false));
+
+        let requests = server.await.unwrap();
+        assert!(!requests[0].contains("if-none-match:"));
+        assert!(requests[1].contains("if-modified-since: wed, 14 oct 2026 07:28:00 gmt"));
+    }
+}
@@ -0,0 +1,236 @@
+use anyhow::Context;
+use quick_xml::{events::Event, Reader};
+
+use crate::input::format::IndicatorParser;
+
+/// Step is an element name of an XmlPath
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Step {
+    /// the element may be nested at any depth below the previous step
+    descendant: bool,
+    /// the local name of the element, `*` matches any element
+    name: String,
+}
+
+/// XmlPath is an XPath-like selector of element texts or attributes, e.g.
+/// `/rss/channel/item/link`, `//entry/link/@href` or `//indicator/*/text()`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XmlPath {
+    steps: Vec<Step>,
+    /// the selected attribute of the last element, its text if None
+    attribute: Option<String>,
+}
+
+impl XmlPath {
+    /// parses a selector
+    ///
+    /// * `path`: the selector starting with `/`
+    pub fn parse(path: &str) -> anyhow::Result<Self> {
+        let rest = path
+            .trim()
+            .strip_prefix('/')
+            .with_context(|| format!("XML path {} doesn't start with /", path))?;
+        let mut steps = vec![];
+        let mut attribute = None;
+        let mut descendant = false;
+        let segments: Vec<&str> = rest.split('/').collect();
+        for (i, segment) in segments.iter().enumerate() {
+            let is_last = i + 1 == segments.len();
+            match *segment {
+                // an empty segment stems from `//`
+                "" if !is_last => descendant = true,
+                "text()" if is_last => {}
+                s if is_last && s.starts_with('@') => attribute = Some(s[1..].to_string()),
+                s if !s.is_empty() && !s.contains(['@', '(', '[']) => {
+                    steps.push(Step {
+                        descendant,
+                        name: s.to_string(),
+                    });
+                    descendant = false;
+                }
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "XML path {} is malformed at {:?}",
+                        path,
+                        segment
+                    ))
+                }
+            }
+        }
+        if steps.is_empty() {
+            return Err(anyhow::anyhow!("XML path {} selects no element", path));
+        }
+        Ok(Self { steps, attribute })
+    }
+
+    /// returns true if the elements from the root to the current one are selected
+    ///
+    /// * `names`: local names of the open elements starting with the root
+    fn matches(&self, names: &[String]) -> bool {
+        fn matches(steps: &[Step], names: &[String]) -> bool {
+            let (step, rest) = match steps.split_first() {
+                Some(split) => split,
+                None => return names.is_empty(),
+            };
+            let is_step = |name: &String| step.name == "*" || step.name == *name;
+            match step.descendant {
+                true => {
+                    (0..names.len()).any(|i| is_step(&names[i]) && matches(rest, &names[i + 1..]))
+                }
+                false => names.first().is_some_and(is_step) && matches(rest, &names[1..]),
+            }
+        }
+        matches(&self.steps, names)
+    }
+
+    /// returns the selected texts or attribute values of a document
+    ///
+    /// * `document`: the XML document
+    pub fn select(&self, document: &str) -> anyhow::Result<Vec<String>> {
+        let mut reader = Reader::from_str(document);
+        reader.trim_text(true);
+        let mut names: Vec<String> = vec![];
+        let mut values = vec![];
+        loop {
+            let event = reader
+                .read_event()
+                .with_context(|| format!("XML error at position {}", reader.buffer_position()))?;
+            match event {
+                Event::Start(ref e) | Event::Empty(ref e) => {
+                    names.push(String::from_utf8_lossy(e.local_name().as_ref()).to_string());
+                    if let Some(attribute) =
+                        self.attribute.as_ref().filter(|_| self.matches(&names))
+                    {
+                        for a in e.attributes().flatten() {
+                            if a.key.local_name().as_ref() == attribute.as_bytes() {
+                                values.push(a.unescape_value()?.trim().to_string());
+                            }
+                        }
+                    }
+                    if matches!(event, Event::Empty(_)) {
+                        names.pop();
+                    }
+                }
+                Event::End(_) => {
+                    names.pop();
+                }
+                Event::Text(e) if self.attribute.is_none() && self.matches(&names) => {
+                    values.push(e.unescape()?.trim().to_string());
+                }
+                Event::CData(e) if self.attribute.is_none() && self.matches(&names) => {
+                    values.push(String::from_utf8_lossy(&e.into_inner()).trim().to_string());
+                }
+                Event::Eof if !names.is_empty() => {
+                    return Err(anyhow::anyhow!(
+                        "XML document ends within element {}",
+                        names.join("/")
+                    ))
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        values.retain(|v| !v.is_empty());
+        Ok(values)
+    }
+}
+
+/// XmlParser reads the indicators selected by an XmlPath from an XML document
+#[derive(Debug)]
+pub struct XmlParser {
+    path: String,
+    /// the lines read so far, the document is parsed once it's complete
+    content: String,
+}
+
+impl XmlParser {
+    /// Initialize a new XmlParser
+    ///
+    /// * `path`: the selector of the indicators
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            content: String::new(),
+        }
+    }
+}
+
+impl IndicatorParser for XmlParser {
+    fn indicators(&mut self, line: &str) -> Vec<String> {
+        self.content.push_str(line);
+        self.content.push('\n');
+        vec![]
+    }
+
+    fn finish(&mut self) -> anyhow::Result<Vec<String>> {
+        let content = std::mem::take(&mut self.content);
+        XmlPath::parse(&self.path)?.select(&content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RSS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:ti="https://threat.example/ns">
+  <channel>
+    <title>Feed</title>
+    <link>https://threat.example/</link>
+    <item>
+      <title>one</title>
+      <link>https://one.domain/path</link>
+      <ti:indicator type="domain">one.domain</ti:indicator>
+    </item>
+    <item>
+      <title>two &amp; three</title>
+      <ti:indicator type="domain"><![CDATA[ two.domain ]]></ti:indicator>
+      <enclosure url="https://two.domain/file.exe"/>
+    </item>
+  </channel>
+</rss>"#;
+
+    fn select(path: &str) -> Vec<String> {
+        XmlPath::parse(path).unwrap().select(RSS).unwrap()
+    }
+
+    #[test]
+    fn test_select() {
+        assert_eq!(
+            select("/rss/channel/item/indicator"),
+            vec!["one.domain", "two.domain"]
+        );
+        assert_eq!(
+            select("//item/link/text()"),
+            vec!["https://one.domain/path"]
+        );
+        assert_eq!(
+            select("//link"),
+            vec!["https://threat.example/", "https://one.domain/path"]
+        );
+        assert_eq!(select("//item/*/@url"), vec!["https://two.domain/file.exe"]);
+        assert_eq!(select("//indicator/@type"), vec!["domain", "domain"]);
+        assert_eq!(select("/channel/item/title"), Vec::<String>::new());
+        assert_eq!(select("//item/title"), vec!["one", "two & three"]);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(XmlPath::parse("rss/channel").is_err());
+        assert!(XmlPath::parse("/").is_err());
+        assert!(XmlPath::parse("//item[1]/link").is_err());
+        assert!(XmlPath::parse("/@href").is_err());
+        assert!(XmlPath::parse("/rss//item/@href").is_ok());
+    }
+
+    #[test]
+    fn test_xml_parser() {
+        let mut parser = XmlParser::new("//indicator");
+        for line in RSS.lines() {
+            assert!(parser.indicators(line).is_empty());
+        }
+        assert_eq!(parser.finish().unwrap(), vec!["one.domain", "two.domain"]);
+        parser.indicators("<open>");
+        assert!(parser.finish().is_err());
+    }
+}
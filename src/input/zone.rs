@@ -0,0 +1,225 @@
+use crate::input::format::IndicatorParser;
+
+/// record types describing the zone itself rather than a listed name
+const ZONE_RECORD_TYPES: [&str; 2] = ["SOA", "NS"];
+const CLASSES: [&str; 4] = ["IN", "CH", "HS", "CS"];
+
+/// Record is a record of a listed name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// the owner name, relative to the origin if it's stripped
+    pub name: String,
+    /// the record type in upper case, e.g. `CNAME`
+    pub record_type: String,
+    /// the fields following the record type
+    pub data: Vec<String>,
+}
+
+/// ZoneParser extracts the owner names from the lines of a zone file in the format
+/// of RFC 1035 as used by BIND
+#[derive(Debug, Default)]
+pub struct ZoneParser {
+    /// the current origin, fully qualified with a trailing dot
+    origin: Option<String>,
+    /// names below the origin are returned relative to it
+    strip_origin: bool,
+    /// owner of the previous record, inherited by records without an owner
+    last_owner: Option<String>,
+    /// the last name returned, so records sharing an owner yield it once
+    last_name: Option<String>,
+    /// open parentheses of a record spanning multiple lines
+    depth: usize,
+}
+
+impl ZoneParser {
+    /// Initialize a new ZoneParser
+    ///
+    /// * `strip_origin`: return names below the origin relative to it, e.g. the
+    ///   listed domains of a response policy zone
+    pub fn new(strip_origin: bool) -> Self {
+        Self {
+            strip_origin,
+            ..Default::default()
+        }
+    }
+
+    /// returns the owner name of the record starting at the line, None if the line
+    /// doesn't start a record of a listed name or its owner was returned just before.
+    /// Wildcard owners yield the domain they cover.
+    ///
+    /// * `line`: a line of the zone file
+    pub fn parse_line(&mut self, line: &str) -> Option<String> {
+        let record = self.parse_record(line)?;
+        let name = record
+            .name
+            .strip_prefix("*.")
+            .map(String::from)
+            .unwrap_or(record.name);
+        if self.last_name.as_ref() == Some(&name) {
+            return None;
+        }
+        self.last_name = Some(name.clone());
+        Some(name)
+    }
+
+    /// returns the record starting at the line, None if the line doesn't start a
+    /// record of a listed name
+    ///
+    /// * `line`: a line of the zone file
+    pub fn parse_record(&mut self, line: &str) -> Option<Record> {
+        let line = strip_comment(line);
+        let continued = self.depth > 0;
+        let opened = line.matches('(').count();
+        let closed = line.matches(')').count();
+        self.depth = (self.depth + opened).saturating_sub(closed);
+        if continued || line.trim().is_empty() {
+            return None;
+        }
+
+        let mut fields = line.split_whitespace();
+        if line.starts_with('$') {
+            if let (Some("$ORIGIN"), Some(origin)) = (fields.next(), fields.next()) {
+                self.origin = Some(self.absolute(origin));
+            }
+            return None;
+        }
+        // a record starting with white space belongs to the previous owner
+        let owner = if line.starts_with(char::is_whitespace) {
+            self.last_owner.clone()?
+        } else {
+            self.absolute(fields.next()?)
+        };
+        self.last_owner = Some(owner.clone());
+        let record_type = fields
+            .find(|f| !f.starts_with(|c: char| c.is_ascii_digit()) && !is_class(f))?
+            .to_uppercase();
+        // files dumped by a server name their apex by the SOA record instead of $ORIGIN
+        if record_type == "SOA" && self.origin.is_none() && owner.ends_with('.') {
+            self.origin = Some(owner.clone());
+        }
+        if ZONE_RECORD_TYPES.contains(&record_type.as_str()) || self.origin.as_ref() == Some(&owner)
+        {
+            return None;
+        }
+
+        let mut name = owner.trim_end_matches('.').to_lowercase();
+        if let Some(origin) = self.origin.as_ref().filter(|_| self.strip_origin) {
+            let suffix = format!(".{}", origin.trim_end_matches('.').to_lowercase());
+            if let Some(relative) = name.strip_suffix(&suffix) {
+                name = relative.to_string();
+            }
+        }
+        Some(Record {
+            name,
+            record_type,
+            data: fields.map(String::from).collect(),
+        })
+    }
+
+    /// returns the fully qualified form of a name, relative names are completed by the
+    /// origin
+    ///
+    /// * `name`: the name as written in the zone file
+    fn absolute(&self, name: &str) -> String {
+        match (name, &self.origin) {
+            ("@", Some(origin)) => origin.clone(),
+            (name, _) if name.ends_with('.') => name.to_string(),
+            (name, Some(origin)) => format!("{}.{}", name, origin),
+            // without an origin relative names stay as they are
+            (name, None) => name.to_string(),
+        }
+    }
+}
+
+impl IndicatorParser for ZoneParser {
+    fn indicators(&mut self, line: &str) -> Vec<String> {
+        self.parse_line(line).into_iter().collect()
+    }
+}
+
+/// returns true if the field is a DNS class
+///
+/// * `field`: a field of a record
+fn is_class(field: &str) -> bool {
+    CLASSES.iter().any(|c| c.eq_ignore_ascii_case(field))
+}
+
+/// removes a comment from a line, semicolons within quoted strings are kept
+///
+/// * `line`: a line of the zone file
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ZONE: &str = r#"$TTL 3600
+$ORIGIN blocked.example.
+@   IN SOA ns1.example. hostmaster.example. (
+        2022120101 ; serial
+        3600 600 86400 300 )
+    IN NS ns1.example.
+ads         IN A 0.0.0.0
+            IN AAAA ::
+Tracker 300 IN CNAME .
+sub         NS ns2.example.
+full.other. A 0.0.0.0 ; absolute name
+txt TXT "a;b"
+"#;
+
+    fn parse(zone: &str, strip_origin: bool) -> Vec<String> {
+        let mut parser = ZoneParser::new(strip_origin);
+        zone.lines().filter_map(|l| parser.parse_line(l)).collect()
+    }
+
+    #[test]
+    fn test_parse_zone() {
+        assert_eq!(
+            parse(ZONE, false),
+            vec![
+                "ads.blocked.example",
+                "tracker.blocked.example",
+                "full.other",
+                "txt.blocked.example"
+            ]
+        );
+        assert_eq!(
+            parse(ZONE, true),
+            vec!["ads", "tracker", "full.other", "txt"]
+        );
+    }
+
+    #[test]
+    fn test_parse_rpz() {
+        let rpz = "$ORIGIN rpz.local.\n@ SOA localhost. root.localhost. 1 3600 600 86400 300\nbad.com CNAME .\n*.bad.com CNAME .\n";
+        assert_eq!(parse(rpz, true), vec!["bad.com"]);
+        let wildcard = "$ORIGIN rpz.local.\n*.wild.com CNAME .\n";
+        assert_eq!(parse(wildcard, true), vec!["wild.com"]);
+    }
+
+    #[test]
+    fn test_soa_origin() {
+        let zone = "rpz.local. 300 IN SOA localhost. root.localhost. 1 3600 600 86400 300\nrpz.local. NS localhost.\nbad.com.rpz.local. 300 IN CNAME .\n";
+        assert_eq!(parse(zone, true), vec!["bad.com"]);
+        let mut parser = ZoneParser::new(false);
+        parser.parse_line("rpz.local. SOA localhost. root.localhost. 1 3600 600 86400 300");
+        assert_eq!(
+            parser.parse_record("bad.com.rpz.local. 300 IN CNAME ."),
+            Some(Record {
+                name: "bad.com.rpz.local".to_string(),
+                record_type: "CNAME".to_string(),
+                data: vec![".".to_string()],
+            })
+        );
+    }
+}
@@ -1,16 +1,19 @@
 use std::{
     fs::{self, File},
     io::Write,
-    path::Path,
+    path::{Path, PathBuf},
     sync::Arc,
 };
 
 use anyhow::Context;
 use futures::lock::Mutex;
 
-use crate::input::{file::FileInput, Input};
+use crate::{
+    input::{file::FileInput, Input},
+    platform,
+};
 
-use super::filter_list_io::FilterListIO;
+use super::{entry_store::EntryStore, filter_list_io::FilterListIO};
 
 /// CategoryListIO contains a reader and a writer used to manipulate category wise
 /// assembled filter lists
@@ -37,21 +40,12 @@ impl<R: Input + Send, W: Write + Send> CategoryListIO<R, W> {
 }
 
 impl<W: Write + Send> CategoryListIO<FileInput, W> {
-    /// Attaches a potentially existing input file to the reader attribute for inspection
+    /// Attaches a reader for the domains of the entry store assembled for the category
     ///
-    /// * `base_dir`: the base directory where the input file is being tried to read
-    pub fn attach_existing_input_file(&mut self, base_dir: &Path) -> anyhow::Result<()> {
-        let mut contents =
-            fs::read_dir(base_dir).with_context(|| "input file directory does not exist")?;
-        let entry = contents
-            .find(|it| {
-                if let Ok(it) = it {
-                    return it.file_name().to_str().unwrap() == self.name;
-                }
-                false
-            })
-            .ok_or_else(|| anyhow::anyhow!("file not found: {}", self.name))??;
-        self.reader = Some(Arc::new(Mutex::new(FileInput::new(entry.path(), None))));
+    /// * `base_dir`: the directory containing the entry store
+    pub fn attach_entry_store_reader(&mut self, base_dir: &Path) -> anyhow::Result<()> {
+        let store = EntryStore::open(base_dir, &self.name)?;
+        self.reader = Some(Arc::new(Mutex::new(store.reader())));
         Ok(())
     }
 }
@@ -88,4 +82,41 @@ impl CategoryListIO<FileInput, File> {
         self.writer = Some(Arc::new(Mutex::new(out_file)));
         Ok(())
     }
+
+    /// Creates a hidden temporary output file and attaches it as writer. The file
+    /// is moved to its final destination by `persist_tmp_file`.
+    ///
+    /// * `base_dir`: the base directory where the output file is being created
+    pub fn attach_new_tmp_file_writer(&mut self, base_dir: &Path) -> anyhow::Result<()> {
+        let mut out_path = base_dir.to_path_buf();
+        fs::create_dir_all(&out_path).with_context(|| "could not create out directory")?;
+        out_path.push(self.tmp_file_name());
+        let out_file = File::create(out_path).with_context(|| "could not write out file")?;
+        self.writer = Some(Arc::new(Mutex::new(out_file)));
+        Ok(())
+    }
+
+    /// Atomically replaces the output file with the temporary file and returns the
+    /// path of the output file
+    ///
+    /// * `base_dir`: the base directory containing the temporary file
+    pub fn persist_tmp_file(&self, base_dir: &Path) -> anyhow::Result<PathBuf> {
+        let tmp_path = self.tmp_file_path(base_dir);
+        let out_path = base_dir.join(&self.name);
+        platform::replace_file(&tmp_path, &out_path)
+            .with_context(|| format!("could not move output file {} into place", self.name))?;
+        Ok(out_path)
+    }
+
+    /// returns the path of the temporary file the output is written to
+    ///
+    /// * `base_dir`: the base directory containing the temporary file
+    pub fn tmp_file_path(&self, base_dir: &Path) -> PathBuf {
+        base_dir.join(self.tmp_file_name())
+    }
+
+    /// name of the temporary file the output is written to
+    fn tmp_file_name(&self) -> String {
+        format!(".{}.tmp", self.name)
+    }
 }
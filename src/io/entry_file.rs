@@ -0,0 +1,124 @@
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use crate::input::file::FileInput;
+
+/// EntryFile is the intermediate representation of a list shared by the stages
+/// following the extraction: one normalized entry per line. It's written once from
+/// the extracted data and read by as many passes as a stage needs, so no input has to
+/// be downloaded or decompressed again.
+#[derive(Debug, Clone)]
+pub struct EntryFile {
+    /// file system path of the entry file
+    pub path: PathBuf,
+}
+
+impl EntryFile {
+    /// returns the normalized form of an entry or None if the line holds no entry
+    ///
+    /// * `line`: the line as extracted from the list
+    pub fn normalize(line: &str) -> Option<String> {
+        // a trailing dot denotes the root of a fully qualified name
+        let entry = line.trim().trim_end_matches('.');
+        if entry.is_empty() {
+            return None;
+        }
+        Some(entry.to_lowercase())
+    }
+
+    /// returns the entry file of the list, it's written from the source file unless it
+    /// is newer than the source file
+    ///
+    /// * `source`: the file containing the extracted lines of the list
+    /// * `dir`: the directory the entry files are stored in
+    /// * `list_id`: the id of the list
+    pub fn for_list(source: &Path, dir: &Path, list_id: &str) -> anyhow::Result<Self> {
+        let path = dir.join(list_id);
+        let modified = |p: &Path| fs::metadata(p).and_then(|m| m.modified()).ok();
+        match (modified(&path), modified(source)) {
+            (Some(entries), Some(source)) if entries > source => Ok(Self { path }),
+            _ => Self::spill(source, dir, list_id),
+        }
+    }
+
+    /// writes the normalized entries of the source file to the entry file of the list
+    ///
+    /// * `source`: the file containing the extracted lines of the list
+    /// * `dir`: the directory the entry files are stored in
+    /// * `list_id`: the id of the list
+    pub fn spill(source: &Path, dir: &Path, list_id: &str) -> anyhow::Result<Self> {
+        fs::create_dir_all(dir).with_context(|| "could not create entry file directory")?;
+        let path = dir.join(list_id);
+        let reader = BufReader::new(
+            File::open(source).with_context(|| format!("could not open {:?}", source))?,
+        );
+        let mut writer = BufWriter::new(
+            File::create(&path).with_context(|| format!("could not create {:?}", path))?,
+        );
+        let mut entries = 0;
+        for line in reader.split(b'\n') {
+            let line = line?;
+            // lines which aren't valid UTF-8 can't hold a domain
+            let entry = match String::from_utf8(line)
+                .ok()
+                .and_then(|l| Self::normalize(&l))
+            {
+                Some(e) => e,
+                None => continue,
+            };
+            writer.write_all(entry.as_bytes())?;
+            writer.write_all(b"\n")?;
+            entries += 1;
+        }
+        writer.flush()?;
+        debug!("{}: {} entries written to {:?}", list_id, entries, path);
+        Ok(Self { path })
+    }
+
+    /// returns an input starting a new pass over the entries
+    pub fn reader(&self) -> FileInput {
+        FileInput::new(self.path.clone(), None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{input::Input, tests::helper::cache_file_creator::TEST_CACHE};
+
+    use super::*;
+
+    async fn read_all(mut input: FileInput) -> String {
+        let mut got = String::new();
+        while let Some(chunk) = input.chunk().await.unwrap() {
+            got.push_str(std::str::from_utf8(&chunk).unwrap());
+            got.push('\n');
+        }
+        got
+    }
+
+    #[tokio::test]
+    async fn test_spill() {
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_entry_file");
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("extracted");
+        fs::write(&source, "One.Domain.\n\n  two.domain \n").unwrap();
+
+        let entry_file = EntryFile::spill(&source, &dir.join("entries"), "list").unwrap();
+        // every pass reads the same normalized entries
+        for _ in 0..2 {
+            assert_eq!(
+                read_all(entry_file.reader()).await,
+                "one.domain\ntwo.domain\n"
+            );
+        }
+        // the entry file is newer than the source and reused
+        let reused = EntryFile::for_list(&source, &dir.join("entries"), "list").unwrap();
+        assert_eq!(reused.path, entry_file.path);
+    }
+}
@@ -0,0 +1,299 @@
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::input::file::FileInput;
+
+/// the entry was found in more than one list of the category
+pub const FLAG_MULTIPLE_SOURCES: u8 = 0b0000_0001;
+/// the entry is in none of the lists of the category anymore but stays in the
+/// accumulating output
+pub const FLAG_ACCUMULATED: u8 = 0b0000_0010;
+/// source of the accumulated entries, which refers to no list
+pub const ACCUMULATED_SOURCE: u16 = u16::MAX;
+
+/// Entry is a normalized domain together with its origin
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Entry {
+    pub domain: String,
+    /// index of the list the entry was read from within the sources of the store
+    pub source: u16,
+    /// bit set of the FLAG_* constants
+    pub flags: u8,
+    /// sum of the weights of the lists containing the domain, 0 unless the category
+    /// is scored
+    pub score: u32,
+}
+
+impl Entry {
+    /// returns an entry which stays in an accumulating output after it left the lists
+    ///
+    /// * `domain`: the normalized domain
+    pub fn accumulated(domain: String) -> Self {
+        Self {
+            domain,
+            source: ACCUMULATED_SOURCE,
+            flags: FLAG_ACCUMULATED,
+            score: 0,
+        }
+    }
+
+    /// merges the origin of another occurrence of the same domain into the entry, the
+    /// score of a list is only counted once
+    ///
+    /// * `other`: the other occurrence of the domain
+    pub fn merge(&mut self, other: &Entry) {
+        if self.source != other.source {
+            self.flags |= FLAG_MULTIPLE_SOURCES;
+            self.score = self.score.saturating_add(other.score);
+        }
+        self.flags |= other.flags;
+    }
+}
+
+/// StoreMeta describes the columns of an entry store, it's written last so a store
+/// is only valid once all columns are complete
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+struct StoreMeta {
+    /// ids of the lists the entries were read from
+    sources: Vec<String>,
+    /// number of entries
+    len: u64,
+}
+
+/// returns the file system path of a column of the store
+///
+/// * `dir`: the directory containing the store
+/// * `name`: the name of the store
+/// * `column`: the name of the column
+fn column_path(dir: &Path, name: &str, column: &str) -> PathBuf {
+    dir.join(format!("{}.{}", name, column))
+}
+
+/// EntryStoreWriter writes the sorted entries of a category to an entry store. The
+/// store consists of one file per column: the domains separated by newlines, which is
+/// the plain category list read by the output adapters, and the sources, the flags and
+/// the scores in fixed width records.
+pub struct EntryStoreWriter<'w> {
+    dir: PathBuf,
+    name: String,
+    domains: &'w mut dyn Write,
+    sources: BufWriter<File>,
+    flags: BufWriter<File>,
+    scores: BufWriter<File>,
+    meta: StoreMeta,
+}
+
+impl<'w> EntryStoreWriter<'w> {
+    /// creates the column files of the store
+    ///
+    /// * `dir`: the directory the store is written to
+    /// * `name`: the name of the store
+    /// * `sources`: ids of the lists the entries are read from
+    /// * `domains`: the writer of the domain column
+    pub fn create(
+        dir: &Path,
+        name: &str,
+        sources: Vec<String>,
+        domains: &'w mut dyn Write,
+    ) -> anyhow::Result<Self> {
+        // a store is invalid until the metadata of the new columns is written
+        fs::remove_file(column_path(dir, name, "meta")).ok();
+        let column = |column: &str| -> anyhow::Result<BufWriter<File>> {
+            let path = column_path(dir, name, column);
+            let file =
+                File::create(&path).with_context(|| format!("could not create {:?}", path))?;
+            Ok(BufWriter::new(file))
+        };
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            name: name.to_string(),
+            domains,
+            sources: column("sources")?,
+            flags: column("flags")?,
+            scores: column("scores")?,
+            meta: StoreMeta { sources, len: 0 },
+        })
+    }
+
+    /// appends an entry, entries must be pushed in sorted order
+    ///
+    /// * `entry`: the entry to be appended
+    pub fn push(&mut self, entry: &Entry) -> anyhow::Result<()> {
+        self.domains.write_all(entry.domain.as_bytes())?;
+        self.domains.write_all(b"\n")?;
+        self.sources.write_all(&entry.source.to_le_bytes())?;
+        self.flags.write_all(&[entry.flags])?;
+        self.scores.write_all(&entry.score.to_le_bytes())?;
+        self.meta.len += 1;
+        Ok(())
+    }
+
+    /// flushes the columns and marks the store as complete
+    pub fn finish(mut self) -> anyhow::Result<()> {
+        self.domains.flush()?;
+        self.sources.flush()?;
+        self.flags.flush()?;
+        self.scores.flush()?;
+        let meta_path = column_path(&self.dir, &self.name, "meta");
+        fs::write(&meta_path, serde_json::to_string(&self.meta)?)
+            .with_context(|| format!("could not write {:?}", meta_path))?;
+        Ok(())
+    }
+}
+
+/// EntryStore reads the entries of a category written by the EntryStoreWriter
+#[derive(Debug, Clone)]
+pub struct EntryStore {
+    dir: PathBuf,
+    name: String,
+    meta: StoreMeta,
+}
+
+impl EntryStore {
+    /// opens a complete store
+    ///
+    /// * `dir`: the directory containing the store
+    /// * `name`: the name of the store
+    pub fn open(dir: &Path, name: &str) -> anyhow::Result<Self> {
+        let meta_path = column_path(dir, name, "meta");
+        let meta = fs::read_to_string(&meta_path)
+            .with_context(|| format!("entry store {} is incomplete", name))?;
+        // stores written before entries were scored are rebuilt
+        if !column_path(dir, name, "scores").exists() {
+            return Err(anyhow::anyhow!("entry store {} has no scores", name));
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            name: name.to_string(),
+            meta: serde_json::from_str(&meta)?,
+        })
+    }
+
+    /// returns the id of the list an entry was read from
+    ///
+    /// * `entry`: an entry of the store
+    pub fn source_id(&self, entry: &Entry) -> Option<&str> {
+        self.meta
+            .sources
+            .get(entry.source as usize)
+            .map(|s| s.as_str())
+    }
+
+    /// returns an input reading the domains in sort order
+    pub fn reader(&self) -> FileInput {
+        FileInput::new(self.dir.join(&self.name), None)
+    }
+
+    /// returns an iterator reading the entries in sort order
+    pub fn entries(&self) -> anyhow::Result<Entries> {
+        let open = |path: PathBuf| -> anyhow::Result<BufReader<File>> {
+            let file = File::open(&path).with_context(|| format!("could not open {:?}", path))?;
+            Ok(BufReader::new(file))
+        };
+        Ok(Entries {
+            domains: open(self.dir.join(&self.name))?,
+            sources: open(column_path(&self.dir, &self.name, "sources"))?,
+            flags: open(column_path(&self.dir, &self.name, "flags"))?,
+            scores: open(column_path(&self.dir, &self.name, "scores"))?,
+            remaining: self.meta.len,
+        })
+    }
+}
+
+/// Entries reads the columns of a store sequentially
+pub struct Entries {
+    domains: BufReader<File>,
+    sources: BufReader<File>,
+    flags: BufReader<File>,
+    scores: BufReader<File>,
+    remaining: u64,
+}
+
+impl Entries {
+    /// reads the next record of every column
+    fn read_entry(&mut self) -> anyhow::Result<Entry> {
+        let mut domain = String::new();
+        self.domains.read_line(&mut domain)?;
+        let mut source = [0; 2];
+        self.sources.read_exact(&mut source)?;
+        let mut flags = [0; 1];
+        self.flags.read_exact(&mut flags)?;
+        let mut score = [0; 4];
+        self.scores.read_exact(&mut score)?;
+        Ok(Entry {
+            domain: domain.trim_end_matches('\n').to_string(),
+            source: u16::from_le_bytes(source),
+            flags: flags[0],
+            score: u32::from_le_bytes(score),
+        })
+    }
+}
+
+impl Iterator for Entries {
+    type Item = anyhow::Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        Some(self.read_entry())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::helper::cache_file_creator::TEST_CACHE;
+
+    use super::*;
+
+    #[test]
+    fn test_entry_store() {
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_entry_store");
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut domains = File::create(dir.join("store")).unwrap();
+        let sources = vec!["first".to_string(), "second".to_string()];
+        let mut writer = EntryStoreWriter::create(&dir, "store", sources, &mut domains).unwrap();
+        let entries = vec![
+            Entry {
+                domain: "a.domain".to_string(),
+                source: 1,
+                flags: 0,
+                score: 1,
+            },
+            Entry {
+                domain: "b.domain".to_string(),
+                source: 0,
+                flags: FLAG_MULTIPLE_SOURCES,
+                score: 2,
+            },
+            Entry {
+                domain: "c.domain".to_string(),
+                source: 0,
+                flags: 0,
+                score: 1,
+            },
+        ];
+        // the store is incomplete until it's finished
+        entries.iter().for_each(|e| writer.push(e).unwrap());
+        assert!(EntryStore::open(&dir, "store").is_err());
+        writer.finish().unwrap();
+
+        let store = EntryStore::open(&dir, "store").unwrap();
+        let read: Vec<Entry> = store.entries().unwrap().map(|e| e.unwrap()).collect();
+        assert_eq!(read, entries);
+        assert_eq!(store.source_id(&read[0]), Some("second"));
+        assert_eq!(
+            fs::read_to_string(dir.join("store")).unwrap(),
+            "a.domain\nb.domain\nc.domain\n"
+        );
+    }
+}
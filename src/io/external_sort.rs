@@ -0,0 +1,217 @@
+use std::{path::Path, sync::Arc};
+
+use crate::memory::{MemoryBudget, Reservation};
+
+use super::run_files::{must_spill, Combine, RunFiles, RunMerge};
+
+/// ExternalSort sorts entries which may not fit into memory. The entries are collected
+/// in memory until the threshold is exceeded or the memory budget is used up, then they
+/// are written sorted to a run file. Once all entries were pushed they are read back in
+/// batches merging the runs.
+#[derive(Debug)]
+pub struct ExternalSort {
+    /// name used in log messages
+    name: String,
+    /// maximum number of bytes to be held in memory
+    threshold: usize,
+    /// drop duplicate entries
+    dedup: bool,
+    /// approximated number of bytes currently held in memory
+    size: usize,
+    entries: Vec<String>,
+    runs: RunFiles,
+    /// the memory reserved for the entries held in memory
+    reservation: Reservation,
+    /// the sorted entries once all were pushed
+    sorted: Option<Sorted>,
+}
+
+/// Sorted are the entries of an ExternalSort read back in order
+#[derive(Debug)]
+enum Sorted {
+    /// the entries fit into memory
+    Memory(std::vec::IntoIter<String>),
+    Runs(RunMerge<String>),
+}
+
+impl ExternalSort {
+    /// Creates a new empty sort
+    ///
+    /// * `dir`: directory where run files are written to
+    /// * `name`: prefix for the run files
+    /// * `threshold`: maximum number of bytes to be held in memory
+    /// * `dedup`: drop duplicate entries
+    pub fn new(dir: &Path, name: &str, threshold: usize, dedup: bool) -> Self {
+        Self {
+            name: name.to_string(),
+            threshold,
+            dedup,
+            size: 0,
+            entries: vec![],
+            runs: RunFiles::new(dir, name),
+            reservation: Reservation::default(),
+            sorted: None,
+        }
+    }
+
+    /// reserves the entries held in memory from a memory budget shared with the rest
+    /// of the pipeline
+    ///
+    /// * `budget`: the memory budget of the run, unlimited if None
+    pub fn with_budget(mut self, budget: Option<Arc<MemoryBudget>>) -> Self {
+        self.reservation = Reservation::new(budget);
+        self
+    }
+
+    /// adds an entry
+    ///
+    /// * `entry`: the entry to be sorted
+    pub fn push(&mut self, entry: String) -> anyhow::Result<()> {
+        let entry_size = entry_size(&entry);
+        self.entries.push(entry);
+        self.size += entry_size;
+        let reserved = self.reservation.reserve(entry_size);
+        if must_spill(self.size, self.threshold, reserved) {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// sorts the entries held in memory, duplicates are dropped if requested
+    fn sort_entries(&mut self) -> Vec<String> {
+        let mut entries = std::mem::take(&mut self.entries);
+        entries.sort_unstable();
+        if self.dedup {
+            entries.dedup();
+        }
+        entries
+    }
+
+    /// writes the entries held in memory sorted to a new run file
+    fn spill(&mut self) -> anyhow::Result<()> {
+        let entries = self.sort_entries();
+        self.runs.write_run(entries)?;
+        debug!("{}: spilled {} bytes to disk", self.name, self.size);
+        self.size = 0;
+        self.reservation.release_all();
+        Ok(())
+    }
+
+    /// returns the next sorted entries once all entries were pushed, an empty batch
+    /// after the last one
+    ///
+    /// * `batch_size`: maximum number of entries returned
+    pub fn next_batch(&mut self, batch_size: usize) -> anyhow::Result<Vec<String>> {
+        if self.sorted.is_none() {
+            self.sorted = Some(match self.runs.is_empty() {
+                true => Sorted::Memory(self.sort_entries().into_iter()),
+                false => {
+                    if !self.entries.is_empty() {
+                        self.spill()?;
+                    }
+                    let combine: Combine<String> = match self.dedup {
+                        true => |last, next| last == next,
+                        false => |_, _| false,
+                    };
+                    Sorted::Runs(self.runs.merge(combine)?)
+                }
+            });
+        }
+        let mut batch = Vec::with_capacity(batch_size);
+        match self.sorted.as_mut().unwrap() {
+            Sorted::Memory(entries) => batch.extend(entries.take(batch_size)),
+            Sorted::Runs(merge) => {
+                while batch.len() < batch_size
+                    && let Some(entry) = merge.next_record()?
+                {
+                    batch.push(entry);
+                }
+            }
+        }
+        // the memory of an entry is released once it's passed on
+        let released = batch.iter().map(|e| entry_size(e)).sum();
+        self.reservation.release(released);
+        Ok(batch)
+    }
+}
+
+/// returns the approximated memory held by an entry
+///
+/// * `entry`: the entry
+pub fn entry_size(entry: &str) -> usize {
+    entry.len() + std::mem::size_of::<String>()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        io::run_files::{MAX_OPEN_RUNS, MIN_RUN_SIZE},
+        tests::helper::cache_file_creator::TEST_CACHE,
+    };
+
+    use super::*;
+
+    /// pushes the entries and returns them read back in batches of two
+    ///
+    /// * `sort`: the sort the entries are pushed into
+    /// * `entries`: the entries to be sorted
+    fn sort_all(sort: &mut ExternalSort, entries: &[String]) -> Vec<String> {
+        for entry in entries {
+            sort.push(entry.clone()).unwrap();
+        }
+        let mut sorted = vec![];
+        loop {
+            let batch = sort.next_batch(2).unwrap();
+            if batch.is_empty() {
+                return sorted;
+            }
+            sorted.extend(batch);
+        }
+    }
+
+    #[test]
+    fn test_external_sort() {
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_external_sort");
+        let entries: Vec<String> = (0..MAX_OPEN_RUNS * 2 + 5)
+            .rev()
+            .map(|i| format!("{:04}.domain", i % 100))
+            .collect();
+        let mut want = entries.clone();
+        want.sort();
+
+        // a tiny threshold spills every entry into a run of its own
+        for threshold in [usize::MAX, 0] {
+            let mut sort = ExternalSort::new(&dir, "sort", threshold, false);
+            assert_eq!(sort_all(&mut sort, &entries), want);
+            assert!(threshold == usize::MAX || sort.runs.paths().len() <= MAX_OPEN_RUNS);
+        }
+        want.dedup();
+        let mut sort = ExternalSort::new(&dir, "dedup", 0, true);
+        assert_eq!(sort_all(&mut sort, &entries), want);
+        let runs = sort.runs.paths().to_vec();
+        drop(sort);
+        assert!(runs.iter().all(|r| !r.exists()));
+    }
+
+    #[test]
+    fn test_external_sort_budget() {
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_external_sort_budget");
+        let label = "a".repeat(500);
+        let entries: Vec<String> = (0..MIN_RUN_SIZE * 3 / 2 / entry_size(&label))
+            .map(|i| format!("{:05}.{}", i, label))
+            .collect();
+        // the threshold is never reached, but the budget is
+        let budget = MemoryBudget::new(MIN_RUN_SIZE);
+        let mut sort =
+            ExternalSort::new(&dir, "sort", usize::MAX, false).with_budget(Some(budget.clone()));
+        assert_eq!(sort_all(&mut sort, &entries), entries);
+        assert_eq!(sort.runs.paths().len(), 2);
+        assert!(budget.peak() <= MIN_RUN_SIZE);
+        // the memory is returned once the entries are passed on
+        assert!(budget.try_reserve(MIN_RUN_SIZE));
+    }
+}
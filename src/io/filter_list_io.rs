@@ -3,15 +3,28 @@ use std::{fs, io::Write, path::Path, sync::Arc};
 use anyhow::Context;
 use futures::lock::Mutex;
 use reqwest::Url;
-use std::fs::File;
+use std::fs::{File, OpenOptions};
 
 use crate::{
     filter_list::FilterList,
     input::{
-        file::{Compression, FileInput},
-        url::UrlInput,
+        api::ApiInput,
+        axfr::AxfrInput,
+        azure::AzureInput,
+        command::CommandInput,
+        dir::DirInput,
+        file::{is_pattern, Compression, FileInput},
+        ftp::FtpInput,
+        gcs::GcsInput,
+        git::GitInput,
+        lookalike::LookalikeInput,
+        s3::S3Input,
+        sftp::SftpInput,
+        source::{SourceInput, SourceType},
+        url::{HttpClients, UrlInput},
         Input,
     },
+    io::entry_file::EntryFile,
 };
 
 /// FilterListIO is a wrapper type for FilterList objects which additionally
@@ -44,12 +57,123 @@ impl<R: Input + Send, W: Write + Send> FilterListIO<R, W> {
     }
 }
 
-impl<W: Write + Send> FilterListIO<UrlInput, W> {
-    /// configures input to read from HTTP response
-    pub fn attach_url_reader(&mut self) -> anyhow::Result<()> {
-        let url = Url::parse(&self.filter_list.source)
-            .with_context(|| format!("config file error: {:?}", &self.filter_list))?;
-        let input = UrlInput::new(url);
+impl<W: Write + Send> FilterListIO<SourceInput, W> {
+    /// configures input to read from the source according to the source type
+    ///
+    /// * `download_dir`: the directory the list is downloaded to, which keeps the
+    ///   checkpoints of incremental APIs, the validators of URLs and the fetched git
+    ///   repositories
+    /// * `clients`: the HTTP clients shared by the lists with an URL source
+    pub fn attach_source_reader(
+        &mut self,
+        download_dir: &Path,
+        clients: &mut HttpClients,
+    ) -> anyhow::Result<()> {
+        let input = match self.filter_list.source_type() {
+            SourceType::Url => {
+                let url = Url::parse(&self.filter_list.source)
+                    .with_context(|| format!("config file error: {:?}", &self.filter_list))?;
+                SourceInput::Url(
+                    UrlInput::new(url)
+                        .with_retries(self.filter_list.retries())
+                        .with_compression(self.filter_list.compression())
+                        .with_http(self.filter_list.http.clone().unwrap_or_default(), clients)
+                        .with_context(|| format!("config file error: {:?}", &self.filter_list))?
+                        .with_validators(
+                            download_dir.join(format!(".{}.validators", self.filter_list.id)),
+                        ),
+                )
+            }
+            SourceType::Command => {
+                SourceInput::Command(CommandInput::new(&self.filter_list.source))
+            }
+            // the file is copied as is, decompression happens in the extract stage
+            SourceType::File if is_pattern(&self.filter_list.source) => {
+                SourceInput::Dir(DirInput::matching(&self.filter_list.source))
+            }
+            SourceType::File => SourceInput::File(
+                FileInput::new(self.filter_list.source.clone().into(), None).unframed(),
+            ),
+            SourceType::Lookalike => {
+                SourceInput::Lookalike(LookalikeInput::new(&self.filter_list.source))
+            }
+            SourceType::Api => {
+                let settings = self.filter_list.api.clone().with_context(|| {
+                    format!(
+                        "config file error: list {} has no api settings",
+                        self.filter_list.id
+                    )
+                })?;
+                let checkpoint = settings.checkpoint;
+                let input = ApiInput::new(&self.filter_list.source, settings);
+                match checkpoint {
+                    true => SourceInput::Api(input.with_checkpoint(
+                        download_dir.join(format!(".{}.checkpoint", self.filter_list.id)),
+                    )),
+                    false => SourceInput::Api(input),
+                }
+            }
+            SourceType::Dir => SourceInput::Dir(DirInput::new(
+                self.filter_list.source.clone().into(),
+                self.filter_list.recursive,
+            )),
+            SourceType::S3 => {
+                let settings = self.filter_list.s3.clone().unwrap_or_default();
+                let input = S3Input::new(&self.filter_list.source, settings)
+                    .with_context(|| format!("config file error: {:?}", &self.filter_list))?;
+                SourceInput::S3(input)
+            }
+            SourceType::Gcs => {
+                let settings = self.filter_list.gcs.clone().unwrap_or_default();
+                let input = GcsInput::new(&self.filter_list.source, settings)
+                    .with_context(|| format!("config file error: {:?}", &self.filter_list))?;
+                SourceInput::Gcs(input)
+            }
+            SourceType::Azure => {
+                let settings = self.filter_list.azure.clone().unwrap_or_default();
+                let input = AzureInput::new(&self.filter_list.source, settings)
+                    .with_context(|| format!("config file error: {:?}", &self.filter_list))?;
+                SourceInput::Azure(input)
+            }
+            SourceType::Ftp => {
+                let settings = self.filter_list.ftp.clone().unwrap_or_default();
+                let input = FtpInput::new(&self.filter_list.source, settings)
+                    .with_context(|| format!("config file error: {:?}", &self.filter_list))?;
+                SourceInput::Ftp(input)
+            }
+            SourceType::Sftp => {
+                let settings = self.filter_list.sftp.clone().unwrap_or_default();
+                let input = SftpInput::new(&self.filter_list.source, settings)
+                    .with_context(|| format!("config file error: {:?}", &self.filter_list))?;
+                SourceInput::Sftp(input)
+            }
+            SourceType::Git => {
+                let settings = self.filter_list.git.clone().with_context(|| {
+                    format!(
+                        "config file error: list {} has no git settings",
+                        self.filter_list.id
+                    )
+                })?;
+                SourceInput::Git(GitInput::new(
+                    &self.filter_list.source,
+                    settings,
+                    &download_dir.join(format!(".{}.git", self.filter_list.id)),
+                ))
+            }
+            SourceType::Axfr => {
+                let settings = self.filter_list.axfr.clone().unwrap_or_default();
+                let timeouts = self
+                    .filter_list
+                    .http
+                    .as_ref()
+                    .map(|http| http.timeouts)
+                    .unwrap_or_default();
+                let input = AxfrInput::new(&self.filter_list.source, settings)
+                    .with_context(|| format!("config file error: {:?}", &self.filter_list))?
+                    .with_timeouts(timeouts);
+                SourceInput::Axfr(input)
+            }
+        };
         self.reader = Some(Arc::new(Mutex::new(input)));
         Ok(())
     }
@@ -91,14 +215,55 @@ impl<W: Write + Send> FilterListIO<FileInput, W> {
                 return Ok(());
             }
         };
-        self.reader = Some(Arc::new(Mutex::new(FileInput::new(
-            entry.path(),
-            compression,
-        ))));
+        let input = FileInput::new(entry.path(), compression)
+            .with_archive_settings(
+                self.filter_list.scan_timeout(),
+                self.filter_list.archive_index,
+            )
+            .with_read_size(self.filter_list.read_size());
+        self.reader = Some(Arc::new(Mutex::new(input)));
+        Ok(())
+    }
+
+    /// Attaches a reader for the normalized entries of the list. The entry file is
+    /// written from the extracted lines found in the given directory if it's outdated.
+    ///
+    /// * `extract_dir`: the file system path of the extracted lists
+    /// * `entries_dir`: the file system path of the entry files
+    pub fn attach_entry_file_reader(
+        &mut self,
+        extract_dir: &Path,
+        entries_dir: &Path,
+    ) -> anyhow::Result<()> {
+        let entry_file = EntryFile::for_list(
+            &extract_dir.join(&self.filter_list.id),
+            entries_dir,
+            &self.filter_list.id,
+        )?;
+        self.reader = Some(Arc::new(Mutex::new(entry_file.reader())));
         Ok(())
     }
 }
 
+impl FilterListIO<SourceInput, File> {
+    /// is_not_modified asks the server whether the list changed since its last
+    /// download, None if the source can't tell
+    pub async fn is_not_modified(&mut self) -> Option<bool> {
+        let mut reader = self.reader.as_ref()?.lock().await;
+        let input = match &mut *reader {
+            SourceInput::Url(input) => input,
+            _ => return None,
+        };
+        match input.is_modified().await {
+            Ok(is_modified) => is_modified.map(|m| !m),
+            Err(e) => {
+                warn!("{}", e);
+                None
+            }
+        }
+    }
+}
+
 impl<R: Input + Send> FilterListIO<R, File> {
     /// returns the writer's content length
     pub async fn writer_len(&self) -> anyhow::Result<u64> {
@@ -162,4 +327,21 @@ impl<R: Input + Send> FilterListIO<R, File> {
         self.writer = Some(Arc::new(Mutex::new(out_file)));
         Ok(())
     }
+
+    /// Opens the output file for appending, it's created with its parent directories
+    /// if it doesn't exist yet, and attaches it to the given FilterListIO object
+    ///
+    /// * `base_dir`: the base directory of the output file
+    pub fn attach_appending_file_writer(&mut self, base_dir: &Path) -> anyhow::Result<()> {
+        let mut out_path = base_dir.to_path_buf();
+        fs::create_dir_all(&out_path).with_context(|| "could not create out directory")?;
+        out_path.push(&self.filter_list.id);
+        let out_file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(out_path)
+            .with_context(|| "could not write out file")?;
+        self.writer = Some(Arc::new(Mutex::new(out_file)));
+        Ok(())
+    }
 }
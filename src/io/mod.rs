@@ -1,2 +1,9 @@
 pub mod category_list_io;
+pub mod entry_file;
+pub mod entry_store;
+pub mod external_sort;
 pub mod filter_list_io;
+pub mod published_file;
+pub mod run_files;
+pub mod seen_file;
+pub mod spool;
@@ -0,0 +1,90 @@
+use std::{
+    collections::BTreeSet,
+    fs::{self, File},
+    io::{BufRead, BufReader, BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+/// PublishedFile keeps the entries of an output as they were last published
+/// successfully, e.g. to a DNS server. The changes of a run are published relative to
+/// this state instead of the deployed output, so changes which couldn't be published
+/// are published again by the next run.
+#[derive(Debug)]
+pub struct PublishedFile {
+    path: PathBuf,
+}
+
+impl PublishedFile {
+    /// Initialize a new PublishedFile
+    ///
+    /// * `dir`: the directory containing the files of all outputs
+    /// * `tag`: the tag of the output
+    pub fn new(dir: &Path, tag: &str) -> Self {
+        Self {
+            path: dir.join(tag),
+        }
+    }
+
+    /// returns the published entries, None if the output was never published
+    pub fn read(&self) -> anyhow::Result<Option<BTreeSet<String>>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("could not open {:?}", self.path)),
+        };
+        let entries = BufReader::new(file)
+            .lines()
+            .collect::<Result<_, _>>()
+            .with_context(|| format!("could not read {:?}", self.path))?;
+        Ok(Some(entries))
+    }
+
+    /// records the entries once they were published
+    ///
+    /// * `entries`: the entries of the output
+    pub fn write(&self, entries: &BTreeSet<String>) -> anyhow::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("could not create {:?}", dir))?;
+        }
+        // the file is replaced at once, so an interrupted run doesn't lose the state
+        let mut tmp_path = self.path.clone();
+        tmp_path.set_file_name(format!(
+            ".{}.tmp",
+            self.path.file_name().unwrap_or_default().to_string_lossy()
+        ));
+        let file =
+            File::create(&tmp_path).with_context(|| format!("could not create {:?}", tmp_path))?;
+        let mut writer = BufWriter::new(file);
+        for entry in entries {
+            writeln!(writer, "{}", entry)?;
+        }
+        writer.flush()?;
+        fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("could not move {:?} into place", self.path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::helper::cache_file_creator::TEST_CACHE;
+
+    use super::*;
+
+    #[test]
+    fn test_published_file() {
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_published_file");
+        fs::remove_dir_all(&dir).ok();
+        let published = PublishedFile::new(&dir, "malware");
+        assert_eq!(published.read().unwrap(), None);
+
+        let entries: BTreeSet<String> = ["b.domain", "a.domain"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        published.write(&entries).unwrap();
+        assert_eq!(published.read().unwrap(), Some(entries));
+    }
+}
@@ -0,0 +1,260 @@
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Lines, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+/// amount of memory in bytes held at least before spilling because the memory budget
+/// is used up, so the runs don't shrink to single entries
+pub const MIN_RUN_SIZE: usize = 1024 * 1024;
+/// maximum number of run files read at once, more runs are merged in several passes
+pub const MAX_OPEN_RUNS: usize = 64;
+
+/// combines a record into the one preceding it in order, returns false if both are
+/// kept, e.g. true for duplicates which are dropped
+pub type Combine<T> = fn(&mut T, &T) -> bool;
+
+/// RunRecord is a record stored as a line of a run file
+pub trait RunRecord: Ord + Sized {
+    /// writes the record as a line of a run file
+    ///
+    /// * `writer`: the run file
+    fn write_line(&self, writer: &mut impl Write) -> io::Result<()>;
+
+    /// parses a line of a run file without its line break
+    ///
+    /// * `line`: the line of the run file
+    fn parse_line(line: &str) -> anyhow::Result<Self>;
+}
+
+impl RunRecord for String {
+    fn write_line(&self, writer: &mut impl Write) -> io::Result<()> {
+        writeln!(writer, "{}", self)
+    }
+
+    fn parse_line(line: &str) -> anyhow::Result<Self> {
+        Ok(line.to_string())
+    }
+}
+
+/// returns true if the records held in memory have to be written to a run, once they
+/// exceed the threshold or the memory budget is used up. A small set is kept beyond
+/// the budget up to the threshold.
+///
+/// * `size`: approximated number of bytes held in memory
+/// * `threshold`: maximum number of bytes to be held in memory
+/// * `reserved`: false if the last reservation from the budget failed
+pub fn must_spill(size: usize, threshold: usize, reserved: bool) -> bool {
+    (!reserved && size >= MIN_RUN_SIZE) || size > threshold
+}
+
+/// RunFiles are the sorted run files of a collection exceeding the memory, they are
+/// removed when being dropped
+#[derive(Debug)]
+pub struct RunFiles {
+    /// directory where the run files are stored
+    dir: PathBuf,
+    /// name used as prefix for the run files
+    name: String,
+    runs: Vec<PathBuf>,
+    /// number of run files created so far, which numbers the next one
+    created_runs: usize,
+}
+
+impl RunFiles {
+    /// Creates an empty set of runs
+    ///
+    /// * `dir`: directory where run files are written to
+    /// * `name`: prefix for the run files
+    pub fn new(dir: &Path, name: &str) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            name: name.to_string(),
+            runs: vec![],
+            created_runs: 0,
+        }
+    }
+
+    /// returns true if no run was written
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    /// returns the file system paths of the runs
+    #[cfg(test)]
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.runs
+    }
+
+    /// creates the next run file, it's removed by drop if it can't be written
+    fn create_run(&mut self) -> anyhow::Result<BufWriter<File>> {
+        fs::create_dir_all(&self.dir).with_context(|| "could not create spool directory")?;
+        let run_path = self
+            .dir
+            .join(format!("{}.{}", self.name, self.created_runs));
+        self.created_runs += 1;
+        let run_file = File::create(&run_path).with_context(|| "could not create spool file")?;
+        self.runs.push(run_path);
+        Ok(BufWriter::new(run_file))
+    }
+
+    /// writes sorted records to a new run file
+    ///
+    /// * `records`: the records in order
+    pub fn write_run<T: RunRecord>(
+        &mut self,
+        records: impl IntoIterator<Item = T>,
+    ) -> anyhow::Result<()> {
+        let mut run_writer = self.create_run()?;
+        for record in records {
+            record.write_line(&mut run_writer)?;
+        }
+        run_writer.flush()?;
+        Ok(())
+    }
+
+    /// merges the oldest runs into one until no more than MAX_OPEN_RUNS are left and
+    /// returns the merge of all runs
+    ///
+    /// * `combine`: combines records following each other in order
+    pub fn merge<T: RunRecord>(&mut self, combine: Combine<T>) -> anyhow::Result<RunMerge<T>> {
+        while self.runs.len() > MAX_OPEN_RUNS {
+            let runs: Vec<PathBuf> = self.runs.drain(..MAX_OPEN_RUNS).collect();
+            let mut run_writer = self.create_run()?;
+            let merged = RunMerge::open(&runs, combine).and_then(|mut merge| {
+                while let Some(record) = merge.next_record()? {
+                    record.write_line(&mut run_writer)?;
+                }
+                Ok(run_writer.flush()?)
+            });
+            for run in runs {
+                fs::remove_file(run).ok();
+            }
+            merged?;
+        }
+        RunMerge::open(&self.runs, combine)
+    }
+}
+
+impl Drop for RunFiles {
+    fn drop(&mut self) {
+        for run in self.runs.iter() {
+            fs::remove_file(run).ok();
+        }
+    }
+}
+
+/// RunMerge reads the records of sorted run files in order
+pub struct RunMerge<T> {
+    readers: Vec<Lines<BufReader<File>>>,
+    /// the next record of every run which isn't exhausted
+    heap: BinaryHeap<Reverse<(T, usize)>>,
+    combine: Combine<T>,
+    /// the record read after the one returned last
+    pending: Option<T>,
+}
+
+impl<T> std::fmt::Debug for RunMerge<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RunMerge")
+            .field("runs", &self.readers.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: RunRecord> RunMerge<T> {
+    /// opens the run files and reads the first record of each
+    ///
+    /// * `runs`: the run files
+    /// * `combine`: combines records following each other in order
+    fn open(runs: &[PathBuf], combine: Combine<T>) -> anyhow::Result<Self> {
+        let readers = runs
+            .iter()
+            .map(|path| -> anyhow::Result<_> { Ok(BufReader::new(File::open(path)?).lines()) })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let mut merge = Self {
+            readers,
+            heap: BinaryHeap::new(),
+            combine,
+            pending: None,
+        };
+        for i in 0..merge.readers.len() {
+            merge.advance(i)?;
+        }
+        Ok(merge)
+    }
+
+    /// reads the next record of a run into the heap
+    ///
+    /// * `i`: the index of the run
+    fn advance(&mut self, i: usize) -> anyhow::Result<()> {
+        if let Some(line) = self.readers[i].next() {
+            self.heap.push(Reverse((T::parse_line(&line?)?, i)));
+        }
+        Ok(())
+    }
+
+    /// returns the next record of the k-way merge without combining it
+    fn pop(&mut self) -> anyhow::Result<Option<T>> {
+        let Some(Reverse((record, i))) = self.heap.pop() else {
+            return Ok(None);
+        };
+        self.advance(i)?;
+        Ok(Some(record))
+    }
+
+    /// returns the next record combined with the ones following it, None once all runs
+    /// are exhausted
+    pub fn next_record(&mut self) -> anyhow::Result<Option<T>> {
+        let mut last = match self.pending.take() {
+            Some(record) => record,
+            None => match self.pop()? {
+                Some(record) => record,
+                None => return Ok(None),
+            },
+        };
+        while let Some(record) = self.pop()? {
+            if !(self.combine)(&mut last, &record) {
+                self.pending = Some(record);
+                break;
+            }
+        }
+        Ok(Some(last))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::helper::cache_file_creator::TEST_CACHE;
+
+    use super::*;
+
+    #[test]
+    fn test_run_files() {
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_run_files");
+        let count = MAX_OPEN_RUNS * 2 + 5;
+        let mut runs = RunFiles::new(&dir, "runs");
+        // every record is written twice in a run of its own
+        for i in (0..count).rev().chain(0..count) {
+            runs.write_run([format!("{:04}.domain", i)]).unwrap();
+        }
+        let mut paths = runs.paths().to_vec();
+
+        let mut merge = runs.merge(|last: &mut String, next| last == next).unwrap();
+        assert!(runs.paths().len() <= MAX_OPEN_RUNS);
+        paths.extend_from_slice(runs.paths());
+        let mut got = vec![];
+        while let Some(record) = merge.next_record().unwrap() {
+            got.push(record);
+        }
+        let want: Vec<String> = (0..count).map(|i| format!("{:04}.domain", i)).collect();
+        assert_eq!(got, want);
+        drop(runs);
+        assert!(paths.iter().all(|r| !r.exists()));
+    }
+}
@@ -0,0 +1,169 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, File},
+    io::{BufRead, BufReader, BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+/// Seen holds when an entry first and last appeared in an output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Seen {
+    /// unix timestamp of the first run the entry was written in
+    pub first: u64,
+    /// unix timestamp of the last run the entry was written in
+    pub last: u64,
+}
+
+/// SeenFile keeps the first and last appearance of every entry an output ever
+/// contained. The entries are stored sorted, one per line, followed by the timestamps
+/// separated by tabs.
+#[derive(Debug)]
+pub struct SeenFile {
+    path: PathBuf,
+}
+
+impl SeenFile {
+    /// Initialize a new SeenFile
+    ///
+    /// * `dir`: the directory containing the files of all outputs
+    /// * `tag`: the tag of the output
+    pub fn new(dir: &Path, tag: &str) -> Self {
+        Self {
+            path: dir.join(tag),
+        }
+    }
+
+    /// returns the appearances of all entries, which are empty if the output was never
+    /// written
+    pub fn read(&self) -> anyhow::Result<BTreeMap<String, Seen>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(e) => return Err(e).with_context(|| format!("could not open {:?}", self.path)),
+        };
+        let mut seen = BTreeMap::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            let (entry, first, last) = parse_line(&line)
+                .ok_or_else(|| anyhow::anyhow!("malformed line in {:?}: {}", self.path, line))?;
+            seen.insert(entry.to_string(), Seen { first, last });
+        }
+        Ok(seen)
+    }
+
+    /// records the entries written to the output in a run and returns the number of
+    /// pruned entries. Entries which disappeared keep their last appearance until it's
+    /// older than the retention.
+    ///
+    /// * `entries`: the entries of the output
+    /// * `now`: unix timestamp of the run
+    /// * `retention`: seconds an entry is kept after its last appearance, forever if None
+    pub fn update(
+        &self,
+        entries: &BTreeSet<String>,
+        now: u64,
+        retention: Option<u64>,
+    ) -> anyhow::Result<usize> {
+        let mut seen = self.read()?;
+        for entry in entries {
+            seen.entry(entry.clone())
+                .and_modify(|s| s.last = now)
+                .or_insert(Seen {
+                    first: now,
+                    last: now,
+                });
+        }
+        let len = seen.len();
+        if let Some(retention) = retention {
+            seen.retain(|_, s| now.saturating_sub(s.last) <= retention);
+        }
+        let pruned = len - seen.len();
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("could not create {:?}", dir))?;
+        }
+        // the file is replaced at once, so an interrupted run doesn't lose the history
+        let mut tmp_path = self.path.clone();
+        tmp_path.set_file_name(format!(
+            ".{}.tmp",
+            self.path.file_name().unwrap_or_default().to_string_lossy()
+        ));
+        let file =
+            File::create(&tmp_path).with_context(|| format!("could not create {:?}", tmp_path))?;
+        let mut writer = BufWriter::new(file);
+        for (entry, s) in seen.iter() {
+            writeln!(writer, "{}\t{}\t{}", entry, s.first, s.last)?;
+        }
+        writer.flush()?;
+        fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("could not move {:?} into place", self.path))?;
+        Ok(pruned)
+    }
+}
+
+/// parses a line consisting of the entry, the first and the last appearance
+///
+/// * `line`: the line of the seen file
+fn parse_line(line: &str) -> Option<(&str, u64, u64)> {
+    let mut fields = line.rsplitn(3, '\t');
+    let last = fields.next()?.parse().ok()?;
+    let first = fields.next()?.parse().ok()?;
+    Some((fields.next()?, first, last))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::helper::cache_file_creator::TEST_CACHE;
+
+    use super::*;
+
+    #[test]
+    fn test_seen_file() {
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_seen_file");
+        fs::remove_dir_all(&dir).ok();
+        let seen_file = SeenFile::new(&dir, "malware");
+        assert!(seen_file.read().unwrap().is_empty());
+
+        let entries = |e: &[&str]| e.iter().map(|s| s.to_string()).collect::<BTreeSet<_>>();
+        seen_file
+            .update(&entries(&["a.domain", "b.domain"]), 100, None)
+            .unwrap();
+        seen_file
+            .update(&entries(&["b.domain", "c.domain"]), 200, None)
+            .unwrap();
+
+        let seen = seen_file.read().unwrap();
+        let got: Vec<(&str, u64, u64)> = seen
+            .iter()
+            .map(|(e, s)| (e.as_str(), s.first, s.last))
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                ("a.domain", 100, 100),
+                ("b.domain", 100, 200),
+                ("c.domain", 200, 200)
+            ]
+        );
+
+        // entries which disappeared are pruned once the retention passed
+        assert_eq!(
+            seen_file
+                .update(&entries(&["c.domain"]), 250, Some(200))
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            seen_file
+                .update(&entries(&["c.domain"]), 350, Some(100))
+                .unwrap(),
+            2
+        );
+        let seen = seen_file.read().unwrap();
+        assert_eq!(seen.keys().collect::<Vec<_>>(), vec!["c.domain"]);
+        assert_eq!(seen["c.domain"].first, 200);
+    }
+}
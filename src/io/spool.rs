@@ -0,0 +1,366 @@
+use std::{
+    collections::{btree_map, BTreeMap},
+    io::{self, Write},
+    path::Path,
+    sync::Arc,
+};
+
+use crate::memory::{MemoryBudget, Reservation};
+
+use super::{
+    entry_store::{Entry, FLAG_MULTIPLE_SOURCES},
+    run_files::{must_spill, RunFiles, RunRecord},
+};
+
+/// default amount of memory in bytes a spool may occupy before spilling to disk
+pub const DEFAULT_SPOOL_THRESHOLD: usize = 64 * 1024 * 1024;
+
+/// Spool collects entries in a sorted map. As soon as the approximated memory usage
+/// exceeds the threshold or the memory budget is used up the map is written to a
+/// sorted run file and cleared. On completion all runs are merged into one sorted and
+/// deduplicated output.
+#[derive(Debug)]
+pub struct Spool {
+    /// name used in log messages
+    name: String,
+    /// maximum number of bytes to be held in memory
+    threshold: usize,
+    /// approximated number of bytes currently held in memory
+    size: usize,
+    /// the entries held in memory mapped to their source, flags and score
+    set: BTreeMap<String, (u16, u8, u32)>,
+    runs: RunFiles,
+    /// the memory reserved for the entries held in memory
+    reservation: Reservation,
+}
+
+impl Spool {
+    /// Creates a new empty spool
+    ///
+    /// * `dir`: directory where run files are written to
+    /// * `name`: prefix for the run files
+    /// * `threshold`: maximum number of bytes to be held in memory
+    pub fn new(dir: &Path, name: &str, threshold: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            threshold,
+            size: 0,
+            set: BTreeMap::new(),
+            runs: RunFiles::new(dir, name),
+            reservation: Reservation::default(),
+        }
+    }
+
+    /// reserves the entries held in memory from a memory budget shared with the rest
+    /// of the pipeline
+    ///
+    /// * `budget`: the memory budget of the run, unlimited if None
+    pub fn with_budget(mut self, budget: Option<Arc<MemoryBudget>>) -> Self {
+        self.reservation = Reservation::new(budget);
+        self
+    }
+
+    /// inserts an entry, the origin of a domain inserted before is merged and the
+    /// scores of different lists are summed
+    ///
+    /// * `entry`: the entry to be inserted
+    pub fn insert_entry(&mut self, entry: Entry) -> anyhow::Result<()> {
+        let entry_size = entry_size(&entry.domain);
+        let mut reserved = true;
+        match self.set.entry(entry.domain) {
+            btree_map::Entry::Occupied(mut occupied) => {
+                let (source, flags, score) = occupied.get_mut();
+                if *source != entry.source {
+                    *flags |= FLAG_MULTIPLE_SOURCES;
+                    *score = score.saturating_add(entry.score);
+                }
+                *flags |= entry.flags;
+            }
+            btree_map::Entry::Vacant(vacant) => {
+                vacant.insert((entry.source, entry.flags, entry.score));
+                self.size += entry_size;
+                reserved = self.reservation.reserve(entry_size);
+            }
+        }
+        if must_spill(self.size, self.threshold, reserved) {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// writes the current set to a new run file
+    fn spill(&mut self) -> anyhow::Result<()> {
+        let set = std::mem::take(&mut self.set);
+        self.runs.write_run(
+            set.into_iter()
+                .map(|(domain, (source, flags, score))| Entry {
+                    domain,
+                    source,
+                    flags,
+                    score,
+                }),
+        )?;
+        debug!("{}: spilled {} bytes to disk", self.name, self.size);
+        self.size = 0;
+        self.reservation.release_all();
+        Ok(())
+    }
+
+    /// passes all collected entries sorted and deduplicated to the function and removes
+    /// the run files
+    ///
+    /// * `f`: the function receiving the entries
+    pub fn drain(mut self, mut f: impl FnMut(Entry) -> anyhow::Result<()>) -> anyhow::Result<()> {
+        if self.runs.is_empty() {
+            // the memory of an entry is released once it's passed on, since the
+            // receiver may hold the entry itself, e.g. in another spool
+            for (domain, (source, flags, score)) in std::mem::take(&mut self.set) {
+                self.reservation.release(entry_size(&domain));
+                f(Entry {
+                    domain,
+                    source,
+                    flags,
+                    score,
+                })?;
+            }
+            self.size = 0;
+            return Ok(());
+        }
+
+        if !self.set.is_empty() {
+            self.spill()?;
+        }
+        let mut merge = self.runs.merge(merge_entries)?;
+        while let Some(entry) = merge.next_record()? {
+            f(entry)?;
+        }
+        Ok(())
+    }
+}
+
+/// returns the approximated memory held by an entry of the spool
+///
+/// * `domain`: the domain of the entry
+fn entry_size(domain: &str) -> usize {
+    domain.len() + std::mem::size_of::<(String, u16, u8, u32)>()
+}
+
+/// merges the origin of an entry of the same domain into the one preceding it
+///
+/// * `last`: the entry preceding in order
+/// * `next`: the entry following it
+fn merge_entries(last: &mut Entry, next: &Entry) -> bool {
+    if last.domain != next.domain {
+        return false;
+    }
+    last.merge(next);
+    true
+}
+
+/// a line of a run file consists of the domain, the source, the flags and the score
+/// separated by tabs
+impl RunRecord for Entry {
+    fn write_line(&self, writer: &mut impl Write) -> io::Result<()> {
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}",
+            self.domain, self.source, self.flags, self.score
+        )
+    }
+
+    fn parse_line(line: &str) -> anyhow::Result<Self> {
+        // the domain is split off last since it's the only field which may contain a tab
+        let mut fields = line.rsplitn(4, '\t');
+        let score = fields.next().unwrap_or_default().parse()?;
+        let flags = fields.next().unwrap_or_default().parse()?;
+        let source = fields.next().unwrap_or_default().parse()?;
+        let domain = fields
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("malformed spool line: {}", line))?;
+        Ok(Entry {
+            domain: domain.to_string(),
+            source,
+            flags,
+            score,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        io::run_files::{MAX_OPEN_RUNS, MIN_RUN_SIZE},
+        tests::helper::cache_file_creator::TEST_CACHE,
+    };
+
+    use super::*;
+
+    /// inserts the domains with the first source
+    ///
+    /// * `spool`: the spool the domains are inserted into
+    /// * `domains`: the domains to be inserted
+    fn insert(spool: &mut Spool, domains: &[&str]) {
+        for domain in domains {
+            spool
+                .insert_entry(Entry {
+                    domain: domain.to_string(),
+                    source: 0,
+                    flags: 0,
+                    score: 0,
+                })
+                .unwrap();
+        }
+    }
+
+    /// returns distinct long domains occupying about the given memory in a spool
+    ///
+    /// * `size`: the approximated memory of all domains
+    fn long_domains(size: usize) -> Vec<String> {
+        let label = "a".repeat(500);
+        let count = size / entry_size(&format!("{:05}.{}.domain", 0, label));
+        (0..count)
+            .map(|i| format!("{:05}.{}.domain", i, label))
+            .collect()
+    }
+
+    /// returns the drained domains separated by newlines
+    ///
+    /// * `spool`: the spool to be drained
+    fn drain(spool: Spool) -> String {
+        let mut got = String::new();
+        spool
+            .drain(|e| {
+                got.push_str(&e.domain);
+                got.push('\n');
+                Ok(())
+            })
+            .unwrap();
+        got
+    }
+
+    #[test]
+    fn test_spool_merges_runs() {
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_spool_merges_runs");
+        // a tiny threshold forces a spill on nearly every insert
+        let mut spool = Spool::new(&dir, "spool", 64);
+        insert(
+            &mut spool,
+            &["d.domain", "b.domain", "a.domain", "c.domain", "b.domain"],
+        );
+        assert!(!spool.runs.is_empty());
+
+        let want = "a.domain\nb.domain\nc.domain\nd.domain\n";
+        assert_eq!(want, drain(spool));
+    }
+
+    #[test]
+    fn test_spool_budget() {
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_spool_budget");
+        // the threshold is never reached, but the budget shared by both spools is
+        let limit = MIN_RUN_SIZE * 2;
+        let budget = MemoryBudget::new(limit);
+        let mut first = Spool::new(&dir, "first", usize::MAX).with_budget(Some(budget.clone()));
+        let mut second = Spool::new(&dir, "second", usize::MAX).with_budget(Some(budget.clone()));
+        let domains = long_domains(MIN_RUN_SIZE * 3 / 2);
+        for domain in domains.iter() {
+            insert(&mut first, &[domain]);
+        }
+        for domain in domains.iter() {
+            insert(&mut second, &[domain]);
+        }
+        assert!(first.runs.is_empty());
+        // the second spool holds a minimum of entries beyond the budget
+        assert_eq!(second.runs.paths().len(), 1);
+        assert!(budget.peak() <= limit);
+
+        assert_eq!(drain(second).lines().count(), domains.len());
+        // the memory is returned once the entries are written
+        drop(first);
+        assert!(budget.try_reserve(limit));
+    }
+
+    #[test]
+    fn test_spool_small_set() {
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_spool_small_set");
+        // a used up budget doesn't spill single entries
+        let budget = MemoryBudget::new(0);
+        let mut spool = Spool::new(&dir, "spool", usize::MAX).with_budget(Some(budget));
+        insert(&mut spool, &["b.domain", "a.domain"]);
+        assert!(spool.runs.is_empty());
+        assert_eq!("a.domain\nb.domain\n", drain(spool));
+    }
+
+    #[test]
+    fn test_spool_drain_releases() {
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_spool_drain_releases");
+        // the entries fit into the budget once, the drained spool releases them while
+        // they are passed on
+        let domains = long_domains(MIN_RUN_SIZE * 3 / 2);
+        let size: usize = domains.iter().map(|d| entry_size(d)).sum();
+        let budget = MemoryBudget::new(size);
+        let mut first = Spool::new(&dir, "first", usize::MAX).with_budget(Some(budget.clone()));
+        let mut second = Spool::new(&dir, "second", usize::MAX).with_budget(Some(budget));
+        for domain in domains.iter() {
+            insert(&mut first, &[domain]);
+        }
+        first.drain(|e| second.insert_entry(e)).unwrap();
+        assert!(second.runs.is_empty());
+    }
+
+    #[test]
+    fn test_spool_merges_many_runs() {
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_spool_merges_many_runs");
+        // every insert spills a run of its own
+        let mut spool = Spool::new(&dir, "spool", 0);
+        let count = MAX_OPEN_RUNS * 2 + 5;
+        for i in (0..count).rev() {
+            insert(&mut spool, &[&format!("{:04}.domain", i)]);
+        }
+        assert_eq!(spool.runs.paths().len(), count);
+        let runs = spool.runs.paths().to_vec();
+
+        let want: String = (0..count).map(|i| format!("{:04}.domain\n", i)).collect();
+        assert_eq!(want, drain(spool));
+        assert!(runs.iter().all(|r| !r.exists()));
+    }
+
+    #[test]
+    fn test_spool_merges_sources() {
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_spool_merges_sources");
+        let mut spool = Spool::new(&dir, "spool", 64);
+        for (domain, source) in [("b.domain", 0), ("a.domain", 1), ("b.domain", 1)] {
+            spool
+                .insert_entry(Entry {
+                    domain: domain.to_string(),
+                    source,
+                    flags: 0,
+                    score: source as u32 + 1,
+                })
+                .unwrap();
+        }
+
+        let mut got = vec![];
+        spool
+            .drain(|e| {
+                got.push(e);
+                Ok(())
+            })
+            .unwrap();
+        let flags: Vec<(&str, u8, u32)> = got
+            .iter()
+            .map(|e| (e.domain.as_str(), e.flags, e.score))
+            .collect();
+        assert_eq!(
+            flags,
+            vec![("a.domain", 0, 2), ("b.domain", FLAG_MULTIPLE_SOURCES, 3)]
+        );
+    }
+}
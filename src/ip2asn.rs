@@ -0,0 +1,141 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    net::IpAddr,
+    path::Path,
+};
+
+use anyhow::Context;
+
+/// AsnRecord is a range of addresses announced by an autonomous system
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsnRecord {
+    pub start: IpAddr,
+    pub end: IpAddr,
+    /// number of the autonomous system, 0 if the range isn't routed
+    pub asn: u32,
+    /// ISO 3166 country code of the autonomous system
+    pub country: String,
+}
+
+/// AsnDatabase maps addresses to autonomous systems, it's read from the tab separated
+/// files of iptoasn.com (ip2asn-v4.tsv, ip2asn-v6.tsv or ip2asn-combined.tsv)
+#[derive(Debug, Default)]
+pub struct AsnDatabase {
+    /// the ranges ordered by their first address
+    records: Vec<AsnRecord>,
+}
+
+impl AsnDatabase {
+    /// reads a database file
+    ///
+    /// * `path`: the file system path of the database
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file = File::open(path).with_context(|| format!("could not open {:?}", path))?;
+        let mut records = vec![];
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record = parse_record(&line)
+                .with_context(|| format!("{:?}:{} is malformed", path, i + 1))?;
+            records.push(record);
+        }
+        records.sort_by(|a, b| a.start.cmp(&b.start));
+        Ok(Self { records })
+    }
+
+    /// returns the range containing the address
+    ///
+    /// * `ip`: the address to be looked up
+    pub fn lookup(&self, ip: IpAddr) -> Option<&AsnRecord> {
+        // the last range starting at or before the address
+        let index = self.records.partition_point(|r| r.start <= ip);
+        let record = self.records.get(index.checked_sub(1)?)?;
+        (ip <= record.end && record.start.is_ipv4() == ip.is_ipv4()).then_some(record)
+    }
+}
+
+/// AsnFilter selects addresses by the autonomous system and country announcing them
+#[derive(Debug, Clone, Default)]
+pub struct AsnFilter {
+    /// if set only addresses of these autonomous systems or countries are kept
+    pub include_asns: Vec<u32>,
+    pub include_countries: Vec<String>,
+    /// addresses of these autonomous systems or countries are dropped
+    pub exclude_asns: Vec<u32>,
+    pub exclude_countries: Vec<String>,
+}
+
+impl AsnFilter {
+    /// returns true if the address passes the filter
+    ///
+    /// * `record`: the range containing the address, None if it isn't routed
+    pub fn is_kept(&self, record: Option<&AsnRecord>) -> bool {
+        let matches = |asns: &[u32], countries: &[String]| {
+            record.is_some_and(|r| {
+                asns.contains(&r.asn)
+                    || countries.iter().any(|c| c.eq_ignore_ascii_case(&r.country))
+            })
+        };
+        let included = (self.include_asns.is_empty() && self.include_countries.is_empty())
+            || matches(&self.include_asns, &self.include_countries);
+        included && !matches(&self.exclude_asns, &self.exclude_countries)
+    }
+}
+
+/// parses a line of the form `start end asn country description`
+///
+/// * `line`: a line of a database file
+fn parse_record(line: &str) -> anyhow::Result<AsnRecord> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    if fields.len() < 4 {
+        return Err(anyhow::anyhow!("expected at least 4 fields"));
+    }
+    Ok(AsnRecord {
+        start: fields[0].parse()?,
+        end: fields[1].parse()?,
+        asn: fields[2].parse()?,
+        country: fields[3].to_string(),
+    })
+}
+
+/// returns the address of an IP entry, networks in CIDR notation are represented by
+/// their first address
+///
+/// * `entry`: the entry
+pub fn entry_address(entry: &str) -> Option<IpAddr> {
+    entry.split('/').next()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use crate::tests::helper::cache_file_creator::TEST_CACHE;
+
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let mut path = PathBuf::from(TEST_CACHE);
+        path.push("test_ip2asn.tsv");
+        fs::write(
+            &path,
+            "1.0.0.0\t1.0.0.255\t13335\tUS\tCLOUDFLARENET\n\
+             1.0.4.0\t1.0.7.255\t38803\tAU\tGTELECOM\n\
+             2001:db8::\t2001:db8::ffff\t64496\tZZ\tDOCUMENTATION\n",
+        )
+        .unwrap();
+        let database = AsnDatabase::load(&path).unwrap();
+
+        let lookup = |entry: &str| database.lookup(entry_address(entry).unwrap());
+        assert_eq!(lookup("1.0.0.1").unwrap().asn, 13335);
+        assert_eq!(lookup("1.0.5.0/24").unwrap().country, "AU");
+        assert_eq!(lookup("1.0.2.1"), None);
+        assert_eq!(lookup("2001:db8::1").unwrap().asn, 64496);
+        assert_eq!(lookup("9.9.9.9"), None);
+        assert_eq!(entry_address("example.com"), None);
+    }
+}
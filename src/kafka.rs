@@ -0,0 +1,200 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Context;
+use rskafka::{
+    client::{partition::Compression, ClientBuilder},
+    record::Record,
+    time::OffsetDateTime,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::report::unix_timestamp;
+
+/// maximum number of records produced in one request if no limit was configured
+pub const DEFAULT_BATCH_SIZE: usize = 1000;
+
+/// KafkaMode selects the records produced for a written output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum KafkaMode {
+    /// one record per added or removed domain
+    #[default]
+    Changes,
+    /// one record per domain of the output
+    Entries,
+}
+
+/// Change tells whether a domain was added to or removed from an output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Added,
+    Removed,
+}
+
+/// KafkaSettings configures the production of the outputs to a Kafka topic
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct KafkaSettings {
+    /// addresses of the bootstrap brokers (e.g. "127.0.0.1:9092")
+    pub brokers: Vec<String>,
+    /// the topic the records are produced to
+    pub topic: String,
+    /// the partition of the topic the records are produced to
+    #[serde(default)]
+    pub partition: i32,
+    /// produce the changes of the outputs or all of their entries
+    #[serde(default)]
+    pub mode: KafkaMode,
+    /// maximum number of records produced in one request
+    pub batch_size: Option<usize>,
+}
+
+/// EntryMessage is the JSON value of a produced record
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct EntryMessage {
+    /// the tag of the output
+    pub output: String,
+    /// the name of the project the output belongs to
+    pub project: Option<String>,
+    /// unix timestamp of the run
+    pub timestamp: u64,
+    pub domain: String,
+    /// the change of the domain, missing if all entries are produced
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change: Option<Change>,
+}
+
+impl EntryMessage {
+    /// returns the messages of all entries of an output
+    ///
+    /// * `output`: the tag of the output
+    /// * `project`: the name of the project the output belongs to
+    /// * `entries`: the domains of the output
+    pub fn entries(
+        output: &str,
+        project: Option<String>,
+        entries: &BTreeSet<String>,
+    ) -> Vec<EntryMessage> {
+        let timestamp = unix_timestamp();
+        entries
+            .iter()
+            .map(|domain| EntryMessage {
+                output: output.to_string(),
+                project: project.clone(),
+                timestamp,
+                domain: domain.clone(),
+                change: None,
+            })
+            .collect()
+    }
+
+    /// returns the messages of the changes of an output, removals come first
+    ///
+    /// * `output`: the tag of the output
+    /// * `project`: the name of the project the output belongs to
+    /// * `added`: the domains added to the output
+    /// * `removed`: the domains removed from the output
+    pub fn changes(
+        output: &str,
+        project: Option<String>,
+        added: &[String],
+        removed: &[String],
+    ) -> Vec<EntryMessage> {
+        let timestamp = unix_timestamp();
+        let removed = removed.iter().map(|d| (d, Change::Removed));
+        let added = added.iter().map(|d| (d, Change::Added));
+        removed
+            .chain(added)
+            .map(|(domain, change)| EntryMessage {
+                output: output.to_string(),
+                project: project.clone(),
+                timestamp,
+                domain: domain.clone(),
+                change: Some(change),
+            })
+            .collect()
+    }
+
+    /// returns the record of the message keyed by the domain, so all records of a
+    /// domain are compacted together
+    fn record(&self) -> anyhow::Result<Record> {
+        Ok(Record {
+            key: Some(self.domain.as_bytes().to_vec()),
+            value: Some(serde_json::to_vec(self)?),
+            headers: BTreeMap::from([("output".to_string(), self.output.as_bytes().to_vec())]),
+            timestamp: OffsetDateTime::now_utc(),
+        })
+    }
+}
+
+impl KafkaSettings {
+    /// returns the maximum number of records produced in one request
+    pub fn batch_size(&self) -> usize {
+        self.batch_size.unwrap_or(DEFAULT_BATCH_SIZE).max(1)
+    }
+
+    /// produces the messages to the configured topic in batches
+    ///
+    /// * `messages`: the messages of a run
+    pub async fn produce(&self, messages: &[EntryMessage]) -> anyhow::Result<()> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+        let client = ClientBuilder::new(self.brokers.clone())
+            .build()
+            .await
+            .with_context(|| format!("could not connect to Kafka brokers {:?}", self.brokers))?;
+        let partition = client
+            .partition_client(self.topic.clone(), self.partition)
+            .with_context(|| format!("could not access Kafka topic {}", self.topic))?;
+        for batch in messages.chunks(self.batch_size()) {
+            let records = batch
+                .iter()
+                .map(|m| m.record())
+                .collect::<anyhow::Result<Vec<Record>>>()?;
+            partition
+                .produce(records, Compression::NoCompression)
+                .await
+                .with_context(|| format!("could not produce to Kafka topic {}", self.topic))?;
+        }
+        debug!(
+            "Produced {} records to Kafka topic {}",
+            messages.len(),
+            self.topic
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_change_messages() {
+        let added = vec!["a.domain".to_string()];
+        let removed = vec!["b.domain".to_string()];
+        let messages = EntryMessage::changes("malware", None, &added, &removed);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].domain, "b.domain");
+        assert_eq!(messages[0].change, Some(Change::Removed));
+        assert_eq!(messages[1].change, Some(Change::Added));
+
+        let record = messages[1].record().unwrap();
+        assert_eq!(record.key, Some(b"a.domain".to_vec()));
+        let value: serde_json::Value = serde_json::from_slice(&record.value.unwrap()).unwrap();
+        assert_eq!(value["change"], "added");
+        assert_eq!(value["output"], "malware");
+    }
+
+    #[test]
+    fn test_entry_messages() {
+        let entries = BTreeSet::from(["a.domain".to_string(), "b.domain".to_string()]);
+        let messages = EntryMessage::entries("malware", Some("dev".to_string()), &entries);
+        assert_eq!(messages.len(), 2);
+        assert!(messages.iter().all(|m| m.change.is_none()));
+        let value = serde_json::to_value(&messages[0]).unwrap();
+        // entries carry no change field
+        assert!(value.get("change").is_none());
+        assert_eq!(value["project"], "dev");
+    }
+}
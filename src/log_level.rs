@@ -17,6 +17,17 @@ impl Display for LogLevel {
     }
 }
 
+impl From<LogLevel> for log::Level {
+    fn from(value: LogLevel) -> Self {
+        match value {
+            LogLevel::Debug => log::Level::Debug,
+            LogLevel::Info => log::Level::Info,
+            LogLevel::Warn => log::Level::Warn,
+            LogLevel::Error => log::Level::Error,
+        }
+    }
+}
+
 impl From<&LogLevel> for Cow<'static, str> {
     fn from(value: &LogLevel) -> Self {
         Cow::Owned(value.to_string())
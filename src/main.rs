@@ -1,16 +1,48 @@
 #![feature(let_chains)]
+mod audit;
+mod bench;
+mod clickhouse;
 mod config;
+mod deploy;
+mod dga;
+mod diff;
+mod dns_update;
+mod elasticsearch;
+mod encryption;
 mod filter_controller;
 mod filter_list;
+mod fuzz_corpus;
+mod health;
+mod hook;
 mod input;
 mod io;
+mod ip2asn;
+mod kafka;
 mod log_level;
+mod memory;
+mod mqtt;
 mod output;
+mod output_settings;
+mod peer;
+mod platform;
+mod post_processing;
+mod promote;
+mod query;
+mod rdap;
+mod report;
+mod schedule;
+#[cfg(windows)]
+mod service;
+mod shared_cache;
 mod stages;
+mod syslog;
+mod telemetry;
 mod tests;
+mod watch;
 
 use std::{
-    path::Path,
+    collections::HashSet,
+    path::{Path, PathBuf},
     process::exit,
     sync::{
         atomic::{AtomicBool, Ordering},
@@ -18,11 +50,18 @@ use std::{
     },
 };
 
-use clap::Parser;
+use audit::AuditRecord;
+use clap::{Parser, Subcommand};
 use colored::*;
 use env_logger::Env;
 use filter_controller::FilterController;
+use health::Health;
 use log_level::LogLevel;
+use opentelemetry::KeyValue;
+use report::{unix_timestamp, RunReport, REPORT_FILE_NAME};
+use syslog::RunEvent;
+use tokio::sync::Notify;
+use watch::{ConfigWatcher, FileWatcher};
 
 use crate::config::Config;
 
@@ -32,6 +71,24 @@ pub const DOWNLOAD_PATH: &str = "download";
 pub const EXTRACT_PATH: &str = "extract";
 /// Sub path for the assembled categorized lists
 pub const CATEGORIZE_PATH: &str = "categorize";
+/// Sub path for the indicators read from lists of other formats than lines
+pub const INDICATORS_PATH: &str = "indicators";
+/// Sub path for the normalized entries of the extracted lists
+pub const ENTRIES_PATH: &str = "entries";
+/// Sub path for category lists exceeding the in-memory threshold
+pub const SPOOL_PATH: &str = "spool";
+/// Sub path for the first and last appearance of the entries of the outputs
+pub const SEEN_PATH: &str = "seen";
+/// Sub path for the entries of the outputs as they were last published
+pub const PUBLISHED_PATH: &str = "published";
+/// Sub path for the data of projects
+pub const PROJECTS_PATH: &str = "projects";
+/// Sub path for the cache and outputs of dry runs
+pub const DRY_RUN_PATH: &str = "dry-run";
+/// Sub path for the cache and outputs of rebuilt runs
+pub const REBUILD_PATH: &str = "rebuild";
+/// Sub path for the previous files of deployed targets
+pub const BACKUP_PATH: &str = "backup";
 
 #[macro_use]
 extern crate log;
@@ -43,6 +100,63 @@ struct Args {
     config: String,
     #[arg(value_enum, short, long, default_value = "warn")]
     log_level: LogLevel,
+    /// keep running and rebuild the outputs when a local file source is modified
+    #[arg(short, long)]
+    watch: bool,
+    /// build the outputs into the cache directory and show the differences to the
+    /// deployed outputs
+    #[arg(long)]
+    dry_run: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// check the outputs in the staging directory and move them to the output directory
+    Promote,
+    /// install the outputs into their system locations, backing up the previous files
+    Deploy {
+        /// restore the files backed up by the last deployment
+        #[arg(long)]
+        rollback: bool,
+    },
+    /// export lines of the downloaded lists rejected during processing as fuzzing corpus
+    FuzzCorpus {
+        /// directory the corpus files are written to
+        #[arg(short, long, default_value = "fuzz/corpus/lint")]
+        out_dir: String,
+    },
+    /// print the output files containing a domain, hashed outputs included, together
+    /// with the times the domain first and last appeared in them
+    Query {
+        /// the domain to look up
+        domain: String,
+    },
+    /// measure the throughput of the extraction, normalization and output formats
+    Bench {
+        /// number of synthetic hosts file lines processed
+        #[arg(short, long, default_value_t = 100_000)]
+        entries: usize,
+        /// a recorded list processed instead of synthetic lines
+        #[arg(short, long)]
+        input: Option<String>,
+        /// the regex extracting the entries of the recorded list
+        #[arg(short, long)]
+        regex: Option<String>,
+    },
+    /// rebuild the outputs of a past run from the inputs recorded in the input store
+    Rebuild {
+        /// the id of the run as found in the audit log
+        #[arg(long)]
+        run: String,
+    },
+    /// manage harvester as a Windows service running in watch mode
+    #[cfg(windows)]
+    Service {
+        #[command(subcommand)]
+        action: service::ServiceAction,
+    },
 }
 
 #[tokio::main]
@@ -50,6 +164,16 @@ async fn main() -> anyhow::Result<()> {
     // setup command line interface
     let args = Args::parse();
 
+    // a Windows service logs to the event log and is controlled by the service manager
+    #[cfg(windows)]
+    if let Some(Command::Service {
+        action: service::ServiceAction::Run,
+    }) = &args.command
+    {
+        eventlog::init(service::EVENT_SOURCE, args.log_level.into())?;
+        return service::run();
+    }
+
     // initialize logging
     let env = Env::default()
         .filter_or("HV_LOG_LEVEL", &args.log_level)
@@ -61,12 +185,16 @@ async fn main() -> anyhow::Result<()> {
     // is_processing determines if the program was interrupted or is still running
     let is_processing = Arc::new(AtomicBool::new(true));
     let is_proc = Arc::clone(&is_processing);
+    // stop wakes up watch mode while waiting for modifications
+    let stop = Arc::new(Notify::new());
+    let stop_ctrl_c = Arc::clone(&stop);
 
     // handle ctrl_c
     tokio::spawn(async move {
         tokio::signal::ctrl_c().await.unwrap();
         info!("{}", "gracefully shutting down ...".yellow());
         is_proc.store(false, Ordering::SeqCst);
+        stop_ctrl_c.notify_one();
     });
 
     // crate configuration
@@ -78,58 +206,370 @@ async fn main() -> anyhow::Result<()> {
         Ok(c) => c,
     };
 
-    // the lists are going through a process of four stages
-    let mut download_controller = FilterController::new(&config, is_processing.clone());
+    if let Some(settings) = &config.telemetry {
+        if let Err(e) = telemetry::init(settings) {
+            error!("{:?}", e);
+        }
+    }
+
+    match &args.command {
+        Some(Command::Promote) => {
+            if let Err(e) = promote::promote_all(&config) {
+                error!("{:?}", e);
+                exit(1);
+            }
+            return Ok(());
+        }
+        Some(Command::Deploy { rollback }) => {
+            let config_path = Path::new(&args.config);
+            let result = match rollback {
+                true => deploy::rollback_all(&config, config_path).await,
+                false => deploy::deploy_all(&config, config_path).await,
+            };
+            if let Err(e) = result {
+                error!("{:?}", e);
+                exit(1);
+            }
+            return Ok(());
+        }
+        Some(Command::FuzzCorpus { out_dir }) => {
+            match fuzz_corpus::export(&config, Path::new(out_dir)).await {
+                Ok(n) => info!("{} rejected lines exported to {}", n, out_dir),
+                Err(e) => {
+                    error!("{:?}", e);
+                    exit(1);
+                }
+            }
+            return Ok(());
+        }
+        Some(Command::Query { domain }) => match query::query(&config, domain) {
+            Ok(found) if found.is_empty() => exit(1),
+            Ok(found) => {
+                found.iter().for_each(|f| println!("{}", f));
+                return Ok(());
+            }
+            Err(e) => {
+                error!("{:?}", e);
+                exit(1);
+            }
+        },
+        Some(Command::Bench {
+            entries,
+            input,
+            regex,
+        }) => {
+            let input = input.as_deref().map(Path::new);
+            match bench::bench(&config, input, regex.as_deref(), *entries).await {
+                Ok(stats) => stats.iter().for_each(|s| println!("{}", s)),
+                Err(e) => {
+                    error!("{:?}", e);
+                    exit(1);
+                }
+            }
+            return Ok(());
+        }
+        Some(Command::Rebuild { run }) => {
+            match rebuild(&config, run, is_processing.clone()).await {
+                Ok(output_dirs) => output_dirs.iter().for_each(|d| println!("{}", d)),
+                Err(e) => {
+                    error!("{:?}", e);
+                    exit(1);
+                }
+            }
+            return Ok(());
+        }
+        #[cfg(windows)]
+        Some(Command::Service { action }) => {
+            let result = match action {
+                service::ServiceAction::Install => service::install(Path::new(&args.config)),
+                service::ServiceAction::Uninstall => service::uninstall(),
+                service::ServiceAction::Run => unreachable!("handled before logging is set up"),
+            };
+            if let Err(e) = result {
+                error!("{:?}", e);
+                exit(1);
+            }
+            return Ok(());
+        }
+        None => {}
+    }
 
-    // start the processing chain by downloading the filter lists
-    info!("{}", "Downalading lists ...".yellow());
-    let mut extract_controller = match download_controller.run(DOWNLOAD_PATH).await {
-        Ok(c) => c,
-        Err(e) => {
+    if args.dry_run {
+        let mut dry_run_config = config.dry_run_config();
+        let result = run(&mut dry_run_config, is_processing.clone()).await;
+        telemetry::shutdown().await;
+        if let Err(e) = result {
+            error!("{:?}", e);
+            exit(1);
+        }
+        match diff::output_diffs(&config, &dry_run_config) {
+            Ok(diffs) => diffs.iter().for_each(|d| print!("{}", d)),
+            Err(e) => {
+                error!("{:?}", e);
+                exit(1);
+            }
+        }
+        return Ok(());
+    }
+
+    if !args.watch {
+        schedule::jitter(config.schedule_jitter).await;
+        let result = run(&mut config, is_processing.clone()).await;
+        telemetry::shutdown().await;
+        if let Err(e) = result {
             error!("{:?}", e);
             exit(1);
         }
+        return Ok(());
+    }
+
+    let result = watch_mode(&args.config, config, is_processing, stop).await;
+    telemetry::shutdown().await;
+    if let Err(e) = result {
+        error!("{:?}", e);
+        exit(1);
+    }
+
+    Ok(())
+}
+
+/// Trigger is the cause of a run in watch mode
+enum Trigger {
+    /// the file sources of the lists were modified
+    Files(Vec<String>),
+    /// the configuration was modified
+    Config,
+    /// the fetch windows of the lists opened
+    Window(Vec<String>),
+    /// the block windows of the outputs opened or closed
+    Schedule(Vec<String>),
+    /// the outputs of the primary are due to be pulled
+    Pull,
+}
+
+/// runs the processing once and keeps rebuilding the outputs whenever the
+/// configuration or a local file source is modified, a fetch window opens or a block
+/// window opens or closes until being stopped
+///
+/// * `config_path`: file system path of the configuration file or directory
+/// * `config`: the configuration loaded from the config path
+/// * `is_processing`: determines if the program was interrupted
+/// * `stop`: notified when the program is supposed to quit
+async fn watch_mode(
+    config_path: &str,
+    mut config: Config,
+    is_processing: Arc<AtomicBool>,
+    stop: Arc<Notify>,
+) -> anyhow::Result<()> {
+    // in watch mode the file sources are watched for modifications after the initial run
+    let mut watcher = FileWatcher::new(&config)?;
+    let mut config_watcher = ConfigWatcher::new(Path::new(config_path))?;
+    let health = Health::default();
+    if let Some(settings) = &config.health {
+        health.serve(settings).await?;
+    }
+    if let Some(settings) = config.peer.as_ref().filter(|p| p.listen.is_some()) {
+        peer::serve(&config, settings).await?;
+    }
+    schedule::jitter(config.schedule_jitter).await;
+    health.run_started();
+    let result = run(&mut config, is_processing.clone()).await;
+    health.run_finished(result.is_ok());
+    if let Err(e) = result {
+        error!("{:?}", e);
+    }
+    while is_processing.load(Ordering::SeqCst) {
+        let lists = config.all_lists().unwrap_or_default();
+        let mut outputs = config.outputs.clone();
+        outputs.extend(config.projects.iter().flat_map(|p| p.outputs.clone()));
+        let trigger = tokio::select! {
+            changed_lists = watcher.changed_lists() => changed_lists.map(Trigger::Files),
+            changed = config_watcher.changed() => changed.map(|_| Trigger::Config),
+            opened = schedule::windows_opened(&lists) => Some(Trigger::Window(opened)),
+            changed = schedule::block_windows_changed(&outputs) => Some(Trigger::Schedule(changed)),
+            _ = peer::pull_due(config.peer.as_ref()) => Some(Trigger::Pull),
+            _ = stop.notified() => break,
+        };
+        let trigger = match trigger {
+            Some(t) => t,
+            None => break,
+        };
+        match &trigger {
+            Trigger::Files(ids) => info!("{} {}", "Modified:".yellow(), ids.join(", ")),
+            Trigger::Config => info!("{} {}", "Modified:".yellow(), config_path),
+            Trigger::Window(ids) => info!("{} {}", "Fetch window:".yellow(), ids.join(", ")),
+            Trigger::Schedule(tags) => info!("{} {}", "Block window:".yellow(), tags.join(", ")),
+            Trigger::Pull => debug!("pulling the outputs of the primary"),
+        }
+        // reload the configuration to get hold of the cached configuration of the last run
+        // an invalid configuration is reported and the previous one is kept
+        config = match Config::load(Path::new(config_path)) {
+            Ok(c) => c,
+            Err(e) => {
+                error!("{}: {:?}", config_path, e);
+                continue;
+            }
+        };
+        match &trigger {
+            // the file sources may have changed along with the configuration
+            Trigger::Config => match FileWatcher::new(&config) {
+                Ok(w) => watcher = w,
+                Err(e) => error!("{:?}", e),
+            },
+            Trigger::Files(ids) => invalidate_downloads(&config, ids),
+            // the lists whose fetch window opened are downloaded if they changed
+            Trigger::Window(_) => schedule::jitter(config.schedule_jitter).await,
+            // the scheduled outputs are rebuilt from the categorized lists
+            Trigger::Schedule(_) | Trigger::Pull => {}
+        }
+        health.run_started();
+        let result = run(&mut config, is_processing.clone()).await;
+        health.run_finished(result.is_ok());
+        if let Err(e) = result {
+            error!("{:?}", e);
+        }
+    }
+
+    Ok(())
+}
+
+/// processes all lists configured and runs the post-run hooks
+///
+/// * `config`: the configuration to process
+/// * `is_processing`: determines if the program was interrupted
+async fn run(config: &mut Config, is_processing: Arc<AtomicBool>) -> anyhow::Result<()> {
+    notify(config, RunEvent::Started).await;
+    let result = telemetry::traced("run", vec![], run_traced(config, is_processing)).await;
+    let event = match &result {
+        Ok(_) => RunEvent::Finished,
+        Err(e) => RunEvent::Failed(format!("{:#}", e)),
     };
+    notify(config, event).await;
+    result
+}
+
+/// reports a state change of the run to the syslog server, a server being unavailable
+/// doesn't fail the run
+///
+/// * `config`: the configuration containing the syslog settings
+/// * `event`: the state change of the run
+async fn notify(config: &Config, event: RunEvent) {
+    if let Some(syslog) = &config.syslog {
+        if let Err(e) = syslog.notify(&event, config.project.as_deref()).await {
+            warn!("could not notify syslog server: {:?}", e);
+        }
+    }
+}
+
+/// processes all lists configured within the span of the run
+///
+/// * `config`: the configuration to process
+/// * `is_processing`: determines if the program was interrupted
+async fn run_traced(config: &mut Config, is_processing: Arc<AtomicBool>) -> anyhow::Result<()> {
+    // a secondary takes over the outputs of its primary instead of harvesting the lists
+    if let Some(settings) = config.peer.as_ref().filter(|p| p.primary.is_some()) {
+        if peer::pull(config, settings).await? > 0 {
+            run_hooks(
+                config,
+                &PathBuf::from(&config.cache_dir).join(REPORT_FILE_NAME),
+            )
+            .await?;
+        }
+        return Ok(());
+    }
+
+    // the lists of all projects are downloaded at once so they share the download cache
+    let download_config = config.download_config()?;
+    let mut download_controller = FilterController::new(&download_config, is_processing.clone());
+
+    // start the processing chain by downloading the filter lists
+    info!("{}", "Downalading lists ...".yellow());
+    let mut extract_controller =
+        telemetry::traced("download", vec![], download_controller.run(DOWNLOAD_PATH)).await?;
+    let cached_lists = extract_controller.cached_lists.take().unwrap_or_default();
+
+    // the records of all projects in the audit log share the id of the run
+    let run_id = unix_timestamp().to_string();
+    build(config, cached_lists.clone(), is_processing.clone(), &run_id).await?;
+
+    let mut projects_failed = 0;
+    for mut project_config in config.project_configs() {
+        if !is_processing.load(Ordering::SeqCst) {
+            break;
+        }
+        let project = project_config.project.clone().unwrap_or_default();
+        info!("{} {}", "Project:".yellow(), project);
+        let attributes = vec![KeyValue::new("project", project.clone())];
+        if let Err(e) = telemetry::traced(
+            "project",
+            attributes,
+            build(
+                &mut project_config,
+                cached_lists.clone(),
+                is_processing.clone(),
+                &run_id,
+            ),
+        )
+        .await
+        {
+            error!("{}: {:?}", project, e);
+            projects_failed += 1;
+        }
+    }
+    if projects_failed > 0 {
+        return Err(anyhow::anyhow!("{} project(s) failed", projects_failed));
+    }
+
+    Ok(())
+}
+
+/// transforms the downloaded lists into the outputs of a configuration and runs its
+/// post-run hooks
+///
+/// * `config`: the configuration to process
+/// * `cached_lists`: ids of the lists which didn't change since the last download
+/// * `is_processing`: determines if the program was interrupted
+/// * `run_id`: the id of the run recorded in the audit log
+async fn build(
+    config: &mut Config,
+    cached_lists: HashSet<String>,
+    is_processing: Arc<AtomicBool>,
+    run_id: &str,
+) -> anyhow::Result<()> {
+    let mut report = RunReport::new(config.build_dir());
+    let extract_path = config.stage_path(EXTRACT_PATH);
+    let categorize_path = config.stage_path(CATEGORIZE_PATH);
+    let mut extract_controller =
+        FilterController::from_downloads(config, cached_lists, is_processing.clone());
 
     // the second stage extracts the URLs from the downloaded lists which come in heterogeneous formats
     if is_processing.load(Ordering::SeqCst) {
         info!("{}", "Extracting domains ...".yellow());
     }
-    let mut categorize_controller = match extract_controller.run(DOWNLOAD_PATH, EXTRACT_PATH).await
-    {
-        Ok(c) => c,
-        Err(e) => {
-            error!("{:?}", e);
-            exit(1);
-        }
-    };
+    let mut categorize_controller = telemetry::traced(
+        "extract",
+        vec![],
+        extract_controller.run(DOWNLOAD_PATH, &extract_path),
+    )
+    .await?;
 
     // the third stage assembles the URLs into lists corresponding to the tags set in the configuration file
     if is_processing.load(Ordering::SeqCst) {
         info!("{}", "Categorizing domains ...".yellow());
     }
-    let mut output_controller = match categorize_controller
-        .run(EXTRACT_PATH, CATEGORIZE_PATH)
-        .await
-    {
-        Ok(c) => c,
-        Err(e) => {
-            error!("{:?}", e);
-            exit(1);
-        }
-    };
+    let mut output_controller = telemetry::traced(
+        "categorize",
+        vec![],
+        categorize_controller.run(&extract_path, &categorize_path),
+    )
+    .await?;
 
     // the fourth stage finally transforms the category lists into the desired output format
     if is_processing.load(Ordering::SeqCst) {
         info!("{}", "Creating output files ...".yellow());
     }
-    match output_controller.run(CATEGORIZE_PATH).await {
-        Ok(c) => c,
-        Err(e) => {
-            error!("{:?}", e);
-            exit(1);
-        }
-    };
+    let updated_lists =
+        telemetry::traced("output", vec![], output_controller.run(&categorize_path)).await?;
 
     if let Err(e) = config.save_to_cache() {
         error!(
@@ -138,5 +578,101 @@ async fn main() -> anyhow::Result<()> {
         );
     }
 
+    if !is_processing.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+
+    // write the run report and hand it over to the post-run hooks
+    report.finish(config.get_tags(), updated_lists);
+    let mut report_path = PathBuf::from(&config.cache_dir);
+    report_path.push(config.stage_path(REPORT_FILE_NAME));
+    report.save(&report_path)?;
+    if let Some(audit_log) = &config.audit_log {
+        let store = config.input_store.as_deref().map(Path::new);
+        AuditRecord::new(config, run_id, store)?.append(Path::new(audit_log))?;
+    }
+    run_hooks(config, &report_path).await
+}
+
+/// rebuilds the outputs of a past run from the configurations and downloads recorded
+/// in the input store, returns the directories containing the rebuilt outputs
+///
+/// * `config`: the configuration containing the audit log and the input store
+/// * `run`: the id of the run
+/// * `is_processing`: determines if the program was interrupted
+async fn rebuild(
+    config: &Config,
+    run: &str,
+    is_processing: Arc<AtomicBool>,
+) -> anyhow::Result<Vec<String>> {
+    let (audit_log, store) = match (&config.audit_log, &config.input_store) {
+        (Some(audit_log), Some(store)) => (Path::new(audit_log), Path::new(store)),
+        _ => {
+            return Err(anyhow::anyhow!(
+                "rebuilding a run requires an audit_log and an input_store"
+            ))
+        }
+    };
+    let records = audit::find_records(audit_log, run)?;
+    if records.is_empty() {
+        return Err(anyhow::anyhow!("run {} not found in {:?}", run, audit_log));
+    }
+    let mut output_dirs = vec![];
+    for record in records {
+        let mut cache_dir = PathBuf::from(&config.cache_dir);
+        cache_dir.push(REBUILD_PATH);
+        cache_dir.push(run);
+        if let Some(project) = &record.project {
+            cache_dir.push(PROJECTS_PATH);
+            cache_dir.push(project);
+        }
+        let mut replay_config = record.replay_config(store, &cache_dir)?;
+        build(
+            &mut replay_config,
+            HashSet::new(),
+            is_processing.clone(),
+            run,
+        )
+        .await?;
+        // outputs depending on the time of the run, e.g. zone serials, can't be equal
+        for output in record.differing_outputs(Path::new(&replay_config.output_dir)) {
+            warn!(
+                "{}: differs from the output recorded in run {}",
+                output, run
+            );
+        }
+        output_dirs.push(replay_config.output_dir);
+    }
+    Ok(output_dirs)
+}
+
+/// runs the post-run hooks of a configuration
+///
+/// * `config`: the configuration containing the hooks
+/// * `report_path`: the file system path of the run report handed over to the hooks
+async fn run_hooks(config: &Config, report_path: &Path) -> anyhow::Result<()> {
+    let mut hooks_failed = 0;
+    for hook in config.hooks.iter() {
+        info!("Running hook: {}", hook.command);
+        if let Err(e) = hook.run(report_path).await {
+            error!("{:?}", e);
+            hooks_failed += 1;
+        }
+    }
+    if hooks_failed > 0 {
+        return Err(anyhow::anyhow!("{} hook(s) failed", hooks_failed));
+    }
+
     Ok(())
 }
+
+/// removes the downloaded copies of the given lists so they are processed on the next run
+///
+/// * `config`: the configuration containing the cache directory
+/// * `list_ids`: ids of the lists to be invalidated
+fn invalidate_downloads(config: &Config, list_ids: &[String]) {
+    let download_path = config.download_dir(DOWNLOAD_PATH);
+    for id in list_ids {
+        std::fs::remove_file(download_path.join(id)).ok();
+    }
+}
@@ -0,0 +1,216 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use tokio::sync::Semaphore;
+
+/// memory estimated for a list processed at once by the download or the extraction,
+/// covering the HTTP and decoder buffers, the line buffer and the writer
+pub const TASK_MEMORY: usize = 8 * 1024 * 1024;
+
+/// MemoryBudget accounts the approximate memory held by the pipeline. Parts of the
+/// pipeline reserve memory before holding data, a failing reservation makes them
+/// move the data to disk or process fewer lists at once.
+#[derive(Debug)]
+pub struct MemoryBudget {
+    /// maximum number of bytes held at once
+    limit: usize,
+    /// number of bytes currently reserved
+    used: AtomicUsize,
+    /// maximum number of bytes reserved at once so far
+    peak: AtomicUsize,
+}
+
+impl MemoryBudget {
+    /// creates a budget with nothing reserved
+    ///
+    /// * `limit`: maximum number of bytes held at once
+    pub fn new(limit: usize) -> Arc<Self> {
+        Arc::new(Self {
+            limit,
+            used: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+        })
+    }
+
+    /// reserves memory, false if the reservation would exceed the budget
+    ///
+    /// * `bytes`: the number of bytes to be held
+    pub fn try_reserve(&self, bytes: usize) -> bool {
+        let reserved = self
+            .used
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                used.checked_add(bytes).filter(|&sum| sum <= self.limit)
+            });
+        match reserved {
+            Ok(used) => {
+                self.peak.fetch_max(used + bytes, Ordering::SeqCst);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// returns reserved memory to the budget
+    ///
+    /// * `bytes`: the number of bytes no longer held
+    pub fn release(&self, bytes: usize) {
+        let _ = self
+            .used
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                Some(used.saturating_sub(bytes))
+            });
+    }
+
+    /// returns the maximum number of bytes held at once
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// returns the maximum number of bytes reserved at once so far
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::SeqCst)
+    }
+
+    /// returns the number of lists processed at once within the budget, at least one
+    /// and at most the configured number
+    ///
+    /// * `configured`: the configured maximum, unlimited if None
+    pub fn concurrency(&self, configured: Option<usize>) -> usize {
+        let affordable = (self.limit / TASK_MEMORY).max(1);
+        configured.map_or(affordable, |n| n.min(affordable))
+    }
+}
+
+/// Reservation is the memory a part of the pipeline holds reserved from a shared
+/// budget, whatever is still reserved is returned when being dropped
+#[derive(Debug, Default)]
+pub struct Reservation {
+    /// the memory budget of the run, unlimited if None
+    budget: Option<Arc<MemoryBudget>>,
+    /// number of bytes reserved from the budget
+    reserved: usize,
+}
+
+impl Reservation {
+    /// creates a reservation holding nothing
+    ///
+    /// * `budget`: the memory budget of the run, unlimited if None
+    pub fn new(budget: Option<Arc<MemoryBudget>>) -> Self {
+        Self {
+            budget,
+            reserved: 0,
+        }
+    }
+
+    /// reserves memory, false if the budget is used up, always true if unlimited
+    ///
+    /// * `bytes`: the number of bytes to be held
+    pub fn reserve(&mut self, bytes: usize) -> bool {
+        match self.budget.as_ref() {
+            Some(budget) if !budget.try_reserve(bytes) => false,
+            Some(_) => {
+                self.reserved += bytes;
+                true
+            }
+            None => true,
+        }
+    }
+
+    /// returns reserved memory to the budget, never more than reserved
+    ///
+    /// * `bytes`: the number of bytes no longer held
+    pub fn release(&mut self, bytes: usize) {
+        let bytes = bytes.min(self.reserved);
+        if let Some(budget) = self.budget.as_ref() {
+            budget.release(bytes);
+        }
+        self.reserved -= bytes;
+    }
+
+    /// returns all reserved memory to the budget
+    pub fn release_all(&mut self) {
+        self.release(self.reserved);
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.release_all();
+    }
+}
+
+/// returns the semaphore limiting the lists processed at once, None if unlimited
+///
+/// * `budget`: the memory budget of the run
+/// * `configured`: the configured maximum, unlimited if None
+pub fn concurrency_limit(
+    budget: Option<&MemoryBudget>,
+    configured: Option<usize>,
+) -> Option<Arc<Semaphore>> {
+    let configured = configured.filter(|&n| n > 0);
+    let limit = match budget {
+        Some(budget) => {
+            let limit = budget.concurrency(configured);
+            if configured.is_none_or(|n| limit < n) {
+                debug!(
+                    "memory budget limits the lists processed at once to {}",
+                    limit
+                );
+            }
+            Some(limit)
+        }
+        None => configured,
+    };
+    limit.map(|n| Arc::new(Semaphore::new(n)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_budget() {
+        let budget = MemoryBudget::new(100);
+        assert!(budget.try_reserve(60));
+        assert!(!budget.try_reserve(50));
+        assert!(budget.try_reserve(40));
+        budget.release(70);
+        assert!(budget.try_reserve(70));
+        assert!(!budget.try_reserve(1));
+        assert_eq!(budget.peak(), 100);
+        // more than reserved is never released
+        budget.release(200);
+        assert!(budget.try_reserve(100));
+    }
+
+    #[test]
+    fn test_reservation() {
+        let budget = MemoryBudget::new(100);
+        let mut reservation = Reservation::new(Some(budget.clone()));
+        assert!(reservation.reserve(60));
+        assert!(!reservation.reserve(50));
+        // only the reserved memory is returned
+        reservation.release(100);
+        assert!(reservation.reserve(80));
+        drop(reservation);
+        assert!(budget.try_reserve(100));
+        assert!(Reservation::new(None).reserve(usize::MAX));
+    }
+
+    #[test]
+    fn test_concurrency() {
+        let budget = MemoryBudget::new(4 * TASK_MEMORY);
+        assert_eq!(budget.concurrency(None), 4);
+        assert_eq!(budget.concurrency(Some(2)), 2);
+        assert_eq!(budget.concurrency(Some(10)), 4);
+        // a tiny budget still processes the lists one by one
+        assert_eq!(MemoryBudget::new(1024).concurrency(None), 1);
+
+        assert!(concurrency_limit(None, None).is_none());
+        assert!(concurrency_limit(None, Some(0)).is_none());
+        let limit = concurrency_limit(Some(&budget), Some(10)).unwrap();
+        assert_eq!(limit.available_permits(), 4);
+    }
+}
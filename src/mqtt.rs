@@ -0,0 +1,318 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use crate::report::unix_timestamp;
+
+/// topic the change events are published under if no topic was configured
+pub const DEFAULT_TOPIC: &str = "harvester/changes";
+/// maximum number of domains listed in an event if no limit was configured
+pub const DEFAULT_MAX_DOMAINS: usize = 1000;
+/// seconds the broker keeps the connection without any packet
+const KEEP_ALIVE: u16 = 60;
+
+const PACKET_CONNECT: u8 = 0x10;
+const PACKET_CONNACK: u8 = 0x20;
+const PACKET_PUBLISH: u8 = 0x30;
+const PACKET_PUBACK: u8 = 0x40;
+const PACKET_DISCONNECT: u8 = 0xe0;
+
+/// MqttSettings configures the publication of change events to an MQTT broker
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MqttSettings {
+    /// address of the broker (e.g. "127.0.0.1:1883")
+    pub broker: String,
+    /// topic prefix, the events of an output are published to `<topic>/<tag>`
+    pub topic: Option<String>,
+    /// client identifier, defaults to "harvester"
+    pub client_id: Option<String>,
+    pub username: Option<String>,
+    /// file containing the password of the user
+    pub password_file: Option<String>,
+    /// quality of service, 0 (at most once) or 1 (at least once)
+    #[serde(default)]
+    pub qos: u8,
+    /// the broker keeps the last event of a topic for new subscribers
+    #[serde(default)]
+    pub retain: bool,
+    /// events of outputs with more changes only contain the counts
+    pub max_domains: Option<usize>,
+}
+
+/// ChangeEvent describes the changes of an output in a run
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ChangeEvent {
+    /// the tag of the output
+    pub output: String,
+    /// the name of the project the output belongs to
+    pub project: Option<String>,
+    /// unix timestamp of the run
+    pub timestamp: u64,
+    pub added_count: usize,
+    pub removed_count: usize,
+    /// the domains added, empty if the changes exceed the configured limit
+    pub added: Vec<String>,
+    /// the domains removed, empty if the changes exceed the configured limit
+    pub removed: Vec<String>,
+}
+
+impl ChangeEvent {
+    /// creates the event of an output, the domains are only listed if they don't exceed
+    /// the limit
+    ///
+    /// * `output`: the tag of the output
+    /// * `project`: the name of the project the output belongs to
+    /// * `added`: the domains added to the output
+    /// * `removed`: the domains removed from the output
+    /// * `max_domains`: the maximum number of domains to be listed
+    pub fn new(
+        output: &str,
+        project: Option<String>,
+        added: &[String],
+        removed: &[String],
+        max_domains: usize,
+    ) -> Self {
+        let listed = added.len() + removed.len() <= max_domains;
+        Self {
+            output: output.to_string(),
+            project,
+            timestamp: unix_timestamp(),
+            added_count: added.len(),
+            removed_count: removed.len(),
+            added: if listed { added.to_vec() } else { vec![] },
+            removed: if listed { removed.to_vec() } else { vec![] },
+        }
+    }
+}
+
+impl MqttSettings {
+    /// returns the maximum number of domains listed in an event
+    pub fn max_domains(&self) -> usize {
+        self.max_domains.unwrap_or(DEFAULT_MAX_DOMAINS)
+    }
+
+    /// publishes the events to the broker, each one to the topic of its output
+    ///
+    /// * `events`: the change events of a run
+    pub async fn publish(&self, events: &[ChangeEvent]) -> anyhow::Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        if self.qos > 1 {
+            return Err(anyhow::anyhow!("unsupported MQTT qos {}", self.qos));
+        }
+        let mut stream = TcpStream::connect(&self.broker)
+            .await
+            .with_context(|| format!("could not connect to MQTT broker {}", self.broker))?;
+        stream.write_all(&self.connect_packet()?).await?;
+        let connack = read_packet(&mut stream).await?;
+        match connack.as_slice() {
+            // fixed header, remaining length, acknowledge flags and return code
+            [PACKET_CONNACK, 2, _, 0] => {}
+            [PACKET_CONNACK, 2, _, code] => {
+                return Err(anyhow::anyhow!("MQTT broker refused connection: {}", code))
+            }
+            _ => return Err(anyhow::anyhow!("MQTT broker sent no CONNACK")),
+        }
+
+        let prefix = self.topic.as_deref().unwrap_or(DEFAULT_TOPIC);
+        for (i, event) in events.iter().enumerate() {
+            let topic = match &event.project {
+                Some(project) => format!("{}/{}/{}", prefix, project, event.output),
+                None => format!("{}/{}", prefix, event.output),
+            };
+            let packet_id = i as u16 + 1;
+            let payload = serde_json::to_vec(event)?;
+            stream
+                .write_all(&publish_packet(
+                    &topic,
+                    &payload,
+                    self.qos,
+                    self.retain,
+                    packet_id,
+                ))
+                .await?;
+            if self.qos == 1 {
+                let puback = read_packet(&mut stream).await?;
+                if puback.first() != Some(&PACKET_PUBACK) || puback[2..] != packet_id.to_be_bytes()
+                {
+                    return Err(anyhow::anyhow!("MQTT broker didn't acknowledge {}", topic));
+                }
+            }
+            debug!("Published change event to {}", topic);
+        }
+        stream.write_all(&[PACKET_DISCONNECT, 0]).await?;
+        stream.shutdown().await?;
+        Ok(())
+    }
+
+    /// returns the CONNECT packet starting a clean session
+    fn connect_packet(&self) -> anyhow::Result<Vec<u8>> {
+        let mut flags = 0x02;
+        let mut payload = vec![];
+        write_string(
+            &mut payload,
+            self.client_id.as_deref().unwrap_or("harvester"),
+        );
+        if let Some(username) = &self.username {
+            flags |= 0x80;
+            write_string(&mut payload, username);
+            if let Some(password_file) = &self.password_file {
+                let password = std::fs::read_to_string(password_file).with_context(|| {
+                    format!("could not read MQTT password file {}", password_file)
+                })?;
+                flags |= 0x40;
+                write_string(&mut payload, password.trim_end());
+            }
+        }
+        let mut body = vec![];
+        write_string(&mut body, "MQTT");
+        // protocol level 4 is MQTT 3.1.1
+        body.extend([4, flags]);
+        body.extend(KEEP_ALIVE.to_be_bytes());
+        body.extend(payload);
+        Ok(packet(PACKET_CONNECT, &body))
+    }
+}
+
+/// returns a PUBLISH packet
+///
+/// * `topic`: the topic the payload is published to
+/// * `payload`: the message
+/// * `qos`: the quality of service
+/// * `retain`: the broker keeps the message for new subscribers
+/// * `packet_id`: identifies the acknowledgement of a message with qos 1
+fn publish_packet(topic: &str, payload: &[u8], qos: u8, retain: bool, packet_id: u16) -> Vec<u8> {
+    let mut body = vec![];
+    write_string(&mut body, topic);
+    if qos > 0 {
+        body.extend(packet_id.to_be_bytes());
+    }
+    body.extend(payload);
+    packet(PACKET_PUBLISH | qos << 1 | retain as u8, &body)
+}
+
+/// appends a length prefixed UTF-8 string
+///
+/// * `buf`: the packet
+/// * `s`: the string
+fn write_string(buf: &mut Vec<u8>, s: &str) {
+    buf.extend((s.len() as u16).to_be_bytes());
+    buf.extend(s.as_bytes());
+}
+
+/// returns a packet consisting of the fixed header and the body
+///
+/// * `header`: the packet type and flags
+/// * `body`: the variable header and the payload
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut buf = vec![header];
+    // the remaining length is encoded in 7 bit groups
+    let mut len = body.len();
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        buf.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+    buf.extend(body);
+    buf
+}
+
+/// reads a packet consisting of the fixed header and the body
+///
+/// * `stream`: the connection to the broker
+async fn read_packet(stream: &mut TcpStream) -> anyhow::Result<Vec<u8>> {
+    let header = stream.read_u8().await?;
+    let mut len = 0;
+    let mut len_bytes = vec![];
+    for shift in (0..4).map(|i| i * 7) {
+        let byte = stream.read_u8().await?;
+        len_bytes.push(byte);
+        len |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    let mut body = vec![0; len];
+    stream.read_exact(&mut body).await?;
+    Ok([vec![header], len_bytes, body].concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn test_packet_remaining_length() {
+        assert_eq!(packet(PACKET_DISCONNECT, &[]), vec![PACKET_DISCONNECT, 0]);
+        let long = packet(PACKET_PUBLISH, &[0; 321]);
+        assert_eq!(&long[..3], &[PACKET_PUBLISH, 0xc1, 0x02]);
+        assert_eq!(long.len(), 324);
+    }
+
+    #[test]
+    fn test_change_event_limit() {
+        let added = vec!["a.domain".to_string(), "b.domain".to_string()];
+        let removed = vec!["c.domain".to_string()];
+        let event = ChangeEvent::new("malware", None, &added, &removed, 3);
+        assert_eq!(event.added, added);
+        let event = ChangeEvent::new("malware", None, &added, &removed, 2);
+        assert!(event.added.is_empty() && event.removed.is_empty());
+        assert_eq!((event.added_count, event.removed_count), (2, 1));
+    }
+
+    #[tokio::test]
+    async fn test_publish() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let settings = MqttSettings {
+            broker: listener.local_addr().unwrap().to_string(),
+            topic: None,
+            client_id: None,
+            username: None,
+            password_file: None,
+            qos: 1,
+            retain: false,
+            max_domains: None,
+        };
+        let broker = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let connect = read_packet(&mut stream).await.unwrap();
+            assert_eq!(connect[0], PACKET_CONNECT);
+            stream.write_all(&[PACKET_CONNACK, 2, 0, 0]).await.unwrap();
+            let publish = read_packet(&mut stream).await.unwrap();
+            assert_eq!(publish[0], PACKET_PUBLISH | 1 << 1);
+            stream.write_all(&[PACKET_PUBACK, 2, 0, 1]).await.unwrap();
+            let disconnect = read_packet(&mut stream).await.unwrap();
+            assert_eq!(disconnect, vec![PACKET_DISCONNECT, 0]);
+            publish
+        });
+
+        let event = ChangeEvent::new("malware", None, &["a.domain".to_string()], &[], 10);
+        settings.publish(&[event.clone()]).await.unwrap();
+        let publish = broker.await.unwrap();
+        // fixed header, topic and packet id precede the event
+        let header_len = 2 + publish[1..].iter().position(|b| b & 0x80 == 0).unwrap();
+        let topic = "harvester/changes/malware";
+        let topic_start = header_len + 2;
+        assert_eq!(
+            &publish[topic_start..topic_start + topic.len()],
+            topic.as_bytes()
+        );
+        let payload = &publish[topic_start + topic.len() + 2..];
+        assert_eq!(
+            serde_json::from_slice::<ChangeEvent>(payload).unwrap(),
+            event
+        );
+    }
+}
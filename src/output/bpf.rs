@@ -0,0 +1,186 @@
+use std::{
+    io::Write,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use futures::lock::Mutex;
+
+use crate::{input::Input, output::writer::OutputWriter};
+
+/// pinned map the IPv4 entries are written to if none is configured
+pub const DEFAULT_IPV4_MAP: &str = "/sys/fs/bpf/harvester_v4";
+/// pinned map the IPv6 entries are written to if none is configured
+pub const DEFAULT_IPV6_MAP: &str = "/sys/fs/bpf/harvester_v6";
+
+/// returns the key of an entry in an LPM trie map, which is the prefix length in
+/// little endian byte order followed by the address in network byte order
+///
+/// * `entry`: an IP address or a network in CIDR notation
+fn lpm_key(entry: &str) -> Option<(bool, Vec<u8>)> {
+    let (address, prefix) = match entry.split_once('/') {
+        Some((address, prefix)) => (
+            address.parse::<IpAddr>().ok()?,
+            Some(prefix.parse::<u32>().ok()?),
+        ),
+        None => (entry.parse::<IpAddr>().ok()?, None),
+    };
+    let (is_ipv4, octets, max_prefix) = match address {
+        IpAddr::V4(address) => (true, address.octets().to_vec(), 32),
+        IpAddr::V6(address) => (false, address.octets().to_vec(), 128),
+    };
+    let prefix = prefix.unwrap_or(max_prefix);
+    if prefix > max_prefix {
+        return None;
+    }
+    Some((is_ipv4, [prefix.to_le_bytes().to_vec(), octets].concat()))
+}
+
+/// writes the bpftool command adding an entry to its map, returns false for domains
+/// which aren't written
+///
+/// * `writer`: the output the line is written to
+/// * `entry`: the normalized entry
+/// * `ipv4_map`: the pinned map of the IPv4 entries
+/// * `ipv6_map`: the pinned map of the IPv6 entries
+pub fn write_map_update(
+    writer: &mut impl Write,
+    entry: &str,
+    ipv4_map: &str,
+    ipv6_map: &str,
+) -> std::io::Result<bool> {
+    let (is_ipv4, key) = match lpm_key(entry) {
+        Some(key) => key,
+        None => return Ok(false),
+    };
+    let map = if is_ipv4 { ipv4_map } else { ipv6_map };
+    write!(writer, "map update pinned {} key hex", map)?;
+    for b in key {
+        write!(writer, " {:02x}", b)?;
+    }
+    writeln!(writer, " value hex 01")?;
+    Ok(true)
+}
+
+/// bpf_adapter translates the IP entries into a bpftool batch file adding them to the
+/// LPM trie maps of XDP filters, domains are left out
+///
+/// * `ipv4_map`: the pinned map of the IPv4 entries
+/// * `ipv6_map`: the pinned map of the IPv6 entries
+/// * `reader`: data source that implements the Input trait
+/// * `writer`: data sink that implements std::io::Write
+/// * `is_processing`: determines if the program was interrupted
+pub async fn bpf_adapter(
+    ipv4_map: String,
+    ipv6_map: String,
+    reader: Arc<Mutex<dyn Input + Send>>,
+    writer: Arc<Mutex<dyn Write + Send>>,
+    is_processing: Arc<AtomicBool>,
+) {
+    let mut domains = 0;
+    let mut command = vec![];
+    let mut writer = OutputWriter::new(writer);
+    loop {
+        if !is_processing.load(Ordering::SeqCst) {
+            return;
+        }
+        match reader.lock().await.chunk().await {
+            Ok(Some(chunk)) => {
+                let str_chunk = match std::str::from_utf8(&chunk) {
+                    Ok(s) => s,
+                    Err(e) => {
+                        warn!("{}", e);
+                        continue;
+                    }
+                };
+                command.clear();
+                match write_map_update(&mut command, str_chunk.trim_end(), &ipv4_map, &ipv6_map) {
+                    Ok(true) => {
+                        if let Err(e) = writer.write_all(&command).await {
+                            error!("{}", e);
+                        }
+                    }
+                    Ok(false) => domains += 1,
+                    Err(e) => error!("{}", e),
+                }
+            }
+            Ok(None) => {
+                break;
+            }
+            Err(e) => {
+                error!("{}", e);
+                break;
+            }
+        }
+    }
+    if let Err(e) = writer.finish().await {
+        error!("{}", e);
+    }
+    if domains > 0 {
+        debug!("{} domains left out of the eBPF maps", domains);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{
+        output::{lint::lint_bpf, parse::parse_map_update},
+        tests::helper::cursor_input::CursorInput,
+    };
+
+    use super::*;
+
+    /// returns the command written for the entry, None for domains
+    fn map_update(entry: &str, ipv4_map: &str, ipv6_map: &str) -> Option<String> {
+        let mut command = vec![];
+        write_map_update(&mut command, entry, ipv4_map, ipv6_map)
+            .unwrap()
+            .then(|| String::from_utf8(command).unwrap().trim_end().to_string())
+    }
+
+    #[test]
+    fn test_map_update() {
+        assert_eq!(
+            map_update("192.168.1.0/24", "v4", "v6").unwrap(),
+            "map update pinned v4 key hex 18 00 00 00 c0 a8 01 00 value hex 01"
+        );
+        assert_eq!(
+            map_update("2001:db8::1", "v4", "v6").unwrap(),
+            "map update pinned v6 key hex 80 00 00 00 20 01 0d b8 00 00 00 00 00 00 00 00 00 00 00 01 value hex 01"
+        );
+        assert_eq!(map_update("one.domain", "v4", "v6"), None);
+        assert_eq!(map_update("192.168.1.0/33", "v4", "v6"), None);
+        for entry in ["192.168.1.0/24", "192.168.1.1", "2001:db8::/32"] {
+            let command = map_update(entry, "v4", "v6").unwrap();
+            assert_eq!(parse_map_update(&command).unwrap(), entry);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bpf_adapter() {
+        let input = Arc::new(Mutex::new(CursorInput::new(
+            "192.168.1.1\none.domain\n2001:db8::/32\n",
+        )));
+        let output = Arc::new(Mutex::new(Cursor::new(vec![])));
+        let is_processing = Arc::new(AtomicBool::new(true));
+
+        bpf_adapter(
+            DEFAULT_IPV4_MAP.to_string(),
+            DEFAULT_IPV6_MAP.to_string(),
+            input,
+            output.clone(),
+            is_processing,
+        )
+        .await;
+        let o = output.lock().await.clone().into_inner();
+        assert!(lint_bpf(o.as_slice()).is_ok());
+        let got = String::from_utf8_lossy(&o).to_string();
+        let entries: Vec<String> = got.lines().filter_map(parse_map_update).collect();
+        assert_eq!(entries, vec!["192.168.1.1", "2001:db8::/32"]);
+    }
+}
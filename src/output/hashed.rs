@@ -0,0 +1,136 @@
+use std::{
+    io::Write,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use futures::lock::Mutex;
+use sha2::{Digest, Sha256};
+
+use crate::{input::Input, output::writer::OutputWriter};
+
+/// first line of a hashed output documenting how the entries were hashed
+pub const HASHED_HEADER: &str = "# sha256(salt + domain)";
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+/// returns the lowercase hex encoded SHA-256 digest of the salt followed by the entry
+/// without allocating
+///
+/// * `salt`: the configured salt
+/// * `entry`: the normalized entry
+fn hex_digest(salt: &str, entry: &[u8]) -> [u8; 64] {
+    let digest = Sha256::new()
+        .chain_update(salt.as_bytes())
+        .chain_update(entry)
+        .finalize();
+    let mut hex = [0; 64];
+    for (i, b) in digest.iter().enumerate() {
+        hex[2 * i] = HEX_DIGITS[(b >> 4) as usize];
+        hex[2 * i + 1] = HEX_DIGITS[(b & 0x0f) as usize];
+    }
+    hex
+}
+
+/// returns the lowercase hex encoded SHA-256 digest of the salt followed by the entry
+///
+/// * `salt`: the configured salt
+/// * `entry`: the normalized entry
+pub fn hash_entry(salt: &str, entry: &str) -> String {
+    hex_digest(salt, entry.as_bytes())
+        .iter()
+        .map(|&b| b as char)
+        .collect()
+}
+
+/// hashed_adapter translates the extracted URLs into their salted SHA-256 digests, so
+/// consumers can match indicators without learning them
+///
+/// * `salt`: the salt prepended to every entry
+/// * `reader`: data source that implements the Input trait
+/// * `writer`: data sink that implements std::io::Write
+/// * `is_processing`: determines if the program was interrupted
+pub async fn hashed_adapter(
+    salt: String,
+    reader: Arc<Mutex<dyn Input + Send>>,
+    writer: Arc<Mutex<dyn Write + Send>>,
+    is_processing: Arc<AtomicBool>,
+) {
+    let mut writer = OutputWriter::new(writer);
+    if let Err(e) = writer.write_line(&[HASHED_HEADER.as_bytes()]).await {
+        error!("{}", e);
+    }
+    loop {
+        if !is_processing.load(Ordering::SeqCst) {
+            return;
+        }
+        match reader.lock().await.chunk().await {
+            Ok(Some(chunk)) => {
+                let str_chunk = match std::str::from_utf8(&chunk) {
+                    Ok(s) => s,
+                    Err(e) => {
+                        warn!("{}", e);
+                        continue;
+                    }
+                };
+                let digest = hex_digest(&salt, str_chunk.trim_end().as_bytes());
+                if let Err(e) = writer.write_line(&[&digest]).await {
+                    error!("{}", e);
+                }
+            }
+            Ok(None) => {
+                break;
+            }
+            Err(e) => {
+                error!("{}", e);
+                break;
+            }
+        }
+    }
+    if let Err(e) = writer.finish().await {
+        error!("{}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{output::lint::lint_sha256, tests::helper::cursor_input::CursorInput};
+
+    use super::*;
+
+    #[test]
+    fn test_hash_entry() {
+        // sha256("example.com")
+        assert_eq!(
+            hash_entry("", "example.com"),
+            "a379a6f6eeafb9a55e378c118034e2751e682fab9f2d30ab13d2125586ce1947"
+        );
+        assert_ne!(
+            hash_entry("salt", "example.com"),
+            hash_entry("", "example.com")
+        );
+        assert_eq!(
+            hash_entry("salt", "example.com"),
+            hash_entry("", "saltexample.com")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hashed_adapter() {
+        let input = Arc::new(Mutex::new(CursorInput::new("domain.one\ndomain.two\n")));
+        let output = Arc::new(Mutex::new(Cursor::new(vec![])));
+        let is_processing = Arc::new(AtomicBool::new(true));
+
+        hashed_adapter("salt".to_string(), input, output.clone(), is_processing).await;
+        let o = output.lock().await.clone().into_inner();
+        assert!(lint_sha256(o.as_slice()).is_ok());
+        let got = String::from_utf8_lossy(&o).to_string();
+        let lines: Vec<&str> = got.lines().collect();
+        assert_eq!(lines[0], HASHED_HEADER);
+        assert_eq!(lines[2], hash_entry("salt", "domain.two"));
+    }
+}
@@ -1,42 +1,120 @@
 use std::{
+    fs,
     io::Write,
+    net::{IpAddr, Ipv4Addr},
+    path::Path,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
 };
 
+use anyhow::Context;
 use futures::lock::Mutex;
 
-use crate::input::Input;
+use crate::{input::Input, output::writer::OutputWriter, platform};
+
+/// address the blocked domains resolve to unless a warn page is configured
+pub const DEFAULT_ADDRESS: IpAddr = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+
+/// returns the lines enclosing the block of an output within a system hosts file
+///
+/// * `tag`: the tag of the output
+fn markers(tag: &str) -> (String, String) {
+    (
+        format!("# BEGIN harvester {}", tag),
+        format!("# END harvester {}", tag),
+    )
+}
+
+/// replaces the block of the output between its markers with the entries, the block
+/// is appended if the hosts file doesn't contain it yet. The lines outside of the
+/// block are preserved.
+///
+/// * `hosts`: the content of the system hosts file
+/// * `tag`: the tag of the output
+/// * `entries`: the content of the output
+pub fn merge_block(hosts: &str, tag: &str, entries: &str) -> anyhow::Result<String> {
+    let (begin, end) = markers(tag);
+    let lines: Vec<&str> = hosts.lines().collect();
+    let begin_index = lines.iter().position(|l| l.trim_end() == begin);
+    let end_index = lines.iter().position(|l| l.trim_end() == end);
+    let (before, after) = match (begin_index, end_index) {
+        (Some(b), Some(e)) if b < e => (&lines[..b], &lines[e + 1..]),
+        (None, None) => (&lines[..], &[][..]),
+        // a damaged block could swallow foreign entries
+        _ => return Err(anyhow::anyhow!("markers of {} are incomplete", tag)),
+    };
+    let mut merged = String::new();
+    for line in before {
+        merged.push_str(line);
+        merged.push('\n');
+    }
+    merged.push_str(&begin);
+    merged.push('\n');
+    for line in entries.lines() {
+        merged.push_str(line);
+        merged.push('\n');
+    }
+    merged.push_str(&end);
+    merged.push('\n');
+    for line in after {
+        merged.push_str(line);
+        merged.push('\n');
+    }
+    Ok(merged)
+}
+
+/// writes the output into the block between its markers of a system hosts file,
+/// returns true if the file changed
+///
+/// * `hosts_file`: the system hosts file, e.g. /etc/hosts
+/// * `tag`: the tag of the output
+/// * `entries`: the content of the output
+pub fn merge_hosts(hosts_file: &Path, tag: &str, entries: &str) -> anyhow::Result<bool> {
+    let hosts = fs::read_to_string(hosts_file)
+        .with_context(|| format!("could not read {:?}", hosts_file))?;
+    let merged = merge_block(&hosts, tag, entries)
+        .with_context(|| format!("could not merge into {:?}", hosts_file))?;
+    if merged == hosts {
+        return Ok(false);
+    }
+    platform::write_system_file(hosts_file, merged.as_bytes())
+        .with_context(|| format!("could not write {:?}", hosts_file))?;
+    Ok(true)
+}
 
 /// hostsfile_adapter translates the extracted URLs int a hosts file format
 /// as found in /etc/hosts
 ///
+/// * `address`: the address the blocked domains resolve to
 /// * `reader`: data source that implements the Input trait
 /// * `writer`: data sink that implements std::io::Write
 /// * `cmd_rx`: channel listening for commands
 /// * `msg_tx`: channel for messaging
 pub async fn hostsfile_adapter(
+    address: IpAddr,
     reader: Arc<Mutex<dyn Input + Send>>,
     writer: Arc<Mutex<dyn Write + Send>>,
     is_processing: Arc<AtomicBool>,
 ) {
+    let mut writer = OutputWriter::new(writer);
+    let prefix = format!("{} ", address);
     loop {
         if !is_processing.load(Ordering::SeqCst) {
             return;
         }
         match reader.lock().await.chunk().await {
             Ok(Some(chunk)) => {
-                let str_chunk = match String::from_utf8(chunk) {
+                let str_chunk = match std::str::from_utf8(&chunk) {
                     Ok(s) => s,
                     Err(e) => {
                         warn!("{}", e);
                         continue;
                     }
                 };
-                let chunk = format!("0.0.0.0 {}\n", str_chunk.trim_end());
-                if let Err(e) = writer.lock().await.write_all(chunk.as_bytes()) {
+                let entry = str_chunk.trim_end().as_bytes();
+                if let Err(e) = writer.write_line(&[prefix.as_bytes(), entry]).await {
                     error!("{}", e);
                 }
             }
@@ -49,15 +127,58 @@ pub async fn hostsfile_adapter(
             }
         }
     }
+    if let Err(e) = writer.finish().await {
+        error!("{}", e);
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::tests::helper::cursor_input::CursorInput;
+    use crate::{output::lint::lint_hostsfile, tests::helper::cursor_input::CursorInput};
 
     use super::*;
+    use proptest::prelude::*;
     use std::io::Cursor;
 
+    proptest! {
+        #[test]
+        fn prop_hostfile_adapter_output_lints(
+            hostnames in proptest::collection::vec("[a-z0-9-]{1,20}\\.[a-z]{2,6}", 0..20)
+        ) {
+            let input_data = hostnames.iter().map(|h| h.to_string() + "\n").collect::<String>();
+            let input = Arc::new(Mutex::new(CursorInput::new(&input_data)));
+            let output = Arc::new(Mutex::new(Cursor::new(vec![])));
+            let is_processing = Arc::new(AtomicBool::new(true));
+
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(hostsfile_adapter(DEFAULT_ADDRESS, input, output.clone(), is_processing));
+            let o = rt.block_on(output.lock()).clone().into_inner();
+            prop_assert!(lint_hostsfile(o.as_slice(), DEFAULT_ADDRESS).is_ok());
+            prop_assert_eq!(o.iter().filter(|b| **b == b'\n').count(), hostnames.len());
+        }
+    }
+
+    #[test]
+    fn test_merge_block() {
+        let hosts = "127.0.0.1 localhost\n\n# BEGIN harvester ads\n0.0.0.0 old.domain\n# END harvester ads\n::1 localhost\n";
+        let merged = merge_block(hosts, "ads", "0.0.0.0 new.domain\n").unwrap();
+        assert_eq!(
+            merged,
+            "127.0.0.1 localhost\n\n# BEGIN harvester ads\n0.0.0.0 new.domain\n# END harvester ads\n::1 localhost\n"
+        );
+        assert_eq!(
+            merge_block(&merged, "ads", "0.0.0.0 new.domain\n").unwrap(),
+            merged
+        );
+
+        let merged = merge_block("127.0.0.1 localhost", "ads", "0.0.0.0 new.domain\n").unwrap();
+        assert_eq!(
+            merged,
+            "127.0.0.1 localhost\n# BEGIN harvester ads\n0.0.0.0 new.domain\n# END harvester ads\n"
+        );
+        assert!(merge_block("# BEGIN harvester ads\n127.0.0.1 localhost\n", "ads", "").is_err());
+    }
+
     #[tokio::test]
     async fn test_hostfile_adapter() {
         // create input data
@@ -67,7 +188,7 @@ mod tests {
         let output = Arc::new(Mutex::new(Cursor::new(vec![0, 32])));
         let is_processing = Arc::new(AtomicBool::new(true));
 
-        hostsfile_adapter(input, output.clone(), is_processing).await;
+        hostsfile_adapter(DEFAULT_ADDRESS, input, output.clone(), is_processing).await;
         let o = output.lock().await.clone().into_inner();
         let expect = "0.0.0.0 domain.one\n0.0.0.0 domain.two\n";
         let got = String::from_utf8_lossy(&o);
@@ -0,0 +1,356 @@
+use std::{io::BufRead, net::IpAddr};
+
+use regex::Regex;
+
+use super::parse::{parse_map_update, parse_regex_rule, parse_sni_rule, rpz_policy};
+
+/// maximum length of a domain name
+const MAX_HOSTNAME_LEN: usize = 253;
+/// maximum length of a single label of a domain name
+const MAX_LABEL_LEN: usize = 63;
+
+/// returns true if the byte may appear in a host name, the dot included. It has no
+/// branches, so a check of all bytes of a name compiles to vector instructions.
+///
+/// * `b`: the byte to be checked
+fn is_hostname_byte(b: u8) -> bool {
+    // setting the case bit maps upper to lower case letters and no other byte to one
+    let lower = b | 0x20;
+    (lower.wrapping_sub(b'a') < 26)
+        | (b.wrapping_sub(b'0') < 10)
+        | (b == b'-')
+        | (b == b'_')
+        | (b == b'.')
+}
+
+/// returns true if the string is a syntactically valid host name
+///
+/// * `hostname`: the host name to be checked
+pub fn is_valid_hostname(hostname: &str) -> bool {
+    let bytes = hostname.as_bytes();
+    if bytes.is_empty() || bytes.len() > MAX_HOSTNAME_LEN {
+        return false;
+    }
+    // folding without an early exit lets the compiler check many bytes at once
+    if !bytes
+        .iter()
+        .fold(true, |valid, &b| valid & is_hostname_byte(b))
+    {
+        return false;
+    }
+    let name = hostname.trim_end_matches('.').as_bytes();
+    let mut start = 0;
+    for end in memchr::memchr_iter(b'.', name).chain(std::iter::once(name.len())) {
+        let len = end - start;
+        if len == 0 || len > MAX_LABEL_LEN {
+            return false;
+        }
+        start = end + 1;
+    }
+    true
+}
+
+/// checks every line of a hashed output for a hex encoded SHA-256 digest
+///
+/// * `reader`: the output file to be checked
+pub fn lint_sha256(reader: impl BufRead) -> anyhow::Result<()> {
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.starts_with('#') {
+            continue;
+        }
+        let valid = line.len() == 64
+            && line
+                .bytes()
+                .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+        if !valid {
+            return Err(anyhow::anyhow!("line {}: invalid digest: {}", i + 1, line));
+        }
+    }
+    Ok(())
+}
+
+/// checks every line of a hosts file for valid syntax
+///
+/// * `reader`: the output file to be checked
+pub fn lint_hostsfile(reader: impl BufRead, address: IpAddr) -> anyhow::Result<()> {
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let valid = fields.next().and_then(|a| a.parse().ok()) == Some(address)
+            && fields.next().is_some_and(is_valid_hostname)
+            && fields.next().is_none();
+        if !valid {
+            return Err(anyhow::anyhow!(
+                "line {}: invalid hosts entry: {}",
+                i + 1,
+                line
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// checks that the file is a lua module returning a table of strings
+///
+/// * `reader`: the output file to be checked
+pub fn lint_lua(reader: impl BufRead) -> anyhow::Result<()> {
+    let lines = reader.lines().collect::<Result<Vec<String>, _>>()?;
+    if lines.first().map(|l| l.as_str()) != Some("return {") {
+        return Err(anyhow::anyhow!("line 1: expected `return {{`"));
+    }
+    if lines.len() < 2 || lines.last().map(|l| l.as_str()) != Some("}") {
+        return Err(anyhow::anyhow!("line {}: expected `}}`", lines.len()));
+    }
+    for (i, line) in lines.iter().enumerate().take(lines.len() - 1).skip(1) {
+        let valid = line
+            .trim()
+            .strip_prefix('"')
+            .and_then(|l| l.strip_suffix("\","))
+            .is_some_and(|s| !s.is_empty() && !s.contains(['"', '\\']));
+        if !valid {
+            return Err(anyhow::anyhow!(
+                "line {}: invalid lua string: {}",
+                i + 1,
+                line
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// checks that the file is a zone containing an SOA record followed by records
+/// answering the listed domains with NXDOMAIN or the address they resolve to
+///
+/// * `reader`: the output file to be checked
+/// * `address`: the address the blocked domains resolve to
+pub fn lint_rpz(reader: impl BufRead, address: IpAddr) -> anyhow::Result<()> {
+    let policy = format!(" {}", rpz_policy(address));
+    let mut has_soa = false;
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with(';') || line.starts_with('$') {
+            continue;
+        }
+        // records of the zone's origin
+        if let Some(record) = line.strip_prefix("@ ") {
+            has_soa |= record.split_whitespace().any(|f| f == "SOA");
+            continue;
+        }
+        let valid = has_soa
+            && line
+                .strip_suffix(policy.as_str())
+                .is_some_and(is_valid_hostname);
+        if !valid {
+            return Err(anyhow::anyhow!(
+                "line {}: invalid rpz record: {}",
+                i + 1,
+                line
+            ));
+        }
+    }
+    if !has_soa {
+        return Err(anyhow::anyhow!("zone has no SOA record"));
+    }
+    Ok(())
+}
+
+/// checks every line of a regex output for a valid rule matching valid host names
+///
+/// * `reader`: the output file to be checked
+pub fn lint_regex(reader: impl BufRead) -> anyhow::Result<()> {
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let valid = Regex::new(&line).is_ok()
+            && parse_regex_rule(&line)
+                .is_some_and(|domains| domains.iter().all(|d| is_valid_hostname(d)));
+        if !valid {
+            return Err(anyhow::anyhow!("line {}: invalid rule: {}", i + 1, line));
+        }
+    }
+    Ok(())
+}
+
+/// checks an SNI ruleset for a complete filter table whose rules match valid host names
+///
+/// * `reader`: the output file to be checked
+pub fn lint_sni(reader: impl BufRead) -> anyhow::Result<()> {
+    let mut lines = vec![];
+    for line in reader.lines() {
+        lines.push(line?);
+    }
+    if lines.first().map(|l| l.as_str()) != Some("*filter")
+        || lines.last().map(|l| l.as_str()) != Some("COMMIT")
+    {
+        return Err(anyhow::anyhow!("ruleset isn't a complete filter table"));
+    }
+    for (i, line) in lines.iter().enumerate().skip(1).take(lines.len() - 2) {
+        let valid = match parse_sni_rule(line) {
+            Some(domain) => is_valid_hostname(domain),
+            // chain declarations and the jumps to the chunk chains
+            None => line.starts_with(':') || line.starts_with("-A "),
+        };
+        if !valid {
+            return Err(anyhow::anyhow!("line {}: invalid rule: {}", i + 1, line));
+        }
+    }
+    Ok(())
+}
+
+/// checks every line of a bpftool batch file for an update of an LPM trie map
+///
+/// * `reader`: the output file to be checked
+pub fn lint_bpf(reader: impl BufRead) -> anyhow::Result<()> {
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let valid = line.starts_with("map update pinned /") && parse_map_update(&line).is_some();
+        if !valid {
+            return Err(anyhow::anyhow!(
+                "line {}: invalid map update: {}",
+                i + 1,
+                line
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn prop_lint_arbitrary_input(data in proptest::collection::vec(any::<u8>(), 0..512)) {
+            let _ = lint_hostsfile(data.as_slice(), IpAddr::from([0, 0, 0, 0]));
+            let _ = lint_lua(data.as_slice());
+            let _ = lint_rpz(data.as_slice(), IpAddr::from([0, 0, 0, 0]));
+            let _ = lint_sha256(data.as_slice());
+            let _ = lint_regex(data.as_slice());
+            let _ = lint_sni(data.as_slice());
+            let _ = lint_bpf(data.as_slice());
+            let _ = is_valid_hostname(&String::from_utf8_lossy(&data));
+        }
+
+        #[test]
+        fn prop_valid_hostnames_pass(hostname in "[a-z0-9_-]{1,60}(\\.[a-z0-9-]{1,60}){0,3}") {
+            prop_assert!(is_valid_hostname(&hostname));
+            let hostsfile = format!("0.0.0.0 {}\n", hostname);
+            prop_assert!(lint_hostsfile(hostsfile.as_bytes(), IpAddr::from([0, 0, 0, 0])).is_ok());
+            let lua = format!("return {{\n  \"{}\",\n}}", hostname);
+            prop_assert!(lint_lua(lua.as_bytes()).is_ok());
+        }
+
+        #[test]
+        fn prop_hostname_bytes(b in any::<u8>()) {
+            let expected = b.is_ascii_alphanumeric() || b == b'-' || b == b'_' || b == b'.';
+            prop_assert_eq!(is_hostname_byte(b), expected);
+        }
+    }
+
+    #[test]
+    fn test_is_valid_hostname() {
+        assert!(is_valid_hostname("sub.domain.tld"));
+        assert!(is_valid_hostname("_dmarc.domain.tld"));
+        assert!(!is_valid_hostname("domain.tld/path"));
+        assert!(!is_valid_hostname("domain..tld"));
+        assert!(!is_valid_hostname(""));
+        assert!(is_valid_hostname("Sub-Domain.TLD."));
+        assert!(!is_valid_hostname("."));
+        assert!(!is_valid_hostname(".domain.tld"));
+        assert!(!is_valid_hostname(&format!("{}.tld", "a".repeat(64))));
+        // bytes next to the valid ranges
+        for invalid in [
+            "a@b.tld", "a[b.tld", "a`b.tld", "a{b.tld", "a/b.tld", "a:b.tld", "ä.tld",
+        ] {
+            assert!(!is_valid_hostname(invalid), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_lint_hostsfile() {
+        let address = IpAddr::from([0, 0, 0, 0]);
+        assert!(lint_hostsfile(
+            "0.0.0.0 one.domain\n0.0.0.0 two.domain\n".as_bytes(),
+            address
+        )
+        .is_ok());
+        assert!(lint_hostsfile("0.0.0.0 one.domain two.domain\n".as_bytes(), address).is_err());
+        assert!(lint_hostsfile("0.0.0.0 http://one.domain\n".as_bytes(), address).is_err());
+        // only the address of the warn page may be used
+        let address = IpAddr::from([192, 0, 2, 80]);
+        assert!(lint_hostsfile("192.0.2.80 one.domain\n".as_bytes(), address).is_ok());
+        assert!(lint_hostsfile("0.0.0.0 one.domain\n".as_bytes(), address).is_err());
+    }
+
+    #[test]
+    fn test_lint_lua() {
+        assert!(lint_lua("return {\n  \"one.domain\",\n}".as_bytes()).is_ok());
+        assert!(lint_lua("return {\n  \"one\"domain\",\n}".as_bytes()).is_err());
+        assert!(lint_lua("return {\n  \"one.domain\",\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_lint_rpz() {
+        let header =
+            "$TTL 300\n@ IN SOA localhost. hostmaster.localhost. ( 1 3600 600 86400 300 )\n";
+        let address = IpAddr::from([0, 0, 0, 0]);
+        assert!(lint_rpz(
+            format!("{}one.domain CNAME .\n", header).as_bytes(),
+            address
+        )
+        .is_ok());
+        assert!(lint_rpz(
+            format!("{}one.domain A 0.0.0.0\n", header).as_bytes(),
+            address
+        )
+        .is_err());
+        assert!(lint_rpz("one.domain CNAME .\n".as_bytes(), address).is_err());
+        assert!(lint_rpz("".as_bytes(), address).is_err());
+        // the domains resolve to the address of the warn page
+        let address = IpAddr::from([192, 0, 2, 80]);
+        assert!(lint_rpz(
+            format!("{}one.domain A 192.0.2.80\n", header).as_bytes(),
+            address
+        )
+        .is_ok());
+        assert!(lint_rpz(
+            format!("{}one.domain CNAME .\n", header).as_bytes(),
+            address
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_lint_sha256() {
+        let digest = "a379a6f6eeafb9a55e378c118034e2751e682fab9f2d30ab13d2125586ce1947";
+        assert!(lint_sha256(format!("# sha256(salt + domain)\n{}\n", digest).as_bytes()).is_ok());
+        assert!(lint_sha256(digest.to_uppercase().as_bytes()).is_err());
+        assert!(lint_sha256("one.domain\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_lint_regex() {
+        assert!(lint_regex("^(.*\\.)?(one\\.domain|two\\.domain)$\n".as_bytes()).is_ok());
+        assert!(lint_regex("^one.domain$\n".as_bytes()).is_err());
+        assert!(lint_regex("^one\\.domain\\/path$\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_lint_sni() {
+        let rule = "-A SNI-0 -m string --algo bm --string \"one.domain\" -j REJECT";
+        assert!(
+            lint_sni(format!("*filter\n:SNI-0 - [0:0]\n{}\nCOMMIT\n", rule).as_bytes()).is_ok()
+        );
+        assert!(lint_sni(format!("*filter\n{}\n", rule).as_bytes()).is_err());
+        assert!(lint_sni(
+            "*filter\n-A SNI-0 -m tls --tls-host \"a b\" -j DROP\nCOMMIT\n".as_bytes()
+        )
+        .is_err());
+    }
+}
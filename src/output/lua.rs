@@ -8,7 +8,7 @@ use std::{
 
 use futures::lock::Mutex;
 
-use crate::input::Input;
+use crate::{input::Input, output::writer::OutputWriter};
 
 /// lua_adapter translates the extracted URLs int a lua module format
 ///
@@ -21,6 +21,7 @@ pub async fn lua_adapter(
     writer: Arc<Mutex<dyn Write + Send>>,
     is_processing: Arc<AtomicBool>,
 ) {
+    let mut writer = OutputWriter::new(writer);
     let mut worte_header = false;
     loop {
         if !is_processing.load(Ordering::SeqCst) {
@@ -28,7 +29,7 @@ pub async fn lua_adapter(
         }
         // write header line
         if !worte_header {
-            if let Err(e) = writer.lock().await.write_all("return {\n".as_bytes()) {
+            if let Err(e) = writer.write_all(b"return {\n").await {
                 error!("{}", e);
             }
             worte_header = true;
@@ -36,21 +37,21 @@ pub async fn lua_adapter(
 
         match reader.lock().await.chunk().await {
             Ok(Some(chunk)) => {
-                let str_chunk = match String::from_utf8(chunk) {
+                let str_chunk = match std::str::from_utf8(&chunk) {
                     Ok(s) => s,
                     Err(e) => {
                         warn!("{}", e);
                         continue;
                     }
                 };
-                let chunk = format!("  \"{}\",\n", str_chunk.trim_end());
-                if let Err(e) = writer.lock().await.write_all(chunk.as_bytes()) {
+                let entry = str_chunk.trim_end().as_bytes();
+                if let Err(e) = writer.write_line(&[b"  \"", entry, b"\","]).await {
                     error!("{}", e);
                 }
             }
             Ok(None) => {
                 // write footer line
-                if let Err(e) = writer.lock().await.write_all("}".as_bytes()) {
+                if let Err(e) = writer.write_all(b"}").await {
                     error!("{}", e);
                 }
                 break;
@@ -61,6 +62,9 @@ pub async fn lua_adapter(
             }
         }
     }
+    if let Err(e) = writer.finish().await {
+        error!("{}", e);
+    }
 }
 
 #[cfg(test)]
@@ -1,5 +1,9 @@
 use std::{
+    collections::BTreeSet,
     fs::File,
+    io::{BufRead, BufReader},
+    net::IpAddr,
+    path::Path,
     pin::Pin,
     sync::{atomic::AtomicBool, Arc},
 };
@@ -7,32 +11,245 @@ use std::{
 use futures::{lock::Mutex, Future};
 use serde::{Deserialize, Serialize};
 
-use crate::input::file::FileInput;
+use crate::{
+    encryption::{read_output, EncryptionSettings},
+    input::Input,
+};
 
-use self::{hostsfile::hostsfile_adapter, lua::lua_adapter};
+use self::{
+    bpf::bpf_adapter,
+    hashed::hashed_adapter,
+    hostsfile::hostsfile_adapter,
+    lua::lua_adapter,
+    regex_rules::regex_rules_adapter,
+    rpz::rpz_adapter,
+    sni::{sni_adapter, SniModule},
+};
 
-mod hostsfile;
+pub mod bpf;
+pub mod hashed;
+pub mod hostsfile;
+pub mod lint;
 mod lua;
+pub mod parse;
+pub mod regex_rules;
+pub mod rpz;
+pub mod sni;
+pub mod validate;
+pub mod warn_page;
+pub mod writer;
+
+/// number of entries combined in one rule of a regex output if none is configured
+pub const DEFAULT_REGEX_BATCH_SIZE: usize = 1;
 
 /// OutputType represents a result format for the created block lists
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub enum OutputType {
     /// Lua module format
     Lua,
     /// Hostsfile format as found in /etc/hosts
     Hostsfile,
+    /// DNS response policy zone
+    Rpz,
+    /// salted SHA-256 digests of the entries in hex encoding, one per line
+    Sha256 {
+        /// prepended to every entry before hashing
+        #[serde(default)]
+        salt: String,
+    },
+    /// anchored regular expressions, one rule per line
+    Regex {
+        /// the rules match the subdomains of the entries too
+        #[serde(default)]
+        subdomains: bool,
+        /// number of entries combined in an alternation of one rule
+        batch_size: Option<usize>,
+    },
+    /// iptables-restore ruleset rejecting TLS connections by their server name
+    Sni {
+        /// the match extension searching the server name
+        #[serde(default)]
+        module: SniModule,
+        /// the chain the ruleset is hooked in with
+        chain: Option<String>,
+        /// number of rules per chunk chain
+        chunk_size: Option<usize>,
+    },
+    /// bpftool batch file adding the IP entries to the LPM trie maps of XDP filters
+    Bpf {
+        /// pinned map of the IPv4 entries
+        ipv4_map: Option<String>,
+        /// pinned map of the IPv6 entries
+        ipv6_map: Option<String>,
+    },
 }
 
 impl OutputType {
+    /// returns the adapter writing the entries in this format
+    ///
+    /// * `address`: the address the blocked domains of a Hostsfile or RPZ resolve to
+    /// * `reader`: the entries of the category list
+    /// * `writer`: the output file
+    /// * `is_processing`: determines if the program was interrupted
     pub fn get_adapter<'a>(
         &self,
-        reader: Arc<Mutex<FileInput>>,
+        address: IpAddr,
+        reader: Arc<Mutex<dyn Input + Send>>,
         writer: Arc<Mutex<File>>,
         is_processing: Arc<AtomicBool>,
     ) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> {
         match self {
             OutputType::Lua => Box::pin(lua_adapter(reader, writer, is_processing)),
-            OutputType::Hostsfile => Box::pin(hostsfile_adapter(reader, writer, is_processing)),
+            OutputType::Hostsfile => {
+                Box::pin(hostsfile_adapter(address, reader, writer, is_processing))
+            }
+            OutputType::Rpz => Box::pin(rpz_adapter(address, reader, writer, is_processing)),
+            OutputType::Sha256 { salt } => {
+                Box::pin(hashed_adapter(salt.clone(), reader, writer, is_processing))
+            }
+            OutputType::Regex {
+                subdomains,
+                batch_size,
+            } => Box::pin(regex_rules_adapter(
+                *subdomains,
+                batch_size.unwrap_or(DEFAULT_REGEX_BATCH_SIZE),
+                reader,
+                writer,
+                is_processing,
+            )),
+            OutputType::Sni {
+                module,
+                chain,
+                chunk_size,
+            } => Box::pin(sni_adapter(
+                *module,
+                chain
+                    .clone()
+                    .unwrap_or_else(|| sni::DEFAULT_SNI_CHAIN.to_string()),
+                chunk_size.unwrap_or(sni::DEFAULT_SNI_CHUNK_SIZE),
+                reader,
+                writer,
+                is_processing,
+            )),
+            OutputType::Bpf { ipv4_map, ipv6_map } => Box::pin(bpf_adapter(
+                ipv4_map
+                    .clone()
+                    .unwrap_or_else(|| bpf::DEFAULT_IPV4_MAP.to_string()),
+                ipv6_map
+                    .clone()
+                    .unwrap_or_else(|| bpf::DEFAULT_IPV6_MAP.to_string()),
+                reader,
+                writer,
+                is_processing,
+            )),
+        }
+    }
+
+    /// returns the file extension of outputs written in an additional format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputType::Lua => "lua",
+            OutputType::Hostsfile => "hosts",
+            OutputType::Rpz => "rpz",
+            OutputType::Sha256 { .. } => "sha256",
+            OutputType::Regex { .. } => "regex",
+            OutputType::Sni { .. } => "sni",
+            OutputType::Bpf { .. } => "bpf",
+        }
+    }
+
+    /// returns true if the entry can be written in this format, the others are dropped
+    /// before they reach the adapter
+    ///
+    /// * `entry`: the normalized entry
+    pub fn accepts(&self, entry: &str) -> bool {
+        match self {
+            // hashes cover any entry and the adapter skips the entries which aren't
+            // addresses
+            OutputType::Sha256 { .. } | OutputType::Bpf { .. } => !entry.is_empty(),
+            _ => lint::is_valid_hostname(entry),
+        }
+    }
+
+    /// re-parses a written output file and returns an error if it is malformed
+    ///
+    /// * `path`: the file system path of the output file
+    /// * `address`: the address the blocked domains of a Hostsfile or RPZ resolve to
+    pub fn lint(&self, path: &Path, address: IpAddr) -> anyhow::Result<()> {
+        self.lint_reader(BufReader::new(File::open(path)?), address)
+    }
+
+    /// re-parses the content of an output and returns an error if it is malformed
+    ///
+    /// * `reader`: the content of the output
+    /// * `address`: the address the blocked domains of a Hostsfile or RPZ resolve to
+    pub fn lint_reader(&self, reader: impl BufRead, address: IpAddr) -> anyhow::Result<()> {
+        match self {
+            OutputType::Lua => lint::lint_lua(reader),
+            OutputType::Hostsfile => lint::lint_hostsfile(reader, address),
+            OutputType::Rpz => lint::lint_rpz(reader, address),
+            OutputType::Sha256 { .. } => lint::lint_sha256(reader),
+            OutputType::Regex { .. } => lint::lint_regex(reader),
+            OutputType::Sni { .. } => lint::lint_sni(reader),
+            OutputType::Bpf { .. } => lint::lint_bpf(reader),
+        }
+    }
+
+    /// returns the distinct entries of an output file written in this format
+    ///
+    /// * `path`: the file system path of the output file
+    /// * `encryption`: the encryption settings of the output, if any
+    /// * `address`: the address the blocked domains of a Hostsfile or RPZ resolve to
+    pub fn entries(
+        &self,
+        path: &Path,
+        encryption: Option<&EncryptionSettings>,
+        address: IpAddr,
+    ) -> anyhow::Result<BTreeSet<String>> {
+        let contents = read_output(path, encryption)?;
+        Ok(contents
+            .lines()
+            .flat_map(|l| self.parse_entries(l, address))
+            .collect())
+    }
+
+    /// extracts the domains from a line of an output file, which is empty if the line
+    /// doesn't contain an entry
+    ///
+    /// * `line`: a line of an output file written in this format
+    /// * `address`: the address the blocked domains of a Hostsfile or RPZ resolve to, the
+    ///   lines of other addresses are included from a header or footer
+    pub fn parse_entries(&self, line: &str, address: IpAddr) -> Vec<String> {
+        let entry = match self {
+            OutputType::Lua => line
+                .trim()
+                .strip_prefix('"')
+                .and_then(|l| l.strip_suffix("\",")),
+            OutputType::Hostsfile => line
+                .trim_end()
+                .split_once(' ')
+                .filter(|(a, _)| a.parse().ok() == Some(address))
+                .map(|(_, entry)| entry),
+            OutputType::Rpz => line
+                .trim_end()
+                .strip_suffix(format!(" {}", parse::rpz_policy(address)).as_str()),
+            OutputType::Sha256 { .. } => Some(line.trim_end()).filter(|l| !l.starts_with('#')),
+            OutputType::Sni { .. } => parse::parse_sni_rule(line),
+            // a rule may combine several entries
+            OutputType::Regex { .. } => return parse::parse_regex_rule(line).unwrap_or_default(),
+            OutputType::Bpf { .. } => return parse::parse_map_update(line).into_iter().collect(),
+        };
+        entry.map(|e| vec![e.to_string()]).unwrap_or_default()
+    }
+
+    /// returns the form an entry takes in an output file written in this format, which
+    /// is the entry itself unless it's hashed
+    ///
+    /// * `entry`: the normalized entry
+    pub fn query_key(&self, entry: &str) -> String {
+        match self {
+            OutputType::Sha256 { salt } => hashed::hash_entry(salt, entry),
+            _ => entry.to_string(),
         }
     }
 }
@@ -0,0 +1,92 @@
+use std::net::IpAddr;
+
+/// group matching any subdomain in front of the listed domains of a regex rule
+pub const SUBDOMAIN_GROUP: &str = r"(.*\.)?";
+/// option of the xt_string match the server name of an SNI rule follows
+pub const SNI_STRING_OPTION: &str = "-m string --algo bm --string";
+/// option of the xt_tls match the server name of an SNI rule follows
+pub const SNI_TLS_OPTION: &str = "-m tls --tls-host";
+
+/// returns the data of the records a response policy zone answers the listed domains
+/// with, NXDOMAIN for an unspecified address and the address itself otherwise
+///
+/// * `address`: the address the blocked domains resolve to
+pub fn rpz_policy(address: IpAddr) -> String {
+    match address {
+        address if address.is_unspecified() => "CNAME .".to_string(),
+        IpAddr::V4(address) => format!("A {}", address),
+        IpAddr::V6(address) => format!("AAAA {}", address),
+    }
+}
+
+/// extracts the domains from a rule, returns None if the line isn't a rule written
+/// by the regex_rules_adapter
+///
+/// * `line`: a line of a regex output
+pub fn parse_regex_rule(line: &str) -> Option<Vec<String>> {
+    let rule = line.trim_end().strip_prefix('^')?.strip_suffix('$')?;
+    let rule = rule.strip_prefix(SUBDOMAIN_GROUP).unwrap_or(rule);
+    let alternation = match rule.strip_prefix('(') {
+        Some(group) => group.strip_suffix(')')?,
+        None => rule,
+    };
+    // escaped domains contain neither parentheses nor bars
+    alternation.split('|').map(unescape).collect()
+}
+
+/// removes the escaping of regex::escape, returns None for unescaped meta characters
+///
+/// * `escaped`: an escaped domain
+fn unescape(escaped: &str) -> Option<String> {
+    let mut domain = String::with_capacity(escaped.len());
+    let mut chars = escaped.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => domain.push(chars.next()?),
+            '.' | '*' | '+' | '?' | '(' | ')' | '[' | ']' | '{' | '}' | '^' | '$' | '|' => {
+                return None
+            }
+            c => domain.push(c),
+        }
+    }
+    Some(domain).filter(|d| !d.is_empty())
+}
+
+/// extracts the domain from a rule, returns None if the line isn't a rule
+///
+/// * `line`: a line of an SNI ruleset
+pub fn parse_sni_rule(line: &str) -> Option<&str> {
+    if !line.starts_with("-A ") {
+        return None;
+    }
+    let option = [SNI_STRING_OPTION, SNI_TLS_OPTION]
+        .iter()
+        .map(|o| format!(" {} \"", o))
+        .find(|o| line.contains(o.as_str()))?;
+    let (_, rest) = line.split_once(option.as_str())?;
+    rest.split_once('"').map(|(domain, _)| domain)
+}
+
+/// extracts the entry from a bpftool command, returns None if the line isn't a map
+/// update written by the bpf_adapter
+///
+/// * `line`: a line of a bpftool batch file
+pub fn parse_map_update(line: &str) -> Option<String> {
+    let (_, rest) = line.trim_end().split_once(" key hex ")?;
+    let (key, _) = rest.split_once(" value hex ")?;
+    let key: Vec<u8> = key
+        .split(' ')
+        .map(|b| u8::from_str_radix(b, 16).ok())
+        .collect::<Option<_>>()?;
+    let prefix = u32::from_le_bytes(key.get(..4)?.try_into().ok()?);
+    let (address, max_prefix) = match key.len() {
+        8 => (IpAddr::from(<[u8; 4]>::try_from(&key[4..]).ok()?), 32),
+        20 => (IpAddr::from(<[u8; 16]>::try_from(&key[4..]).ok()?), 128),
+        _ => return None,
+    };
+    match prefix {
+        p if p == max_prefix => Some(address.to_string()),
+        p if p < max_prefix => Some(format!("{}/{}", address, p)),
+        _ => None,
+    }
+}
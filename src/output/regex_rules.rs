@@ -0,0 +1,141 @@
+use std::{
+    io::Write,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use futures::lock::Mutex;
+
+use crate::{
+    input::Input,
+    output::{parse::SUBDOMAIN_GROUP, writer::OutputWriter},
+};
+
+/// returns an anchored rule matching the domains, several domains are combined in
+/// an alternation
+///
+/// * `domains`: the normalized domains
+/// * `subdomains`: whether the subdomains of the domains match too
+pub fn regex_rule(domains: &[String], subdomains: bool) -> String {
+    let escaped: Vec<String> = domains.iter().map(|d| regex::escape(d)).collect();
+    let alternation = match escaped.len() {
+        1 => escaped[0].clone(),
+        _ => format!("({})", escaped.join("|")),
+    };
+    let prefix = if subdomains { SUBDOMAIN_GROUP } else { "" };
+    format!("^{}{}$", prefix, alternation)
+}
+
+/// regex_rules_adapter translates the extracted URLs into anchored regular expressions
+/// for proxies and SNI filters which only match patterns
+///
+/// * `subdomains`: whether the rules match the subdomains of the entries too
+/// * `batch_size`: the number of entries combined in one rule
+/// * `reader`: data source that implements the Input trait
+/// * `writer`: data sink that implements std::io::Write
+/// * `is_processing`: determines if the program was interrupted
+pub async fn regex_rules_adapter(
+    subdomains: bool,
+    batch_size: usize,
+    reader: Arc<Mutex<dyn Input + Send>>,
+    writer: Arc<Mutex<dyn Write + Send>>,
+    is_processing: Arc<AtomicBool>,
+) {
+    let batch_size = batch_size.max(1);
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut writer = OutputWriter::new(writer);
+    loop {
+        if !is_processing.load(Ordering::SeqCst) {
+            return;
+        }
+        match reader.lock().await.chunk().await {
+            Ok(Some(chunk)) => {
+                let str_chunk = match std::str::from_utf8(&chunk) {
+                    Ok(s) => s,
+                    Err(e) => {
+                        warn!("{}", e);
+                        continue;
+                    }
+                };
+                batch.push(str_chunk.trim_end().to_string());
+                if batch.len() < batch_size {
+                    continue;
+                }
+                let rule = regex_rule(&batch, subdomains);
+                batch.clear();
+                if let Err(e) = writer.write_line(&[rule.as_bytes()]).await {
+                    error!("{}", e);
+                }
+            }
+            Ok(None) => {
+                break;
+            }
+            Err(e) => {
+                error!("{}", e);
+                break;
+            }
+        }
+    }
+    // the last rule holds the remaining entries
+    if !batch.is_empty() {
+        let rule = regex_rule(&batch, subdomains);
+        if let Err(e) = writer.write_line(&[rule.as_bytes()]).await {
+            error!("{}", e);
+        }
+    }
+    if let Err(e) = writer.finish().await {
+        error!("{}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{
+        output::{lint::lint_regex, parse::parse_regex_rule},
+        tests::helper::cursor_input::CursorInput,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_regex_rule() {
+        let domains = vec!["one.domain".to_string(), "two-x.domain".to_string()];
+        assert_eq!(regex_rule(&domains[..1], false), r"^one\.domain$");
+        assert_eq!(
+            regex_rule(&domains, true),
+            r"^(.*\.)?(one\.domain|two\-x\.domain)$"
+        );
+        for subdomains in [false, true] {
+            let rule = regex_rule(&domains, subdomains);
+            assert_eq!(parse_regex_rule(&rule), Some(domains.clone()));
+            let re = regex::Regex::new(&rule).unwrap();
+            assert!(re.is_match("two-x.domain"));
+            assert_eq!(re.is_match("sub.one.domain"), subdomains);
+            assert!(!re.is_match("oneXdomain"));
+            assert!(!re.is_match("none.domain"));
+        }
+        assert_eq!(parse_regex_rule("^one.domain$"), None);
+        assert_eq!(parse_regex_rule("one\\.domain"), None);
+    }
+
+    #[tokio::test]
+    async fn test_regex_rules_adapter() {
+        let input = Arc::new(Mutex::new(CursorInput::new(
+            "one.domain\ntwo.domain\nthree.domain\n",
+        )));
+        let output = Arc::new(Mutex::new(Cursor::new(vec![])));
+        let is_processing = Arc::new(AtomicBool::new(true));
+
+        regex_rules_adapter(true, 2, input, output.clone(), is_processing).await;
+        let o = output.lock().await.clone().into_inner();
+        assert!(lint_regex(o.as_slice()).is_ok());
+        assert_eq!(
+            String::from_utf8_lossy(&o),
+            "^(.*\\.)?(one\\.domain|two\\.domain)$\n^(.*\\.)?three\\.domain$\n"
+        );
+    }
+}
@@ -0,0 +1,239 @@
+use std::{
+    fs,
+    io::Write,
+    net::IpAddr,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::Context;
+use futures::lock::Mutex;
+
+use crate::{encryption::EncryptionSettings, input::Input, report::unix_timestamp};
+
+use super::{parse::rpz_policy, writer::OutputWriter, OutputType};
+
+/// time to live of the records in seconds
+pub const RPZ_TTL: u32 = 300;
+
+/// returns the records every zone starts with, names are relative to the zone's origin
+///
+/// * `serial`: the serial number of the SOA record
+fn zone_header(serial: u32) -> String {
+    format!(
+        "$TTL {ttl}\n@ IN SOA localhost. hostmaster.localhost. ( {serial} 3600 600 86400 {ttl} )\n@ IN NS localhost.\n",
+        ttl = RPZ_TTL,
+        serial = serial
+    )
+}
+
+/// rpz_adapter translates the extracted URLs into a response policy zone answering
+/// every listed domain with NXDOMAIN, or with the address of the warn page if one is
+/// configured
+///
+/// * `address`: the address the blocked domains resolve to
+/// * `reader`: data source that implements the Input trait
+/// * `writer`: data sink that implements std::io::Write
+/// * `is_processing`: determines if the program was interrupted
+pub async fn rpz_adapter(
+    address: IpAddr,
+    reader: Arc<Mutex<dyn Input + Send>>,
+    writer: Arc<Mutex<dyn Write + Send>>,
+    is_processing: Arc<AtomicBool>,
+) {
+    // the serial has to increase with every full zone the secondaries transfer
+    let serial = unix_timestamp() as u32;
+    let policy = format!(" {}", rpz_policy(address));
+    let mut writer = OutputWriter::new(writer);
+    if let Err(e) = writer.write_all(zone_header(serial).as_bytes()).await {
+        error!("{}", e);
+    }
+    loop {
+        if !is_processing.load(Ordering::SeqCst) {
+            return;
+        }
+        match reader.lock().await.chunk().await {
+            Ok(Some(chunk)) => {
+                let str_chunk = match std::str::from_utf8(&chunk) {
+                    Ok(s) => s,
+                    Err(e) => {
+                        warn!("{}", e);
+                        continue;
+                    }
+                };
+                let entry = str_chunk.trim_end().as_bytes();
+                if let Err(e) = writer.write_line(&[entry, policy.as_bytes()]).await {
+                    error!("{}", e);
+                }
+            }
+            Ok(None) => {
+                break;
+            }
+            Err(e) => {
+                error!("{}", e);
+                break;
+            }
+        }
+    }
+    if let Err(e) = writer.finish().await {
+        error!("{}", e);
+    }
+}
+
+/// returns an nsupdate script removing and adding the records of the changed domains
+///
+/// * `zone`: the name of the zone
+/// * `added`: domains missing in the deployed zone
+/// * `removed`: domains no longer part of the zone
+/// * `address`: the address the blocked domains resolve to
+pub fn nsupdate_script(zone: &str, added: &[&str], removed: &[&str], address: IpAddr) -> String {
+    let zone = zone.trim_end_matches('.');
+    let policy = rpz_policy(address);
+    let mut script = format!("zone {}.\n", zone);
+    // all records of a name are deleted, whichever policy they were added with
+    for domain in removed {
+        script += &format!("update delete {}.{}.\n", domain, zone);
+    }
+    for domain in added {
+        script += &format!("update add {}.{}. {} {}\n", domain, zone, RPZ_TTL, policy);
+    }
+    script += "send\n";
+    script
+}
+
+/// writes the nsupdate script turning the deployed zone into the built zone, returns
+/// false if there's no deployed zone the changes could be applied to
+///
+/// * `zone`: the name of the zone
+/// * `deployed_path`: the zone currently deployed
+/// * `built_path`: the newly built zone
+/// * `update_path`: the file system path of the nsupdate script
+/// * `encryption`: the encryption settings of the zone, if any
+/// * `address`: the address the blocked domains resolve to
+pub fn write_update(
+    zone: &str,
+    deployed_path: &Path,
+    built_path: &Path,
+    update_path: &Path,
+    encryption: Option<&EncryptionSettings>,
+    address: IpAddr,
+) -> anyhow::Result<bool> {
+    let deployed = match OutputType::Rpz.entries(deployed_path, encryption, address) {
+        Ok(entries) => entries,
+        Err(_) => {
+            // an outdated script must not be applied to a fully transferred zone
+            fs::remove_file(update_path).ok();
+            return Ok(false);
+        }
+    };
+    let built = OutputType::Rpz.entries(built_path, encryption, address)?;
+    let added: Vec<&str> = built.difference(&deployed).map(|e| e.as_str()).collect();
+    let removed: Vec<&str> = deployed.difference(&built).map(|e| e.as_str()).collect();
+    fs::write(
+        update_path,
+        nsupdate_script(zone, &added, &removed, address),
+    )
+    .with_context(|| format!("could not write {:?}", update_path))?;
+    debug!(
+        "{}: {} records added, {} records removed",
+        zone,
+        added.len(),
+        removed.len()
+    );
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{
+        output::{hostsfile::DEFAULT_ADDRESS, lint::lint_rpz},
+        tests::helper::{cache_file_creator::TEST_CACHE, cursor_input::CursorInput},
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rpz_adapter() {
+        let input = Arc::new(Mutex::new(CursorInput::new("domain.one\ndomain.two\n")));
+        let output = Arc::new(Mutex::new(Cursor::new(vec![])));
+        let is_processing = Arc::new(AtomicBool::new(true));
+
+        rpz_adapter(DEFAULT_ADDRESS, input, output.clone(), is_processing).await;
+        let o = output.lock().await.clone().into_inner();
+        assert!(lint_rpz(o.as_slice(), DEFAULT_ADDRESS).is_ok());
+        let got = String::from_utf8_lossy(&o);
+        assert!(got.ends_with("domain.one CNAME .\ndomain.two CNAME .\n"));
+    }
+
+    #[tokio::test]
+    async fn test_rpz_adapter_warn_page() {
+        let input = Arc::new(Mutex::new(CursorInput::new("domain.one\n")));
+        let output = Arc::new(Mutex::new(Cursor::new(vec![])));
+        let address = IpAddr::from([0x2001, 0xdb8, 0, 0, 0, 0, 0, 0x80]);
+
+        rpz_adapter(
+            address,
+            input,
+            output.clone(),
+            Arc::new(AtomicBool::new(true)),
+        )
+        .await;
+        let o = output.lock().await.clone().into_inner();
+        assert!(lint_rpz(o.as_slice(), address).is_ok());
+        assert!(String::from_utf8_lossy(&o).ends_with("domain.one AAAA 2001:db8::80\n"));
+        assert_eq!(
+            nsupdate_script("rpz", &["a.domain"], &[], IpAddr::from([192, 0, 2, 80])),
+            "zone rpz.\nupdate add a.domain.rpz. 300 A 192.0.2.80\nsend\n"
+        );
+    }
+
+    #[test]
+    fn test_write_update() {
+        let mut dir = std::path::PathBuf::from(TEST_CACHE);
+        dir.push("test_rpz_write_update");
+        fs::create_dir_all(&dir).unwrap();
+        let update_path = dir.join("zone.nsupdate");
+        fs::write(
+            dir.join("built"),
+            zone_header(2) + "b.domain CNAME .\nc.domain CNAME .\n",
+        )
+        .unwrap();
+
+        // without a deployed zone there's nothing to update
+        fs::remove_file(dir.join("deployed")).ok();
+        assert!(!write_update(
+            "rpz",
+            &dir.join("deployed"),
+            &dir.join("built"),
+            &update_path,
+            None,
+            DEFAULT_ADDRESS
+        )
+        .unwrap());
+        assert!(!update_path.exists());
+
+        fs::write(
+            dir.join("deployed"),
+            zone_header(1) + "a.domain CNAME .\nb.domain CNAME .\n",
+        )
+        .unwrap();
+        assert!(write_update(
+            "rpz",
+            &dir.join("deployed"),
+            &dir.join("built"),
+            &update_path,
+            None,
+            DEFAULT_ADDRESS
+        )
+        .unwrap());
+        assert_eq!(
+            fs::read_to_string(&update_path).unwrap(),
+            "zone rpz.\nupdate delete a.domain.rpz.\nupdate add c.domain.rpz. 300 CNAME .\nsend\n"
+        );
+    }
+}
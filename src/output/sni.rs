@@ -0,0 +1,203 @@
+use std::{
+    io::Write,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use futures::lock::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    input::Input,
+    output::{
+        parse::{SNI_STRING_OPTION, SNI_TLS_OPTION},
+        writer::OutputWriter,
+    },
+};
+
+/// chain the server name rules are jumped to from if none is configured
+pub const DEFAULT_SNI_CHAIN: &str = "HARVESTER-SNI";
+/// number of rules per chunk chain if none is configured
+pub const DEFAULT_SNI_CHUNK_SIZE: usize = 1000;
+
+/// SniModule is the match extension the server names are searched with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum SniModule {
+    /// xt_string searches the first packets for the name, it's available in every
+    /// kernel and in nftables by iptables-nft
+    #[default]
+    String,
+    /// the out of tree xt_tls module parses the server name of the TLS handshake
+    Tls,
+}
+
+impl SniModule {
+    /// returns the option of the match the server name follows
+    fn option(&self) -> &'static str {
+        match self {
+            SniModule::String => SNI_STRING_OPTION,
+            SniModule::Tls => SNI_TLS_OPTION,
+        }
+    }
+}
+
+/// writes the rule rejecting connections to the server name
+///
+/// * `writer`: the output the line is written to
+/// * `module`: the match extension
+/// * `chain`: the chain the ruleset is hooked in with
+/// * `chunk`: the number of the chunk chain the rule is appended to
+/// * `domain`: the normalized domain
+fn write_rule(
+    writer: &mut impl Write,
+    module: SniModule,
+    chain: &str,
+    chunk: usize,
+    domain: &str,
+) -> std::io::Result<()> {
+    writeln!(
+        writer,
+        "-A {}-{} {} \"{}\" -j REJECT --reject-with tcp-reset",
+        chain,
+        chunk,
+        module.option(),
+        domain
+    )
+}
+
+/// sni_adapter translates the extracted URLs into an iptables-restore ruleset rejecting
+/// TLS connections by their server name, the rules are split into chunk chains the
+/// configured chain jumps to for HTTPS traffic
+///
+/// * `module`: the match extension
+/// * `chain`: the chain the ruleset is hooked in with
+/// * `chunk_size`: the number of rules per chunk chain
+/// * `reader`: data source that implements the Input trait
+/// * `writer`: data sink that implements std::io::Write
+/// * `is_processing`: determines if the program was interrupted
+pub async fn sni_adapter(
+    module: SniModule,
+    chain: String,
+    chunk_size: usize,
+    reader: Arc<Mutex<dyn Input + Send>>,
+    writer: Arc<Mutex<dyn Write + Send>>,
+    is_processing: Arc<AtomicBool>,
+) {
+    let chunk_size = chunk_size.max(1);
+    let mut writer = OutputWriter::new(writer);
+    let header = format!("*filter\n:{} - [0:0]\n", chain);
+    if let Err(e) = writer.write_all(header.as_bytes()).await {
+        error!("{}", e);
+    }
+    let mut rules = 0;
+    let mut rule = vec![];
+    loop {
+        if !is_processing.load(Ordering::SeqCst) {
+            return;
+        }
+        match reader.lock().await.chunk().await {
+            Ok(Some(chunk)) => {
+                let str_chunk = match std::str::from_utf8(&chunk) {
+                    Ok(s) => s,
+                    Err(e) => {
+                        warn!("{}", e);
+                        continue;
+                    }
+                };
+                let chunk_chain = rules / chunk_size;
+                // every chunk chain is declared and jumped to before its first rule
+                if rules % chunk_size == 0 {
+                    let declaration = format!(
+                        ":{0}-{1} - [0:0]\n-A {0} -p tcp --dport 443 -j {0}-{1}\n",
+                        chain, chunk_chain
+                    );
+                    if let Err(e) = writer.write_all(declaration.as_bytes()).await {
+                        error!("{}", e);
+                    }
+                }
+                rules += 1;
+                let domain = str_chunk.trim_end();
+                rule.clear();
+                if let Err(e) = write_rule(&mut rule, module, &chain, chunk_chain, domain) {
+                    error!("{}", e);
+                }
+                if let Err(e) = writer.write_all(&rule).await {
+                    error!("{}", e);
+                }
+            }
+            Ok(None) => {
+                break;
+            }
+            Err(e) => {
+                error!("{}", e);
+                break;
+            }
+        }
+    }
+    if let Err(e) = writer.write_all(b"COMMIT\n").await {
+        error!("{}", e);
+    }
+    if let Err(e) = writer.finish().await {
+        error!("{}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{
+        output::{lint::lint_sni, parse::parse_sni_rule},
+        tests::helper::cursor_input::CursorInput,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_parse_rule() {
+        for module in [SniModule::String, SniModule::Tls] {
+            let mut rule = vec![];
+            write_rule(&mut rule, module, "HARVESTER-SNI", 0, "one.domain").unwrap();
+            let rule = String::from_utf8(rule).unwrap();
+            assert_eq!(parse_sni_rule(&rule), Some("one.domain"));
+        }
+        assert_eq!(parse_sni_rule(":HARVESTER-SNI-0 - [0:0]"), None);
+        assert_eq!(
+            parse_sni_rule("-A HARVESTER-SNI -p tcp --dport 443 -j HARVESTER-SNI-0"),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sni_adapter() {
+        let input = Arc::new(Mutex::new(CursorInput::new(
+            "one.domain\ntwo.domain\nthree.domain\n",
+        )));
+        let output = Arc::new(Mutex::new(Cursor::new(vec![])));
+        let is_processing = Arc::new(AtomicBool::new(true));
+
+        sni_adapter(
+            SniModule::Tls,
+            "SNI".to_string(),
+            2,
+            input,
+            output.clone(),
+            is_processing,
+        )
+        .await;
+        let o = output.lock().await.clone().into_inner();
+        assert!(lint_sni(o.as_slice()).is_ok());
+        assert_eq!(
+            String::from_utf8_lossy(&o),
+            "*filter\n:SNI - [0:0]\n\
+             :SNI-0 - [0:0]\n-A SNI -p tcp --dport 443 -j SNI-0\n\
+             -A SNI-0 -m tls --tls-host \"one.domain\" -j REJECT --reject-with tcp-reset\n\
+             -A SNI-0 -m tls --tls-host \"two.domain\" -j REJECT --reject-with tcp-reset\n\
+             :SNI-1 - [0:0]\n-A SNI -p tcp --dport 443 -j SNI-1\n\
+             -A SNI-1 -m tls --tls-host \"three.domain\" -j REJECT --reject-with tcp-reset\n\
+             COMMIT\n"
+        );
+    }
+}
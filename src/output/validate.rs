@@ -0,0 +1,97 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::lock::Mutex;
+
+use crate::{input::Input, output::OutputType};
+
+/// ValidatedInput reads the entries of another input and drops the ones which can't be
+/// written in an output format, so a single malformed entry of a source doesn't fail
+/// the lint of the whole output. The entries are expected one per chunk.
+pub struct ValidatedInput {
+    reader: Arc<Mutex<dyn Input + Send>>,
+    format: OutputType,
+    /// the file name of the output, for the warning about dropped entries
+    name: String,
+    dropped: usize,
+}
+
+impl ValidatedInput {
+    /// Initialize a new ValidatedInput
+    ///
+    /// * `reader`: the input providing the entries
+    /// * `format`: the format the entries are written in
+    /// * `name`: the file name of the output
+    pub fn new(reader: Arc<Mutex<dyn Input + Send>>, format: OutputType, name: &str) -> Self {
+        Self {
+            reader,
+            format,
+            name: name.to_string(),
+            dropped: 0,
+        }
+    }
+}
+
+#[async_trait]
+impl Input for ValidatedInput {
+    async fn chunk(&mut self) -> anyhow::Result<Option<Bytes>> {
+        let mut reader = self.reader.lock().await;
+        while let Some(chunk) = reader.chunk().await? {
+            let valid = std::str::from_utf8(&chunk)
+                .is_ok_and(|entry| self.format.accepts(entry.trim_end()));
+            if valid {
+                return Ok(Some(chunk));
+            }
+            self.dropped += 1;
+        }
+        if self.dropped > 0 {
+            warn!(
+                "{}: {} invalid entries dropped",
+                self.name,
+                std::mem::take(&mut self.dropped)
+            );
+        }
+        Ok(None)
+    }
+
+    async fn reset(&mut self) -> anyhow::Result<()> {
+        self.dropped = 0;
+        self.reader.lock().await.reset().await
+    }
+
+    async fn len(&mut self) -> anyhow::Result<u64> {
+        Err(anyhow::anyhow!(
+            "the length of validated entries is unknown in advance"
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::helper::cursor_input::CursorInput;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_validated_input() {
+        let entries = "bad..domain\none.domain\n1.2.3.0/24\n";
+        let mut got = vec![];
+        for format in [
+            OutputType::Hostsfile,
+            OutputType::Sha256 { salt: "".into() },
+        ] {
+            let mut input = ValidatedInput::new(
+                Arc::new(Mutex::new(CursorInput::new(entries))),
+                format,
+                "test",
+            );
+            let mut lines = String::new();
+            while let Some(chunk) = input.chunk().await.unwrap() {
+                lines.push_str(std::str::from_utf8(&chunk).unwrap());
+            }
+            got.push(lines);
+        }
+        assert_eq!(got, vec!["one.domain\n", entries]);
+    }
+}
@@ -0,0 +1,111 @@
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    io::{BufWriter, Write},
+    net::IpAddr,
+    path::Path,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{output_settings::OutputSettings, platform};
+
+/// WarnPageSettings let the blocked domains of an output resolve to a landing page
+/// explaining the block instead of an unreachable address
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct WarnPageSettings {
+    /// address of the web server serving the landing page
+    pub address: IpAddr,
+    /// writes an nginx map of the blocked hosts next to the output, so the landing
+    /// page can tell the category a host was blocked for
+    #[serde(default)]
+    pub nginx_map: bool,
+}
+
+/// returns the file name of the nginx map of an output
+///
+/// * `tag`: the tag of the output
+pub fn nginx_map_name(tag: &str) -> String {
+    format!("{}.nginx", tag)
+}
+
+/// writes the entries of an output as nginx map entries whose value is the tag. The
+/// leading dot matches the sub domains too if the map is declared with `hostnames`.
+/// The map is encrypted and gets the permissions of the output before it's moved into
+/// place, as it lists the same hosts.
+///
+/// * `path`: the file system path of the map
+/// * `tag`: the tag of the output, which is the value of every entry
+/// * `entries`: the blocked hosts
+/// * `settings`: the settings of the output
+pub fn write_nginx_map(
+    path: &Path,
+    tag: &str,
+    entries: &BTreeSet<String>,
+    settings: &OutputSettings,
+) -> anyhow::Result<()> {
+    let tmp_path = path.with_file_name(format!(".{}.tmp", nginx_map_name(tag)));
+    let mut writer = BufWriter::new(
+        File::create(&tmp_path).with_context(|| format!("could not create {:?}", tmp_path))?,
+    );
+    writeln!(writer, "# blocked hosts of {}, generated by harvester", tag)?;
+    for entry in entries {
+        writeln!(writer, ".{} \"{}\";", entry.trim_end_matches('.'), tag)?;
+    }
+    writer.flush()?;
+    drop(writer);
+    if let Some(encryption) = &settings.encryption {
+        encryption.encrypt_file(&tmp_path)?;
+    }
+    settings.apply_permissions(&tmp_path)?;
+    platform::replace_file(&tmp_path, path)
+        .with_context(|| format!("could not write {:?}", path))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use crate::{
+        encryption::{read_output, EncryptionSettings},
+        tests::helper::cache_file_creator::TEST_CACHE,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_write_nginx_map() {
+        let path = PathBuf::from(TEST_CACHE).join(nginx_map_name("test_write_nginx_map"));
+        let entries = BTreeSet::from(["one.domain".to_string(), "two.domain.".to_string()]);
+        write_nginx_map(&path, "malware", &entries, &OutputSettings::default()).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# blocked hosts of malware, generated by harvester\n\
+             .one.domain \"malware\";\n\
+             .two.domain \"malware\";\n"
+        );
+    }
+
+    #[test]
+    fn test_write_nginx_map_encrypted() {
+        let dir = PathBuf::from(TEST_CACHE).join("test_write_nginx_map_encrypted");
+        fs::create_dir_all(&dir).unwrap();
+        let key_file = dir.join("key");
+        fs::write(&key_file, base64::encode([7u8; 32])).unwrap();
+        let settings = OutputSettings {
+            encryption: Some(EncryptionSettings {
+                key_file: key_file.to_string_lossy().to_string(),
+            }),
+            ..Default::default()
+        };
+        let path = dir.join(nginx_map_name("malware"));
+        let entries = BTreeSet::from(["confidential.domain".to_string()]);
+        write_nginx_map(&path, "malware", &entries, &settings).unwrap();
+        assert!(!String::from_utf8_lossy(&fs::read(&path).unwrap()).contains("confidential"));
+        assert!(read_output(&path, settings.encryption.as_ref())
+            .unwrap()
+            .contains(".confidential.domain \"malware\";"));
+    }
+}
@@ -0,0 +1,166 @@
+use std::{
+    io::{self, Write},
+    mem,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use futures::lock::Mutex;
+use tokio::{sync::mpsc, task::JoinHandle};
+
+/// number of bytes of an output collected before they are handed to the writer task
+const OUTPUT_BUFFER_SIZE: usize = 64 * 1024;
+/// maximum time lines stay in the buffer, so slowly generated outputs reach the file
+/// as well
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+/// number of full buffers waiting for the writer task before the adapter is paused
+const PENDING_BUFFERS: usize = 2;
+
+/// OutputWriter collects the lines of an output in memory and hands them to a blocking
+/// task writing the file, so the adapters never stall the runtime on disk I/O. Every
+/// write hands the buffer over once it's full, so it stays bounded.
+pub struct OutputWriter {
+    buf: Vec<u8>,
+    /// the buffers handed to the writer task, None once finished
+    tx: Option<mpsc::Sender<Vec<u8>>>,
+    task: Option<JoinHandle<io::Result<()>>>,
+    /// the time the buffer was last handed over
+    handed_over: Instant,
+}
+
+impl OutputWriter {
+    /// starts the task writing the output file
+    ///
+    /// * `writer`: the output file, locked by the writer task until it's finished
+    pub fn new(writer: Arc<Mutex<dyn Write + Send>>) -> Self {
+        let (tx, mut rx) = mpsc::channel::<Vec<u8>>(PENDING_BUFFERS);
+        let task = tokio::task::spawn_blocking(move || {
+            let mut writer = futures::executor::block_on(writer.lock());
+            while let Some(buf) = rx.blocking_recv() {
+                writer.write_all(&buf)?;
+            }
+            writer.flush()
+        });
+        Self {
+            buf: Vec::with_capacity(OUTPUT_BUFFER_SIZE),
+            tx: Some(tx),
+            task: Some(task),
+            handed_over: Instant::now(),
+        }
+    }
+
+    /// writes the parts of a line one after another followed by a line break and hands
+    /// the buffer over if it's due
+    ///
+    /// * `parts`: the parts of the line, e.g. a prefix, the entry and a suffix
+    pub async fn write_line(&mut self, parts: &[&[u8]]) -> io::Result<()> {
+        for part in parts {
+            self.buf.extend_from_slice(part);
+        }
+        self.buf.push(b'\n');
+        self.hand_over().await
+    }
+
+    /// writes the data as is, e.g. a header of several lines, and hands the buffer over
+    /// if it's due
+    ///
+    /// * `data`: the data to be written
+    pub async fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        self.buf.extend_from_slice(data);
+        self.hand_over().await
+    }
+
+    /// hands the buffer to the writer task once it's full or it wasn't handed over for
+    /// a while, waits if the task falls behind
+    async fn hand_over(&mut self) -> io::Result<()> {
+        if self.buf.len() < OUTPUT_BUFFER_SIZE && self.handed_over.elapsed() < FLUSH_INTERVAL {
+            return Ok(());
+        }
+        self.send().await
+    }
+
+    /// sends the buffered lines to the writer task
+    async fn send(&mut self) -> io::Result<()> {
+        self.handed_over = Instant::now();
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let buf = mem::replace(&mut self.buf, Vec::with_capacity(OUTPUT_BUFFER_SIZE));
+        let tx = self.tx.as_ref().ok_or_else(stopped)?;
+        match tx.send(buf).await {
+            Ok(()) => Ok(()),
+            // the task only stops early on a write error, which is returned instead
+            Err(_) => Err(self.join().await.err().unwrap_or_else(stopped)),
+        }
+    }
+
+    /// writes the rest of the output, flushes the file and waits for the writer task
+    pub async fn finish(mut self) -> io::Result<()> {
+        self.send().await?;
+        self.join().await
+    }
+
+    /// closes the channel and returns the result of the writer task
+    async fn join(&mut self) -> io::Result<()> {
+        self.tx = None;
+        match self.task.take() {
+            Some(task) => task.await.map_err(io::Error::other)?,
+            None => Err(stopped()),
+        }
+    }
+}
+
+/// returns the error of writes after the writer task stopped
+fn stopped() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "output writer stopped")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_output_writer() {
+        let output = Arc::new(Mutex::new(Cursor::new(vec![])));
+        let mut writer = OutputWriter::new(output.clone());
+        writer.write_all(b"# header\n").await.unwrap();
+        // more lines than fit into one buffer
+        let entry = "a".repeat(1000);
+        for _ in 0..100 {
+            writer
+                .write_line(&[b"0.0.0.0 ", entry.as_bytes()])
+                .await
+                .unwrap();
+        }
+        writer.finish().await.unwrap();
+
+        let written = String::from_utf8(output.lock().await.get_ref().clone()).unwrap();
+        let lines: Vec<&str> = written.lines().collect();
+        assert_eq!(lines.len(), 101);
+        assert_eq!(lines[0], "# header");
+        assert_eq!(lines[100], format!("0.0.0.0 {}", entry));
+    }
+
+    /// a writer failing every write
+    struct FailingWriter;
+
+    impl Write for FailingWriter {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("disk full"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write_error() {
+        let mut writer = OutputWriter::new(Arc::new(Mutex::new(FailingWriter)));
+        writer.write_line(&[b"one.domain"]).await.unwrap();
+        let e = writer.finish().await.unwrap_err();
+        assert_eq!(e.to_string(), "disk full");
+    }
+}
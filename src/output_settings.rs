@@ -0,0 +1,369 @@
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    net::IpAddr,
+    path::Path,
+};
+
+use anyhow::Context;
+use chrono::NaiveDateTime;
+use regex::RegexSet;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    deploy::DeploySettings,
+    dns_update::DnsUpdateSettings,
+    encryption::{read_output, EncryptionSettings},
+    output::{
+        hostsfile::{merge_hosts, DEFAULT_ADDRESS},
+        warn_page::WarnPageSettings,
+    },
+    schedule::FetchWindow,
+};
+
+/// OutputSettings contains options for a single output file. The settings apply to
+/// the category list named after the tag.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct OutputSettings {
+    /// the tag of the category list the settings belong to
+    pub tag: String,
+    /// file mode in octal notation (e.g. "0644")
+    pub mode: Option<String>,
+    /// user name or uid the file is owned by
+    pub owner: Option<String>,
+    /// group name or gid the file is owned by
+    pub group: Option<String>,
+    /// domains which never end up in the output, a leading `*.` matches all sub domains
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// regular expressions matching domains which never end up in the output
+    #[serde(default)]
+    pub exclude_regex: Vec<String>,
+    /// minimum number of entries required to promote the output
+    pub min_entries: Option<usize>,
+    /// maximum number of entries allowed to promote the output
+    pub max_entries: Option<usize>,
+    /// minimum sum of the weights of the lists a domain must be found in to end up in
+    /// the output
+    pub min_score: Option<u32>,
+    /// domains which must never be blocked, the output isn't promoted if it contains any
+    #[serde(default)]
+    pub safeguard: Vec<String>,
+    /// name of the response policy zone, defaults to the tag
+    pub zone: Option<String>,
+    /// publishes the changes of the output to a DNS server by dynamic updates
+    pub dns_update: Option<DnsUpdateSettings>,
+    /// encrypts the output files
+    pub encryption: Option<EncryptionSettings>,
+    /// days the first and last appearance of an entry which left the lists are kept
+    pub prune_after_days: Option<u64>,
+    /// days an entry stays in the output after it left the lists, the output only
+    /// holds the entries of the current run if None
+    pub accumulate_days: Option<u64>,
+    /// file whose content is written in front of the output, e.g. the static entries
+    /// of /etc/hosts
+    pub header_file: Option<String>,
+    /// file whose content is appended to the output
+    pub footer_file: Option<String>,
+    /// system hosts file the Hostsfile output is merged into between markers, e.g.
+    /// /etc/hosts
+    pub hosts_file: Option<String>,
+    /// installs the output into a system location by the deploy sub command
+    pub deploy: Option<DeploySettings>,
+    /// resolves the blocked domains to a landing page explaining the block
+    pub warn_page: Option<WarnPageSettings>,
+    /// the local times the output blocks its domains in, always if empty. Outside
+    /// of them the output is written without entries.
+    #[serde(default)]
+    pub block_windows: Vec<FetchWindow>,
+}
+
+/// Exclusions matches domains which must not be written to an output
+#[derive(Debug, Default)]
+pub struct Exclusions {
+    domains: HashSet<String>,
+    /// domains excluded along with their sub domains
+    wildcards: Vec<String>,
+    regex: Option<RegexSet>,
+}
+
+impl Exclusions {
+    /// returns true if the domain must not be written to the output
+    ///
+    /// * `domain`: the domain to be checked
+    pub fn is_excluded(&self, domain: &str) -> bool {
+        self.domains.contains(domain)
+            || self.wildcards.iter().any(|base| {
+                domain == base
+                    || domain
+                        .strip_suffix(base.as_str())
+                        .is_some_and(|sub| sub.ends_with('.'))
+            })
+            || self.regex.as_ref().is_some_and(|r| r.is_match(domain))
+    }
+}
+
+impl OutputSettings {
+    /// returns the seconds the appearance of an entry which left the lists is kept,
+    /// None keeps it forever
+    pub fn seen_retention(&self) -> Option<u64> {
+        self.prune_after_days.map(|days| days * 24 * 60 * 60)
+    }
+
+    /// returns the seconds an entry stays in an accumulating output after it left the
+    /// lists, None if the output is a snapshot of the current run
+    pub fn accumulation_window(&self) -> Option<u64> {
+        self.accumulate_days.map(|days| days * 24 * 60 * 60)
+    }
+
+    /// returns true if the output blocks its domains at the local time
+    ///
+    /// * `time`: a local date and time
+    pub fn is_blocking(&self, time: NaiveDateTime) -> bool {
+        self.block_windows.is_empty() || self.block_windows.iter().any(|w| w.contains(time))
+    }
+
+    /// returns the address the blocked domains of a Hostsfile output resolve to
+    pub fn address(&self) -> IpAddr {
+        self.warn_page
+            .as_ref()
+            .map_or(DEFAULT_ADDRESS, |w| w.address)
+    }
+
+    /// surrounds a written output with the content of the header and footer files
+    ///
+    /// * `path`: the file system path of the written output
+    pub fn apply_includes(&self, path: &Path) -> anyhow::Result<()> {
+        if self.header_file.is_none() && self.footer_file.is_none() {
+            return Ok(());
+        }
+        let (header, footer) = self.includes()?;
+        let tmp_path = path.with_extension("includes");
+        let mut writer = BufWriter::new(
+            File::create(&tmp_path).with_context(|| format!("could not create {:?}", tmp_path))?,
+        );
+        writer.write_all(&header)?;
+        io::copy(
+            &mut File::open(path).with_context(|| format!("could not open {:?}", path))?,
+            &mut writer,
+        )?;
+        writer.write_all(&footer)?;
+        writer.flush()?;
+        fs::rename(&tmp_path, path).with_context(|| format!("could not write {:?}", path))?;
+        Ok(())
+    }
+
+    /// returns the content of the header and footer files as written to the output
+    pub fn includes(&self) -> anyhow::Result<(Vec<u8>, Vec<u8>)> {
+        let read = |file: &Option<String>| -> anyhow::Result<Vec<u8>> {
+            let mut content = match file {
+                Some(file) => fs::read(file)
+                    .with_context(|| format!("output {}: could not read {}", self.tag, file))?,
+                None => vec![],
+            };
+            // the included lines must not be joined with the generated ones
+            if content.last().is_some_and(|b| *b != b'\n') {
+                content.push(b'\n');
+            }
+            Ok(content)
+        };
+        Ok((read(&self.header_file)?, read(&self.footer_file)?))
+    }
+
+    /// returns true if a header or footer file was changed after the output was
+    /// written, so the output has to be written again
+    ///
+    /// * `path`: the file system path of the output
+    pub fn includes_modified_after(&self, path: &Path) -> bool {
+        let written = match fs::metadata(path).and_then(|m| m.modified()) {
+            Ok(written) => written,
+            Err(_) => return true,
+        };
+        [&self.header_file, &self.footer_file]
+            .into_iter()
+            .flatten()
+            .any(|file| {
+                fs::metadata(file)
+                    .and_then(|m| m.modified())
+                    .map_or(true, |modified| modified > written)
+            })
+    }
+
+    /// writes a deployed Hostsfile output into the configured system hosts file
+    ///
+    /// * `path`: the file system path of the output
+    pub fn apply_hosts_merge(&self, path: &Path) -> anyhow::Result<()> {
+        let hosts_file = match &self.hosts_file {
+            Some(hosts_file) => hosts_file,
+            None => return Ok(()),
+        };
+        let entries = read_output(path, self.encryption.as_ref())?;
+        if merge_hosts(Path::new(hosts_file), &self.tag, &entries)? {
+            info!("Merged {} into {}", self.tag, hosts_file);
+        }
+        Ok(())
+    }
+
+    /// compiles the configured exclusions
+    pub fn exclusions(&self) -> anyhow::Result<Exclusions> {
+        let mut exclusions = Exclusions::default();
+        for domain in self.exclude.iter() {
+            match domain.strip_prefix("*.") {
+                Some(base) if !base.is_empty() && !base.contains('*') => {
+                    exclusions.wildcards.push(base.to_string())
+                }
+                _ if domain.contains('*') => {
+                    return Err(anyhow::anyhow!(
+                        "output {}: invalid exclude {}, only a leading *. is supported",
+                        self.tag,
+                        domain
+                    ));
+                }
+                _ => {
+                    exclusions.domains.insert(domain.to_string());
+                }
+            }
+        }
+        if !self.exclude_regex.is_empty() {
+            let regex = RegexSet::new(&self.exclude_regex)
+                .with_context(|| format!("output {}: invalid exclude regex", self.tag))?;
+            exclusions.regex = Some(regex);
+        }
+        Ok(exclusions)
+    }
+
+    /// sets the configured mode and ownership on the output file
+    ///
+    /// * `path`: the file system path of the output file
+    #[cfg(unix)]
+    pub fn apply_permissions(&self, path: &Path) -> anyhow::Result<()> {
+        use std::{
+            fs,
+            os::unix::fs::{chown, PermissionsExt},
+        };
+
+        if let Some(mode) = &self.mode {
+            let mode = u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+                .with_context(|| format!("output {}: invalid mode {}", self.tag, mode))?;
+            fs::set_permissions(path, fs::Permissions::from_mode(mode))
+                .with_context(|| format!("output {}: could not set mode", self.tag))?;
+        }
+        if self.owner.is_some() || self.group.is_some() {
+            let uid = self.owner.as_deref().map(resolve_uid).transpose()?;
+            let gid = self.group.as_deref().map(resolve_gid).transpose()?;
+            chown(path, uid, gid)
+                .with_context(|| format!("output {}: could not change ownership", self.tag))?;
+        }
+        Ok(())
+    }
+
+    /// file modes and ownership can't be set on Windows where the output inherits the
+    /// permissions of the output directory
+    ///
+    /// * `_path`: the file system path of the output file
+    #[cfg(not(unix))]
+    pub fn apply_permissions(&self, _path: &Path) -> anyhow::Result<()> {
+        if self.mode.is_some() || self.owner.is_some() || self.group.is_some() {
+            warn!(
+                "output {}: mode, owner and group are not supported on this platform",
+                self.tag
+            );
+        }
+        Ok(())
+    }
+}
+
+/// returns the uid for a user name or numeric uid
+///
+/// * `owner`: user name or uid
+#[cfg(unix)]
+fn resolve_uid(owner: &str) -> anyhow::Result<u32> {
+    use nix::unistd::User;
+
+    if let Ok(uid) = owner.parse() {
+        return Ok(uid);
+    }
+    let user = User::from_name(owner)?.ok_or_else(|| anyhow::anyhow!("unknown user {}", owner))?;
+    Ok(user.uid.as_raw())
+}
+
+/// returns the gid for a group name or numeric gid
+///
+/// * `group`: group name or gid
+#[cfg(unix)]
+fn resolve_gid(group: &str) -> anyhow::Result<u32> {
+    use nix::unistd::Group;
+
+    if let Ok(gid) = group.parse() {
+        return Ok(gid);
+    }
+    let group =
+        Group::from_name(group)?.ok_or_else(|| anyhow::anyhow!("unknown group {}", group))?;
+    Ok(group.gid.as_raw())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::tests::helper::cache_file_creator::TEST_CACHE;
+
+    use super::*;
+
+    #[test]
+    fn test_apply_includes() {
+        let dir = PathBuf::from(TEST_CACHE).join("test_apply_includes");
+        fs::create_dir_all(&dir).unwrap();
+        let header_file = dir.join("header");
+        fs::write(&header_file, "127.0.0.1 localhost").unwrap();
+        let output = dir.join(".hosts.tmp");
+        fs::write(&output, "0.0.0.0 one.domain\n").unwrap();
+        let settings = OutputSettings {
+            tag: "hosts".to_string(),
+            header_file: Some(header_file.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+
+        settings.apply_includes(&output).unwrap();
+        assert_eq!(
+            fs::read_to_string(&output).unwrap(),
+            "127.0.0.1 localhost\n0.0.0.0 one.domain\n"
+        );
+        assert!(!settings.includes_modified_after(&output));
+        assert!(settings.includes_modified_after(&dir.join("missing")));
+
+        let settings = OutputSettings {
+            footer_file: Some(dir.join("missing").to_string_lossy().to_string()),
+            ..settings
+        };
+        assert!(settings.apply_includes(&output).is_err());
+    }
+
+    #[test]
+    fn test_exclusions() {
+        let settings = OutputSettings {
+            tag: "proxy".to_string(),
+            exclude: vec!["*.sharepoint.com".to_string(), "exact.domain".to_string()],
+            exclude_regex: vec![r"^ads\.".to_string()],
+            ..Default::default()
+        };
+        let exclusions = settings.exclusions().unwrap();
+
+        assert!(exclusions.is_excluded("corp.sharepoint.com"));
+        assert!(exclusions.is_excluded("exact.domain"));
+        assert!(exclusions.is_excluded("ads.domain"));
+        assert!(exclusions.is_excluded("sharepoint.com"));
+        assert!(!exclusions.is_excluded("evilsharepoint.com"));
+        assert!(!exclusions.is_excluded("sub.exact.domain"));
+        assert!(!exclusions.is_excluded("malicious.domain"));
+
+        for exclude in ["*sharepoint.com", "corp.*.com", "*.", "*.*.com"] {
+            let settings = OutputSettings {
+                exclude: vec![exclude.to_string()],
+                ..settings.clone()
+            };
+            assert!(settings.exclusions().is_err(), "{}", exclude);
+        }
+    }
+}
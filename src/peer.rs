@@ -0,0 +1,647 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::Write,
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::Semaphore,
+    task::JoinHandle,
+};
+
+use crate::{config::Config, output_settings::OutputSettings, platform, report::REPORT_FILE_NAME};
+
+/// path of the list of outputs served by a primary
+pub const MANIFEST_PATH: &str = "/peer/manifest";
+/// path of a single output served by a primary
+pub const FILE_PATH: &str = "/peer/file";
+/// seconds between two pulls of a secondary if no interval was configured
+pub const DEFAULT_PULL_INTERVAL: u64 = 60;
+/// maximum length of a request's head
+const MAX_REQUEST_LEN: usize = 8192;
+/// time a secondary has to send the head of its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// maximum number of connections served at once, further ones wait to be accepted
+const MAX_CONNECTIONS: usize = 64;
+/// maximum length of a manifest pulled by a secondary
+const MAX_MANIFEST_LEN: usize = 64 * 1024 * 1024;
+
+/// PeerSettings configure the synchronization of the outputs between a primary
+/// instance harvesting the lists and secondary instances taking over its outputs
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PeerSettings {
+    /// address the primary serves its outputs on in watch mode (e.g. "0.0.0.0:8642")
+    pub listen: Option<String>,
+    /// URL of the primary a secondary pulls the outputs from instead of harvesting
+    pub primary: Option<String>,
+    /// file containing the token a secondary authenticates with
+    pub token_file: Option<String>,
+    /// seconds between two pulls of a secondary in watch mode
+    pub interval: Option<u64>,
+}
+
+impl PeerSettings {
+    /// returns the shared token, None if the peers don't authenticate
+    fn token(&self) -> anyhow::Result<Option<String>> {
+        match &self.token_file {
+            Some(token_file) => {
+                let token = fs::read_to_string(token_file)
+                    .with_context(|| format!("could not read {}", token_file))?;
+                Ok(Some(token.trim().to_string()))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// OutputFile describes an output of the primary
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct OutputFile {
+    /// the name of the output directory, see output_roots
+    pub root: String,
+    /// the path of the file relative to the output directory
+    pub path: String,
+    /// the hex encoded SHA-256 digest of the content
+    pub sha256: String,
+    /// the length of the content in bytes
+    pub size: u64,
+}
+
+/// Manifest lists the outputs of the primary and the report of its last run
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Manifest {
+    pub files: Vec<OutputFile>,
+    pub report: Option<serde_json::Value>,
+}
+
+/// returns the output directories of a configuration by their name, which is
+/// `outputs` for the main outputs and `projects/<name>` for those of a project
+///
+/// * `config`: the configuration
+fn output_roots(config: &Config) -> Vec<(String, PathBuf)> {
+    let mut roots = vec![("outputs".to_string(), PathBuf::from(&config.output_dir))];
+    roots.extend(
+        config
+            .projects
+            .iter()
+            .map(|p| (format!("projects/{}", p.name), PathBuf::from(&p.output_dir))),
+    );
+    roots
+}
+
+/// returns the settings of the output a file of an output directory belongs to, which
+/// is the output itself or a file written next to it like `<tag>.rpz`
+///
+/// * `config`: the configuration
+/// * `root`: the name of the output directory, see output_roots
+/// * `path`: the path of the file relative to the output directory
+fn output_settings<'a>(config: &'a Config, root: &str, path: &str) -> Option<&'a OutputSettings> {
+    let outputs = match root.strip_prefix("projects/") {
+        Some(name) => &config.projects.iter().find(|p| p.name == name)?.outputs,
+        None => &config.outputs,
+    };
+    outputs.iter().find(|o| {
+        path.strip_prefix(o.tag.as_str())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+    })
+}
+
+/// compares the token of a request with the expected one in constant time, so the
+/// time taken doesn't tell how many leading bytes matched
+///
+/// * `token`: the token sent by the secondary
+/// * `expected`: the shared token
+fn token_matches(token: &str, expected: &str) -> bool {
+    token.len() == expected.len()
+        && token
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// returns true if the path stays within the directory it's relative to
+///
+/// * `path`: the relative path
+fn is_contained(path: &str) -> bool {
+    !path.is_empty()
+        && Path::new(path)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+}
+
+/// returns the paths of the files within a directory relative to it, hidden files
+/// like temporary outputs are left out
+///
+/// * `dir`: the directory
+fn relative_files(dir: &Path) -> Vec<String> {
+    let mut files = vec![];
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(relative_dir) = dirs.pop() {
+        for entry in fs::read_dir(dir.join(&relative_dir))
+            .into_iter()
+            .flatten()
+            .flatten()
+        {
+            let name = entry.file_name();
+            if name.to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let relative = relative_dir.join(&name);
+            match entry.file_type() {
+                Ok(t) if t.is_dir() => dirs.push(relative),
+                Ok(t) if t.is_file() => files.push(relative.to_string_lossy().to_string()),
+                _ => {}
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// returns the hex encoded SHA-256 digest of a file
+///
+/// * `path`: the file system path of the file
+pub fn file_digest(path: &Path) -> anyhow::Result<String> {
+    let mut file = fs::File::open(path).with_context(|| format!("could not open {:?}", path))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+/// length, modification time and digest of an output by its path
+type Digests = HashMap<PathBuf, (u64, SystemTime, String)>;
+
+/// Primary serves its outputs to secondary instances
+#[derive(Debug, Clone)]
+struct Primary {
+    roots: Arc<Vec<(String, PathBuf)>>,
+    report_path: PathBuf,
+    token: Option<String>,
+    /// digests of the outputs by their path, length and modification time
+    digests: Arc<Mutex<Digests>>,
+}
+
+impl Primary {
+    /// returns the length and the digest of an output, the digest is only computed
+    /// again if the output was modified
+    ///
+    /// * `path`: the file system path of the output
+    fn digest(&self, path: &Path) -> anyhow::Result<(u64, String)> {
+        let meta = fs::metadata(path)?;
+        let key = (meta.len(), meta.modified()?);
+        if let Some((len, modified, digest)) = self.digests.lock().unwrap().get(path)
+            && (*len, *modified) == key
+        {
+            return Ok((key.0, digest.clone()));
+        }
+        let digest = file_digest(path)?;
+        self.digests
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), (key.0, key.1, digest.clone()));
+        Ok((key.0, digest))
+    }
+
+    /// returns the current outputs and the report of the last run
+    fn manifest(&self) -> anyhow::Result<Manifest> {
+        let mut files = vec![];
+        for (root, dir) in self.roots.iter() {
+            for path in relative_files(dir) {
+                // an output replaced in the meantime is listed by the next manifest
+                if let Ok((size, sha256)) = self.digest(&dir.join(&path)) {
+                    files.push(OutputFile {
+                        root: root.clone(),
+                        path,
+                        sha256,
+                        size,
+                    });
+                }
+            }
+        }
+        let report = fs::read_to_string(&self.report_path)
+            .ok()
+            .and_then(|r| serde_json::from_str(&r).ok());
+        Ok(Manifest { files, report })
+    }
+
+    /// answers a single HTTP request of a secondary
+    ///
+    /// * `stream`: the connection to the secondary
+    async fn respond(&self, mut stream: TcpStream) -> anyhow::Result<()> {
+        let mut request = vec![];
+        let mut buf = [0; 1024];
+        // a connection sending nothing mustn't be held open forever
+        tokio::time::timeout(REQUEST_TIMEOUT, async {
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST_LEN {
+                let n = stream.read(&mut buf).await?;
+                if n == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..n]);
+            }
+            anyhow::Ok(())
+        })
+        .await
+        .with_context(|| "request timed out")??;
+        let request = String::from_utf8_lossy(&request).to_string();
+        let target = request.split_whitespace().nth(1).unwrap_or_default();
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let authorized = match &self.token {
+            Some(token) => request.lines().any(|l| {
+                l.split_once(':').is_some_and(|(name, value)| {
+                    name.eq_ignore_ascii_case("authorization")
+                        && value
+                            .trim()
+                            .strip_prefix("Bearer ")
+                            .is_some_and(|t| token_matches(t, token))
+                })
+            }),
+            None => true,
+        };
+
+        let file = match path {
+            _ if !authorized => None,
+            FILE_PATH => {
+                let params: HashMap<String, String> = url::form_urlencoded::parse(query.as_bytes())
+                    .into_owned()
+                    .collect();
+                let dir = params
+                    .get("root")
+                    .and_then(|root| self.roots.iter().find(|(name, _)| name == root));
+                match (dir, params.get("path")) {
+                    (Some((_, dir)), Some(path)) if is_contained(path) => Some(dir.join(path)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        let (code, body) = match path {
+            _ if !authorized => ("401 Unauthorized", vec![]),
+            MANIFEST_PATH => {
+                let primary = self.clone();
+                let manifest = tokio::task::spawn_blocking(move || primary.manifest()).await??;
+                ("200 OK", serde_json::to_vec(&manifest)?)
+            }
+            FILE_PATH => match &file {
+                Some(file) if file.is_file() => {
+                    let mut file = tokio::fs::File::open(file).await?;
+                    let head = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        file.metadata().await?.len()
+                    );
+                    stream.write_all(head.as_bytes()).await?;
+                    tokio::io::copy(&mut file, &mut stream).await?;
+                    stream.shutdown().await?;
+                    return Ok(());
+                }
+                _ => ("404 Not Found", vec![]),
+            },
+            _ => ("404 Not Found", vec![]),
+        };
+        let head = format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            code,
+            body.len()
+        );
+        stream.write_all(head.as_bytes()).await?;
+        stream.write_all(&body).await?;
+        stream.shutdown().await?;
+        Ok(())
+    }
+}
+
+/// serves the outputs of the configuration to secondary instances
+///
+/// * `config`: the configuration of the primary
+/// * `settings`: the peer settings containing the listen address
+pub async fn serve(config: &Config, settings: &PeerSettings) -> anyhow::Result<JoinHandle<()>> {
+    let listen = settings
+        .listen
+        .as_ref()
+        .with_context(|| "no address configured to serve the outputs on")?;
+    let listener = TcpListener::bind(listen)
+        .await
+        .with_context(|| format!("could not listen on {}", listen))?;
+    info!(
+        "Serving outputs to secondaries on http://{}{}",
+        listen, MANIFEST_PATH
+    );
+    serve_listener(config, settings, listener)
+}
+
+/// serves the outputs of the configuration to the secondaries connecting to the
+/// listener
+///
+/// * `config`: the configuration of the primary
+/// * `settings`: the peer settings containing the token
+/// * `listener`: the bound address of the primary
+fn serve_listener(
+    config: &Config,
+    settings: &PeerSettings,
+    listener: TcpListener,
+) -> anyhow::Result<JoinHandle<()>> {
+    let primary = Primary {
+        roots: Arc::new(output_roots(config)),
+        report_path: PathBuf::from(&config.cache_dir).join(REPORT_FILE_NAME),
+        token: settings.token()?,
+        digests: Arc::new(Mutex::new(HashMap::new())),
+    };
+    let connections = Arc::new(Semaphore::new(MAX_CONNECTIONS));
+    Ok(tokio::spawn(async move {
+        loop {
+            // the semaphore is never closed
+            let Ok(permit) = connections.clone().acquire_owned().await else {
+                return;
+            };
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let primary = primary.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = primary.respond(stream).await {
+                            debug!("peer: {}", e);
+                        }
+                        drop(permit);
+                    });
+                }
+                Err(e) => warn!("peer: {}", e),
+            }
+        }
+    }))
+}
+
+/// reads the body of a response, the primary has to send each part of it within the
+/// read timeout. Returns the length of the body.
+///
+/// * `response`: the response of the primary
+/// * `read_timeout`: time the next part of the body is waited for
+/// * `limit`: maximum length of the body
+/// * `consume`: called with each part of the body
+async fn read_body(
+    response: &mut reqwest::Response,
+    read_timeout: Duration,
+    limit: u64,
+    mut consume: impl FnMut(&[u8]) -> anyhow::Result<()>,
+) -> anyhow::Result<u64> {
+    let mut len = 0;
+    while let Some(chunk) = tokio::time::timeout(read_timeout, response.chunk())
+        .await
+        .map_err(|_| anyhow::anyhow!("no data received within {}s", read_timeout.as_secs()))??
+    {
+        len += chunk.len() as u64;
+        if len > limit {
+            return Err(anyhow::anyhow!("response exceeds {} bytes", limit));
+        }
+        consume(&chunk)?;
+    }
+    Ok(len)
+}
+
+/// takes over the outputs of the primary which differ from the local ones along with
+/// the report of its last run, returns the number of outputs updated. Outputs the
+/// primary doesn't have anymore are kept.
+///
+/// * `config`: the configuration of the secondary
+/// * `settings`: the peer settings containing the URL of the primary
+pub async fn pull(config: &Config, settings: &PeerSettings) -> anyhow::Result<usize> {
+    let primary = settings
+        .primary
+        .as_ref()
+        .with_context(|| "no primary configured to pull the outputs from")?
+        .trim_end_matches('/');
+    let token = settings.token()?;
+    // a hung primary mustn't stall the secondary, it uses the timeouts of the lists
+    let timeouts = config.timeouts.unwrap_or_default();
+    let read_timeout = timeouts.read();
+    let mut builder = reqwest::Client::builder().connect_timeout(timeouts.connect());
+    if let Some(total) = timeouts.total {
+        builder = builder.timeout(Duration::from_secs(total));
+    }
+    let client = builder.build()?;
+    let get = |path: String| {
+        let request = client.get(format!("{}{}", primary, path));
+        let request = match &token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        };
+        async move {
+            tokio::time::timeout(read_timeout, request.send())
+                .await
+                .map_err(|_| anyhow::anyhow!("no response within {}s", read_timeout.as_secs()))?
+                .map_err(anyhow::Error::from)
+        }
+    };
+
+    let mut response = get(MANIFEST_PATH.to_string())
+        .await
+        .with_context(|| format!("could not reach the primary {}", primary))?
+        .error_for_status()?;
+    let mut body = vec![];
+    read_body(
+        &mut response,
+        read_timeout,
+        MAX_MANIFEST_LEN as u64,
+        |chunk| {
+            body.extend_from_slice(chunk);
+            Ok(())
+        },
+    )
+    .await
+    .with_context(|| format!("could not read the manifest of {}", primary))?;
+    let manifest: Manifest = serde_json::from_slice(&body)
+        .with_context(|| format!("{} returned no manifest", primary))?;
+
+    let roots = output_roots(config);
+    let mut updated = 0;
+    for file in &manifest.files {
+        let dir = match roots.iter().find(|(name, _)| *name == file.root) {
+            Some((_, dir)) => dir,
+            None => {
+                debug!("{}: no such output directory", file.root);
+                continue;
+            }
+        };
+        if !is_contained(&file.path) {
+            warn!("{}: invalid path {}", primary, file.path);
+            continue;
+        }
+        let path = dir.join(&file.path);
+        if file_digest(&path).is_ok_and(|d| d == file.sha256) {
+            continue;
+        }
+        let query = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("root", &file.root)
+            .append_pair("path", &file.path)
+            .finish();
+        let mut response = get(format!("{}?{}", FILE_PATH, query))
+            .await?
+            .error_for_status()
+            .with_context(|| format!("could not pull {}", file.path))?;
+
+        // the output is replaced at once after its content was verified
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let tmp_path = path.with_file_name(format!(".{}.tmp", file_name));
+        let mut tmp_file = fs::File::create(&tmp_path)
+            .with_context(|| format!("could not create {:?}", tmp_path))?;
+        let mut hasher = Sha256::new();
+        // the output mustn't grow beyond the size listed by the manifest
+        let read = read_body(&mut response, read_timeout, file.size, |chunk| {
+            hasher.update(chunk);
+            Ok(tmp_file.write_all(chunk)?)
+        })
+        .await
+        .and_then(|_| Ok(tmp_file.flush()?));
+        if let Err(e) = read {
+            fs::remove_file(&tmp_path).ok();
+            return Err(e.context(format!("could not pull {}", file.path)));
+        }
+        let digest: String = hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        if digest != file.sha256 {
+            fs::remove_file(&tmp_path).ok();
+            return Err(anyhow::anyhow!("{} changed while being pulled", file.path));
+        }
+        if let Some(settings) = output_settings(config, &file.root, &file.path) {
+            settings.apply_permissions(&tmp_path)?;
+        }
+        platform::replace_file(&tmp_path, &path)
+            .with_context(|| format!("could not write {:?}", path))?;
+        debug!("pulled {}", file.path);
+        updated += 1;
+    }
+    info!(
+        "{} of {} outputs pulled from {}",
+        updated,
+        manifest.files.len(),
+        primary
+    );
+
+    if let Some(report) = &manifest.report {
+        let report_path = PathBuf::from(&config.cache_dir).join(REPORT_FILE_NAME);
+        fs::create_dir_all(&config.cache_dir)?;
+        fs::write(&report_path, serde_json::to_string_pretty(report)?)
+            .with_context(|| format!("could not write {:?}", report_path))?;
+    }
+    Ok(updated)
+}
+
+/// waits until the next pull of a secondary is due, never returns on a primary
+///
+/// * `settings`: the peer settings
+pub async fn pull_due(settings: Option<&PeerSettings>) {
+    match settings {
+        Some(settings) if settings.primary.is_some() => {
+            let interval = settings.interval.unwrap_or(DEFAULT_PULL_INTERVAL);
+            tokio::time::sleep(Duration::from_secs(interval)).await
+        }
+        _ => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::helper::cache_file_creator::CacheFileCreator;
+
+    use super::*;
+
+    #[test]
+    fn test_is_contained() {
+        assert!(is_contained("malware"));
+        assert!(is_contained("zones/malware.rpz"));
+        assert!(!is_contained("../etc/passwd"));
+        assert!(!is_contained("/etc/passwd"));
+        assert!(!is_contained(""));
+    }
+
+    #[test]
+    fn test_token_matches() {
+        assert!(token_matches("secret", "secret"));
+        assert!(!token_matches("secreT", "secret"));
+        assert!(!token_matches("secret2", "secret"));
+        assert!(!token_matches("", "secret"));
+    }
+
+    #[test]
+    fn test_output_settings() {
+        let mut config =
+            CacheFileCreator::new("test_peer_output_settings", "in", "out").new_test_config();
+        config.outputs = vec![OutputSettings {
+            tag: "malware".to_string(),
+            ..Default::default()
+        }];
+        assert!(output_settings(&config, "outputs", "malware").is_some());
+        assert!(output_settings(&config, "outputs", "malware.rpz").is_some());
+        assert!(output_settings(&config, "outputs", "malwarebytes").is_none());
+        assert!(output_settings(&config, "projects/other", "malware").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_pull() {
+        let primary_cache = CacheFileCreator::new("test_pull_primary", "in", "out");
+        let primary_config = primary_cache.new_test_config();
+        let output_dir = PathBuf::from(&primary_config.output_dir);
+        fs::create_dir_all(output_dir.join("zones")).unwrap();
+        fs::write(output_dir.join("malware"), "0.0.0.0 evil.com\n").unwrap();
+        fs::write(output_dir.join("zones/malware.rpz"), "evil.com CNAME .\n").unwrap();
+        fs::write(output_dir.join(".malware.tmp"), "partial").unwrap();
+        fs::write(
+            PathBuf::from(&primary_config.cache_dir).join(REPORT_FILE_NAME),
+            r#"{"finished": 1}"#,
+        )
+        .unwrap();
+
+        let token_path = PathBuf::from(&primary_config.cache_dir).join("token");
+        fs::write(&token_path, "secret\n").unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let settings = PeerSettings {
+            listen: None,
+            primary: Some(format!("http://{}/", address)),
+            token_file: Some(token_path.to_string_lossy().to_string()),
+            interval: None,
+        };
+        serve_listener(&primary_config, &settings, listener).unwrap();
+
+        let secondary_cache = CacheFileCreator::new("test_pull_secondary", "in", "out");
+        let secondary_config = secondary_cache.new_test_config();
+        let secondary_dir = PathBuf::from(&secondary_config.output_dir);
+        fs::remove_dir_all(&secondary_dir).ok();
+        assert_eq!(pull(&secondary_config, &settings).await.unwrap(), 2);
+        assert_eq!(
+            fs::read_to_string(secondary_dir.join("zones/malware.rpz")).unwrap(),
+            "evil.com CNAME .\n"
+        );
+        assert!(!secondary_dir.join(".malware.tmp").exists());
+        let report =
+            fs::read_to_string(PathBuf::from(&secondary_config.cache_dir).join(REPORT_FILE_NAME))
+                .unwrap();
+        assert!(report.contains("finished"));
+
+        // unchanged outputs aren't pulled again
+        assert_eq!(pull(&secondary_config, &settings).await.unwrap(), 0);
+
+        let unauthorized = PeerSettings {
+            token_file: None,
+            ..settings
+        };
+        assert!(pull(&secondary_config, &unauthorized).await.is_err());
+    }
+}
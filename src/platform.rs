@@ -0,0 +1,255 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use tokio::process::Command;
+
+/// number of attempts to replace a file which is locked by another process
+const REPLACE_ATTEMPTS: u32 = 20;
+/// time to wait before replacing a locked file again
+const REPLACE_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// returns a command executing the command line by the platform's shell, which is
+/// `sh -c` on unix and `cmd /C` on Windows
+///
+/// * `command_line`: the command line to be executed
+pub fn shell(command_line: &str) -> Command {
+    #[cfg(windows)]
+    let (shell, flag) = ("cmd", "/C");
+    #[cfg(not(windows))]
+    let (shell, flag) = ("sh", "-c");
+    let mut command = Command::new(shell);
+    command.arg(flag).arg(command_line);
+    command
+}
+
+/// atomically replaces the destination file by the source file. On Windows a file
+/// opened by another process without delete sharing (e.g. the hosts file read by the
+/// DNS client) can't be replaced for a moment, so the rename is retried.
+///
+/// * `from`: the source file
+/// * `to`: the destination file
+pub fn replace_file(from: &Path, to: &Path) -> io::Result<()> {
+    let mut attempt = 1;
+    loop {
+        match fs::rename(from, to) {
+            Err(e) if is_locked(&e) && attempt < REPLACE_ATTEMPTS => {
+                debug!("{:?} is locked, retrying: {}", to, e);
+                attempt += 1;
+                std::thread::sleep(REPLACE_RETRY_DELAY);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// writes a file owned by the system like /etc/hosts, which is replaced atomically
+/// keeping the permissions of the previous file. A file mounted into a container
+/// can't be replaced and is overwritten instead.
+///
+/// * `path`: the file to be written
+/// * `contents`: the new content of the file
+pub fn write_system_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut tmp_path = PathBuf::from(path);
+    tmp_path.set_file_name(format!(
+        ".{}.harvester",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    fs::write(&tmp_path, contents)?;
+    if let Ok(meta) = fs::metadata(path) {
+        fs::set_permissions(&tmp_path, meta.permissions())?;
+    }
+    if let Err(e) = replace_file(&tmp_path, path) {
+        debug!("could not replace {:?}, overwriting it: {}", path, e);
+        fs::remove_file(&tmp_path).ok();
+        fs::write(path, contents)?;
+    }
+    Ok(())
+}
+
+/// returns true if the process runs with the privileges of root
+#[cfg(unix)]
+pub fn is_privileged() -> bool {
+    nix::unistd::geteuid().is_root()
+}
+
+/// privileged deployments are only supported on unix
+#[cfg(not(unix))]
+pub fn is_privileged() -> bool {
+    false
+}
+
+/// returns an error unless the file can only be modified by root, so a privileged
+/// process may trust its content. The entries of a directory are checked as well,
+/// following symbolic links like the ones of a mounted ConfigMap.
+///
+/// * `path`: the file or directory to be checked
+#[cfg(unix)]
+pub fn check_trusted(path: &Path) -> io::Result<()> {
+    check_trusted_tree(path, &mut std::collections::HashSet::new())
+}
+
+/// checks a file or directory and the entries of a directory once each
+///
+/// * `path`: the file or directory to be checked
+/// * `visited`: the canonical paths of the directories checked so far, so a symbolic
+///   link to a parent directory doesn't loop
+#[cfg(unix)]
+fn check_trusted_tree(
+    path: &Path,
+    visited: &mut std::collections::HashSet<PathBuf>,
+) -> io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let meta = fs::metadata(path)?;
+    if meta.uid() != 0 || meta.mode() & 0o022 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{:?} may be modified by other users than root", path),
+        ));
+    }
+    if meta.is_dir() && visited.insert(fs::canonicalize(path)?) {
+        for entry in fs::read_dir(path)? {
+            check_trusted_tree(&entry?.path(), visited)?;
+        }
+    }
+    Ok(())
+}
+
+/// file ownership can't be checked on Windows
+///
+/// * `_path`: the file or directory to be checked
+#[cfg(not(unix))]
+pub fn check_trusted(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// reads a regular file without following a symlink in its place, so a privileged
+/// process can't be tricked into reading another file
+///
+/// * `path`: the file to be read
+pub fn read_regular_file(path: &Path) -> io::Result<Vec<u8>> {
+    use std::io::Read;
+
+    let mut options = fs::OpenOptions::new();
+    options.read(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.custom_flags(nix::libc::O_NOFOLLOW);
+    }
+    let mut file = options.open(path)?;
+    if !file.metadata()?.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{:?} isn't a regular file", path),
+        ));
+    }
+    let mut contents = vec![];
+    file.read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+/// returns true if the error is caused by another process holding the file open
+///
+/// * `e`: the error returned by a file operation
+#[cfg(windows)]
+fn is_locked(e: &io::Error) -> bool {
+    /// ERROR_ACCESS_DENIED and ERROR_SHARING_VIOLATION
+    const LOCK_ERRORS: [i32; 2] = [5, 32];
+    e.raw_os_error()
+        .map_or(false, |code| LOCK_ERRORS.contains(&code))
+}
+
+/// returns true if the error is caused by another process holding the file open, which
+/// never prevents a rename on unix
+///
+/// * `_e`: the error returned by a file operation
+#[cfg(not(windows))]
+fn is_locked(_e: &io::Error) -> bool {
+    false
+}
+
+/// returns true if the file is a stream like a FIFO or a character device which can't
+/// be read twice
+///
+/// * `meta`: the metadata of the file
+#[cfg(unix)]
+pub fn is_stream(meta: &fs::Metadata) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    meta.file_type().is_fifo() || meta.file_type().is_char_device()
+}
+
+/// returns true if the file is a stream, named pipes aren't supported on Windows
+///
+/// * `_meta`: the metadata of the file
+#[cfg(not(unix))]
+pub fn is_stream(_meta: &fs::Metadata) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::helper::cache_file_creator::TEST_CACHE;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_shell() {
+        let output = shell("echo harvester").output().await.unwrap();
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "harvester");
+    }
+
+    #[test]
+    fn test_replace_file() {
+        let mut dir = std::path::PathBuf::from(TEST_CACHE);
+        dir.push("test_replace_file");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("new"), "new").unwrap();
+        fs::write(dir.join("old"), "old").unwrap();
+
+        replace_file(&dir.join("new"), &dir.join("old")).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("old")).unwrap(), "new");
+        assert!(!dir.join("new").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_trusted() {
+        use std::os::unix::fs::{chown, symlink, PermissionsExt};
+
+        if !is_privileged() {
+            return;
+        }
+        let mut dir = std::path::PathBuf::from(TEST_CACHE);
+        dir.push("test_check_trusted");
+        fs::remove_dir_all(&dir).ok();
+        // the layout of a mounted ConfigMap
+        let config = dir.join("config");
+        let data = config.join("..2022_12_01");
+        fs::create_dir_all(&data).unwrap();
+        fs::write(data.join("base.json"), "{}").unwrap();
+        fs::write(dir.join("lists.json"), "{}").unwrap();
+        symlink("..2022_12_01", config.join("..data")).unwrap();
+        symlink("..data/base.json", config.join("base.json")).unwrap();
+        symlink("../lists.json", config.join("lists.json")).unwrap();
+        for path in [&config, &data] {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        for path in [data.join("base.json"), dir.join("lists.json")] {
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        }
+        assert!(check_trusted(&config).is_ok());
+
+        // a fragment linked to a file of another user isn't trusted
+        chown(dir.join("lists.json"), Some(65534), None).unwrap();
+        assert!(check_trusted(&config).is_err());
+        chown(dir.join("lists.json"), Some(0), None).unwrap();
+        // neither is a file in a sub directory writable by others
+        fs::set_permissions(data.join("base.json"), fs::Permissions::from_mode(0o666)).unwrap();
+        assert!(check_trusted(&config).is_err());
+    }
+}
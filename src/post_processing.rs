@@ -0,0 +1,960 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    path::PathBuf,
+    process::Stdio,
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::lock::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+use crate::{
+    config::DEFAULT_BATCH_SIZE,
+    dga::{dga_score, DEFAULT_MIN_DGA_SCORE},
+    input::Input,
+    io::{
+        external_sort::{entry_size, ExternalSort},
+        run_files::must_spill,
+        spool::DEFAULT_SPOOL_THRESHOLD,
+    },
+    ip2asn::{entry_address, AsnDatabase, AsnFilter},
+    memory::{MemoryBudget, Reservation},
+    output::lint::is_valid_hostname,
+    platform,
+    rdap::{RdapClient, DEFAULT_RDAP_URL},
+    report::unix_timestamp,
+};
+
+/// lookups per minute of the DomainAge stage if no rate was configured
+pub const DEFAULT_REQUESTS_PER_MINUTE: u32 = 60;
+/// lookups per list and run of the DomainAge stage if no limit was configured
+pub const DEFAULT_MAX_LOOKUPS: usize = 1000;
+
+/// StageConfig describes a post-processing stage applied to every category list
+/// before it is written in the output format
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum StageConfig {
+    /// drops entries which were already seen
+    Dedup,
+    /// sorts all entries
+    Sort,
+    /// drops entries which aren't valid host names
+    Validate,
+    /// pipes all entries through a command, one entry per line
+    Command {
+        /// the command line which is passed to `sh -c`
+        command: String,
+    },
+    /// looks up the registration date of every entry's domain by RDAP and drops or
+    /// selects newly registered domains
+    DomainAge {
+        /// domains registered less than this number of days ago are newly registered
+        max_age_days: u64,
+        #[serde(default)]
+        action: NewDomainAction,
+        /// file the lookups are cached in between runs
+        cache_file: String,
+        /// RDAP service the domain is appended to
+        rdap_url: Option<String>,
+        /// maximum number of lookups per minute
+        requests_per_minute: Option<u32>,
+        /// maximum number of lookups per list and run, the age of the remaining
+        /// domains is treated as unknown
+        max_lookups: Option<usize>,
+    },
+    /// filters IP entries by the autonomous system announcing them, other entries
+    /// pass unchanged
+    Asn {
+        /// iptoasn.com database file in tab separated format
+        database: String,
+        /// if set only addresses of these autonomous systems or countries are kept
+        #[serde(default)]
+        include_asns: Vec<u32>,
+        #[serde(default)]
+        include_countries: Vec<String>,
+        /// addresses of these autonomous systems or countries are dropped
+        #[serde(default)]
+        exclude_asns: Vec<u32>,
+        #[serde(default)]
+        exclude_countries: Vec<String>,
+    },
+    /// scores domains by a heuristic for names made up by domain generation
+    /// algorithms, the likely generated ones are dropped from or selected for the
+    /// outputs of the given tags
+    Dga {
+        /// domains scoring at least this number from 0 to 100 are likely generated
+        min_score: Option<u32>,
+        /// tags of the outputs the likely generated domains are dropped from
+        #[serde(default)]
+        drop_tags: Vec<String>,
+        /// tags of the outputs containing nothing but the likely generated domains
+        #[serde(default)]
+        only_tags: Vec<String>,
+    },
+}
+
+/// NewDomainAction tells what happens to newly registered domains
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum NewDomainAction {
+    /// drops newly registered domains
+    #[default]
+    Drop,
+    /// passes only newly registered domains on, e.g. for a list of its own
+    Only,
+}
+
+impl StageConfig {
+    /// creates the stage described by the configuration
+    ///
+    /// * `tag`: the tag of the category list passing the stage
+    /// * `index`: the position of the stage in the pipeline
+    /// * `spill`: where and when the entries held back are moved to disk
+    pub fn build(&self, tag: &str, index: usize, spill: &SpillSettings) -> Box<dyn Stage> {
+        let name = format!("{}.{}", tag, index);
+        match self {
+            StageConfig::Dedup => Box::new(DedupStage::new(spill, &name)),
+            StageConfig::Sort => Box::new(SortStage::new(spill, &name)),
+            StageConfig::Validate => Box::<ValidateStage>::default(),
+            StageConfig::Command { command } => Box::new(CommandStage::new(command)),
+            StageConfig::DomainAge {
+                max_age_days,
+                action,
+                cache_file,
+                rdap_url,
+                requests_per_minute,
+                max_lookups,
+            } => {
+                let per_minute = requests_per_minute.unwrap_or(DEFAULT_REQUESTS_PER_MINUTE);
+                let client = RdapClient::new(
+                    rdap_url.as_deref().unwrap_or(DEFAULT_RDAP_URL),
+                    PathBuf::from(cache_file),
+                    Duration::from_secs(60) / per_minute.max(1),
+                    max_lookups.unwrap_or(DEFAULT_MAX_LOOKUPS),
+                );
+                Box::new(DomainAgeStage::new(client, *max_age_days, *action))
+            }
+            StageConfig::Asn {
+                database,
+                include_asns,
+                include_countries,
+                exclude_asns,
+                exclude_countries,
+            } => Box::new(AsnStage::new(
+                database,
+                AsnFilter {
+                    include_asns: include_asns.clone(),
+                    include_countries: include_countries.clone(),
+                    exclude_asns: exclude_asns.clone(),
+                    exclude_countries: exclude_countries.clone(),
+                },
+            )),
+            StageConfig::Dga {
+                min_score,
+                drop_tags,
+                only_tags,
+            } => {
+                let action = match (
+                    drop_tags.iter().any(|t| t == tag),
+                    only_tags.iter().any(|t| t == tag),
+                ) {
+                    (_, true) => Some(NewDomainAction::Only),
+                    (true, false) => Some(NewDomainAction::Drop),
+                    (false, false) => None,
+                };
+                Box::new(DgaStage::new(
+                    min_score.unwrap_or(DEFAULT_MIN_DGA_SCORE),
+                    action,
+                ))
+            }
+        }
+    }
+}
+
+/// Stage is the trait all post-processing stages must implement. Entries are streamed
+/// through the stage in batches.
+#[async_trait]
+pub trait Stage: Send {
+    /// processes a single entry and returns the entries to be passed on
+    async fn process(&mut self, entry: String) -> anyhow::Result<Vec<String>>;
+
+    /// processes a batch of entries and returns the entries to be passed on, stages
+    /// handling several entries at once more efficiently override it
+    ///
+    /// * `entries`: the entries in the order they were read
+    async fn process_batch(&mut self, entries: Vec<String>) -> anyhow::Result<Vec<String>> {
+        let mut next = Vec::with_capacity(entries.len());
+        for entry in entries {
+            next.extend(self.process(entry).await?);
+        }
+        Ok(next)
+    }
+
+    /// called after the last entry, returns the entries held back by the stage
+    async fn finish(&mut self) -> anyhow::Result<Vec<String>> {
+        Ok(vec![])
+    }
+
+    /// called after finish until it returns no entries, returns the next entries held
+    /// back by a stage which moved them to disk
+    ///
+    /// * `batch_size`: maximum number of entries returned
+    async fn drain(&mut self, _batch_size: usize) -> anyhow::Result<Vec<String>> {
+        Ok(vec![])
+    }
+}
+
+/// SpillSettings determine where and when the stages holding entries back move them to
+/// disk
+#[derive(Debug, Clone)]
+pub struct SpillSettings {
+    /// directory the run files are written to
+    pub dir: PathBuf,
+    /// maximum number of bytes a stage holds in memory
+    pub threshold: usize,
+    /// the memory budget the entries held in memory are reserved from, unlimited if None
+    pub budget: Option<Arc<MemoryBudget>>,
+}
+
+impl Default for SpillSettings {
+    fn default() -> Self {
+        Self {
+            dir: std::env::temp_dir(),
+            threshold: DEFAULT_SPOOL_THRESHOLD,
+            budget: None,
+        }
+    }
+}
+
+/// DedupStage drops entries which were already seen. The entries seen are reserved from
+/// the memory budget, once it's used up the remaining entries are held back,
+/// deduplicated on disk and passed on sorted after the last entry.
+#[derive(Debug)]
+pub struct DedupStage {
+    seen: HashSet<String>,
+    spill: SpillSettings,
+    /// prefix for the run files
+    name: String,
+    /// approximated number of bytes held by the entries seen
+    size: usize,
+    /// the memory reserved for the entries seen
+    reservation: Reservation,
+    /// the entries read once the budget was used up
+    overflow: Option<ExternalSort>,
+}
+
+impl DedupStage {
+    /// Initialize a new DedupStage
+    ///
+    /// * `spill`: where and when the entries held back are moved to disk
+    /// * `name`: prefix for the run files
+    pub fn new(spill: &SpillSettings, name: &str) -> Self {
+        Self {
+            seen: HashSet::new(),
+            spill: spill.clone(),
+            name: name.to_string(),
+            size: 0,
+            reservation: Reservation::new(spill.budget.clone()),
+            overflow: None,
+        }
+    }
+}
+
+#[async_trait]
+impl Stage for DedupStage {
+    async fn process(&mut self, entry: String) -> anyhow::Result<Vec<String>> {
+        if self.seen.contains(&entry) {
+            return Ok(vec![]);
+        }
+        if let Some(overflow) = self.overflow.as_mut() {
+            overflow.push(entry)?;
+            return Ok(vec![]);
+        }
+        let size = entry_size(&entry);
+        // the entries seen are only limited by the budget, not by the threshold
+        let reserved = self.reservation.reserve(size);
+        if must_spill(self.size, usize::MAX, reserved) {
+            debug!(
+                "{}: memory budget used up, the remaining entries are deduplicated on disk",
+                self.name
+            );
+            let mut overflow =
+                ExternalSort::new(&self.spill.dir, &self.name, self.spill.threshold, true)
+                    .with_budget(self.spill.budget.clone());
+            overflow.push(entry)?;
+            self.overflow = Some(overflow);
+            return Ok(vec![]);
+        }
+        self.size += size;
+        self.seen.insert(entry.clone());
+        Ok(vec![entry])
+    }
+
+    async fn drain(&mut self, batch_size: usize) -> anyhow::Result<Vec<String>> {
+        match self.overflow.as_mut() {
+            Some(overflow) => overflow.next_batch(batch_size),
+            None => Ok(vec![]),
+        }
+    }
+}
+
+/// SortStage sorts all entries, the entries exceeding the threshold or the memory
+/// budget are sorted on disk
+#[derive(Debug)]
+pub struct SortStage {
+    sort: ExternalSort,
+}
+
+impl SortStage {
+    /// Initialize a new SortStage
+    ///
+    /// * `spill`: where and when the entries held back are moved to disk
+    /// * `name`: prefix for the run files
+    pub fn new(spill: &SpillSettings, name: &str) -> Self {
+        Self {
+            sort: ExternalSort::new(&spill.dir, name, spill.threshold, false)
+                .with_budget(spill.budget.clone()),
+        }
+    }
+}
+
+#[async_trait]
+impl Stage for SortStage {
+    async fn process(&mut self, entry: String) -> anyhow::Result<Vec<String>> {
+        self.sort.push(entry)?;
+        Ok(vec![])
+    }
+
+    async fn drain(&mut self, batch_size: usize) -> anyhow::Result<Vec<String>> {
+        self.sort.next_batch(batch_size)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ValidateStage {
+    dropped: usize,
+}
+
+#[async_trait]
+impl Stage for ValidateStage {
+    async fn process(&mut self, entry: String) -> anyhow::Result<Vec<String>> {
+        if is_valid_hostname(&entry) {
+            return Ok(vec![entry]);
+        }
+        self.dropped += 1;
+        Ok(vec![])
+    }
+
+    async fn finish(&mut self) -> anyhow::Result<Vec<String>> {
+        if self.dropped > 0 {
+            debug!("{} invalid entries dropped", self.dropped);
+        }
+        Ok(vec![])
+    }
+}
+
+/// CommandStage collects all entries and pipes them through a command on completion.
+/// Every line the command writes to its standard output becomes an entry.
+#[derive(Debug)]
+pub struct CommandStage {
+    command: String,
+    entries: Vec<String>,
+}
+
+impl CommandStage {
+    /// Initialize a new CommandStage
+    ///
+    /// * `command`: the command line to be executed
+    pub fn new(command: &str) -> Self {
+        Self {
+            command: command.to_string(),
+            entries: vec![],
+        }
+    }
+}
+
+#[async_trait]
+impl Stage for CommandStage {
+    async fn process(&mut self, entry: String) -> anyhow::Result<Vec<String>> {
+        self.entries.push(entry);
+        Ok(vec![])
+    }
+
+    async fn finish(&mut self) -> anyhow::Result<Vec<String>> {
+        let mut child = platform::shell(&self.command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("could not execute command `{}`", self.command))?;
+        let mut stdin = child
+            .stdin
+            .take()
+            .with_context(|| format!("command `{}` has no stdin", self.command))?;
+        let stdout = child
+            .stdout
+            .take()
+            .with_context(|| format!("command `{}` has no stdout", self.command))?;
+
+        // feed the entries concurrently so a command writing early can't block
+        let input = std::mem::take(&mut self.entries);
+        let feeder = tokio::spawn(async move {
+            for entry in input {
+                stdin.write_all(entry.as_bytes()).await?;
+                stdin.write_all(b"\n").await?;
+            }
+            stdin.shutdown().await
+        });
+
+        let mut entries = vec![];
+        let mut lines = BufReader::new(stdout).lines();
+        while let Some(line) = lines.next_line().await? {
+            entries.push(line);
+        }
+        // a command may exit without consuming all of its input
+        feeder
+            .await?
+            .or_else(|e| match e.kind() {
+                std::io::ErrorKind::BrokenPipe => Ok(()),
+                _ => Err(e),
+            })
+            .with_context(|| format!("could not write to command `{}`", self.command))?;
+        let status = child.wait().await?;
+        if !status.success() {
+            return Err(anyhow::anyhow!(
+                "command `{}` failed: {}",
+                self.command,
+                status
+            ));
+        }
+        Ok(entries)
+    }
+}
+
+/// DomainAgeStage drops or selects the entries whose domain was registered recently,
+/// entries of unknown age are never newly registered
+pub struct DomainAgeStage {
+    client: RdapClient,
+    max_age: i64,
+    action: NewDomainAction,
+    newly_registered: usize,
+}
+
+impl DomainAgeStage {
+    /// Initialize a new DomainAgeStage
+    ///
+    /// * `client`: the client looking up the registration dates
+    /// * `max_age_days`: domains registered less than this number of days ago are
+    ///   newly registered
+    /// * `action`: what happens to newly registered domains
+    pub fn new(client: RdapClient, max_age_days: u64, action: NewDomainAction) -> Self {
+        Self {
+            client,
+            max_age: max_age_days as i64 * 24 * 60 * 60,
+            action,
+            newly_registered: 0,
+        }
+    }
+}
+
+#[async_trait]
+impl Stage for DomainAgeStage {
+    async fn process(&mut self, entry: String) -> anyhow::Result<Vec<String>> {
+        let registered = self.client.registered(&entry).await?;
+        let is_new = registered.is_some_and(|r| unix_timestamp() as i64 - r < self.max_age);
+        if is_new {
+            self.newly_registered += 1;
+        }
+        match (self.action, is_new) {
+            (NewDomainAction::Drop, false) | (NewDomainAction::Only, true) => Ok(vec![entry]),
+            _ => Ok(vec![]),
+        }
+    }
+
+    async fn finish(&mut self) -> anyhow::Result<Vec<String>> {
+        debug!("{} newly registered entries", self.newly_registered);
+        self.client.save()?;
+        Ok(vec![])
+    }
+}
+
+/// AsnStage filters IP entries by the autonomous system and country announcing them.
+/// The database is read on the first entry.
+pub struct AsnStage {
+    database_path: PathBuf,
+    database: Option<AsnDatabase>,
+    filter: AsnFilter,
+    dropped: usize,
+}
+
+impl AsnStage {
+    /// Initialize a new AsnStage
+    ///
+    /// * `database`: the file system path of the ip2asn database
+    /// * `filter`: the autonomous systems and countries to be kept or dropped
+    pub fn new(database: &str, filter: AsnFilter) -> Self {
+        Self {
+            database_path: PathBuf::from(database),
+            database: None,
+            filter,
+            dropped: 0,
+        }
+    }
+}
+
+#[async_trait]
+impl Stage for AsnStage {
+    async fn process(&mut self, entry: String) -> anyhow::Result<Vec<String>> {
+        let ip = match entry_address(&entry) {
+            Some(ip) => ip,
+            None => return Ok(vec![entry]),
+        };
+        if self.database.is_none() {
+            self.database = Some(AsnDatabase::load(&self.database_path)?);
+        }
+        let record = self.database.as_ref().and_then(|d| d.lookup(ip));
+        if self.filter.is_kept(record) {
+            return Ok(vec![entry]);
+        }
+        self.dropped += 1;
+        Ok(vec![])
+    }
+
+    async fn finish(&mut self) -> anyhow::Result<Vec<String>> {
+        if self.dropped > 0 {
+            debug!("{} entries dropped by their ASN", self.dropped);
+        }
+        Ok(vec![])
+    }
+}
+
+/// DgaStage drops or selects the entries which were likely made up by a domain
+/// generation algorithm, IP entries are never generated
+pub struct DgaStage {
+    min_score: u32,
+    /// None passes all entries, e.g. for the outputs of other tags
+    action: Option<NewDomainAction>,
+    generated: usize,
+}
+
+impl DgaStage {
+    /// Initialize a new DgaStage
+    ///
+    /// * `min_score`: domains scoring at least this are likely generated
+    /// * `action`: what happens to likely generated domains
+    pub fn new(min_score: u32, action: Option<NewDomainAction>) -> Self {
+        Self {
+            min_score,
+            action,
+            generated: 0,
+        }
+    }
+}
+
+#[async_trait]
+impl Stage for DgaStage {
+    async fn process(&mut self, entry: String) -> anyhow::Result<Vec<String>> {
+        let action = match self.action {
+            Some(action) => action,
+            None => return Ok(vec![entry]),
+        };
+        let is_generated = entry_address(&entry).is_none() && dga_score(&entry) >= self.min_score;
+        if is_generated {
+            self.generated += 1;
+        }
+        match (action, is_generated) {
+            (NewDomainAction::Drop, false) | (NewDomainAction::Only, true) => Ok(vec![entry]),
+            _ => Ok(vec![]),
+        }
+    }
+
+    async fn finish(&mut self) -> anyhow::Result<Vec<String>> {
+        if self.generated > 0 {
+            debug!("{} likely generated entries", self.generated);
+        }
+        Ok(vec![])
+    }
+}
+
+/// Pipeline composes the configured stages, the entries returned by a stage are
+/// passed on to the next one
+pub struct Pipeline {
+    stages: Vec<Box<dyn Stage>>,
+    /// number of stages finished and drained
+    finished: usize,
+    /// the stage at the position of finished was finished and is being drained
+    draining: bool,
+}
+
+impl Pipeline {
+    /// creates a pipeline from the configured stages
+    ///
+    /// * `configs`: the stage configurations in processing order
+    /// * `tag`: the tag of the category list passing the pipeline
+    /// * `spill`: where and when the entries held back are moved to disk
+    pub fn new(configs: &[StageConfig], tag: &str, spill: &SpillSettings) -> Self {
+        Self {
+            stages: configs
+                .iter()
+                .enumerate()
+                .map(|(i, c)| c.build(tag, i, spill))
+                .collect(),
+            finished: 0,
+            draining: false,
+        }
+    }
+
+    /// passes entries through all stages starting at the given position
+    ///
+    /// * `from`: index of the first stage to process the entries
+    /// * `entries`: the entries to be processed
+    async fn push(&mut self, from: usize, mut entries: Vec<String>) -> anyhow::Result<Vec<String>> {
+        for stage in self.stages.iter_mut().skip(from) {
+            if entries.is_empty() {
+                break;
+            }
+            entries = stage.process_batch(entries).await?;
+        }
+        Ok(entries)
+    }
+
+    /// finishes the stages in order and returns the next entries held back, None once
+    /// all stages are finished. The entries held back by a stage pass through all
+    /// subsequent stages before those are finished.
+    ///
+    /// * `batch_size`: maximum number of entries drained from a stage at once
+    async fn finish(&mut self, batch_size: usize) -> anyhow::Result<Option<Vec<String>>> {
+        while self.finished < self.stages.len() {
+            let i = self.finished;
+            let flushed = match self.draining {
+                false => {
+                    self.draining = true;
+                    self.stages[i].finish().await?
+                }
+                true => {
+                    let drained = self.stages[i].drain(batch_size).await?;
+                    if drained.is_empty() {
+                        self.finished += 1;
+                        self.draining = false;
+                    }
+                    drained
+                }
+            };
+            if !flushed.is_empty() {
+                return Ok(Some(self.push(i + 1, flushed).await?));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// PipelineInput reads the entries of another input and passes them through a pipeline
+pub struct PipelineInput {
+    reader: Arc<Mutex<dyn Input + Send>>,
+    configs: Vec<StageConfig>,
+    tag: String,
+    pipeline: Pipeline,
+    pending: VecDeque<String>,
+    /// the reader returned its last entry, the pipeline is being finished
+    exhausted: bool,
+    finished: bool,
+    /// number of entries read and passed through the pipeline at once
+    batch_size: usize,
+    /// where and when the stages move the entries held back to disk
+    spill: SpillSettings,
+}
+
+impl PipelineInput {
+    /// Initialize a new PipelineInput
+    ///
+    /// * `reader`: the input providing the entries
+    /// * `configs`: the stage configurations in processing order
+    /// * `tag`: the tag of the category list the entries belong to
+    pub fn new(reader: Arc<Mutex<dyn Input + Send>>, configs: &[StageConfig], tag: &str) -> Self {
+        Self {
+            reader,
+            configs: configs.to_vec(),
+            tag: tag.to_string(),
+            pipeline: Pipeline::new(configs, tag, &SpillSettings::default()),
+            pending: VecDeque::new(),
+            exhausted: false,
+            finished: false,
+            batch_size: DEFAULT_BATCH_SIZE,
+            spill: SpillSettings::default(),
+        }
+    }
+
+    /// sets where and when the stages holding entries back move them to disk
+    ///
+    /// * `spill`: the spill settings of the output
+    pub fn with_spill(mut self, spill: SpillSettings) -> Self {
+        self.pipeline = Pipeline::new(&self.configs, &self.tag, &spill);
+        self.spill = spill;
+        self
+    }
+
+    /// sets the number of entries read and passed through the pipeline at once
+    ///
+    /// * `batch_size`: number of entries per batch
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+}
+
+#[async_trait]
+impl Input for PipelineInput {
+    async fn chunk(&mut self) -> anyhow::Result<Option<Bytes>> {
+        loop {
+            if let Some(entry) = self.pending.pop_front() {
+                return Ok(Some(Bytes::from(entry + "\n")));
+            }
+            if self.finished {
+                return Ok(None);
+            }
+            if self.exhausted {
+                match self.pipeline.finish(self.batch_size).await? {
+                    Some(entries) => self.pending.extend(entries),
+                    None => self.finished = true,
+                }
+                continue;
+            }
+            let mut batch = Vec::with_capacity(self.batch_size);
+            {
+                let mut reader = self.reader.lock().await;
+                while batch.len() < self.batch_size {
+                    match reader.chunk().await? {
+                        Some(chunk) => {
+                            let entry = std::str::from_utf8(&chunk)?.trim();
+                            if !entry.is_empty() {
+                                batch.push(entry.to_string());
+                            }
+                        }
+                        None => {
+                            self.exhausted = true;
+                            break;
+                        }
+                    }
+                }
+            }
+            self.pending.extend(self.pipeline.push(0, batch).await?);
+        }
+    }
+
+    async fn reset(&mut self) -> anyhow::Result<()> {
+        self.reader.lock().await.reset().await?;
+        self.pipeline = Pipeline::new(&self.configs, &self.tag, &self.spill);
+        self.pending.clear();
+        self.exhausted = false;
+        self.finished = false;
+        Ok(())
+    }
+
+    async fn len(&mut self) -> anyhow::Result<u64> {
+        Err(anyhow::anyhow!(
+            "the length of post-processed entries is unknown in advance"
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        io::run_files::MIN_RUN_SIZE,
+        tests::helper::{cache_file_creator::TEST_CACHE, cursor_input::CursorInput},
+    };
+
+    use super::*;
+
+    async fn read_all(input: &mut PipelineInput) -> String {
+        let mut got = vec![];
+        while let Some(chunk) = input.chunk().await.unwrap() {
+            got.extend_from_slice(&chunk);
+        }
+        String::from_utf8(got).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_input() {
+        let reader = Arc::new(Mutex::new(CursorInput::new(
+            "c.domain\na.domain\nin valid\nc.domain\nb.domain\n",
+        )));
+        let configs = vec![StageConfig::Validate, StageConfig::Dedup, StageConfig::Sort];
+        let mut input = PipelineInput::new(reader, &configs, "malware");
+
+        assert_eq!(read_all(&mut input).await, "a.domain\nb.domain\nc.domain\n");
+        // the stages start over after a reset
+        input.reset().await.unwrap();
+        assert_eq!(read_all(&mut input).await, "a.domain\nb.domain\nc.domain\n");
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_batches() {
+        // the batch size changes neither the entries nor their order
+        let configs = vec![StageConfig::Validate, StageConfig::Dedup];
+        for batch_size in [0, 1, 2, DEFAULT_BATCH_SIZE] {
+            let reader = Arc::new(Mutex::new(CursorInput::new(
+                "c.domain\na.domain\n\nin valid\nc.domain\nb.domain\n",
+            )));
+            let mut input =
+                PipelineInput::new(reader, &configs, "malware").with_batch_size(batch_size);
+            assert_eq!(
+                read_all(&mut input).await,
+                "c.domain\na.domain\nb.domain\n",
+                "batch size {}",
+                batch_size
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_spill() {
+        let label = "a".repeat(500);
+        let unique: Vec<String> = (0..MIN_RUN_SIZE * 3 / entry_size(&label))
+            .rev()
+            .map(|i| format!("{:05}.{}", i, label))
+            .collect();
+        // every entry is read twice
+        let lines = unique
+            .iter()
+            .chain(unique.iter())
+            .fold(String::new(), |lines, e| lines + e + "\n");
+        let mut sorted = unique.clone();
+        sorted.sort();
+        let budget = MemoryBudget::new(MIN_RUN_SIZE / 2);
+        let spill = SpillSettings {
+            dir: PathBuf::from(TEST_CACHE).join("test_pipeline_spill"),
+            threshold: 0,
+            budget: Some(budget.clone()),
+        };
+
+        // the entries beyond the budget are deduplicated on disk and passed on sorted
+        let reader = Arc::new(Mutex::new(CursorInput::new(&lines)));
+        let mut input = PipelineInput::new(reader, &[StageConfig::Dedup], "malware")
+            .with_batch_size(2)
+            .with_spill(spill.clone());
+        let got = read_all(&mut input).await;
+        let got: Vec<&str> = got.lines().collect();
+        assert_eq!(got.len(), unique.len());
+        let passed = got
+            .iter()
+            .zip(unique.iter())
+            .take_while(|(g, u)| *g == u)
+            .count();
+        assert!(passed > 0 && passed < got.len());
+        assert!(got[passed..].is_sorted());
+        let mut got: Vec<&str> = got.to_vec();
+        got.sort();
+        assert_eq!(got, sorted);
+        drop(input);
+
+        let reader = Arc::new(Mutex::new(CursorInput::new(&lines)));
+        let configs = vec![StageConfig::Dedup, StageConfig::Sort];
+        let mut input = PipelineInput::new(reader, &configs, "malware")
+            .with_batch_size(2)
+            .with_spill(spill);
+        let got = read_all(&mut input).await;
+        assert_eq!(got.lines().collect::<Vec<_>>(), sorted);
+        // the memory is returned once the stages are dropped
+        drop(input);
+        assert!(budget.try_reserve(MIN_RUN_SIZE / 2));
+    }
+
+    #[tokio::test]
+    async fn test_command_stage() {
+        let reader = Arc::new(Mutex::new(CursorInput::new("one.domain\ntwo.domain\n")));
+        let configs = vec![
+            StageConfig::Command {
+                command: "grep -v one".to_string(),
+            },
+            StageConfig::Command {
+                command: "sed 's/^/sub./'".to_string(),
+            },
+        ];
+        let mut input = PipelineInput::new(reader, &configs, "malware");
+
+        assert_eq!(read_all(&mut input).await, "sub.two.domain\n");
+    }
+
+    #[tokio::test]
+    async fn test_domain_age_stage() {
+        let mut cache_path = PathBuf::from(TEST_CACHE);
+        cache_path.push("test_domain_age_stage.json");
+        let now = unix_timestamp();
+        // one domain was registered yesterday, the other one years ago
+        std::fs::write(
+            &cache_path,
+            format!(
+                r#"{{"new.domain":{{"registered":{},"checked":{}}},"old.domain":{{"registered":0,"checked":{}}}}}"#,
+                now - 24 * 60 * 60,
+                now,
+                now
+            ),
+        )
+        .unwrap();
+        let reader = Arc::new(Mutex::new(CursorInput::new(
+            "www.new.domain\nold.domain\nunknown.domain\n",
+        )));
+        let configs = vec![StageConfig::DomainAge {
+            max_age_days: 30,
+            action: NewDomainAction::Drop,
+            cache_file: cache_path.to_string_lossy().to_string(),
+            rdap_url: None,
+            requests_per_minute: None,
+            // no lookups, the age of unknown.domain stays unknown
+            max_lookups: Some(0),
+        }];
+        let mut input = PipelineInput::new(reader, &configs, "malware");
+        assert_eq!(read_all(&mut input).await, "old.domain\nunknown.domain\n");
+    }
+
+    #[tokio::test]
+    async fn test_asn_stage() {
+        let mut database = PathBuf::from(TEST_CACHE);
+        database.push("test_asn_stage.tsv");
+        std::fs::write(
+            &database,
+            "1.0.0.0\t1.0.0.255\t13335\tUS\tCLOUDFLARENET\n\
+             1.0.4.0\t1.0.7.255\t38803\tAU\tGTELECOM\n",
+        )
+        .unwrap();
+        let reader = Arc::new(Mutex::new(CursorInput::new(
+            "1.0.0.1\n1.0.4.0/24\n9.9.9.9\nexample.com\n",
+        )));
+        let configs = vec![StageConfig::Asn {
+            database: database.to_string_lossy().to_string(),
+            include_asns: vec![],
+            include_countries: vec!["us".to_string(), "AU".to_string()],
+            exclude_asns: vec![38803],
+            exclude_countries: vec![],
+        }];
+        let mut input = PipelineInput::new(reader, &configs, "malware");
+        // unrouted addresses aren't included, domains aren't filtered
+        assert_eq!(read_all(&mut input).await, "1.0.0.1\nexample.com\n");
+    }
+
+    #[tokio::test]
+    async fn test_dga_stage() {
+        let entries = "example.com\nxjwqkzvbtrplmn.com\n1.0.0.1\n";
+        let configs = vec![StageConfig::Dga {
+            min_score: None,
+            drop_tags: vec!["malware".to_string()],
+            only_tags: vec!["malware-dga".to_string()],
+        }];
+        let expected = [
+            ("malware", "example.com\n1.0.0.1\n"),
+            ("malware-dga", "xjwqkzvbtrplmn.com\n"),
+            ("phishing", entries),
+        ];
+        for (tag, expected) in expected {
+            let reader = Arc::new(Mutex::new(CursorInput::new(entries)));
+            let mut input = PipelineInput::new(reader, &configs, tag);
+            assert_eq!(read_all(&mut input).await, expected);
+        }
+    }
+}
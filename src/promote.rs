@@ -0,0 +1,173 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use crate::{
+    config::Config, encryption::read_output, output::OutputType, output_settings::OutputSettings,
+    platform,
+};
+
+/// checks the staged outputs of a configuration and its projects and moves them to
+/// the output directories
+///
+/// * `config`: the configuration containing the staging directory
+pub fn promote_all(config: &Config) -> anyhow::Result<()> {
+    let mut configs = vec![config.clone()];
+    configs.extend(config.project_configs());
+    let mut promoted = 0;
+    for config in configs.iter().filter(|c| c.staging_dir.is_some()) {
+        promoted += promote(config)?;
+    }
+    if promoted == 0 {
+        warn!("No staged outputs found");
+    }
+    Ok(())
+}
+
+/// checks all staged outputs and moves them to the output directory if all checks
+/// succeeded, returns the number of outputs promoted
+///
+/// * `config`: the configuration containing the staging directory
+pub fn promote(config: &Config) -> anyhow::Result<usize> {
+    let staging_dir = config
+        .staging_dir
+        .as_ref()
+        .with_context(|| "no staging_dir configured")?;
+    // (tag, file name, format) of every staged output including the additional formats
+    let staged_outputs: Vec<(String, String, OutputType)> = config
+        .get_tags()
+        .into_iter()
+        .flat_map(|tag| {
+            config
+                .output_files(&tag)
+                .into_iter()
+                .map(move |(file_name, format)| (tag.clone(), file_name, format))
+        })
+        .filter(|(_, file_name, _)| Path::new(staging_dir).join(file_name).exists())
+        .collect();
+
+    // every output must be valid before any of them gets promoted
+    for (tag, file_name, format) in staged_outputs.iter() {
+        if let Some(settings) = config.output_settings(tag) {
+            check_output(settings, format, &Path::new(staging_dir).join(file_name))?;
+        }
+    }
+
+    fs::create_dir_all(&config.output_dir).with_context(|| "could not create out directory")?;
+    for (tag, file_name, format) in staged_outputs.iter() {
+        let out_file = Path::new(&config.output_dir).join(file_name);
+        move_file(&Path::new(staging_dir).join(file_name), &out_file)?;
+        info!("Promoted: {}", file_name);
+        if let Some(settings) = config.output_settings(tag)
+            && *format == OutputType::Hostsfile
+            && file_name == tag
+        {
+            settings.apply_hosts_merge(&out_file)?;
+        }
+    }
+    Ok(staged_outputs.len())
+}
+
+/// checks the number of entries and the absence of safeguard domains in an output file
+///
+/// * `settings`: the output's settings containing the checks
+/// * `output_format`: the format the output file was written in
+/// * `path`: the file system path of the output file
+fn check_output(
+    settings: &OutputSettings,
+    output_format: &OutputType,
+    path: &Path,
+) -> anyhow::Result<()> {
+    let contents = read_output(path, settings.encryption.as_ref())
+        .with_context(|| format!("could not read staged output {}", settings.tag))?;
+    let mut entries = 0;
+    for entry in contents
+        .lines()
+        .flat_map(|l| output_format.parse_entries(l, settings.address()))
+    {
+        if settings.safeguard.iter().any(|s| *s == entry) {
+            return Err(anyhow::anyhow!(
+                "output {} contains safeguard domain {}",
+                settings.tag,
+                entry
+            ));
+        }
+        entries += 1;
+    }
+    if let Some(min_entries) = settings.min_entries
+        && entries < min_entries
+    {
+        return Err(anyhow::anyhow!(
+            "output {} contains {} entries, expected at least {}",
+            settings.tag,
+            entries,
+            min_entries
+        ));
+    }
+    if let Some(max_entries) = settings.max_entries
+        && entries > max_entries
+    {
+        return Err(anyhow::anyhow!(
+            "output {} contains {} entries, expected at most {}",
+            settings.tag,
+            entries,
+            max_entries
+        ));
+    }
+    Ok(())
+}
+
+/// atomically replaces the destination file with the source file
+///
+/// * `from`: the source file
+/// * `to`: the destination file
+fn move_file(from: &Path, to: &Path) -> anyhow::Result<()> {
+    if platform::replace_file(from, to).is_ok() {
+        return Ok(());
+    }
+    // staging and output directory reside on different file systems
+    let mut tmp_path = PathBuf::from(to);
+    tmp_path.set_file_name(format!(
+        ".{}.tmp",
+        to.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    fs::copy(from, &tmp_path).with_context(|| format!("could not copy {:?}", from))?;
+    platform::replace_file(&tmp_path, to)
+        .with_context(|| format!("could not move {:?} into place", to))?;
+    fs::remove_file(from)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::helper::cache_file_creator::TEST_CACHE;
+
+    use super::*;
+
+    #[test]
+    fn test_check_output() {
+        let mut path = PathBuf::from(TEST_CACHE);
+        path.push("test_check_output");
+        fs::create_dir_all(&path).unwrap();
+        path.push("malware");
+        fs::write(&path, "0.0.0.0 one.domain\n0.0.0.0 two.domain\n").unwrap();
+
+        let mut settings = OutputSettings {
+            tag: "malware".to_string(),
+            min_entries: Some(2),
+            max_entries: Some(2),
+            ..Default::default()
+        };
+        assert!(check_output(&settings, &OutputType::Hostsfile, &path).is_ok());
+
+        settings.min_entries = Some(3);
+        assert!(check_output(&settings, &OutputType::Hostsfile, &path).is_err());
+
+        settings.min_entries = None;
+        settings.safeguard = vec!["two.domain".to_string()];
+        assert!(check_output(&settings, &OutputType::Hostsfile, &path).is_err());
+    }
+}
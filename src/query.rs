@@ -0,0 +1,127 @@
+use std::path::Path;
+
+use crate::{
+    config::Config,
+    io::{
+        entry_file::EntryFile,
+        seen_file::{Seen, SeenFile},
+    },
+    report::rfc3339,
+};
+
+/// QueryMatch is an output file containing the queried entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryMatch {
+    /// the file system path of the output file
+    pub path: String,
+    /// when the entry first and last appeared in the output, if recorded
+    pub seen: Option<Seen>,
+}
+
+impl std::fmt::Display for QueryMatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.seen {
+            Some(seen) => write!(
+                f,
+                "{}\t{}\t{}",
+                self.path,
+                rfc3339(seen.first),
+                rfc3339(seen.last)
+            ),
+            None => write!(f, "{}", self.path),
+        }
+    }
+}
+
+/// returns the output files containing the entry, hashed outputs are searched for the
+/// entry's digest
+///
+/// * `config`: the configuration whose outputs and projects are searched
+/// * `entry`: the domain to look up
+pub fn query(config: &Config, entry: &str) -> anyhow::Result<Vec<QueryMatch>> {
+    let entry = EntryFile::normalize(entry)
+        .ok_or_else(|| anyhow::anyhow!("{:?} is not a valid entry", entry))?;
+    let mut found = vec![];
+    for config in std::iter::once(config.clone()).chain(config.project_configs()) {
+        for tag in config.get_tags() {
+            let seen = SeenFile::new(&config.seen_dir(), &tag).read()?;
+            for (file_name, format) in config.output_files(&tag) {
+                let path = Path::new(&config.output_dir).join(&file_name);
+                // outputs which weren't built yet can't contain the entry
+                let encryption = config.output_encryption(&tag);
+                let entries = match format.entries(&path, encryption, config.output_address(&tag)) {
+                    Ok(entries) => entries,
+                    Err(_) => continue,
+                };
+                let key = format.query_key(&entry);
+                if entries.contains(&key) {
+                    found.push(QueryMatch {
+                        path: path.to_string_lossy().to_string(),
+                        seen: seen.get(&entry).copied(),
+                    });
+                }
+            }
+        }
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{
+        filter_list::FilterList,
+        output::{hashed::hash_entry, OutputType},
+        tests::helper::cache_file_creator::CacheFileCreator,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_query() {
+        let mut config = CacheFileCreator::new("test_query", "in", "out").new_test_config();
+        config.lists = vec![FilterList {
+            tags: vec!["malware".to_string()],
+            ..Default::default()
+        }];
+        config.output_format = OutputType::Sha256 {
+            salt: "salt".to_string(),
+        };
+        config.additional_formats = vec![OutputType::Hostsfile];
+        let out = Path::new(&config.output_dir);
+        fs::create_dir_all(out).unwrap();
+        fs::write(out.join("malware"), hash_entry("salt", "one.domain") + "\n").unwrap();
+        fs::write(out.join("malware.hosts"), "0.0.0.0 two.domain\n").unwrap();
+        fs::remove_dir_all(config.seen_dir()).ok();
+        let entries = ["one.domain".to_string()].into_iter().collect();
+        SeenFile::new(&config.seen_dir(), "malware")
+            .update(&entries, 1669852800, None)
+            .unwrap();
+
+        let found = query(&config, "One.Domain.").unwrap();
+        let path = out.join("malware").to_string_lossy().to_string();
+        assert_eq!(
+            found,
+            vec![QueryMatch {
+                path: path.clone(),
+                seen: Some(Seen {
+                    first: 1669852800,
+                    last: 1669852800
+                }),
+            }]
+        );
+        assert_eq!(
+            found[0].to_string(),
+            format!("{}\t2022-12-01T00:00:00Z\t2022-12-01T00:00:00Z", path)
+        );
+        assert_eq!(
+            query(&config, "two.domain").unwrap(),
+            vec![QueryMatch {
+                path: out.join("malware.hosts").to_string_lossy().to_string(),
+                seen: None,
+            }]
+        );
+        assert!(query(&config, "three.domain").unwrap().is_empty());
+    }
+}
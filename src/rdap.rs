@@ -0,0 +1,235 @@
+use std::{collections::HashMap, fs, path::PathBuf, time::Duration};
+
+use anyhow::Context;
+use chrono::DateTime;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::report::unix_timestamp;
+
+/// RDAP service redirecting to the authoritative registry if no service was configured
+pub const DEFAULT_RDAP_URL: &str = "https://rdap.org/domain/";
+/// seconds an unknown registration date is cached before the domain is looked up again
+pub const UNKNOWN_TTL: u64 = 7 * 24 * 60 * 60;
+
+/// CachedLookup is the result of a lookup kept between runs
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+struct CachedLookup {
+    /// unix timestamp of the registration, None if the registry didn't tell
+    registered: Option<i64>,
+    /// unix timestamp of the lookup
+    checked: u64,
+}
+
+/// RdapClient looks up the registration dates of domains, the results are cached in a
+/// file and the requests are rate limited
+pub struct RdapClient {
+    client: Client,
+    url: String,
+    cache_path: PathBuf,
+    cache: HashMap<String, CachedLookup>,
+    interval: Duration,
+    last_request: Option<Instant>,
+    /// lookups left in this run
+    budget: usize,
+}
+
+impl RdapClient {
+    /// creates a client using the results cached in the given file
+    ///
+    /// * `url`: the RDAP service the domain is appended to
+    /// * `cache_path`: the file the results are cached in
+    /// * `interval`: the minimum time between two requests
+    /// * `max_lookups`: the maximum number of requests
+    pub fn new(url: &str, cache_path: PathBuf, interval: Duration, max_lookups: usize) -> Self {
+        // a damaged cache only costs lookups
+        let cache = fs::read_to_string(&cache_path)
+            .ok()
+            .and_then(|c| serde_json::from_str(&c).ok())
+            .unwrap_or_default();
+        Self {
+            client: Client::new(),
+            url: url.to_string(),
+            cache_path,
+            cache,
+            interval,
+            last_request: None,
+            budget: max_lookups,
+        }
+    }
+
+    /// returns the unix timestamp of the registration of the domain the entry belongs
+    /// to, None if it's unknown or the lookup budget is exhausted
+    ///
+    /// * `entry`: the domain or one of its sub domains
+    pub async fn registered(&mut self, entry: &str) -> anyhow::Result<Option<i64>> {
+        // without a public suffix list the registered domain is guessed, a suffix
+        // like co.uk is followed by a third label
+        let labels: Vec<&str> = entry.split('.').collect();
+        for n in 2..=labels.len().min(3) {
+            let domain = labels[labels.len() - n..].join(".");
+            match self.lookup(&domain).await? {
+                Some(Some(registered)) => return Ok(Some(registered)),
+                Some(None) => continue,
+                None => return Ok(None),
+            }
+        }
+        Ok(None)
+    }
+
+    /// returns the cached or looked up registration of a domain, None if the budget
+    /// is exhausted
+    ///
+    /// * `domain`: the domain to be looked up
+    async fn lookup(&mut self, domain: &str) -> anyhow::Result<Option<Option<i64>>> {
+        if let Some(cached) = self.cache.get(domain) {
+            if cached.registered.is_some() || cached.checked + UNKNOWN_TTL > unix_timestamp() {
+                return Ok(Some(cached.registered));
+            }
+        }
+        if self.budget == 0 {
+            return Ok(None);
+        }
+        self.budget -= 1;
+        if let Some(last_request) = self.last_request {
+            tokio::time::sleep_until(last_request + self.interval).await;
+        }
+        self.last_request = Some(Instant::now());
+
+        let response = self
+            .client
+            .get(format!("{}{}", self.url, domain))
+            .header("Accept", "application/rdap+json")
+            .send()
+            .await
+            .with_context(|| format!("RDAP lookup of {} failed", domain))?;
+        let registered = match response.status() {
+            StatusCode::OK => registration(&response.text().await?),
+            StatusCode::NOT_FOUND => None,
+            StatusCode::TOO_MANY_REQUESTS => {
+                // the service asks to back off, further lookups wait for the next run
+                warn!("RDAP service is rate limiting, no more lookups in this run");
+                self.budget = 0;
+                return Ok(None);
+            }
+            status => {
+                return Err(anyhow::anyhow!(
+                    "RDAP lookup of {} failed: {}",
+                    domain,
+                    status
+                ))
+            }
+        };
+        self.cache.insert(
+            domain.to_string(),
+            CachedLookup {
+                registered,
+                checked: unix_timestamp(),
+            },
+        );
+        Ok(Some(registered))
+    }
+
+    /// writes the cached results to the cache file
+    pub fn save(&self) -> anyhow::Result<()> {
+        if let Some(parent) = self.cache_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // concurrently processed lists share the cache, so it's replaced atomically
+        let tmp_path = self.cache_path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_string(&self.cache)?)
+            .with_context(|| format!("could not write {:?}", tmp_path))?;
+        fs::rename(&tmp_path, &self.cache_path)
+            .with_context(|| format!("could not write {:?}", self.cache_path))?;
+        Ok(())
+    }
+}
+
+/// returns the unix timestamp of the registration event of an RDAP domain object
+///
+/// * `body`: the RDAP response
+fn registration(body: &str) -> Option<i64> {
+    let object: serde_json::Value = serde_json::from_str(body).ok()?;
+    object["events"]
+        .as_array()?
+        .iter()
+        .find(|e| e["eventAction"] == "registration")
+        .and_then(|e| e["eventDate"].as_str())
+        .and_then(|d| DateTime::parse_from_rfc3339(d).ok())
+        .map(|d| d.timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use crate::tests::helper::cache_file_creator::TEST_CACHE;
+
+    use super::*;
+
+    #[test]
+    fn test_registration() {
+        let body = r#"{"objectClassName":"domain","events":[
+            {"eventAction":"last changed","eventDate":"2022-01-01T00:00:00Z"},
+            {"eventAction":"registration","eventDate":"2022-11-30T12:00:00Z"}
+        ]}"#;
+        assert_eq!(registration(body), Some(1669809600));
+        assert_eq!(registration(r#"{"events":[]}"#), None);
+        assert_eq!(registration("not json"), None);
+    }
+
+    #[tokio::test]
+    async fn test_lookup_cache() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/domain/", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let mut requests = vec![];
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 1024];
+                let n = stream.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                // the second level domain is unknown, the third level domain exists
+                let response = match request.starts_with("GET /domain/co.uk ") {
+                    true => {
+                        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                            .to_string()
+                    }
+                    false => {
+                        let body = r#"{"events":[{"eventAction":"registration","eventDate":"2022-11-30T12:00:00Z"}]}"#;
+                        format!(
+                            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                            body.len(),
+                            body
+                        )
+                    }
+                };
+                stream.write_all(response.as_bytes()).await.unwrap();
+                stream.shutdown().await.unwrap();
+                requests.push(request.lines().next().unwrap().to_string());
+            }
+            requests
+        });
+
+        let mut cache_path = PathBuf::from(TEST_CACHE);
+        cache_path.push("test_rdap_cache.json");
+        fs::remove_file(&cache_path).ok();
+        let mut client = RdapClient::new(&url, cache_path.clone(), Duration::ZERO, 10);
+        let registered = client.registered("www.example.co.uk").await.unwrap();
+        assert_eq!(registered, Some(1669809600));
+        client.save().unwrap();
+        let requests = server.await.unwrap();
+        assert!(requests[1].starts_with("GET /domain/example.co.uk "));
+
+        // the cached results need no requests, not even a budget
+        let mut client = RdapClient::new(&url, cache_path, Duration::ZERO, 0);
+        assert_eq!(
+            client.registered("mail.example.co.uk").await.unwrap(),
+            Some(1669809600)
+        );
+    }
+}
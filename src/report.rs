@@ -0,0 +1,82 @@
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use chrono::{SecondsFormat, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+pub const REPORT_FILE_NAME: &str = "last_run.json";
+
+/// RunReport summarizes the result of a program run
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct RunReport {
+    /// unix timestamp of the beginning of the run
+    pub started: u64,
+    /// unix timestamp of the end of the run
+    pub finished: u64,
+    /// the directory containing the output files
+    pub output_dir: String,
+    /// output files written during the run
+    pub updated: Vec<String>,
+    /// output files left untouched because their sources didn't change
+    pub unchanged: Vec<String>,
+}
+
+impl RunReport {
+    /// Creates a new report for a run starting now
+    ///
+    /// * `output_dir`: the directory containing the output files
+    pub fn new(output_dir: &str) -> Self {
+        Self {
+            started: unix_timestamp(),
+            output_dir: output_dir.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// completes the report with the lists written during the run
+    ///
+    /// * `tags`: all category lists of the run
+    /// * `updated`: the category lists written during the run
+    pub fn finish(&mut self, tags: Vec<String>, updated: Vec<String>) {
+        self.finished = unix_timestamp();
+        self.unchanged = tags.into_iter().filter(|t| !updated.contains(t)).collect();
+        self.updated = updated;
+    }
+
+    /// writes the report to the given file
+    ///
+    /// * `report_path`: the file system path the report is written to
+    pub fn save(&self, report_path: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = report_path.parent() {
+            fs::create_dir_all(parent).with_context(|| "could not create report directory")?;
+        }
+        let mut report_file =
+            File::create(report_path).with_context(|| "could not create run report")?;
+        let report_str = serde_json::to_string(&self)?;
+        report_file.write_all(report_str.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// returns the seconds passed since the unix epoch
+pub fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// returns a unix timestamp in the format of RFC 3339
+///
+/// * `timestamp`: seconds since the unix epoch
+pub fn rfc3339(timestamp: u64) -> String {
+    Utc.timestamp_opt(timestamp as i64, 0)
+        .single()
+        .unwrap_or_default()
+        .to_rfc3339_opts(SecondsFormat::Secs, true)
+}
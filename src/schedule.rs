@@ -0,0 +1,297 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+use chrono::{Datelike, Local, NaiveDateTime, NaiveTime, TimeZone, Weekday};
+use serde::{Deserialize, Serialize};
+
+use crate::{filter_list::FilterList, output_settings::OutputSettings};
+
+/// Day is a day of the week a fetch window is open on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Day {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+}
+
+impl From<Weekday> for Day {
+    fn from(weekday: Weekday) -> Self {
+        match weekday {
+            Weekday::Mon => Day::Mon,
+            Weekday::Tue => Day::Tue,
+            Weekday::Wed => Day::Wed,
+            Weekday::Thu => Day::Thu,
+            Weekday::Fri => Day::Fri,
+            Weekday::Sat => Day::Sat,
+            Weekday::Sun => Day::Sun,
+        }
+    }
+}
+
+/// ClockTime is a local time of day written as `HH:MM`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ClockTime(NaiveTime);
+
+impl TryFrom<String> for ClockTime {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        NaiveTime::parse_from_str(&value, "%H:%M")
+            .map(ClockTime)
+            .map_err(|_| format!("invalid time {:?}, expected HH:MM", value))
+    }
+}
+
+impl From<ClockTime> for String {
+    fn from(time: ClockTime) -> Self {
+        time.0.format("%H:%M").to_string()
+    }
+}
+
+/// FetchWindow is a period of local time a list may be downloaded in, e.g. because
+/// the provider throttles or bills requests by the time of day, or an output blocks
+/// its domains in
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FetchWindow {
+    /// the days the window opens on, every day if empty
+    #[serde(default)]
+    pub days: Vec<Day>,
+    /// the time the window opens, defaults to midnight
+    pub start: Option<ClockTime>,
+    /// the time the window closes, defaults to the end of the day. A window closing
+    /// before it opens lasts until the next day.
+    pub end: Option<ClockTime>,
+}
+
+impl FetchWindow {
+    /// returns true if the window opens on the day of the date
+    ///
+    /// * `date`: a local date and time
+    fn opens_on(&self, date: NaiveDateTime) -> bool {
+        self.days.is_empty() || self.days.contains(&date.weekday().into())
+    }
+
+    /// returns the time the window opens
+    fn start(&self) -> NaiveTime {
+        self.start
+            .map_or(NaiveTime::from_hms_opt(0, 0, 0).unwrap(), |t| t.0)
+    }
+
+    /// returns true if the window is open at the time
+    ///
+    /// * `time`: a local date and time
+    pub fn contains(&self, time: NaiveDateTime) -> bool {
+        let (start, clock) = (self.start(), time.time());
+        let end = match self.end {
+            Some(end) => end.0,
+            None => return clock >= start && self.opens_on(time),
+        };
+        match start <= end {
+            true => clock >= start && clock < end && self.opens_on(time),
+            // the early hours belong to the window opened the day before
+            false => {
+                (clock >= start && self.opens_on(time))
+                    || (clock < end && self.opens_on(time - chrono::Duration::days(1)))
+            }
+        }
+    }
+
+    /// returns the next time after the given one the window opens
+    ///
+    /// * `time`: a local date and time
+    pub fn next_start(&self, time: NaiveDateTime) -> Option<NaiveDateTime> {
+        (0..=7)
+            .map(|days| (time.date() + chrono::Duration::days(days)).and_time(self.start()))
+            .find(|start| *start > time && self.opens_on(*start))
+    }
+
+    /// returns the next time after the given one the window closes
+    ///
+    /// * `time`: a local date and time
+    pub fn next_end(&self, time: NaiveDateTime) -> Option<NaiveDateTime> {
+        let start = self.start();
+        (0..=8).find_map(|days| {
+            let day = time.date() + chrono::Duration::days(days);
+            // the day the window closing on this day opened on
+            let (end, opened) = match self.end {
+                // a window without an end closes at midnight
+                None => (day.and_hms_opt(0, 0, 0)?, day - chrono::Duration::days(1)),
+                Some(end) if start <= end.0 => (day.and_time(end.0), day),
+                Some(end) => (day.and_time(end.0), day - chrono::Duration::days(1)),
+            };
+            (end > time && self.opens_on(opened.and_time(start))).then_some(end)
+        })
+    }
+}
+
+/// waits until the next fetch window of the lists opens and returns the ids of the
+/// lists whose window opened, never returns if no list has a fetch window
+///
+/// * `lists`: the configured lists
+pub async fn windows_opened(lists: &[FilterList]) -> Vec<String> {
+    let now = Local::now().naive_local();
+    let starts: Vec<(NaiveDateTime, &str)> = lists
+        .iter()
+        .flat_map(|l| {
+            l.fetch_windows
+                .iter()
+                .filter_map(move |w| w.next_start(now).map(|s| (s, l.id.as_str())))
+        })
+        .collect();
+    wait_for_next(starts, "fetch window opens").await
+}
+
+/// waits until the next block window of the outputs opens or closes and returns the
+/// tags of the outputs whose window changed, never returns if no output has a block
+/// window
+///
+/// * `outputs`: the settings of the outputs
+pub async fn block_windows_changed(outputs: &[OutputSettings]) -> Vec<String> {
+    let now = Local::now().naive_local();
+    let changes: Vec<(NaiveDateTime, &str)> = outputs
+        .iter()
+        .flat_map(|o| {
+            o.block_windows.iter().flat_map(move |w| {
+                [w.next_start(now), w.next_end(now)]
+                    .into_iter()
+                    .flatten()
+                    .map(move |t| (t, o.tag.as_str()))
+            })
+        })
+        .collect();
+    wait_for_next(changes, "block window changes").await
+}
+
+/// waits until the earliest of the times and returns the names belonging to it,
+/// never returns if there is no time
+///
+/// * `times`: the local times and the names of what happens at them
+/// * `event`: describes what happens for the debug log
+async fn wait_for_next(times: Vec<(NaiveDateTime, &str)>, event: &str) -> Vec<String> {
+    let next = match times.iter().map(|(t, _)| *t).min() {
+        Some(next) => next,
+        None => return std::future::pending().await,
+    };
+    // the local time may be skipped or repeated when daylight saving time changes
+    let wait = Local
+        .from_local_datetime(&next)
+        .earliest()
+        .and_then(|n| (n - Local::now()).to_std().ok())
+        .unwrap_or(Duration::from_secs(60));
+    debug!("next {} at {}", event, next);
+    tokio::time::sleep(wait).await;
+
+    let mut names = vec![];
+    for (_, name) in times.into_iter().filter(|(t, _)| *t == next) {
+        if !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+    }
+    names
+}
+
+/// returns a random duration of up to the given number of seconds, so instances
+/// sharing a configuration don't hit the sources all at once
+///
+/// * `max_secs`: the maximum number of seconds
+pub fn random_delay(max_secs: u64) -> Duration {
+    if max_secs == 0 {
+        return Duration::ZERO;
+    }
+    // the keys of a RandomState are random for every process
+    let random = RandomState::new().build_hasher().finish();
+    Duration::from_millis(random % (max_secs * 1000 + 1))
+}
+
+/// waits for a random time before a scheduled run
+///
+/// * `max_secs`: the maximum number of seconds, no delay if None
+pub async fn jitter(max_secs: Option<u64>) {
+    let delay = random_delay(max_secs.unwrap_or_default());
+    if !delay.is_zero() {
+        info!("Delaying the run by {:.1}s", delay.as_secs_f64());
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    /// returns the local time on a day of June 2024, the 3rd is a Monday
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 6, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    fn window(json: &str) -> FetchWindow {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_contains() {
+        let night = window(r#"{"start": "02:00", "end": "05:00"}"#);
+        assert!(night.contains(at(3, 2, 0)));
+        assert!(night.contains(at(3, 4, 59)));
+        assert!(!night.contains(at(3, 5, 0)));
+        assert!(!night.contains(at(3, 12, 0)));
+
+        let sunday = window(r#"{"days": ["Sun"]}"#);
+        assert!(sunday.contains(at(9, 23, 0)));
+        assert!(!sunday.contains(at(10, 0, 0)));
+
+        // Friday night until Saturday morning
+        let weekend = window(r#"{"days": ["Fri"], "start": "22:00", "end": "06:00"}"#);
+        assert!(weekend.contains(at(7, 23, 0)));
+        assert!(weekend.contains(at(8, 3, 0)));
+        assert!(!weekend.contains(at(7, 3, 0)));
+        assert!(!weekend.contains(at(8, 23, 0)));
+
+        assert!(serde_json::from_str::<FetchWindow>(r#"{"start": "2am"}"#).is_err());
+    }
+
+    #[test]
+    fn test_next_end() {
+        let work = window(r#"{"days": ["Mon"], "start": "09:00", "end": "17:00"}"#);
+        assert_eq!(work.next_end(at(3, 10, 0)), Some(at(3, 17, 0)));
+        assert_eq!(work.next_end(at(3, 17, 0)), Some(at(10, 17, 0)));
+
+        // Friday night until Saturday morning
+        let weekend = window(r#"{"days": ["Fri"], "start": "22:00", "end": "06:00"}"#);
+        assert_eq!(weekend.next_end(at(7, 23, 0)), Some(at(8, 6, 0)));
+
+        let sunday = window(r#"{"days": ["Sun"]}"#);
+        assert_eq!(sunday.next_end(at(9, 12, 0)), Some(at(10, 0, 0)));
+    }
+
+    #[test]
+    fn test_next_start() {
+        let night = window(r#"{"start": "02:00", "end": "05:00"}"#);
+        assert_eq!(night.next_start(at(3, 1, 0)), Some(at(3, 2, 0)));
+        assert_eq!(night.next_start(at(3, 2, 0)), Some(at(4, 2, 0)));
+
+        let sunday = window(r#"{"days": ["Sun"]}"#);
+        assert_eq!(sunday.next_start(at(3, 12, 0)), Some(at(9, 0, 0)));
+        assert_eq!(sunday.next_start(at(9, 12, 0)), Some(at(16, 0, 0)));
+    }
+
+    #[test]
+    fn test_random_delay() {
+        assert_eq!(random_delay(0), Duration::ZERO);
+        let delays: Vec<Duration> = (0..20).map(|_| random_delay(60)).collect();
+        assert!(delays.iter().all(|d| *d <= Duration::from_secs(60)));
+        assert!(delays.iter().any(|d| *d != delays[0]));
+    }
+}
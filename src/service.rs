@@ -0,0 +1,164 @@
+use std::{
+    ffi::OsString,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use tokio::sync::Notify;
+use windows_service::{
+    define_windows_service,
+    service::{
+        ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode,
+        ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+    },
+    service_control_handler::{self, ServiceControlHandlerResult},
+    service_dispatcher,
+    service_manager::{ServiceManager, ServiceManagerAccess},
+};
+
+use crate::{config::Config, telemetry, watch_mode, Args};
+
+/// name the service is registered with
+pub const SERVICE_NAME: &str = "harvester";
+/// name of the event log source the service logs to
+pub const EVENT_SOURCE: &str = "Harvester";
+const SERVICE_DISPLAY_NAME: &str = "Harvester block list builder";
+const SERVICE_TYPE: ServiceType = ServiceType::OWN_PROCESS;
+
+/// ServiceAction manages harvester as a Windows service
+#[derive(Subcommand, Debug)]
+pub enum ServiceAction {
+    /// register a service running harvester in watch mode with the given configuration
+    Install,
+    /// remove the registered service
+    Uninstall,
+    /// entry point called by the service control manager
+    Run,
+}
+
+/// registers the service and the event log source, the service is started on boot
+///
+/// * `config_path`: file system path of the configuration the service runs with
+pub fn install(config_path: &Path) -> anyhow::Result<()> {
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )
+    .with_context(|| "could not connect to the service manager")?;
+    let config_path = config_path
+        .canonicalize()
+        .with_context(|| format!("configuration {:?} not found", config_path))?;
+    let service_info = ServiceInfo {
+        name: OsString::from(SERVICE_NAME),
+        display_name: OsString::from(SERVICE_DISPLAY_NAME),
+        service_type: SERVICE_TYPE,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: std::env::current_exe()?,
+        launch_arguments: vec![
+            OsString::from("--config"),
+            config_path.into_os_string(),
+            OsString::from("service"),
+            OsString::from("run"),
+        ],
+        dependencies: vec![],
+        account_name: None,
+        account_password: None,
+    };
+    manager
+        .create_service(&service_info, ServiceAccess::QUERY_STATUS)
+        .with_context(|| format!("could not register service {}", SERVICE_NAME))?;
+    eventlog::register(EVENT_SOURCE)
+        .with_context(|| format!("could not register event source {}", EVENT_SOURCE))?;
+    info!("Service {} installed", SERVICE_NAME);
+    Ok(())
+}
+
+/// removes the service and the event log source
+pub fn uninstall() -> anyhow::Result<()> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)
+        .with_context(|| "could not connect to the service manager")?;
+    let service = manager
+        .open_service(SERVICE_NAME, ServiceAccess::DELETE)
+        .with_context(|| format!("service {} not found", SERVICE_NAME))?;
+    service.delete()?;
+    eventlog::deregister(EVENT_SOURCE)
+        .with_context(|| format!("could not remove event source {}", EVENT_SOURCE))?;
+    info!("Service {} uninstalled", SERVICE_NAME);
+    Ok(())
+}
+
+define_windows_service!(ffi_service_main, service_main);
+
+/// hands the process over to the service control manager, blocks until the service stopped
+pub fn run() -> anyhow::Result<()> {
+    service_dispatcher::start(SERVICE_NAME, ffi_service_main)
+        .with_context(|| "harvester service must be started by the service manager")?;
+    Ok(())
+}
+
+/// called by the service control manager on a thread of its own
+///
+/// * `_arguments`: start parameters passed by the service control manager
+fn service_main(_arguments: Vec<OsString>) {
+    if let Err(e) = run_service() {
+        error!("{:?}", e);
+    }
+}
+
+/// runs watch mode until the service control manager requests a stop
+fn run_service() -> anyhow::Result<()> {
+    // the launch arguments registered on install are passed to the process
+    let args = Args::parse();
+    let is_processing = Arc::new(AtomicBool::new(true));
+    let stop = Arc::new(Notify::new());
+
+    let is_proc = Arc::clone(&is_processing);
+    let stop_handler = Arc::clone(&stop);
+    let status_handle =
+        service_control_handler::register(SERVICE_NAME, move |control| match control {
+            ServiceControl::Stop | ServiceControl::Shutdown => {
+                info!("gracefully shutting down ...");
+                is_proc.store(false, Ordering::SeqCst);
+                stop_handler.notify_one();
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        })?;
+    let status = |state, exit_code| ServiceStatus {
+        service_type: SERVICE_TYPE,
+        current_state: state,
+        controls_accepted: match state {
+            ServiceState::Running => ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+            _ => ServiceControlAccept::empty(),
+        },
+        exit_code: ServiceExitCode::Win32(exit_code),
+        checkpoint: 0,
+        wait_hint: Duration::default(),
+        process_id: None,
+    };
+    status_handle.set_service_status(status(ServiceState::Running, 0))?;
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    let result = runtime.block_on(async {
+        let config = Config::load(Path::new(&args.config))
+            .with_context(|| format!("{}: invalid configuration", args.config))?;
+        if let Some(settings) = &config.telemetry {
+            telemetry::init(settings)?;
+        }
+        let result = watch_mode(&args.config, config, is_processing, stop).await;
+        telemetry::shutdown().await;
+        result
+    });
+
+    let exit_code = if result.is_ok() { 0 } else { 1 };
+    status_handle.set_service_status(status(ServiceState::Stopped, exit_code))?;
+    result
+}
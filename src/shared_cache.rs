@@ -0,0 +1,390 @@
+use std::{
+    collections::HashSet,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+
+/// seconds a download is used by other instances if no maximum age was configured
+pub const DEFAULT_MAX_AGE: u64 = 3600;
+/// seconds a lock is held at most if no timeout was configured
+pub const DEFAULT_LOCK_TIMEOUT: u64 = 900;
+/// sub directory of the shared cache the lists are downloaded to before being moved
+/// into place, so other instances never read a partial download
+pub const PARTIAL_PATH: &str = ".partial";
+/// time between two checks of a lock held by another instance
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// number of times a held lock is touched within the lock timeout
+const TOUCHES_PER_TIMEOUT: u32 = 4;
+/// minimum time between two touches of a held lock
+const MIN_TOUCH_INTERVAL: Duration = Duration::from_millis(10);
+
+/// SharedCacheSettings configure a download directory on shared storage, e.g. an NFS
+/// mount, used by a fleet of instances which download each list only once
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SharedCacheSettings {
+    /// the directory the lists are downloaded to
+    pub dir: String,
+    /// seconds a download is used by other instances before it's downloaded again
+    pub max_age: Option<u64>,
+    /// seconds after which the lock of an instance which didn't finish is taken over
+    pub lock_timeout: Option<u64>,
+}
+
+impl SharedCacheSettings {
+    /// returns the configured maximum age of a download or the default one
+    pub fn max_age(&self) -> Duration {
+        Duration::from_secs(self.max_age.unwrap_or(DEFAULT_MAX_AGE))
+    }
+
+    /// returns the configured lock timeout or the default one
+    pub fn lock_timeout(&self) -> Duration {
+        Duration::from_secs(self.lock_timeout.unwrap_or(DEFAULT_LOCK_TIMEOUT))
+    }
+}
+
+/// DownloadLock is held by the instance downloading a list into the shared cache. The
+/// lock is touched periodically while being held, so a long download isn't taken for
+/// a stale lock, and released when being dropped.
+#[derive(Debug)]
+pub struct DownloadLock {
+    path: PathBuf,
+    /// the content of the lock identifying this holder
+    holder: String,
+    /// the task touching the lock
+    heartbeat: JoinHandle<()>,
+}
+
+impl DownloadLock {
+    /// takes the lock of a list and returns it, None if another instance holds it. A
+    /// lock which wasn't touched within the timeout was left over by an instance which
+    /// quit and is taken over.
+    ///
+    /// * `dir`: the shared download directory
+    /// * `id`: the id of the list
+    /// * `timeout`: the time after which a lock which wasn't touched is stale
+    pub fn try_acquire(dir: &Path, id: &str, timeout: Duration) -> anyhow::Result<Option<Self>> {
+        fs::create_dir_all(dir).with_context(|| format!("could not create {:?}", dir))?;
+        let path = lock_path(dir, id);
+        // the holder is written for the operator's information and identifies the lock
+        // of this instance, the nanoseconds tell apart instances with the same pid
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let holder = format!(
+            "{} {}.{:09}\n",
+            std::process::id(),
+            now.as_secs(),
+            now.subsec_nanos()
+        );
+        for _ in 0..2 {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    file.write_all(holder.as_bytes())
+                        .with_context(|| format!("could not write lock {:?}", path))?;
+                    let interval = (timeout / TOUCHES_PER_TIMEOUT).max(MIN_TOUCH_INTERVAL);
+                    let heartbeat =
+                        tokio::spawn(keep_touched(path.clone(), holder.clone(), interval));
+                    return Ok(Some(Self {
+                        path,
+                        holder,
+                        heartbeat,
+                    }));
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    if !is_older(&path, timeout) {
+                        return Ok(None);
+                    }
+                    // the stale lock is moved aside before a new one is created, of
+                    // several instances taking it over only one can move it
+                    let stale = dir.join(format!(".{}.lock.{}", id, holder.trim_end()));
+                    if fs::rename(&path, &stale).is_err() {
+                        continue;
+                    }
+                    if !is_older(&stale, timeout) {
+                        // another instance took the lock over in the meantime and its
+                        // lock is put back unless a third one created a lock already
+                        fs::hard_link(&stale, &path).ok();
+                        fs::remove_file(&stale).ok();
+                        return Ok(None);
+                    }
+                    warn!("taking over the stale lock {:?}", path);
+                    fs::remove_file(&stale).ok();
+                }
+                Err(e) => {
+                    return Err(e).with_context(|| format!("could not create lock {:?}", path))
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Drop for DownloadLock {
+    fn drop(&mut self) {
+        self.heartbeat.abort();
+        // a lock taken over by another instance isn't removed
+        if !is_holder(&self.path, &self.holder) {
+            warn!(
+                "the lock {:?} was taken over by another instance",
+                self.path
+            );
+            return;
+        }
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!("could not release the lock {:?}: {}", self.path, e);
+        }
+    }
+}
+
+/// returns true if the lock was created by the holder
+///
+/// * `path`: the file system path of the lock
+/// * `holder`: the content of the lock identifying its holder
+fn is_holder(path: &Path, holder: &str) -> bool {
+    fs::read_to_string(path).is_ok_and(|content| content == holder)
+}
+
+/// touches a held lock periodically until the task is aborted or the lock was taken
+/// over by another instance
+///
+/// * `path`: the file system path of the lock
+/// * `holder`: the content of the lock identifying its holder
+/// * `interval`: the time between two touches
+async fn keep_touched(path: PathBuf, holder: String, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    // the first tick completes immediately, the lock was just created
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        if !is_holder(&path, &holder) {
+            warn!("the lock {:?} was taken over by another instance", path);
+            return;
+        }
+        let touched = OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(SystemTime::now()));
+        if let Err(e) = touched {
+            warn!("could not touch the lock {:?}: {}", path, e);
+        }
+    }
+}
+
+/// returns the file system path of the lock of a list
+///
+/// * `dir`: the shared download directory
+/// * `id`: the id of the list
+fn lock_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!(".{}.lock", id))
+}
+
+/// returns true if the file doesn't exist or was modified longer ago than the age, a
+/// modification in the future due to skewed clocks counts as recent
+///
+/// * `path`: the file system path of the file
+/// * `age`: the maximum age
+pub fn is_older(path: &Path, age: Duration) -> bool {
+    match fs::metadata(path).and_then(|m| m.modified()) {
+        Ok(modified) => modified.elapsed().is_ok_and(|elapsed| elapsed >= age),
+        Err(_) => true,
+    }
+}
+
+/// SharedDownloads tracks the lists of a run in the shared cache
+#[derive(Debug, Default)]
+pub struct SharedDownloads {
+    /// the lists downloaded by this instance and their locks
+    pub locked: Vec<(String, DownloadLock)>,
+    /// the lists being downloaded by other instances
+    pub waiting: Vec<String>,
+}
+
+impl SharedDownloads {
+    /// moves the complete downloads of this instance into place, removes the failed
+    /// ones, releases their locks and waits for the downloads of other instances
+    ///
+    /// * `dir`: the shared download directory
+    /// * `complete`: the ids of the lists downloaded completely
+    /// * `timeout`: the time a lock is held at most
+    /// * `is_processing`: determines if the program was interrupted
+    pub async fn finish(
+        self,
+        dir: &Path,
+        complete: &HashSet<String>,
+        timeout: Duration,
+        is_processing: Arc<AtomicBool>,
+    ) -> anyhow::Result<()> {
+        let partial_dir = dir.join(PARTIAL_PATH);
+        for (id, lock) in self.locked {
+            // unchanged lists weren't downloaded again
+            let partial = partial_dir.join(&id);
+            if partial.is_file() {
+                // a truncated download must not be taken for a fresh one by others
+                match complete.contains(&id) {
+                    true => fs::rename(&partial, dir.join(&id))
+                        .with_context(|| format!("could not move {:?} into place", partial))?,
+                    false => {
+                        warn!("{}: download failed, the shared one is kept", id);
+                        fs::remove_file(&partial)
+                            .with_context(|| format!("could not remove {:?}", partial))?;
+                    }
+                }
+            }
+            drop(lock);
+        }
+        for id in wait_for_locks(dir, &self.waiting, timeout, is_processing).await {
+            warn!("{}: download of another instance timed out", id);
+        }
+        Ok(())
+    }
+}
+
+/// waits until the lists locked by other instances are downloaded, returns the ids of
+/// the lists whose lock timed out
+///
+/// * `dir`: the shared download directory
+/// * `ids`: the ids of the lists
+/// * `timeout`: the time a lock is held at most
+/// * `is_processing`: determines if the program was interrupted
+pub async fn wait_for_locks(
+    dir: &Path,
+    ids: &[String],
+    timeout: Duration,
+    is_processing: Arc<AtomicBool>,
+) -> Vec<String> {
+    let mut timed_out = vec![];
+    for id in ids {
+        let path = lock_path(dir, id);
+        while path.exists() && is_processing.load(Ordering::SeqCst) {
+            if is_older(&path, timeout) {
+                timed_out.push(id.clone());
+                break;
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+    timed_out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::helper::cache_file_creator::TEST_CACHE;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_download_lock() {
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_download_lock");
+        fs::remove_dir_all(&dir).ok();
+        let timeout = Duration::from_secs(60);
+
+        let lock = DownloadLock::try_acquire(&dir, "list", timeout).unwrap();
+        assert!(lock.is_some());
+        // another instance has to wait for the download
+        assert!(DownloadLock::try_acquire(&dir, "list", timeout)
+            .unwrap()
+            .is_none());
+        assert!(DownloadLock::try_acquire(&dir, "other", timeout)
+            .unwrap()
+            .is_some());
+        drop(lock);
+        assert!(!lock_path(&dir, "list").exists());
+
+        // a stale lock is taken over and not released by its former holder
+        let stale = DownloadLock::try_acquire(&dir, "list", timeout)
+            .unwrap()
+            .unwrap();
+        stale.heartbeat.abort();
+        OpenOptions::new()
+            .write(true)
+            .open(lock_path(&dir, "list"))
+            .unwrap()
+            .set_modified(SystemTime::now() - timeout * 2)
+            .unwrap();
+        let lock = DownloadLock::try_acquire(&dir, "list", timeout).unwrap();
+        assert!(lock.is_some());
+        drop(stale);
+        assert!(lock_path(&dir, "list").exists());
+        // the stale lock moved aside was removed
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_download_lock_touched() {
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_download_lock_touched");
+        fs::remove_dir_all(&dir).ok();
+        let timeout = Duration::from_millis(200);
+
+        // a lock held longer than the timeout isn't stale
+        let lock = DownloadLock::try_acquire(&dir, "list", timeout).unwrap();
+        assert!(lock.is_some());
+        tokio::time::sleep(timeout * 3).await;
+        assert!(DownloadLock::try_acquire(&dir, "list", timeout)
+            .unwrap()
+            .is_none());
+        drop(lock);
+        assert!(!lock_path(&dir, "list").exists());
+    }
+
+    #[tokio::test]
+    async fn test_shared_downloads_finish() {
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_shared_downloads_finish");
+        fs::remove_dir_all(&dir).ok();
+        let timeout = Duration::from_secs(60);
+        fs::create_dir_all(dir.join(PARTIAL_PATH)).unwrap();
+        let mut downloads = SharedDownloads::default();
+        for id in ["complete", "failed"] {
+            fs::write(dir.join(id), "old").unwrap();
+            fs::write(dir.join(PARTIAL_PATH).join(id), "new").unwrap();
+            let lock = DownloadLock::try_acquire(&dir, id, timeout)
+                .unwrap()
+                .unwrap();
+            downloads.locked.push((id.to_string(), lock));
+        }
+
+        // only a complete download replaces the shared one
+        let complete = HashSet::from(["complete".to_string()]);
+        let is_processing = Arc::new(AtomicBool::new(true));
+        downloads
+            .finish(&dir, &complete, timeout, is_processing)
+            .await
+            .unwrap();
+        assert_eq!(fs::read_to_string(dir.join("complete")).unwrap(), "new");
+        assert_eq!(fs::read_to_string(dir.join("failed")).unwrap(), "old");
+        assert_eq!(fs::read_dir(dir.join(PARTIAL_PATH)).unwrap().count(), 0);
+        assert!(!lock_path(&dir, "failed").exists());
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_locks() {
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_wait_for_locks");
+        fs::remove_dir_all(&dir).ok();
+        let is_processing = Arc::new(AtomicBool::new(true));
+        let lock = DownloadLock::try_acquire(&dir, "list", Duration::from_secs(60))
+            .unwrap()
+            .unwrap();
+        let release = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            drop(lock);
+        });
+        let ids = vec!["list".to_string(), "unlocked".to_string()];
+        let timed_out = wait_for_locks(&dir, &ids, Duration::from_secs(60), is_processing).await;
+        assert!(timed_out.is_empty());
+        assert!(!lock_path(&dir, "list").exists());
+        release.await.unwrap();
+    }
+}
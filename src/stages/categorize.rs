@@ -1,7 +1,6 @@
 use std::{
-    collections::{BTreeSet, HashSet},
+    collections::HashSet,
     fs::{self, File},
-    io::Write,
     marker::PhantomData,
     path::{Path, PathBuf},
     str::FromStr,
@@ -15,12 +14,22 @@ use tokio::task::JoinHandle;
 use crate::{
     filter_controller::{FilterController, StageCategorize, StageOutput},
     input::{file::FileInput, Input},
-    io::{category_list_io::CategoryListIO, filter_list_io::FilterListIO},
+    io::{
+        category_list_io::CategoryListIO,
+        entry_store::{Entry, EntryStore, EntryStoreWriter},
+        filter_list_io::FilterListIO,
+        seen_file::SeenFile,
+        spool::{Spool, DEFAULT_SPOOL_THRESHOLD},
+    },
+    memory::MemoryBudget,
+    output_settings::Exclusions,
+    report::unix_timestamp,
+    ENTRIES_PATH, SPOOL_PATH,
 };
 
 /// This stage assembles the category lists from the data extracted in the previous stage
 /// A category corresponds to a tag on a list.
-impl<'config> FilterController<'config, StageCategorize, FileInput, File> {
+impl FilterController<'_, StageCategorize, FileInput, File> {
     /// runs the categorize stage and return controller for the output stage
     ///
     /// * `extract_base_path`: The source path containing the URL lists
@@ -35,7 +44,10 @@ impl<'config> FilterController<'config, StageCategorize, FileInput, File> {
         let mut categorize_path = PathBuf::from_str(&self.config.cache_dir)?;
         categorize_path.push(categorize_base_path);
 
-        self.prepare_categorize(&extract_path, &categorize_path)?;
+        let mut entries_path = PathBuf::from_str(&self.config.cache_dir)?;
+        entries_path.push(self.config.stage_path(ENTRIES_PATH));
+
+        self.prepare_categorize(&extract_path, &entries_path, &categorize_path)?;
         self.categorize(categorize_path).await?;
         let output_controller = FilterController::<StageOutput, FileInput, File> {
             stage: PhantomData,
@@ -51,10 +63,12 @@ impl<'config> FilterController<'config, StageCategorize, FileInput, File> {
     /// Attaches the source file reader to the FilterListIO
     ///
     /// * `extract_path`: The directory where the extracted data from the previous stage was stored
+    /// * `entries_path`: The directory where the normalized entries of the lists are stored
     /// * `categorize_path`: The directory wehre the results of this stage will be stored
     fn prepare_categorize(
         &mut self,
         extract_path: &Path,
+        entries_path: &Path,
         categorize_path: &Path,
     ) -> anyhow::Result<()> {
         // prepare category lists for writing
@@ -82,21 +96,32 @@ impl<'config> FilterController<'config, StageCategorize, FileInput, File> {
                 // length no list has been removed since the last run
                 if let Some(cached_config) = &self.config.cached_config
                     && self.config.lists_with_tag(tag).len() == cached_config.lists_with_tag(tag).len()
+                    // changed output settings may exclude other domains
+                    && self.config.output_settings(tag) == cached_config.output_settings(tag)
+                    // changed weights may lift or drop domains across the minimum score
+                    && self.config.list_weights(tag) == cached_config.list_weights(tag)
+                    // the window of an accumulating output moves on with every run
+                    && self.config.output_settings(tag).is_none_or(|s| s.accumulate_days.is_none())
+                    // a scheduled output follows the time of day
+                    && self.config.output_settings(tag).is_none_or(|s| s.block_windows.is_empty())
                     // if there is no difference between cached lists and included lists there is no need for action
                     && difference.is_empty()
                     // check if there was actually a file written on the last run
                     && category_list.attach_existing_file_writer(categorize_path).is_ok()
+                    // the output adapters read the complete entry store
+                    && EntryStore::open(categorize_path, tag).is_ok()
                 {
                     self.cached_lists.as_mut().unwrap().insert(tag.clone());
                     category_list.writer = None;
-                    info!("Unchanged: {}", tag.to_string());
+                    info!("Unchanged: {}", tag);
                     return Ok(());
                 }
 
                 category_list.attach_new_file_writer(categorize_path)?;
                 category_list.included_filter_lists = included_lists.into_iter().filter_map(|flist| {
                     let mut flist_io = FilterListIO::new(flist.to_owned());
-                    if let Err(e) = flist_io.attach_existing_input_file(extract_path, None) {
+                    // the entry file can be read again by any pass without touching the input
+                    if let Err(e) = flist_io.attach_entry_file_reader(extract_path, entries_path) {
                         error!("Error: {} - {}", flist_io.filter_list.id, e);
                         return None;
                     }
@@ -115,19 +140,74 @@ impl<'config> FilterController<'config, StageCategorize, FileInput, File> {
     /// * `categorize_path`: the file system path where the resulting lists are stored
     async fn categorize(&mut self, categorize_path: PathBuf) -> anyhow::Result<()> {
         fs::create_dir_all(&categorize_path).with_context(|| "could not create out directory")?;
+        let mut spool_path = PathBuf::from_str(&self.config.cache_dir)?;
+        spool_path.push(self.config.stage_path(SPOOL_PATH));
+        let spool_threshold = self
+            .config
+            .spool_threshold
+            .unwrap_or(DEFAULT_SPOOL_THRESHOLD);
+        // the spools of all categories share the budget, including the ones still written
+        let budget = self.config.memory_budget.map(MemoryBudget::new);
+        let batch_size = self.config.batch_size();
         let mut handles: Vec<JoinHandle<()>> = vec![];
         for category_list in self.category_lists.iter_mut() {
             if !self.is_processing.load(Ordering::SeqCst) {
                 return Ok(());
             }
 
-            // QUESTION: is there a better data structure to enable concurrent access?
-            let mut tree_set: BTreeSet<String> = BTreeSet::new();
+            // the spool keeps the lines sorted and moves them to disk if they exceed the threshold
+            let mut spool = Spool::new(&spool_path, &category_list.name, spool_threshold)
+                .with_budget(budget.clone());
+            let settings = self.config.output_settings(&category_list.name);
+            let exclusions = match settings {
+                Some(settings) => settings.exclusions()?,
+                None => Exclusions::default(),
+            };
+            let min_score = settings.and_then(|s| s.min_score);
+            let mut excluded = 0;
+            // the domains which left the lists within the window of an accumulating output
+            let accumulated: Vec<String> = match settings.and_then(|s| s.accumulation_window()) {
+                Some(window) => {
+                    let now = unix_timestamp();
+                    SeenFile::new(&self.config.seen_dir(), &category_list.name)
+                        .read()?
+                        .into_iter()
+                        .filter(|(domain, seen)| {
+                            now.saturating_sub(seen.last) <= window
+                                && !exclusions.is_excluded(domain)
+                        })
+                        .map(|(domain, _)| domain)
+                        .collect()
+                }
+                None => vec![],
+            };
 
             info!("Updated: {}", category_list.name);
 
-            // read lines from the included list and insert them into a tree set to remove duplicates
-            for filter_list_io in category_list.included_filter_lists.iter_mut() {
+            // the entries refer to their list by its position within the sources
+            let sources: Vec<String> = category_list
+                .included_filter_lists
+                .iter()
+                .map(|l| l.filter_list.id.clone())
+                .collect();
+
+            // read lines from the included list and insert them into the spool to remove duplicates
+            for (source, filter_list_io) in
+                category_list.included_filter_lists.iter_mut().enumerate()
+            {
+                let source = u16::try_from(source).with_context(|| {
+                    format!("category {} includes too many lists", category_list.name)
+                })?;
+                // a list counts once towards the score of a domain, so its duplicates
+                // are removed before the entries are merged with the other lists
+                let mut list_spool = min_score.map(|_| {
+                    let name = format!("{}.{}", category_list.name, source);
+                    Spool::new(&spool_path, &name, spool_threshold).with_budget(budget.clone())
+                });
+                let score = match min_score {
+                    Some(_) => filter_list_io.filter_list.weight(),
+                    None => 0,
+                };
                 let flist = match filter_list_io.reader.as_mut() {
                     Some(l) => l,
                     None => {
@@ -138,37 +218,114 @@ impl<'config> FilterController<'config, StageCategorize, FileInput, File> {
                         continue;
                     }
                 };
-                while let Ok(Some(chunk)) = flist.lock().await.chunk().await {
-                    // insert the URLs into a BTreeSet to deduplicate and sort the data
-                    let str_chunk = match String::from_utf8(chunk) {
-                        Ok(s) => s.trim().to_string(),
-                        Err(e) => {
-                            warn!("{}", e);
+                let mut batch = Vec::with_capacity(batch_size);
+                let mut exhausted = false;
+                while !exhausted {
+                    // the reader is locked once per batch instead of per chunk
+                    {
+                        let mut reader = flist.lock().await;
+                        while batch.len() < batch_size {
+                            match reader.chunk().await {
+                                Ok(Some(chunk)) => batch.push(chunk),
+                                Ok(None) => {
+                                    exhausted = true;
+                                    break;
+                                }
+                                Err(e) => {
+                                    error!("Error: {} - {}", filter_list_io.filter_list.id, e);
+                                    exhausted = true;
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    for chunk in batch.drain(..) {
+                        // insert the URLs into the spool to deduplicate and sort the data
+                        let str_chunk = match std::str::from_utf8(&chunk) {
+                            Ok(s) => s.trim().to_string(),
+                            Err(e) => {
+                                warn!("{}", e);
+                                continue;
+                            }
+                        };
+                        if str_chunk.is_empty() {
                             continue;
                         }
-                    };
-                    if str_chunk.is_empty() {
-                        continue;
+                        if exclusions.is_excluded(&str_chunk) {
+                            excluded += 1;
+                            continue;
+                        }
+                        let entry = Entry {
+                            domain: str_chunk,
+                            source,
+                            flags: 0,
+                            score,
+                        };
+                        match list_spool.as_mut() {
+                            Some(list_spool) => list_spool.insert_entry(entry)?,
+                            None => spool.insert_entry(entry)?,
+                        }
                     }
-                    tree_set.insert(str_chunk);
+                }
+                if let Some(list_spool) = list_spool {
+                    list_spool.drain(|entry| spool.insert_entry(entry))?;
                 }
             }
+            if excluded > 0 {
+                debug!("{}: {} lines excluded", category_list.name, excluded);
+            }
 
             let writer = category_list.writer.take().unwrap();
+            let store_path = categorize_path.clone();
+            let name = category_list.name.clone();
             let handle = tokio::spawn(async move {
-                for mut line in tree_set {
-                    if !line.ends_with('\n') {
-                        line.push('\n');
-                    }
-                    if let Err(e) = writer.lock().await.write_all(line.as_bytes()) {
-                        error!("{:?}", e);
-                        break;
-                    }
+                let mut writer = writer.lock().await;
+                let mut below_score = 0;
+                let mut accumulated = accumulated.into_iter().peekable();
+                let result = EntryStoreWriter::create(&store_path, &name, sources, &mut *writer)
+                    .and_then(|mut store| {
+                        spool.drain(|entry| {
+                            // the accumulated domains are merged in sort order, domains
+                            // which are still listed keep their origin
+                            let mut is_accumulated = false;
+                            while let Some(domain) = accumulated.next_if(|d| *d <= entry.domain) {
+                                match domain == entry.domain {
+                                    true => is_accumulated = true,
+                                    false => store.push(&Entry::accumulated(domain))?,
+                                }
+                            }
+                            // domains found in too few lists are left out of a scored output
+                            if entry.score < min_score.unwrap_or_default() {
+                                below_score += 1;
+                                if is_accumulated {
+                                    return store.push(&Entry::accumulated(entry.domain));
+                                }
+                                return Ok(());
+                            }
+                            store.push(&entry)
+                        })?;
+                        for domain in accumulated {
+                            store.push(&Entry::accumulated(domain))?;
+                        }
+                        store.finish()
+                    });
+                if below_score > 0 {
+                    debug!("{}: {} domains below the minimum score", name, below_score);
+                }
+                if let Err(e) = result {
+                    error!("{:?}", e);
                 }
             });
             handles.push(handle);
         }
         join_all(handles).await;
+        if let Some(budget) = budget {
+            debug!(
+                "spools held at most {} of {} bytes in memory",
+                budget.peak(),
+                budget.limit()
+            );
+        }
         Ok(())
     }
 }
@@ -177,12 +334,13 @@ impl<'config> FilterController<'config, StageCategorize, FileInput, File> {
 mod tests {
 
     use std::{
-        collections::HashMap,
+        collections::{BTreeSet, HashMap},
         sync::{atomic::AtomicBool, Arc},
     };
 
     use crate::{
-        filter_list::FilterList, tests::helper::cache_file_creator::CacheFileCreator,
+        filter_list::FilterList, io::entry_store::FLAG_ACCUMULATED,
+        output_settings::OutputSettings, tests::helper::cache_file_creator::CacheFileCreator,
         CATEGORIZE_PATH, EXTRACT_PATH,
     };
 
@@ -203,6 +361,7 @@ mod tests {
                 source: "".to_string(),
                 tags: vec!["advertising".to_string()],
                 regex: r"(.*)".to_string(),
+                ..Default::default()
             },
             FilterList {
                 id: "malware".to_string(),
@@ -211,6 +370,7 @@ mod tests {
                 source: "".to_string(),
                 tags: vec!["malware".to_string()],
                 regex: r"(.*)".to_string(),
+                ..Default::default()
             },
             FilterList {
                 id: "advertising_malware".to_string(),
@@ -219,16 +379,17 @@ mod tests {
                 source: "".to_string(),
                 tags: vec!["malware".to_string(), "advertising".to_string()],
                 regex: r"(.*)".to_string(),
+                ..Default::default()
             },
         ];
         // the contents of each filter list
-        let contents = vec![
-            vec!["one.domain", "another.domain"].join("\n"),
-            vec!["third.domain", "fourth.domain"].join("\n"),
-            vec!["fith.domain", "sixth.domain"].join("\n"),
+        let contents = [
+            ["one.domain", "another.domain"].join("\n"),
+            ["third.domain", "fourth.domain"].join("\n"),
+            ["fith.domain", "sixth.domain"].join("\n"),
         ];
-        for i in 0..=2 {
-            cache.write_input(&config.lists[i].id, &contents[i]);
+        for (list, content) in config.lists.iter().zip(contents.iter()) {
+            cache.write_input(&list.id, content);
         }
 
         let mut categorize_controller = FilterController::<StageCategorize, FileInput, File> {
@@ -247,13 +408,13 @@ mod tests {
         }
 
         // the advertising list is expected to have the contents from list 0 and 2
-        let mut advertising = vec![
+        let mut advertising = [
             contents[0].split("\n").collect::<Vec<&str>>(),
             contents[2].split("\n").collect::<Vec<&str>>(),
         ]
         .concat();
         // the malware list is expected to have the contents from list 1 and 2
-        let mut malware = vec![
+        let mut malware = [
             contents[1].split("\n").collect::<Vec<&str>>(),
             contents[2].split("\n").collect::<Vec<&str>>(),
         ]
@@ -265,10 +426,138 @@ mod tests {
             ("malware", malware.join("\n") + "\n"),
         ]);
         // read from files written and compare results for each category list
-        for category in vec!["advertising", "malware"] {
-            let got = cache.read_result(&category).unwrap();
+        for category in ["advertising", "malware"] {
+            let got = cache.read_result(category).unwrap();
             let want = want.get(&category).unwrap();
             assert_eq!(want, &got);
         }
     }
+
+    #[tokio::test]
+    async fn test_categorize_scored() {
+        let cache = CacheFileCreator::new("test_categorize_scored", EXTRACT_PATH, CATEGORIZE_PATH);
+        let mut config = cache.new_test_config();
+        let tags = vec!["strict".to_string(), "permissive".to_string()];
+        config.lists = vec![
+            FilterList {
+                id: "trusted".to_string(),
+                tags: tags.clone(),
+                regex: r"(.*)".to_string(),
+                weight: Some(2),
+                ..Default::default()
+            },
+            FilterList {
+                id: "noisy".to_string(),
+                tags: tags.clone(),
+                regex: r"(.*)".to_string(),
+                ..Default::default()
+            },
+            FilterList {
+                id: "other".to_string(),
+                tags,
+                regex: r"(.*)".to_string(),
+                ..Default::default()
+            },
+        ];
+        config.outputs = vec![
+            OutputSettings {
+                tag: "strict".to_string(),
+                min_score: Some(3),
+                ..Default::default()
+            },
+            OutputSettings {
+                tag: "permissive".to_string(),
+                min_score: Some(1),
+                ..Default::default()
+            },
+        ];
+        cache.write_input("trusted", "one.domain\ntwo.domain");
+        // a domain listed twice by the same list counts once
+        cache.write_input("noisy", "two.domain\nthree.domain\nthree.domain");
+        cache.write_input("other", "three.domain");
+
+        let mut categorize_controller = FilterController::<StageCategorize, FileInput, File> {
+            stage: PhantomData,
+            cached_lists: Some(HashSet::new()),
+            config: &config,
+            filter_lists: vec![],
+            category_lists: vec![],
+            is_processing: Arc::new(AtomicBool::new(true)),
+        };
+        categorize_controller
+            .run(&cache.inpath, &cache.outpath)
+            .await
+            .unwrap();
+
+        assert_eq!(cache.read_result("strict").unwrap(), "two.domain\n");
+        assert_eq!(
+            cache.read_result("permissive").unwrap(),
+            "one.domain\nthree.domain\ntwo.domain\n"
+        );
+        let store =
+            EntryStore::open(&Path::new(&config.cache_dir).join(&cache.outpath), "strict").unwrap();
+        let entries: Vec<Entry> = store.entries().unwrap().map(|e| e.unwrap()).collect();
+        assert_eq!(entries[0].score, 3);
+    }
+
+    #[tokio::test]
+    async fn test_categorize_accumulated() {
+        let cache =
+            CacheFileCreator::new("test_categorize_accumulated", EXTRACT_PATH, CATEGORIZE_PATH);
+        let mut config = cache.new_test_config();
+        config.lists = vec![FilterList {
+            id: "malware".to_string(),
+            tags: vec!["malware".to_string()],
+            regex: r"(.*)".to_string(),
+            ..Default::default()
+        }];
+        config.outputs = vec![OutputSettings {
+            tag: "malware".to_string(),
+            accumulate_days: Some(7),
+            ..Default::default()
+        }];
+        cache.write_input("malware", "listed.domain");
+
+        // one domain left the list a day ago, the other a month ago
+        let day = 24 * 60 * 60;
+        let now = unix_timestamp();
+        fs::remove_dir_all(config.seen_dir()).ok();
+        let seen_file = SeenFile::new(&config.seen_dir(), "malware");
+        let entries = |e: &[&str]| e.iter().map(|s| s.to_string()).collect::<BTreeSet<_>>();
+        seen_file
+            .update(&entries(&["old.domain"]), now - 30 * day, None)
+            .unwrap();
+        seen_file
+            .update(
+                &entries(&["listed.domain", "recent.domain"]),
+                now - day,
+                None,
+            )
+            .unwrap();
+
+        let mut categorize_controller = FilterController::<StageCategorize, FileInput, File> {
+            stage: PhantomData,
+            cached_lists: Some(HashSet::new()),
+            config: &config,
+            filter_lists: vec![],
+            category_lists: vec![],
+            is_processing: Arc::new(AtomicBool::new(true)),
+        };
+        categorize_controller
+            .run(&cache.inpath, &cache.outpath)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            cache.read_result("malware").unwrap(),
+            "listed.domain\nrecent.domain\n"
+        );
+        let store = EntryStore::open(
+            &Path::new(&config.cache_dir).join(&cache.outpath),
+            "malware",
+        )
+        .unwrap();
+        let flags: Vec<u8> = store.entries().unwrap().map(|e| e.unwrap().flags).collect();
+        assert_eq!(flags, vec![0, FLAG_ACCUMULATED]);
+    }
 }
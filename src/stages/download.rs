@@ -1,27 +1,31 @@
 use std::{
     collections::HashSet,
-    fs::File,
+    fs::{self, File},
     marker::PhantomData,
     path::PathBuf,
-    str::FromStr,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
 };
 
+use anyhow::Context;
+use chrono::Local;
 use futures::future::join_all;
 
 use crate::{
     config::Config,
     filter_controller::{process, FilterController, StageDownload, StageExtract},
-    input::{file::FileInput, url::UrlInput},
+    input::{file::FileInput, source::SourceInput, url::HttpClients},
     io::filter_list_io::FilterListIO,
+    memory::{concurrency_limit, MemoryBudget},
+    schedule::random_delay,
+    shared_cache::{is_older, DownloadLock, SharedDownloads, PARTIAL_PATH},
 };
 
-/// This implementation for UrlInput and File is the first phase where the lists
+/// This implementation for SourceInput and File is the first phase where the lists
 /// are downloaded.
-impl<'config> FilterController<'config, StageDownload, UrlInput, File> {
+impl<'config> FilterController<'config, StageDownload, SourceInput, File> {
     pub fn new(config: &'config Config, is_processing: Arc<AtomicBool>) -> Self {
         Self {
             stage: PhantomData,
@@ -33,7 +37,7 @@ impl<'config> FilterController<'config, StageDownload, UrlInput, File> {
         }
     }
 
-    /// Runs the data processing function with UrlInput as input source and a
+    /// Runs the data processing function with SourceInput as input source and a
     /// file as output destination. Returns the controller for the extract stage
     ///
     /// * `download_base_path`: target path for files being downloaded
@@ -41,11 +45,20 @@ impl<'config> FilterController<'config, StageDownload, UrlInput, File> {
         &mut self,
         download_base_path: &str,
     ) -> anyhow::Result<FilterController<StageExtract, FileInput, File>> {
-        let mut download_path = PathBuf::from_str(&self.config.cache_dir)?;
-        download_path.push(download_base_path);
+        let download_path = self.config.download_dir(download_base_path);
 
-        self.prepare_download(download_path.clone()).await?;
-        self.download().await?;
+        let shared_downloads = self.prepare_download(download_path.clone()).await?;
+        let complete = self.download().await?;
+        if let Some(shared_cache) = &self.config.shared_cache {
+            shared_downloads
+                .finish(
+                    &download_path,
+                    &complete,
+                    shared_cache.lock_timeout(),
+                    self.is_processing.clone(),
+                )
+                .await?;
+        }
         let extract_controller = FilterController::<StageExtract, FileInput, File> {
             stage: PhantomData,
             cached_lists: self.cached_lists.take(),
@@ -57,54 +70,158 @@ impl<'config> FilterController<'config, StageDownload, UrlInput, File> {
         Ok(extract_controller)
     }
 
-    /// Equips the FilterListIO objects with a reader and writers
+    /// Equips the FilterListIO objects with a reader and writers, returns the lists
+    /// downloaded by this instance and by others if the cache is shared
     ///
     /// * `download_path`: the file system path to the directory where the raw lists
-    ///               are going to be downloaded
-    async fn prepare_download(&mut self, download_path: PathBuf) -> anyhow::Result<()> {
-        let configured_lists: Vec<FilterListIO<UrlInput, File>> = self
+    ///   are going to be downloaded
+    async fn prepare_download(
+        &mut self,
+        download_path: PathBuf,
+    ) -> anyhow::Result<SharedDownloads> {
+        // another instance may have changed a shared download since the last extraction
+        let is_shared = self.config.shared_cache.is_some();
+        let mut shared_downloads = SharedDownloads::default();
+        let configured_lists: Vec<FilterListIO<SourceInput, File>> = self
             .config
             .lists
             .iter()
             .map(|f| FilterListIO::new(f.clone()))
             .collect();
+        // simultaneous downloads from the same host reuse the connections
+        let mut clients = HttpClients::new(self.config.http_client.clone().unwrap_or_default());
 
         for mut list in configured_lists.into_iter() {
             if !self.is_processing.load(Ordering::SeqCst) {
-                return Ok(());
+                return Ok(shared_downloads);
+            }
+
+            // a list outside its fetch windows keeps its former download
+            if !list.filter_list.in_fetch_window(Local::now().naive_local())
+                && download_path.join(&list.filter_list.id).is_file()
+            {
+                info!("Outside fetch window: {}", list.filter_list.id);
+                if !is_shared {
+                    self.cached_lists
+                        .as_mut()
+                        .unwrap()
+                        .insert(list.filter_list.id);
+                }
+                continue;
             }
 
-            list.attach_url_reader()?;
+            // the download is written next to the shared one and moved into place once
+            // it's complete
+            let mut writer_path = download_path.clone();
+            if let Some(shared_cache) = &self.config.shared_cache {
+                let id = list.filter_list.id.clone();
+                match DownloadLock::try_acquire(&download_path, &id, shared_cache.lock_timeout())? {
+                    None => {
+                        info!("Downloaded by another instance: {}", id);
+                        shared_downloads.waiting.push(id);
+                        continue;
+                    }
+                    Some(_) if !is_older(&download_path.join(&id), shared_cache.max_age()) => {
+                        info!("Shared: {}", id);
+                        continue;
+                    }
+                    Some(lock) => shared_downloads.locked.push((id.clone(), lock)),
+                }
+                writer_path.push(PARTIAL_PATH);
+                if list.filter_list.is_incremental() && download_path.join(&id).is_file() {
+                    fs::create_dir_all(&writer_path)?;
+                    fs::copy(download_path.join(&id), writer_path.join(&id))
+                        .with_context(|| format!("could not copy the download of {}", id))?;
+                }
+            }
+
+            list.attach_source_reader(&download_path, &mut clients)?;
 
             let mut is_cached = false;
             // we can only check for a cached result if the former downloaded file is available
-            if list.attach_existing_file_writer(&download_path).is_ok() {
-                is_cached = list.is_cached().await?;
+            // and the server tells whether it changed or the source's length is known in advance
+            if list.filter_list.source_type().has_len()
+                && list.attach_existing_file_writer(&download_path).is_ok()
+            {
+                is_cached = match list.is_not_modified().await {
+                    Some(is_not_modified) => is_not_modified,
+                    None => list.is_cached().await?,
+                };
             }
             if !is_cached {
                 info!("Updated: {}", list.filter_list.id);
-                list.attach_new_file_writer(&download_path)?;
+                // incremental APIs only return the indicators added since the last run
+                match list.filter_list.is_incremental() {
+                    true => list.attach_appending_file_writer(&writer_path)?,
+                    false => list.attach_new_file_writer(&writer_path)?,
+                }
                 self.filter_lists.push(list);
             } else {
                 info!("Unchanged: {}", list.filter_list.id);
-                self.cached_lists
-                    .as_mut()
-                    .unwrap()
-                    .insert(list.filter_list.id);
+                if !is_shared {
+                    self.cached_lists
+                        .as_mut()
+                        .unwrap()
+                        .insert(list.filter_list.id);
+                }
             }
         }
-        Ok(())
+        Ok(shared_downloads)
     }
 
-    /// downloads lists to temp files
-    async fn download(&mut self) -> anyhow::Result<()> {
+    /// downloads lists to temp files, each list after its random delay and at most
+    /// max_concurrent_downloads or as many as the memory budget affords at once. Returns
+    /// the ids of the lists downloaded completely.
+    async fn download(&mut self) -> anyhow::Result<HashSet<String>> {
+        let (delayed, mut lists): (Vec<_>, Vec<_>) = std::mem::take(&mut self.filter_lists)
+            .into_iter()
+            .partition(|l| l.filter_list.random_delay.unwrap_or_default() > 0);
+        let budget = self.config.memory_budget.map(MemoryBudget::new);
+        let limit = concurrency_limit(budget.as_deref(), self.config.max_concurrent_downloads);
         let handles = process(
-            &mut self.filter_lists,
+            &mut lists,
+            &|_| Ok(()),
             &|_, chunk| async { Ok(chunk) },
             self.is_processing.clone(),
+            limit.clone(),
+            self.config.batch_size(),
         )
         .await;
-        join_all(handles).await;
-        Ok(())
+        let batch_size = self.config.batch_size();
+        let mut delayed_handles = vec![];
+        for list in delayed {
+            let is_processing = self.is_processing.clone();
+            let limit = limit.clone();
+            delayed_handles.push(tokio::spawn(async move {
+                let delay = random_delay(list.filter_list.random_delay.unwrap_or_default());
+                debug!(
+                    "{}: delayed by {:.1}s",
+                    list.filter_list.id,
+                    delay.as_secs_f64()
+                );
+                tokio::time::sleep(delay).await;
+                let mut lists = vec![list];
+                let handles = process(
+                    &mut lists,
+                    &|_| Ok(()),
+                    &|_, chunk| async { Ok(chunk) },
+                    is_processing,
+                    limit,
+                    batch_size,
+                )
+                .await;
+                join_all(handles).await
+            }));
+        }
+        let mut outcomes = join_all(handles).await;
+        for delayed in join_all(delayed_handles).await {
+            outcomes.extend(delayed?);
+        }
+        Ok(outcomes
+            .into_iter()
+            .flatten()
+            .filter(|outcome| outcome.complete)
+            .map(|outcome| outcome.id)
+            .collect())
     }
 }
@@ -1,46 +1,93 @@
-use std::{fs::File, marker::PhantomData, path::PathBuf, str::FromStr, sync::Arc};
+use std::{
+    collections::HashSet,
+    fs::File,
+    marker::PhantomData,
+    path::PathBuf,
+    str::FromStr,
+    sync::{atomic::AtomicBool, Arc},
+};
 
+use anyhow::Context;
+use bytes::Bytes;
 use futures::future::join_all;
 use regex::Regex;
 
 use crate::{
+    config::Config,
     filter_controller::{process, FilterController, StageCategorize, StageExtract},
-    filter_list::FilterList,
-    input::file::FileInput,
+    filter_list::{FilterList, ListFormat},
+    input::{file::FileInput, format::convert},
     io::filter_list_io::FilterListIO,
+    memory::{concurrency_limit, MemoryBudget},
+    ENTRIES_PATH, INDICATORS_PATH,
 };
 
+/// ListRegex is the regex of a FilterList, compiled once for all lines of the list
+#[derive(Debug)]
+pub(crate) struct ListRegex {
+    regex: Regex,
+}
+
+impl ListRegex {
+    /// Compiles the regex of a list
+    ///
+    /// * `flist`: the FilterList whose lines are matched
+    pub fn new(flist: &FilterList) -> anyhow::Result<Self> {
+        let regex = Regex::new(&flist.regex).with_context(|| format!("List {}", flist.id))?;
+        Ok(Self { regex })
+    }
+}
+
 /// regex_match matches a line against a regex an extracts the first match group
 ///
-/// * `flist`: FilterList where the chunk to be matched belongs to
+/// * `re`: the compiled regex of the FilterList the chunk belongs to
 /// * `chunk`: A line from a list of URL to be matched against
-async fn regex_match(
-    flist: Arc<FilterList>,
-    chunk: Option<Vec<u8>>,
-) -> anyhow::Result<Option<Vec<u8>>> {
-    if chunk.is_none() {
-        return Ok(None);
-    }
-    let str_chunk = match String::from_utf8(chunk.unwrap()) {
+pub(crate) async fn regex_match(
+    re: Arc<ListRegex>,
+    chunk: Option<Bytes>,
+) -> anyhow::Result<Option<Bytes>> {
+    let chunk = match chunk {
+        Some(chunk) => chunk,
+        None => return Ok(None),
+    };
+    let str_chunk = match std::str::from_utf8(&chunk) {
         Ok(s) => s,
         Err(e) => {
             return Err(anyhow::anyhow!("Error: {}", e));
         }
     };
-    let re = match Regex::new(&flist.regex) {
-        Ok(r) => r,
-        Err(e) => return Err(anyhow::anyhow!(format!("List {} - {}", flist.id, e))),
-    };
-    if let Some(caps) = re.captures(&str_chunk) && let Some(cap) = caps.get(1) {
-                    let result = cap.as_str().to_owned() + "\n";
-                    return Ok(Some(result.as_bytes().to_owned()));
-                }
+    if let Some(caps) = re.regex.captures(str_chunk)
+        && let Some(cap) = caps.get(1)
+    {
+        let result = cap.as_str().to_owned() + "\n";
+        return Ok(Some(Bytes::from(result)));
+    }
     Ok(None)
 }
 
 /// This implementation for FileInput and File is the second stage where URLs are
 /// being extracted
 impl<'config> FilterController<'config, StageExtract, FileInput, File> {
+    /// Creates the controller for the extract stage from the result of a previous download
+    ///
+    /// * `config`: the configuration containing the lists to be extracted
+    /// * `cached_lists`: ids of the lists which didn't change since the last download
+    /// * `is_processing`: determines if the program was interrupted
+    pub fn from_downloads(
+        config: &'config Config,
+        cached_lists: HashSet<String>,
+        is_processing: Arc<AtomicBool>,
+    ) -> Self {
+        Self {
+            stage: PhantomData,
+            config,
+            cached_lists: Some(cached_lists),
+            filter_lists: vec![],
+            category_lists: vec![],
+            is_processing,
+        }
+    }
+
     /// Runs the extract stage and returns the controller for the categorize stage
     ///
     /// * `download_base_path`: The path where downloaded lists have been stored
@@ -50,8 +97,7 @@ impl<'config> FilterController<'config, StageExtract, FileInput, File> {
         download_base_path: &str,
         extract_base_path: &str,
     ) -> anyhow::Result<FilterController<StageCategorize, FileInput, File>> {
-        let mut download_path = PathBuf::from_str(&self.config.cache_dir)?;
-        download_path.push(download_base_path);
+        let download_path = self.config.download_dir(download_base_path);
         let mut extract_path = PathBuf::from_str(&self.config.cache_dir)?;
         extract_path.push(extract_base_path);
 
@@ -104,8 +150,12 @@ impl<'config> FilterController<'config, StageExtract, FileInput, File> {
                     .unwrap()
                     .retain(|l| l != &list.filter_list.id);
                 info!("Updated: {}", list.filter_list.id);
-                let compression = list.filter_list.compression.clone();
+                let compression = list.filter_list.compression();
                 list.attach_existing_input_file(&download_path, compression)?;
+                match list.filter_list.format.clone() {
+                    None | Some(ListFormat::Lines) => {}
+                    Some(format) => self.read_indicators(&mut list, &format).await?,
+                }
                 list.attach_new_file_writer(&extract_path)?;
                 self.filter_lists.push(list);
             }
@@ -113,12 +163,46 @@ impl<'config> FilterController<'config, StageExtract, FileInput, File> {
         Ok(())
     }
 
+    /// Replaces the reader of a list in another format than lines by a reader of its
+    /// indicators, e.g. the owner names of a zone
+    ///
+    /// * `list`: the list with the downloaded file attached
+    /// * `format`: the format of the list
+    async fn read_indicators(
+        &self,
+        list: &mut FilterListIO<FileInput, File>,
+        format: &ListFormat,
+    ) -> anyhow::Result<()> {
+        let reader = match list.reader.take() {
+            Some(reader) => reader,
+            None => return Ok(()),
+        };
+        let mut indicators_path = PathBuf::from_str(&self.config.cache_dir)?;
+        indicators_path.push(self.config.stage_path(INDICATORS_PATH));
+        let mut entries_path = PathBuf::from_str(&self.config.cache_dir)?;
+        entries_path.push(self.config.stage_path(ENTRIES_PATH));
+        let count = convert(
+            &mut *reader.lock().await,
+            &indicators_path.join(&list.filter_list.id),
+            format,
+            &entries_path,
+        )
+        .await
+        .with_context(|| format!("could not convert list {}", list.filter_list.id))?;
+        debug!("{} indicators in list {}", count, list.filter_list.id);
+        list.attach_existing_input_file(&indicators_path, None)
+    }
+
     /// extracts URLs from lines by employing the regex given in the configuration file
     async fn extract(&mut self) -> anyhow::Result<()> {
+        let budget = self.config.memory_budget.map(MemoryBudget::new);
         let handles = process(
             &mut self.filter_lists,
+            &ListRegex::new,
             &regex_match,
             self.is_processing.clone(),
+            concurrency_limit(budget.as_deref(), None),
+            self.config.batch_size(),
         )
         .await;
         join_all(handles).await;
@@ -128,12 +212,26 @@ impl<'config> FilterController<'config, StageExtract, FileInput, File> {
 
 #[cfg(test)]
 mod tests {
-    use std::{collections::HashSet, sync::atomic::AtomicBool};
-
     use crate::{tests::helper::cache_file_creator::CacheFileCreator, DOWNLOAD_PATH, EXTRACT_PATH};
+    use proptest::prelude::*;
 
     use super::*;
 
+    proptest! {
+        #[test]
+        fn prop_regex_match_arbitrary_bytes(chunk in proptest::collection::vec(any::<u8>(), 0..512)) {
+            let re = Arc::new(ListRegex::new(&FilterList {
+                id: "test".to_string(),
+                regex: r"^0\.0\.0\.0 (.*)".to_string(),
+                ..Default::default()
+            }).unwrap());
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            if let Ok(Some(result)) = rt.block_on(regex_match(re, Some(Bytes::from(chunk)))) {
+                prop_assert_eq!(result.last(), Some(&b'\n'));
+            }
+        }
+    }
+
     #[tokio::test]
     async fn test_extract_successful() {
         let cache = CacheFileCreator::new("test_extract_successful", DOWNLOAD_PATH, EXTRACT_PATH);
@@ -146,6 +244,7 @@ mod tests {
             tags: vec![],
             // the regex for matching lines
             regex: r"127.0.0.1 (.*)".to_string(),
+            ..Default::default()
         }];
         // prepare the file to extract from
         cache.write_input(
@@ -175,6 +274,43 @@ another.domain
         assert_eq!(want, got);
     }
 
+    #[tokio::test]
+    async fn test_extract_zone() {
+        let cache = CacheFileCreator::new("test_extract_zone", DOWNLOAD_PATH, EXTRACT_PATH);
+        let mut config = cache.new_test_config();
+        config.lists = vec![FilterList {
+            id: "test".to_string(),
+            format: Some(ListFormat::Zone { strip_origin: true }),
+            regex: r"^(.+)$".to_string(),
+            ..Default::default()
+        }];
+        cache.write_input(
+            &config.lists[0].id,
+            r#"$ORIGIN rpz.local.
+@ IN SOA localhost. root.localhost. 1 3600 600 86400 300
+  IN NS localhost.
+one.domain CNAME .
+*.one.domain CNAME .
+*.two.domain CNAME .
+"#,
+        );
+
+        let mut extract_controller = FilterController::<StageExtract, FileInput, File> {
+            stage: PhantomData,
+            cached_lists: Some(HashSet::new()),
+            config: &config,
+            filter_lists: vec![],
+            category_lists: vec![],
+            is_processing: Arc::new(AtomicBool::new(true)),
+        };
+        extract_controller
+            .run(&cache.inpath, &cache.outpath)
+            .await
+            .unwrap();
+        let got = cache.read_result(&config.lists[0].id).unwrap();
+        assert_eq!(got, "one.domain\ntwo.domain\n");
+    }
+
     #[tokio::test]
     async fn test_regex_match_positive() {
         let regex = "^0.0.0.0 (.*)".to_string();
@@ -185,14 +321,15 @@ another.domain
             source: "".to_string(),
             tags: vec![],
             regex,
+            ..Default::default()
         };
-        let chunk = Vec::from("0.0.0.0 domain.tech\n");
+        let chunk = Bytes::from("0.0.0.0 domain.tech\n");
 
-        let got = regex_match(Arc::new(filter_list), Some(chunk))
+        let got = regex_match(Arc::new(ListRegex::new(&filter_list).unwrap()), Some(chunk))
             .await
             .unwrap()
             .unwrap();
-        let want = Vec::from("domain.tech\n");
+        let want = Bytes::from("domain.tech\n");
 
         assert_eq!(got, want);
     }
@@ -207,14 +344,26 @@ another.domain
             source: "".to_string(),
             tags: vec![],
             regex,
+            ..Default::default()
         };
-        let chunk = Vec::from("# some comment\n");
+        let chunk = Bytes::from("# some comment\n");
 
-        let got = regex_match(Arc::new(filter_list), Some(chunk))
+        let got = regex_match(Arc::new(ListRegex::new(&filter_list).unwrap()), Some(chunk))
             .await
             .unwrap();
-        let want: Option<Vec<u8>> = None;
+        let want: Option<Bytes> = None;
 
         assert_eq!(got, want);
     }
+
+    #[test]
+    fn test_list_regex_invalid() {
+        let filter_list = FilterList {
+            id: "test_list".to_string(),
+            regex: "^0.0.0.0 (.*".to_string(),
+            ..Default::default()
+        };
+        let err = ListRegex::new(&filter_list).unwrap_err();
+        assert_eq!(err.to_string(), "List test_list");
+    }
 }
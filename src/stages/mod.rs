@@ -1,4 +1,4 @@
 mod categorize;
 mod download;
-mod extract;
+pub(crate) mod extract;
 mod output;
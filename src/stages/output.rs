@@ -1,31 +1,108 @@
 use std::{
+    collections::BTreeSet,
     fs::File,
-    path::PathBuf,
+    path::{Path, PathBuf},
     str::FromStr,
     sync::{atomic::Ordering, Arc},
 };
 
-use futures::future::join_all;
+use anyhow::Context;
+use chrono::{Local, SecondsFormat, Utc};
+use futures::{future::join_all, lock::Mutex};
+use opentelemetry::KeyValue;
 use tokio::task::JoinHandle;
 
 use crate::{
+    clickhouse::{EntryRow, RunRow},
+    elasticsearch::EntryDocument,
     filter_controller::{FilterController, StageOutput},
+    input::empty::EmptyInput,
     input::file::FileInput,
-    io::category_list_io::CategoryListIO,
+    input::Input,
+    io::{
+        category_list_io::CategoryListIO,
+        entry_store::{EntryStore, FLAG_ACCUMULATED, FLAG_MULTIPLE_SOURCES},
+        published_file::PublishedFile,
+        seen_file::{Seen, SeenFile},
+        spool::DEFAULT_SPOOL_THRESHOLD,
+    },
+    ip2asn::{entry_address, AsnDatabase, AsnRecord},
+    kafka::{EntryMessage, KafkaMode},
+    memory::MemoryBudget,
+    mqtt::ChangeEvent,
+    output::{rpz, validate::ValidatedInput, warn_page, OutputType},
+    post_processing::{PipelineInput, SpillSettings},
+    report::{rfc3339, unix_timestamp},
+    telemetry, SPOOL_PATH,
 };
 
-impl<'config> FilterController<'config, StageOutput, FileInput, File> {
-    /// Runs the output stage
+/// WrittenEntry is an entry of a written list together with its origin and age
+struct WrittenEntry {
+    domain: String,
+    source: Option<String>,
+    multiple_sources: bool,
+    seen: Option<Seen>,
+    /// the autonomous system announcing an IP entry
+    asn: Option<AsnRecord>,
+}
+
+/// name of the published state of the outputs updated by dynamic DNS updates
+const DNS_UPDATE_TARGET: &str = "dns_update";
+/// name of the published state of the outputs produced to Kafka as changes
+const KAFKA_TARGET: &str = "kafka";
+
+/// OutputChanges are the entries an output gained and lost in a run, which are
+/// published to DNS servers, MQTT brokers, Kafka and ClickHouse
+struct OutputChanges {
+    tag: String,
+    /// the entries of the written output
+    entries: BTreeSet<String>,
+    /// the entries of the output deployed before the run
+    deployed: BTreeSet<String>,
+    added: Vec<String>,
+    removed: Vec<String>,
+}
+
+impl OutputChanges {
+    /// returns the entries added and removed since the output was last published to
+    /// a target, the changes of the run if it was never published
+    ///
+    /// * `published`: the entries last published to the target
+    fn unpublished(&self, published: &PublishedFile) -> anyhow::Result<(Vec<String>, Vec<String>)> {
+        let previous = published.read()?.unwrap_or_else(|| self.deployed.clone());
+        Ok((
+            self.entries.difference(&previous).cloned().collect(),
+            previous.difference(&self.entries).cloned().collect(),
+        ))
+    }
+}
+
+impl FilterController<'_, StageOutput, FileInput, File> {
+    /// Runs the output stage and returns the names of the lists written
     ///
     /// * `categorize_base_path`: The path where categorized URL lists were stored
-    pub async fn run(&mut self, categorize_base_path: &str) -> anyhow::Result<()> {
+    pub async fn run(&mut self, categorize_base_path: &str) -> anyhow::Result<Vec<String>> {
         let mut categorize_path = PathBuf::from_str(&self.config.cache_dir)?;
         categorize_path.push(categorize_base_path);
-        let out_path = PathBuf::from_str(&self.config.output_dir)?;
+        let out_path = PathBuf::from_str(self.config.build_dir())?;
 
-        self.prepare_output(categorize_path.clone(), out_path)?;
-        self.output().await?;
-        Ok(())
+        self.prepare_output(categorize_path.clone(), out_path.clone())?;
+        let updated_lists = self.output(&categorize_path, &out_path).await?;
+        // don't replace existing outputs with partially written files
+        if !self.is_processing.load(Ordering::SeqCst) {
+            return Ok(vec![]);
+        }
+        self.lint_output(&out_path, &updated_lists)?;
+        let output_changes = self.output_changes(&out_path, &updated_lists)?;
+        self.finalize_output(&out_path, &updated_lists)?;
+        self.update_seen(&categorize_path)?;
+        self.publish_changes(&out_path, &output_changes).await?;
+        self.retry_dns_updates(&out_path, &updated_lists).await?;
+        self.index_entries(&categorize_path, &out_path, &updated_lists)
+            .await?;
+        self.insert_analytics(&categorize_path, &out_path, &output_changes)
+            .await?;
+        Ok(updated_lists)
     }
 
     /// Attaches the readers and writers to the CategoryListIO objects
@@ -37,6 +114,11 @@ impl<'config> FilterController<'config, StageOutput, FileInput, File> {
         categorize_path: PathBuf,
         output_path: PathBuf,
     ) -> anyhow::Result<()> {
+        // changed post-processing stages or formats may change every output
+        let post_processing_unchanged = self.config.cached_config.as_ref().is_some_and(|c| {
+            c.post_processing == self.config.post_processing
+                && c.additional_formats == self.config.additional_formats
+        });
         self.category_lists = self
             .config
             .get_tags()
@@ -46,30 +128,54 @@ impl<'config> FilterController<'config, StageOutput, FileInput, File> {
         self.category_lists
             .iter_mut()
             .try_for_each(|list| -> anyhow::Result<()> {
-                // set readers
-                list.attach_existing_input_file(&categorize_path)?;
+                // set readers, all output formats are generated from the entry store
+                list.attach_entry_store_reader(&categorize_path)?;
 
                 // set writers
                 if self.cached_lists.as_ref().unwrap().contains(&list.name)
-                    && list.attach_existing_input_file(&categorize_path).is_ok()
+                    && post_processing_unchanged
+                    && list.attach_entry_store_reader(&categorize_path).is_ok()
                     && list.attach_existing_file_writer(&output_path).is_ok()
+                    && self
+                        .config
+                        .output_files(&list.name)
+                        .iter()
+                        .all(|(file_name, _)| output_path.join(file_name).exists())
+                    && self
+                        .config
+                        .output_settings(&list.name)
+                        .is_none_or(|s| !s.includes_modified_after(&output_path.join(&list.name)))
                 {
                     // set writer to None so it will be skipped in the output method
                     list.writer = None;
                     return Ok(());
                 }
-                list.attach_new_file_writer(&output_path)?;
+                list.attach_new_tmp_file_writer(&output_path)?;
                 Ok(())
             })?;
         Ok(())
     }
 
-    /// generates the final result lists
-    async fn output(&mut self) -> anyhow::Result<()> {
+    /// generates the final result lists and returns the names of the lists written. Every
+    /// format of a list is written by a task of its own reading the list's entry store.
+    ///
+    /// * `categorize_path`: the file system path to where the category lists where stored
+    /// * `output_path`: the file system path for the lists in the final result format
+    async fn output(
+        &mut self,
+        categorize_path: &Path,
+        output_path: &Path,
+    ) -> anyhow::Result<Vec<String>> {
         let mut handles: Vec<JoinHandle<()>> = vec![];
+        let mut updated_lists = vec![];
+        let now = Local::now().naive_local();
+        let mut spool_path = PathBuf::from_str(&self.config.cache_dir)?;
+        spool_path.push(self.config.stage_path(SPOOL_PATH));
+        // the post-processing stages of all outputs share the budget
+        let budget = self.config.memory_budget.map(MemoryBudget::new);
         for list in self.category_lists.iter_mut() {
             if !self.is_processing.load(Ordering::SeqCst) {
-                return Ok(());
+                return Ok(updated_lists);
             }
             // do nothing if the list was already written on the last run
             if self.cached_lists.as_ref().unwrap().contains(&list.name) && list.writer.is_none() {
@@ -77,18 +183,488 @@ impl<'config> FilterController<'config, StageOutput, FileInput, File> {
                 continue;
             }
             info!("Updated: {}", list.name);
-            let reader = Arc::clone(&list.reader.take().unwrap());
-            let writer = Arc::clone(&list.writer.take().unwrap());
-            let output_adapter =
-                self.config
-                    .output_format
-                    .get_adapter(reader, writer, self.is_processing.clone());
-            let handle = tokio::spawn(async move {
-                output_adapter.await;
-            });
-            handles.push(handle);
+            updated_lists.push(list.name.clone());
+            for (i, (file_name, format)) in
+                self.config.output_files(&list.name).into_iter().enumerate()
+            {
+                // the first file is the output_format prepared before, the additional
+                // formats read the entry store once more
+                let (reader, writer) = if i == 0 {
+                    (list.reader.take().unwrap(), list.writer.take().unwrap())
+                } else {
+                    let mut file = CategoryListIO::<FileInput, File>::new(&file_name);
+                    file.attach_new_tmp_file_writer(output_path)?;
+                    let store = EntryStore::open(categorize_path, &list.name)?;
+                    (
+                        Arc::new(Mutex::new(store.reader())),
+                        file.writer.take().unwrap(),
+                    )
+                };
+                let mut reader: Arc<Mutex<dyn Input + Send>> = reader;
+                // outside of its block windows the output is written without entries
+                if let Some(settings) = self.config.output_settings(&list.name)
+                    && !settings.is_blocking(now)
+                {
+                    reader = Arc::new(Mutex::new(EmptyInput));
+                }
+                if !self.config.post_processing.is_empty() {
+                    reader = Arc::new(Mutex::new(
+                        PipelineInput::new(reader, &self.config.post_processing, &list.name)
+                            .with_batch_size(self.config.batch_size())
+                            .with_spill(SpillSettings {
+                                dir: spool_path.join(&file_name),
+                                threshold: self
+                                    .config
+                                    .spool_threshold
+                                    .unwrap_or(DEFAULT_SPOOL_THRESHOLD),
+                                budget: budget.clone(),
+                            }),
+                    ));
+                }
+                // malformed entries of a source are dropped rather than failing the lint
+                reader = Arc::new(Mutex::new(ValidatedInput::new(
+                    reader,
+                    format.clone(),
+                    &file_name,
+                )));
+                let output_adapter = format.get_adapter(
+                    self.config.output_address(&list.name),
+                    reader,
+                    writer,
+                    self.is_processing.clone(),
+                );
+                let attributes = vec![
+                    KeyValue::new("list", list.name.clone()),
+                    KeyValue::new("format", format.extension()),
+                ];
+                let handle = telemetry::spawn_traced("list", attributes, output_adapter);
+                handles.push(handle);
+            }
         }
         join_all(handles).await;
+        Ok(updated_lists)
+    }
+
+    /// checks the written lists for format errors before they are moved into place
+    ///
+    /// * `output_path`: the file system path for the lists in the final result format
+    /// * `updated_lists`: names of the lists written in this run
+    fn lint_output(&self, output_path: &Path, updated_lists: &[String]) -> anyhow::Result<()> {
+        for tag in updated_lists {
+            for (file_name, format) in self.config.output_files(tag) {
+                let tmp_path =
+                    CategoryListIO::<FileInput, File>::new(&file_name).tmp_file_path(output_path);
+                format
+                    .lint(&tmp_path, self.config.output_address(tag))
+                    .with_context(|| {
+                        format!("output {} is malformed: {:?}", file_name, tmp_path)
+                    })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// compares the written lists whose changes are published with the lists they
+    /// replace, a list written for the first time counts as added completely
+    ///
+    /// * `output_path`: the file system path for the lists in the final result format
+    /// * `updated_lists`: names of the lists written in this run
+    fn output_changes(
+        &self,
+        output_path: &Path,
+        updated_lists: &[String],
+    ) -> anyhow::Result<Vec<OutputChanges>> {
+        let mut output_changes = vec![];
+        for tag in updated_lists {
+            let dns_update = self
+                .config
+                .output_settings(tag)
+                .is_some_and(|s| s.dns_update.is_some());
+            let published = self.config.mqtt.is_some()
+                || self.config.kafka.is_some()
+                || self.config.clickhouse.is_some();
+            if !dns_update && !published {
+                continue;
+            }
+            let format = &self.config.output_format;
+            let encryption = self.config.output_encryption(tag);
+            let address = self.config.output_address(tag);
+            let tmp_path = CategoryListIO::<FileInput, File>::new(tag).tmp_file_path(output_path);
+            let built = format.entries(&tmp_path, None, address)?;
+            let deployed_path = output_path.join(tag);
+            let deployed = match deployed_path.exists() {
+                true => format
+                    .entries(&deployed_path, encryption, address)
+                    .with_context(|| format!("could not read deployed output {}", tag))?,
+                false => BTreeSet::new(),
+            };
+            output_changes.push(OutputChanges {
+                tag: tag.clone(),
+                added: built.difference(&deployed).cloned().collect(),
+                removed: deployed.difference(&built).cloned().collect(),
+                entries: built,
+                deployed,
+            });
+        }
+        Ok(output_changes)
+    }
+
+    /// publishes the changes by dynamic DNS updates, as MQTT change events and as
+    /// Kafka records
+    ///
+    /// * `output_path`: the file system path for the lists in the final result format
+    /// * `output_changes`: the changes of the lists written in this run
+    async fn publish_changes(
+        &self,
+        output_path: &Path,
+        output_changes: &[OutputChanges],
+    ) -> anyhow::Result<()> {
+        for changes in output_changes {
+            let settings = match self.config.output_settings(&changes.tag) {
+                Some(settings) => settings,
+                None => continue,
+            };
+            // changes which failed to be published are published again by the next run
+            if let Some(dns_update) = &settings.dns_update {
+                let zone = settings.zone.as_deref().unwrap_or(&changes.tag);
+                let published =
+                    PublishedFile::new(&self.config.published_dir(DNS_UPDATE_TARGET), &changes.tag);
+                let (added, removed) = changes.unpublished(&published)?;
+                dns_update
+                    .publish(zone, &added, &removed, settings.address())
+                    .await?;
+                published.write(&changes.entries)?;
+            }
+        }
+        // change events are notifications, a broker being unavailable doesn't fail the run
+        if let Some(mqtt) = &self.config.mqtt {
+            let events: Vec<ChangeEvent> = output_changes
+                .iter()
+                .map(|c| {
+                    ChangeEvent::new(
+                        &c.tag,
+                        self.config.project.clone(),
+                        &c.added,
+                        &c.removed,
+                        mqtt.max_domains(),
+                    )
+                })
+                .collect();
+            if let Err(e) = mqtt.publish(&events).await {
+                warn!("could not publish change events: {:?}", e);
+            }
+        }
+        if let Some(kafka) = &self.config.kafka {
+            let mut messages = vec![];
+            let mut produced = vec![];
+            for c in output_changes {
+                let project = self.config.project.clone();
+                match kafka.mode {
+                    // changes which failed to be produced are produced again by the next run
+                    KafkaMode::Changes => {
+                        let published =
+                            PublishedFile::new(&self.config.published_dir(KAFKA_TARGET), &c.tag);
+                        let (added, removed) = c.unpublished(&published)?;
+                        messages.extend(EntryMessage::changes(&c.tag, project, &added, &removed));
+                        produced.push((published, &c.entries));
+                    }
+                    KafkaMode::Entries => {
+                        let entries = self.config.output_format.entries(
+                            &output_path.join(&c.tag),
+                            self.config.output_encryption(&c.tag),
+                            self.config.output_address(&c.tag),
+                        )?;
+                        messages.extend(EntryMessage::entries(&c.tag, project, &entries));
+                    }
+                }
+            }
+            kafka.produce(&messages).await?;
+            for (published, entries) in produced {
+                published.write(entries)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// publishes the changes of the outputs which weren't written in this run, but
+    /// differ from the entries last published by dynamic DNS updates
+    ///
+    /// * `output_path`: the file system path for the lists in the final result format
+    /// * `updated_lists`: names of the lists written in this run
+    async fn retry_dns_updates(
+        &self,
+        output_path: &Path,
+        updated_lists: &[String],
+    ) -> anyhow::Result<()> {
+        for tag in self.config.get_tags() {
+            let settings = match self.config.output_settings(&tag) {
+                Some(settings) if !updated_lists.contains(&tag) => settings,
+                _ => continue,
+            };
+            let dns_update = match &settings.dns_update {
+                Some(dns_update) => dns_update,
+                None => continue,
+            };
+            let published = PublishedFile::new(&self.config.published_dir(DNS_UPDATE_TARGET), &tag);
+            // an output never published is published completely once it's written
+            let previous = match published.read()? {
+                Some(previous) => previous,
+                None => continue,
+            };
+            let deployed = self.config.output_format.entries(
+                &output_path.join(&tag),
+                self.config.output_encryption(&tag),
+                self.config.output_address(&tag),
+            )?;
+            if deployed == previous {
+                continue;
+            }
+            let zone = settings.zone.as_deref().unwrap_or(&tag);
+            dns_update
+                .publish(
+                    zone,
+                    &deployed.difference(&previous).cloned().collect::<Vec<_>>(),
+                    &previous.difference(&deployed).cloned().collect::<Vec<_>>(),
+                    settings.address(),
+                )
+                .await?;
+            published.write(&deployed)?;
+        }
+        Ok(())
+    }
+
+    /// returns the entries of a written list together with their origin
+    ///
+    /// * `categorize_path`: the file system path to where the category lists where stored
+    /// * `output_path`: the file system path for the lists in the final result format
+    /// * `tag`: the name of the list
+    /// * `asn_database`: the database the IP entries are looked up in
+    fn written_entries(
+        &self,
+        categorize_path: &Path,
+        output_path: &Path,
+        tag: &str,
+        asn_database: Option<&AsnDatabase>,
+    ) -> anyhow::Result<Vec<WrittenEntry>> {
+        // the store holds the origin, the output tells which entries weren't excluded
+        let written = self.config.output_format.entries(
+            &output_path.join(tag),
+            self.config.output_encryption(tag),
+            self.config.output_address(tag),
+        )?;
+        let store = EntryStore::open(categorize_path, tag)?;
+        let seen = SeenFile::new(&self.config.seen_dir(), tag).read()?;
+        let mut entries = vec![];
+        for entry in store.entries()? {
+            let entry = entry?;
+            if !written.contains(&entry.domain) {
+                continue;
+            }
+            entries.push(WrittenEntry {
+                source: store.source_id(&entry).map(|s| s.to_string()),
+                multiple_sources: entry.flags & FLAG_MULTIPLE_SOURCES != 0,
+                seen: seen.get(&entry.domain).copied(),
+                asn: asn_database
+                    .zip(entry_address(&entry.domain))
+                    .and_then(|(database, ip)| database.lookup(ip))
+                    .cloned(),
+                domain: entry.domain,
+            });
+        }
+        Ok(entries)
+    }
+
+    /// indexes the entries of the written lists together with their origin into
+    /// Elasticsearch
+    ///
+    /// * `categorize_path`: the file system path to where the category lists where stored
+    /// * `output_path`: the file system path for the lists in the final result format
+    /// * `updated_lists`: names of the lists written in this run
+    async fn index_entries(
+        &self,
+        categorize_path: &Path,
+        output_path: &Path,
+        updated_lists: &[String],
+    ) -> anyhow::Result<()> {
+        let elasticsearch = match &self.config.elasticsearch {
+            Some(elasticsearch) => elasticsearch,
+            None => return Ok(()),
+        };
+        let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+        let asn_database = self.config.load_asn_database()?;
+        let mut documents = vec![];
+        for tag in updated_lists {
+            let entries =
+                self.written_entries(categorize_path, output_path, tag, asn_database.as_ref())?;
+            documents.extend(entries.into_iter().map(|e| EntryDocument {
+                timestamp: timestamp.clone(),
+                domain: e.domain,
+                category: tag.clone(),
+                source: e.source,
+                multiple_sources: e.multiple_sources,
+                first_seen: e.seen.map(|s| rfc3339(s.first)),
+                last_seen: e.seen.map(|s| rfc3339(s.last)),
+                asn: e.asn.as_ref().map(|r| r.asn),
+                country: e.asn.map(|r| r.country),
+                project: self.config.project.clone(),
+            }));
+        }
+        elasticsearch.index(&documents).await
+    }
+
+    /// inserts the entries of the written lists and their churn into ClickHouse
+    ///
+    /// * `categorize_path`: the file system path to where the category lists where stored
+    /// * `output_path`: the file system path for the lists in the final result format
+    /// * `output_changes`: the changes of the lists written in this run
+    async fn insert_analytics(
+        &self,
+        categorize_path: &Path,
+        output_path: &Path,
+        output_changes: &[OutputChanges],
+    ) -> anyhow::Result<()> {
+        let clickhouse = match &self.config.clickhouse {
+            Some(clickhouse) => clickhouse,
+            None => return Ok(()),
+        };
+        let run = unix_timestamp();
+        let project = self.config.project.clone().unwrap_or_default();
+        let asn_database = self.config.load_asn_database()?;
+        let mut entries = vec![];
+        let mut runs = vec![];
+        for c in output_changes {
+            let written =
+                self.written_entries(categorize_path, output_path, &c.tag, asn_database.as_ref())?;
+            runs.push(RunRow {
+                run,
+                project: project.clone(),
+                category: c.tag.clone(),
+                entries: written.len() as u64,
+                added: c.added.len() as u64,
+                removed: c.removed.len() as u64,
+            });
+            entries.extend(written.into_iter().map(|e| EntryRow {
+                run,
+                project: project.clone(),
+                category: c.tag.clone(),
+                domain: e.domain,
+                source: e.source.unwrap_or_default(),
+                multiple_sources: e.multiple_sources as u8,
+                first_seen: e.seen.map_or(run, |s| s.first),
+                last_seen: e.seen.map_or(run, |s| s.last),
+                asn: e.asn.as_ref().map_or(0, |r| r.asn),
+                country: e.asn.map(|r| r.country).unwrap_or_default(),
+            }));
+        }
+        clickhouse.insert(&entries, &runs).await
+    }
+
+    /// records the first and last appearance of the domains in the lists of every
+    /// category, unchanged categories included
+    ///
+    /// * `categorize_path`: the file system path to where the category lists where stored
+    fn update_seen(&self, categorize_path: &Path) -> anyhow::Result<()> {
+        let now = unix_timestamp();
+        for tag in self.config.get_tags() {
+            let store = match EntryStore::open(categorize_path, &tag) {
+                Ok(store) => store,
+                // a category which wasn't assembled yet has no domains to be recorded
+                Err(_) => continue,
+            };
+            let mut entries = BTreeSet::new();
+            for entry in store.entries()? {
+                let entry = entry?;
+                // accumulated domains aren't listed anymore
+                if entry.flags & FLAG_ACCUMULATED == 0 {
+                    entries.insert(entry.domain);
+                }
+            }
+            let retention = self
+                .config
+                .output_settings(&tag)
+                .and_then(|s| s.seen_retention());
+            let pruned =
+                SeenFile::new(&self.config.seen_dir(), &tag).update(&entries, now, retention)?;
+            if pruned > 0 {
+                debug!("{}: {} long unseen entries pruned", tag, pruned);
+            }
+        }
+        Ok(())
+    }
+
+    /// moves the written lists into place, encrypts them and applies the configured file
+    /// permissions
+    ///
+    /// * `output_path`: the file system path for the lists in the final result format
+    /// * `updated_lists`: names of the lists written in this run
+    fn finalize_output(&self, output_path: &Path, updated_lists: &[String]) -> anyhow::Result<()> {
+        for list in self.category_lists.iter() {
+            let settings = self.config.output_settings(&list.name);
+            for (file_name, format) in self.config.output_files(&list.name) {
+                let file = CategoryListIO::<FileInput, File>::new(&file_name);
+                let out_file = if updated_lists.contains(&list.name) {
+                    // the includes surround the output_format only, after it was linted
+                    if file_name == list.name
+                        && let Some(settings) = settings
+                    {
+                        let tmp_path = file.tmp_file_path(output_path);
+                        if settings.warn_page.as_ref().is_some_and(|w| w.nginx_map) {
+                            let entries = format.entries(&tmp_path, None, settings.address())?;
+                            warn_page::write_nginx_map(
+                                &output_path.join(warn_page::nginx_map_name(&list.name)),
+                                &list.name,
+                                &entries,
+                                settings,
+                            )?;
+                        }
+                        settings.apply_includes(&tmp_path)?;
+                    }
+                    // the changes to the previous zone are applied by dynamic updates
+                    if format == OutputType::Rpz {
+                        let zone = settings
+                            .and_then(|s| s.zone.clone())
+                            .unwrap_or_else(|| list.name.clone());
+                        rpz::write_update(
+                            &zone,
+                            &output_path.join(&file_name),
+                            &file.tmp_file_path(output_path),
+                            &output_path.join(format!("{}.nsupdate", file_name)),
+                            self.config.output_encryption(&list.name),
+                            self.config.output_address(&list.name),
+                        )?;
+                    }
+                    // a sensitive output never appears in plain text or with the default
+                    // permissions under its published name
+                    if let Some(settings) = settings {
+                        let tmp_path = file.tmp_file_path(output_path);
+                        if let Some(encryption) = &settings.encryption {
+                            encryption.encrypt_file(&tmp_path)?;
+                        }
+                        settings.apply_permissions(&tmp_path)?;
+                    }
+                    file.persist_tmp_file(output_path)?
+                } else {
+                    let out_file = output_path.join(&file_name);
+                    if let Some(settings) = settings {
+                        // unchanged outputs are encrypted as well once encryption is enabled
+                        if let Some(encryption) = &settings.encryption {
+                            encryption.encrypt_file(&out_file)?;
+                        }
+                        settings.apply_permissions(&out_file)?;
+                    }
+                    out_file
+                };
+                // staged outputs are merged once they are promoted
+                if let Some(settings) = settings
+                    && updated_lists.contains(&list.name)
+                    && format == OutputType::Hostsfile
+                    && file_name == list.name
+                    && self.config.staging_dir.is_none()
+                {
+                    settings.apply_hosts_merge(&out_file)?;
+                }
+            }
+        }
         Ok(())
     }
 }
@@ -103,8 +679,13 @@ mod tests {
     };
 
     use crate::{
-        filter_list::FilterList, tests::helper::cache_file_creator::CacheFileCreator,
-        CATEGORIZE_PATH,
+        encryption::{read_output, EncryptionSettings},
+        filter_controller::StageExtract,
+        filter_list::{FilterList, ListFormat},
+        output::{hostsfile::DEFAULT_ADDRESS, warn_page::WarnPageSettings},
+        output_settings::OutputSettings,
+        tests::helper::cache_file_creator::{CacheFileCreator, TEST_CACHE},
+        CATEGORIZE_PATH, DOWNLOAD_PATH, EXTRACT_PATH,
     };
 
     use super::*;
@@ -123,6 +704,7 @@ mod tests {
                 source: "".to_string(),
                 tags: vec!["advertising".to_string()],
                 regex: r"(.*)".to_string(),
+                ..Default::default()
             },
             FilterList {
                 id: "malware".to_string(),
@@ -131,13 +713,14 @@ mod tests {
                 source: "".to_string(),
                 tags: vec!["malware".to_string()],
                 regex: r"(.*)".to_string(),
+                ..Default::default()
             },
         ];
         // the contents of each filter list
         let contents: HashMap<&str, String> = HashMap::from([
             (
                 "advertising",
-                vec![
+                [
                     "another.domain",
                     "fith.domain",
                     "one.domain",
@@ -148,7 +731,7 @@ mod tests {
             ),
             (
                 "malware",
-                vec![
+                [
                     "fith.domain",
                     "fourth.domain",
                     "sixth.domain",
@@ -160,7 +743,7 @@ mod tests {
         ]);
 
         for (list, content) in &contents {
-            cache.write_input(list, &content);
+            cache.write_store(list, content);
         }
 
         let mut output_controller = FilterController::<StageOutput, FileInput, File> {
@@ -176,7 +759,7 @@ mod tests {
         }
 
         let mut want = HashMap::new();
-        for category in vec!["advertising", "malware"] {
+        for category in ["advertising", "malware"] {
             let cont = &contents.get(&category).unwrap();
             let cont_str = cont
                 .trim_end()
@@ -188,10 +771,328 @@ mod tests {
             want.insert(category, cont_str).unwrap_or_default();
         }
         // read from files written and compare results for each category list
-        for category in vec!["advertising", "malware"] {
-            let got = cache.read_result(&category).unwrap();
+        for category in ["advertising", "malware"] {
+            let got = cache.read_result(category).unwrap();
             let want = want.get(&category).unwrap();
             assert_eq!(want, &got);
         }
     }
+
+    #[tokio::test]
+    async fn test_output_additional_formats() {
+        let cache =
+            CacheFileCreator::new("test_output_additional_formats", CATEGORIZE_PATH, "output");
+        let mut config = cache.new_test_config();
+        config.additional_formats = vec![OutputType::Lua];
+        config.lists = vec![FilterList {
+            id: "advertising".to_string(),
+            tags: vec!["advertising".to_string()],
+            regex: r"(.*)".to_string(),
+            ..Default::default()
+        }];
+        cache.write_store("advertising", "one.domain\ntwo.domain\n");
+
+        let mut output_controller = FilterController::<StageOutput, FileInput, File> {
+            stage: PhantomData,
+            cached_lists: Some(HashSet::new()),
+            config: &config,
+            filter_lists: vec![],
+            category_lists: vec![],
+            is_processing: Arc::new(AtomicBool::new(true)),
+        };
+        let updated = output_controller.run(&cache.inpath).await.unwrap();
+        assert_eq!(updated, vec!["advertising"]);
+
+        // both formats are generated from the same entry store
+        let hosts = cache.read_result("advertising").unwrap();
+        assert_eq!(hosts, "0.0.0.0 one.domain\n0.0.0.0 two.domain\n");
+        let lua = cache.read_result("advertising.lua").unwrap();
+        let entries: Vec<String> = lua
+            .lines()
+            .flat_map(|l| OutputType::Lua.parse_entries(l, DEFAULT_ADDRESS))
+            .collect();
+        assert_eq!(entries, vec!["one.domain", "two.domain"]);
+    }
+
+    #[tokio::test]
+    async fn test_output_invalid_entries() {
+        let cache = CacheFileCreator::new("test_output_invalid_entries", CATEGORIZE_PATH, "output");
+        let mut config = cache.new_test_config();
+        config.additional_formats = vec![OutputType::Rpz];
+        config.lists = vec![FilterList {
+            id: "malware".to_string(),
+            tags: vec!["malware".to_string()],
+            regex: r"(.*)".to_string(),
+            ..Default::default()
+        }];
+        cache.write_store(
+            "malware",
+            "bad..domain
+one.domain
+",
+        );
+
+        let mut output_controller = FilterController::<StageOutput, FileInput, File> {
+            stage: PhantomData,
+            cached_lists: Some(HashSet::new()),
+            config: &config,
+            filter_lists: vec![],
+            category_lists: vec![],
+            is_processing: Arc::new(AtomicBool::new(true)),
+        };
+        // the malformed entry is dropped instead of failing every output
+        let updated = output_controller.run(&cache.inpath).await.unwrap();
+        assert_eq!(updated, vec!["malware"]);
+        assert_eq!(
+            cache.read_result("malware").unwrap(),
+            "0.0.0.0 one.domain\n"
+        );
+        let rpz = cache.read_result("malware.rpz").unwrap();
+        assert!(rpz.contains("one.domain CNAME .") && !rpz.contains("bad..domain"));
+    }
+
+    #[tokio::test]
+    async fn test_output_zone_wildcards() {
+        let cache = CacheFileCreator::new("test_output_zone_wildcards", DOWNLOAD_PATH, "output");
+        let mut config = cache.new_test_config();
+        config.additional_formats = vec![OutputType::Rpz];
+        config.lists = vec![FilterList {
+            id: "malware".to_string(),
+            tags: vec!["malware".to_string()],
+            format: Some(ListFormat::Zone { strip_origin: true }),
+            regex: r"^(.+)$".to_string(),
+            ..Default::default()
+        }];
+        cache.write_input(
+            "malware",
+            r#"$ORIGIN rpz.local.
+@ IN SOA localhost. root.localhost. 1 3600 600 86400 300
+  IN NS localhost.
+bad.com CNAME .
+*.bad.com CNAME .
+*.wild.com CNAME .
+"#,
+        );
+
+        let mut extract_controller = FilterController::<StageExtract, FileInput, File> {
+            stage: PhantomData,
+            cached_lists: Some(HashSet::new()),
+            config: &config,
+            filter_lists: vec![],
+            category_lists: vec![],
+            is_processing: Arc::new(AtomicBool::new(true)),
+        };
+        let mut categorize_controller = extract_controller
+            .run(DOWNLOAD_PATH, EXTRACT_PATH)
+            .await
+            .unwrap();
+        let mut output_controller = categorize_controller
+            .run(EXTRACT_PATH, CATEGORIZE_PATH)
+            .await
+            .unwrap();
+        // the wildcards neither fail the lint nor show up in the outputs
+        let updated = output_controller.run(CATEGORIZE_PATH).await.unwrap();
+        assert_eq!(updated, vec!["malware"]);
+        assert_eq!(
+            cache.read_result("malware").unwrap(),
+            "0.0.0.0 bad.com\n0.0.0.0 wild.com\n"
+        );
+        let rpz = cache.read_result("malware.rpz").unwrap();
+        let entries: Vec<String> = rpz
+            .lines()
+            .flat_map(|l| OutputType::Rpz.parse_entries(l, DEFAULT_ADDRESS))
+            .collect();
+        assert_eq!(entries, vec!["bad.com", "wild.com"]);
+    }
+
+    #[tokio::test]
+    async fn test_output_warn_page() {
+        let cache = CacheFileCreator::new("test_output_warn_page", CATEGORIZE_PATH, "output");
+        let mut config = cache.new_test_config();
+        config.lists = vec![FilterList {
+            id: "malware".to_string(),
+            tags: vec!["malware".to_string()],
+            regex: r"(.*)".to_string(),
+            ..Default::default()
+        }];
+        config.outputs = vec![OutputSettings {
+            tag: "malware".to_string(),
+            warn_page: Some(WarnPageSettings {
+                address: "192.0.2.80".parse().unwrap(),
+                nginx_map: true,
+            }),
+            ..Default::default()
+        }];
+        cache.write_store("malware", "one.domain\ntwo.domain\n");
+
+        let mut output_controller = FilterController::<StageOutput, FileInput, File> {
+            stage: PhantomData,
+            cached_lists: Some(HashSet::new()),
+            config: &config,
+            filter_lists: vec![],
+            category_lists: vec![],
+            is_processing: Arc::new(AtomicBool::new(true)),
+        };
+        output_controller.run(&cache.inpath).await.unwrap();
+
+        // the hosts and the map of the landing page list the same domains
+        let hosts = cache.read_result("malware").unwrap();
+        assert_eq!(hosts, "192.0.2.80 one.domain\n192.0.2.80 two.domain\n");
+        let map = cache.read_result("malware.nginx").unwrap();
+        assert!(map.ends_with(".one.domain \"malware\";\n.two.domain \"malware\";\n"));
+    }
+
+    #[tokio::test]
+    async fn test_output_encrypted() {
+        std::fs::remove_dir_all(PathBuf::from(TEST_CACHE).join("test_output_encrypted")).ok();
+        let cache = CacheFileCreator::new("test_output_encrypted", CATEGORIZE_PATH, "output");
+        let mut config = cache.new_test_config();
+        config.lists = vec![FilterList {
+            id: "malware".to_string(),
+            tags: vec!["malware".to_string()],
+            regex: r"(.*)".to_string(),
+            ..Default::default()
+        }];
+        let key_file = PathBuf::from(&config.cache_dir).join("key");
+        config.outputs = vec![OutputSettings {
+            tag: "malware".to_string(),
+            encryption: Some(EncryptionSettings {
+                key_file: key_file.to_string_lossy().to_string(),
+            }),
+            ..Default::default()
+        }];
+        cache.write_store("malware", "confidential.domain\n");
+        let controller = |config| FilterController::<StageOutput, FileInput, File> {
+            stage: PhantomData,
+            cached_lists: Some(HashSet::new()),
+            config,
+            filter_lists: vec![],
+            category_lists: vec![],
+            is_processing: Arc::new(AtomicBool::new(true)),
+        };
+
+        // without a key the output isn't published at all
+        assert!(controller(&config).run(&cache.inpath).await.is_err());
+        let path = PathBuf::from(&config.output_dir).join("malware");
+        assert!(!path.exists());
+
+        std::fs::write(&key_file, base64::encode([7u8; 32])).unwrap();
+        controller(&config).run(&cache.inpath).await.unwrap();
+        let published = std::fs::read(&path).unwrap();
+        assert!(!String::from_utf8_lossy(&published).contains("confidential"));
+        assert_eq!(
+            read_output(&path, config.output_encryption("malware")).unwrap(),
+            "0.0.0.0 confidential.domain\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_output_block_windows() {
+        let cache = CacheFileCreator::new("test_output_block_windows", CATEGORIZE_PATH, "output");
+        let mut config = cache.new_test_config();
+        config.lists = vec![FilterList {
+            id: "social".to_string(),
+            tags: vec!["social".to_string()],
+            regex: r"(.*)".to_string(),
+            ..Default::default()
+        }];
+        // a window closing when it opens never contains the current time
+        config.outputs = vec![OutputSettings {
+            tag: "social".to_string(),
+            block_windows: vec![
+                serde_json::from_str(r#"{"start": "12:00", "end": "12:00"}"#).unwrap(),
+            ],
+            ..Default::default()
+        }];
+        cache.write_store("social", "one.domain\n");
+
+        let mut output_controller = FilterController::<StageOutput, FileInput, File> {
+            stage: PhantomData,
+            cached_lists: Some(HashSet::new()),
+            config: &config,
+            filter_lists: vec![],
+            category_lists: vec![],
+            is_processing: Arc::new(AtomicBool::new(true)),
+        };
+        output_controller.run(&cache.inpath).await.unwrap();
+        assert_eq!(cache.read_result("social").unwrap(), "");
+    }
+
+    #[tokio::test]
+    async fn test_written_entries_asn() {
+        let cache = CacheFileCreator::new("test_written_entries_asn", CATEGORIZE_PATH, "output");
+        let mut config = cache.new_test_config();
+        config.lists = vec![FilterList {
+            id: "botnet".to_string(),
+            tags: vec!["botnet".to_string()],
+            regex: r"(.*)".to_string(),
+            ..Default::default()
+        }];
+        let database = PathBuf::from(TEST_CACHE).join("test_written_entries_asn.tsv");
+        std::fs::write(&database, "1.0.0.0\t1.0.0.255\t13335\tUS\tCLOUDFLARENET\n").unwrap();
+        config.asn_database = Some(database.to_string_lossy().to_string());
+        cache.write_store("botnet", "1.0.0.1\none.domain\n");
+
+        let mut output_controller = FilterController::<StageOutput, FileInput, File> {
+            stage: PhantomData,
+            cached_lists: Some(HashSet::new()),
+            config: &config,
+            filter_lists: vec![],
+            category_lists: vec![],
+            is_processing: Arc::new(AtomicBool::new(true)),
+        };
+        output_controller.run(&cache.inpath).await.unwrap();
+
+        // only IP entries are annotated with their autonomous system
+        let database = config.load_asn_database().unwrap();
+        let entries = output_controller
+            .written_entries(
+                &Path::new(&config.cache_dir).join(&cache.inpath),
+                Path::new(config.build_dir()),
+                "botnet",
+                database.as_ref(),
+            )
+            .unwrap();
+        let asns: Vec<(String, Option<u32>)> = entries
+            .into_iter()
+            .map(|e| (e.domain, e.asn.map(|r| r.asn)))
+            .collect();
+        assert_eq!(
+            asns,
+            vec![
+                ("1.0.0.1".to_string(), Some(13335)),
+                ("one.domain".to_string(), None)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unpublished_changes() {
+        let mut dir = PathBuf::from(TEST_CACHE);
+        dir.push("test_unpublished_changes");
+        std::fs::remove_dir_all(&dir).ok();
+        let set = |e: &[&str]| e.iter().map(|s| s.to_string()).collect::<BTreeSet<_>>();
+        let changes = OutputChanges {
+            tag: "malware".to_string(),
+            entries: set(&["b.domain", "c.domain"]),
+            deployed: set(&["a.domain", "b.domain"]),
+            added: vec!["c.domain".to_string()],
+            removed: vec!["a.domain".to_string()],
+        };
+        // without a published state the changes of the run are published
+        let published = PublishedFile::new(&dir, "malware");
+        assert_eq!(
+            changes.unpublished(&published).unwrap(),
+            (vec!["c.domain".to_string()], vec!["a.domain".to_string()])
+        );
+        // the changes of a run which failed to be published are published again
+        published.write(&set(&["x.domain"])).unwrap();
+        assert_eq!(
+            changes.unpublished(&published).unwrap(),
+            (
+                vec!["b.domain".to_string(), "c.domain".to_string()],
+                vec!["x.domain".to_string()]
+            )
+        );
+    }
 }
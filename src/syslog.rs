@@ -0,0 +1,223 @@
+use anyhow::Context;
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    net::{lookup_host, TcpStream, UdpSocket},
+};
+
+/// facility of the messages if no facility was configured (system daemons)
+pub const DEFAULT_FACILITY: u8 = 3;
+/// private enterprise number reserved for documentation, used for the structured data
+const ENTERPRISE_NUMBER: u32 = 32473;
+
+const SEVERITY_ERROR: u8 = 3;
+const SEVERITY_INFORMATIONAL: u8 = 6;
+
+/// SyslogTransport is the protocol the messages are sent with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum SyslogTransport {
+    /// one message per datagram (RFC 5426)
+    #[default]
+    Udp,
+    /// octet counted messages over TCP (RFC 6587)
+    Tcp,
+    /// octet counted messages over TLS (RFC 5425)
+    Tls,
+}
+
+/// RunEvent is a state change of a run reported to the log collector
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunEvent {
+    Started,
+    Finished,
+    /// the run failed with the given error
+    Failed(String),
+}
+
+impl RunEvent {
+    /// returns the message id, severity and text of the event
+    fn describe(&self) -> (&'static str, u8, String) {
+        match self {
+            RunEvent::Started => ("start", SEVERITY_INFORMATIONAL, "run started".to_string()),
+            RunEvent::Finished => ("finish", SEVERITY_INFORMATIONAL, "run finished".to_string()),
+            RunEvent::Failed(e) => ("error", SEVERITY_ERROR, format!("run failed: {}", e)),
+        }
+    }
+}
+
+/// SyslogSettings configures the notification of a log collector about runs
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SyslogSettings {
+    /// address of the log collector (e.g. "logs.example.com:514")
+    pub server: String,
+    #[serde(default)]
+    pub transport: SyslogTransport,
+    /// facility code of the messages
+    pub facility: Option<u8>,
+    /// host name the messages are sent with, the nil value is sent if it's missing
+    pub hostname: Option<String>,
+    /// application name the messages are sent with, defaults to "harvester"
+    pub app_name: Option<String>,
+}
+
+impl SyslogSettings {
+    /// sends the event to the log collector
+    ///
+    /// * `event`: the state change of the run
+    /// * `project`: the name of the project the run belongs to
+    pub async fn notify(&self, event: &RunEvent, project: Option<&str>) -> anyhow::Result<()> {
+        let message = self.message(event, project)?;
+        let addr = lookup_host(&self.server)
+            .await?
+            .next()
+            .with_context(|| format!("could not resolve syslog server {}", self.server))?;
+        match self.transport {
+            SyslogTransport::Udp => {
+                let local = if addr.is_ipv6() {
+                    "[::]:0"
+                } else {
+                    "0.0.0.0:0"
+                };
+                let socket = UdpSocket::bind(local).await?;
+                socket.send_to(message.as_bytes(), addr).await?;
+            }
+            SyslogTransport::Tcp => {
+                let mut stream = TcpStream::connect(addr).await?;
+                send_framed(&mut stream, &message).await?;
+            }
+            SyslogTransport::Tls => {
+                let stream = TcpStream::connect(addr).await?;
+                let connector = tokio_native_tls::TlsConnector::from(
+                    native_tls::TlsConnector::new().with_context(|| "could not initialize TLS")?,
+                );
+                let host = self.server.rsplit_once(':').map_or(&*self.server, |s| s.0);
+                let mut stream = connector
+                    .connect(host.trim_start_matches('[').trim_end_matches(']'), stream)
+                    .await
+                    .with_context(|| format!("TLS handshake with {} failed", self.server))?;
+                send_framed(&mut stream, &message).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// returns the RFC 5424 message of the event
+    ///
+    /// * `event`: the state change of the run
+    /// * `project`: the name of the project the run belongs to
+    fn message(&self, event: &RunEvent, project: Option<&str>) -> anyhow::Result<String> {
+        let facility = self.facility.unwrap_or(DEFAULT_FACILITY);
+        if facility > 23 {
+            return Err(anyhow::anyhow!("invalid syslog facility {}", facility));
+        }
+        let (msg_id, severity, text) = event.describe();
+        let structured_data = match project {
+            Some(project) => format!(
+                "[harvester@{} project=\"{}\"]",
+                ENTERPRISE_NUMBER,
+                escape_param(project)
+            ),
+            None => "-".to_string(),
+        };
+        Ok(format!(
+            "<{}>1 {} {} {} {} {} {} {}",
+            facility * 8 + severity,
+            Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            self.hostname.as_deref().unwrap_or("-"),
+            self.app_name.as_deref().unwrap_or("harvester"),
+            std::process::id(),
+            msg_id,
+            structured_data,
+            text
+        ))
+    }
+}
+
+/// escapes the characters which terminate a structured data parameter value
+///
+/// * `value`: the parameter value
+fn escape_param(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace(']', "\\]")
+}
+
+/// writes the message prefixed by its length in octets
+///
+/// * `stream`: the connection to the log collector
+/// * `message`: the syslog message
+async fn send_framed<S: AsyncWrite + Unpin>(stream: &mut S, message: &str) -> anyhow::Result<()> {
+    stream
+        .write_all(format!("{} {}", message.len(), message).as_bytes())
+        .await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{io::AsyncReadExt, net::TcpListener};
+
+    use super::*;
+
+    fn settings(server: String, transport: SyslogTransport) -> SyslogSettings {
+        SyslogSettings {
+            server,
+            transport,
+            facility: None,
+            hostname: Some("appliance".to_string()),
+            app_name: None,
+        }
+    }
+
+    #[test]
+    fn test_message() {
+        let settings = settings("127.0.0.1:514".to_string(), SyslogTransport::Udp);
+        let message = settings
+            .message(&RunEvent::Failed("boom".to_string()), Some("a\"b"))
+            .unwrap();
+        // daemon facility with error severity
+        assert!(message.starts_with("<27>1 "));
+        assert!(message.contains(" appliance harvester "));
+        assert!(message.ends_with(" error [harvester@32473 project=\"a\\\"b\"] run failed: boom"));
+
+        let message = settings.message(&RunEvent::Started, None).unwrap();
+        assert!(message.starts_with("<30>1 "));
+        assert!(message.ends_with(" start - run started"));
+    }
+
+    #[tokio::test]
+    async fn test_notify_udp() {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let settings = settings(
+            socket.local_addr().unwrap().to_string(),
+            SyslogTransport::Udp,
+        );
+        settings.notify(&RunEvent::Finished, None).await.unwrap();
+        let mut buf = [0; 1024];
+        let len = socket.recv(&mut buf).await.unwrap();
+        assert!(String::from_utf8_lossy(&buf[..len]).ends_with("finish - run finished"));
+    }
+
+    #[tokio::test]
+    async fn test_notify_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let settings = settings(
+            listener.local_addr().unwrap().to_string(),
+            SyslogTransport::Tcp,
+        );
+        let collector = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut received = String::new();
+            stream.read_to_string(&mut received).await.unwrap();
+            received
+        });
+        settings.notify(&RunEvent::Started, None).await.unwrap();
+        let received = collector.await.unwrap();
+        let (len, message) = received.split_once(' ').unwrap();
+        assert_eq!(len.parse::<usize>().unwrap(), message.len());
+        assert!(message.ends_with("run started"));
+    }
+}
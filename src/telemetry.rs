@@ -0,0 +1,106 @@
+use futures::Future;
+use opentelemetry::{
+    global,
+    sdk::{trace, Resource},
+    trace::{FutureExt as _, TraceContextExt, Tracer},
+    Context, KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+
+/// name of the tracer creating all spans
+const TRACER_NAME: &str = "harvester";
+/// service name reported if none was configured
+const DEFAULT_SERVICE_NAME: &str = "harvester";
+
+/// TelemetrySettings configures the export of traces to an OTLP collector
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TelemetrySettings {
+    /// OTLP/HTTP endpoint receiving the traces (e.g. "http://localhost:4318/v1/traces")
+    pub endpoint: String,
+    /// the service name the spans are reported with
+    pub service_name: Option<String>,
+}
+
+/// installs the OTLP exporter as global tracer provider. Without an installed
+/// exporter spans are discarded.
+///
+/// * `settings`: the telemetry configuration
+pub fn init(settings: &TelemetrySettings) -> anyhow::Result<()> {
+    let service_name = settings
+        .service_name
+        .clone()
+        .unwrap_or_else(|| DEFAULT_SERVICE_NAME.to_string());
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .http()
+                .with_endpoint(&settings.endpoint),
+        )
+        .with_trace_config(
+            trace::config().with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                service_name,
+            )])),
+        )
+        .install_batch(opentelemetry::runtime::Tokio)
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "could not set up OTLP export to {}: {}",
+                settings.endpoint,
+                e
+            )
+        })?;
+    Ok(())
+}
+
+/// exports all pending spans
+pub async fn shutdown() {
+    // the batch exporter blocks until all spans are sent
+    if let Err(e) = tokio::task::spawn_blocking(global::shutdown_tracer_provider).await {
+        warn!("could not export pending spans: {}", e);
+    }
+}
+
+/// runs the future within a new span which is a child of the current span. The span
+/// ends when the future completes, so its duration is the time the future took.
+///
+/// * `name`: the name of the span
+/// * `attributes`: attributes attached to the span
+/// * `future`: the operation to be traced
+pub async fn traced<F: Future>(
+    name: &'static str,
+    attributes: Vec<KeyValue>,
+    future: F,
+) -> F::Output {
+    let tracer = global::tracer(TRACER_NAME);
+    let span = tracer
+        .span_builder(name)
+        .with_attributes(attributes)
+        .start(&tracer);
+    let cx = Context::current_with_span(span);
+    let output = future.with_context(cx.clone()).await;
+    cx.span().end();
+    output
+}
+
+/// spawns a task running the future within a new span which is a child of the
+/// current span
+///
+/// * `name`: the name of the span
+/// * `attributes`: attributes attached to the span
+/// * `future`: the operation to be traced
+pub fn spawn_traced<F>(
+    name: &'static str,
+    attributes: Vec<KeyValue>,
+    future: F,
+) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    // the current span has to be passed explicitly since the task may run on another thread
+    tokio::spawn(traced(name, attributes, future).with_context(Context::current()))
+}
@@ -5,7 +5,10 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use crate::config::Config;
+use crate::{
+    config::Config,
+    io::entry_store::{Entry, EntryStoreWriter},
+};
 
 pub const TEST_CACHE: &str = "test_cache";
 
@@ -19,8 +22,8 @@ pub struct CacheFileCreator {
 fn namespace_path(namespace: impl AsRef<Path>, dir: Option<impl AsRef<Path>>) -> PathBuf {
     let mut target_path = PathBuf::from(TEST_CACHE);
     target_path.push(namespace);
-    if dir.is_some() {
-        target_path.push(dir.unwrap());
+    if let Some(dir) = dir {
+        target_path.push(dir);
     }
     target_path
 }
@@ -52,6 +55,25 @@ impl CacheFileCreator {
         infile.write_all(input.as_bytes()).unwrap();
     }
 
+    /// writes the sorted lines of the input as entry store of a category
+    pub fn write_store(&self, name: &str, input: &str) {
+        let dir = namespace_path(&self.namespace, Some(&self.inpath));
+        let mut domains = File::create(dir.join(name)).unwrap();
+        let mut store =
+            EntryStoreWriter::create(&dir, name, vec![name.to_string()], &mut domains).unwrap();
+        for line in input.lines().filter(|l| !l.is_empty()) {
+            store
+                .push(&Entry {
+                    domain: line.to_string(),
+                    source: 0,
+                    flags: 0,
+                    score: 0,
+                })
+                .unwrap();
+        }
+        store.finish().unwrap();
+    }
+
     pub fn new_test_config(&self) -> Config {
         Config {
             lists: vec![],
@@ -64,6 +86,35 @@ impl CacheFileCreator {
                 .unwrap()
                 .to_string(),
             output_format: crate::output::OutputType::Hostsfile,
+            additional_formats: vec![],
+            staging_dir: None,
+            spool_threshold: None,
+            outputs: vec![],
+            post_processing: vec![],
+            hooks: vec![],
+            projects: vec![],
+            telemetry: None,
+            health: None,
+            mqtt: None,
+            kafka: None,
+            syslog: None,
+            elasticsearch: None,
+            clickhouse: None,
+            asn_database: None,
+            schedule_jitter: None,
+            max_concurrent_downloads: None,
+            memory_budget: None,
+            batch_size: None,
+            proxy: None,
+            timeouts: None,
+            redirects: None,
+            http_client: None,
+            backup_dir: None,
+            shared_cache: None,
+            peer: None,
+            audit_log: None,
+            input_store: None,
+            project: None,
             cached_config: None,
         }
     }
@@ -71,8 +122,8 @@ impl CacheFileCreator {
     pub fn read_result(&self, list_id: &str) -> anyhow::Result<String> {
         let mut outfile_path = namespace_path(&self.namespace, Some(&self.outpath));
         outfile_path.push(list_id);
-        let result = fs::read_to_string(&outfile_path)
-            .with_context(|| format!("{} not found", &outfile_path.to_str().unwrap()));
-        result
+
+        fs::read_to_string(&outfile_path)
+            .with_context(|| format!("{} not found", &outfile_path.to_str().unwrap()))
     }
 }
@@ -1,4 +1,5 @@
 use async_trait::async_trait;
+use bytes::Bytes;
 use std::io::{BufRead, BufReader, Cursor};
 
 use crate::input::Input;
@@ -23,13 +24,13 @@ impl CursorInput {
 
 #[async_trait]
 impl Input for CursorInput {
-    async fn chunk(&mut self) -> anyhow::Result<Option<Vec<u8>>> {
+    async fn chunk(&mut self) -> anyhow::Result<Option<Bytes>> {
         let mut line = String::new();
         let n = self.cursor.read_line(&mut line)?;
         if n == 0 {
             Ok(None)
         } else {
-            Ok(Some(line.as_bytes().to_vec()))
+            Ok(Some(Bytes::from(line)))
         }
     }
 
@@ -0,0 +1,271 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Context;
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+
+use crate::{
+    config::{self, Config},
+    input::{dir::pattern_base, file::is_pattern, source::SourceType},
+};
+
+/// time to wait for further events after a modification was noticed
+const DEBOUNCE: Duration = Duration::from_millis(500);
+/// number of file system events buffered
+const EVENT_BUFFER: usize = 64;
+/// a `*` of a pattern doesn't match the separator, like when the pattern is expanded
+const PATTERN_OPTIONS: glob::MatchOptions = glob::MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: true,
+};
+
+/// FileWatcher notifies about modifications of local file sources
+pub struct FileWatcher {
+    /// the watcher has to be kept alive as long as events are received
+    _watcher: RecommendedWatcher,
+    rx: mpsc::Receiver<notify::Result<Event>>,
+    /// maps the watched file paths to the list ids
+    sources: HashMap<PathBuf, String>,
+    /// the watched directories of Dir sources and their list ids
+    dirs: Vec<(PathBuf, String)>,
+    /// the glob patterns of File sources and their list ids
+    patterns: Vec<(glob::Pattern, String)>,
+}
+
+impl FileWatcher {
+    /// Starts watching the file sources of the configured lists
+    ///
+    /// * `config`: the configuration containing the lists
+    pub fn new(config: &Config) -> anyhow::Result<Self> {
+        let (tx, rx) = mpsc::channel(EVENT_BUFFER);
+        let mut watcher = RecommendedWatcher::new(
+            move |event: notify::Result<Event>| {
+                tx.blocking_send(event).ok();
+            },
+            notify::Config::default(),
+        )?;
+
+        let mut sources = HashMap::new();
+        let mut patterns = vec![];
+        for list in config
+            .all_lists()?
+            .iter()
+            .filter(|l| l.source_type() == SourceType::File)
+        {
+            // the directory in front of the wildcards is watched for matching files
+            if is_pattern(&list.source) {
+                let (base, rest) = pattern_base(&list.source);
+                let base = base.canonicalize().with_context(|| {
+                    format!("{}: directory of {} not found", list.id, list.source)
+                })?;
+                // event paths are absolute, so the pattern has to be as well
+                let absolute =
+                    Path::new(&glob::Pattern::escape(&base.to_string_lossy())).join(&rest);
+                let pattern = glob::Pattern::new(&absolute.to_string_lossy())
+                    .with_context(|| format!("{}: invalid pattern {}", list.id, list.source))?;
+                let mode = match rest.components().count() > 1 {
+                    true => RecursiveMode::Recursive,
+                    false => RecursiveMode::NonRecursive,
+                };
+                watcher.watch(&base, mode)?;
+                patterns.push((pattern, list.id.clone()));
+                continue;
+            }
+            // the parent directory is watched since editors tend to replace files on save
+            let path = Path::new(&list.source);
+            let dir = match path.parent() {
+                Some(p) if !p.as_os_str().is_empty() => p,
+                _ => Path::new("."),
+            };
+            let dir = dir
+                .canonicalize()
+                .with_context(|| format!("{}: directory of {} not found", list.id, list.source))?;
+            let file_name = path
+                .file_name()
+                .with_context(|| format!("{}: {} is not a file", list.id, list.source))?;
+            watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+            sources.insert(dir.join(file_name), list.id.clone());
+        }
+        let mut dirs = vec![];
+        for list in config
+            .all_lists()?
+            .iter()
+            .filter(|l| l.source_type() == SourceType::Dir)
+        {
+            let dir = Path::new(&list.source)
+                .canonicalize()
+                .with_context(|| format!("{}: directory {} not found", list.id, list.source))?;
+            let mode = match list.recursive {
+                true => RecursiveMode::Recursive,
+                false => RecursiveMode::NonRecursive,
+            };
+            watcher.watch(&dir, mode)?;
+            dirs.push((dir, list.id.clone()));
+        }
+        if sources.is_empty() && dirs.is_empty() && patterns.is_empty() {
+            warn!("No file sources configured to be watched");
+        }
+
+        Ok(Self {
+            _watcher: watcher,
+            rx,
+            sources,
+            dirs,
+            patterns,
+        })
+    }
+
+    /// waits for modifications and returns the ids of the lists whose source changed
+    pub async fn changed_lists(&mut self) -> Option<Vec<String>> {
+        let mut changed_lists = vec![];
+        while changed_lists.is_empty() {
+            let event = self.rx.recv().await?;
+            self.collect(event, &mut changed_lists);
+        }
+        // a single save usually causes several events
+        tokio::time::sleep(DEBOUNCE).await;
+        while let Ok(event) = self.rx.try_recv() {
+            self.collect(event, &mut changed_lists);
+        }
+        Some(changed_lists)
+    }
+
+    /// adds the list id affected by the event to the changed lists
+    ///
+    /// * `event`: the file system event
+    /// * `changed_lists`: the ids of the lists modified so far
+    fn collect(&self, event: notify::Result<Event>, changed_lists: &mut Vec<String>) {
+        let event = match event {
+            Ok(e) => e,
+            Err(e) => {
+                warn!("{}", e);
+                return;
+            }
+        };
+        // a removed file only changes a directory or the matches of a pattern, a removed
+        // file source can't be read
+        let is_removal = event.kind.is_remove();
+        if !event.kind.is_modify() && !event.kind.is_create() && !is_removal {
+            return;
+        }
+        for path in event.paths.iter() {
+            let file_id = self.sources.get(path).filter(|_| !is_removal);
+            let dir_ids = self
+                .dirs
+                .iter()
+                .filter(|(dir, _)| path.starts_with(dir))
+                .map(|(_, id)| id);
+            let pattern_ids = self
+                .patterns
+                .iter()
+                .filter(|(pattern, _)| pattern.matches_path_with(path, PATTERN_OPTIONS))
+                .map(|(_, id)| id);
+            for id in file_id.into_iter().chain(dir_ids).chain(pattern_ids) {
+                if !changed_lists.contains(id) {
+                    changed_lists.push(id.clone());
+                }
+            }
+        }
+    }
+}
+
+/// ConfigWatcher notifies about modifications of the configuration file or directory.
+/// Kubernetes updates a mounted ConfigMap by atomically swapping a symlink, so instead
+/// of tracking single files the merged content is compared after every event.
+pub struct ConfigWatcher {
+    /// the watcher has to be kept alive as long as events are received
+    _watcher: RecommendedWatcher,
+    rx: mpsc::Receiver<notify::Result<Event>>,
+    path: PathBuf,
+    /// the content of the configuration which was loaded last
+    revision: Option<String>,
+}
+
+impl ConfigWatcher {
+    /// Starts watching the configuration
+    ///
+    /// * `path`: file system path of the configuration file or directory
+    pub fn new(path: &Path) -> anyhow::Result<Self> {
+        let (tx, rx) = mpsc::channel(EVENT_BUFFER);
+        let mut watcher = RecommendedWatcher::new(
+            move |event: notify::Result<Event>| {
+                tx.blocking_send(event).ok();
+            },
+            notify::Config::default(),
+        )?;
+        let dir = match path.is_dir() {
+            true => path,
+            false => match path.parent() {
+                Some(p) if !p.as_os_str().is_empty() => p,
+                _ => Path::new("."),
+            },
+        };
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .with_context(|| format!("could not watch {:?}", dir))?;
+
+        Ok(Self {
+            _watcher: watcher,
+            rx,
+            path: path.to_path_buf(),
+            revision: Self::revision(path),
+        })
+    }
+
+    /// returns the merged content of the configuration
+    ///
+    /// * `path`: file system path of the configuration file or directory
+    fn revision(path: &Path) -> Option<String> {
+        config::load_value(path).ok().map(|v| v.to_string())
+    }
+
+    /// waits until the content of the configuration changed
+    pub async fn changed(&mut self) -> Option<()> {
+        loop {
+            if let Err(e) = self.rx.recv().await? {
+                warn!("{}", e);
+            }
+            // a rotation of the mount causes several events
+            tokio::time::sleep(DEBOUNCE).await;
+            while self.rx.try_recv().is_ok() {}
+
+            let revision = Self::revision(&self.path);
+            if revision.is_some() && revision != self.revision {
+                self.revision = revision;
+                return Some(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{filter_list::FilterList, tests::helper::cache_file_creator::CacheFileCreator};
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_file_watcher() {
+        let cache = CacheFileCreator::new("test_file_watcher", "source", "output");
+        let mut config = cache.new_test_config();
+        config.lists = vec![FilterList {
+            id: "local".to_string(),
+            source: format!("{}/source/local", config.cache_dir),
+            source_type: Some(SourceType::File),
+            ..Default::default()
+        }];
+        cache.write_input("local", "one.domain\n");
+
+        let mut watcher = FileWatcher::new(&config).unwrap();
+        cache.write_input("local", "one.domain\ntwo.domain\n");
+        let got = tokio::time::timeout(Duration::from_secs(5), watcher.changed_lists())
+            .await
+            .unwrap();
+        assert_eq!(got, Some(vec!["local".to_string()]));
+    }
+}
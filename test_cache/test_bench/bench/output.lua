@@ -0,0 +1,102 @@
+return {
+  "akhkzqfsfejdv-76.example.com",
+  "allmwsd-60.example.com",
+  "asxhmumsh-74.example.com",
+  "axsdxsuvvs-22.example.com",
+  "bbey-75.example.com",
+  "bhtdqjyrwccsl-3.example.com",
+  "cktw-72.example.com",
+  "cnqqmoumjtxppoovith-84.example.com",
+  "covmoqetqthrb-68.example.com",
+  "ctyimxprubbamb-86.example.com",
+  "dlcldyjcbe-27.example.com",
+  "dttxlczvkgabujrrtn-97.example.com",
+  "dvglajwfedu-31.example.com",
+  "dwcvrbzavhzy-93.example.com",
+  "efdidpf-88.example.com",
+  "esxqntpqubdh-56.example.com",
+  "exctxcwcfglnoptb-42.example.com",
+  "fuafsvqwaah-59.example.com",
+  "fynwerwhwjxctxz-21.example.com",
+  "gascejmfxsvzudnj-50.example.com",
+  "gddjbz-30.example.com",
+  "glassbqawnrgn-44.example.com",
+  "gosbvsklkql-66.example.com",
+  "gvykzlo-64.example.com",
+  "gxufcoquiolq-70.example.com",
+  "gxwioktbj-6.example.com",
+  "hbkg-61.example.com",
+  "hnfulafjwcbsvlbkx-45.example.com",
+  "hpliptbus-11.example.com",
+  "ibelsqlpsuymhevpaji-20.example.com",
+  "icszrndiw-10.example.com",
+  "icudamznavsnweokwn-4.example.com",
+  "ideobarl-58.example.com",
+  "iowkj-52.example.com",
+  "isqeocyw-48.example.com",
+  "jihnzopdemgwqmrl-47.example.com",
+  "jrqgyjeoanpfj-67.example.com",
+  "juvayxnecrdzmzxsaz-65.example.com",
+  "kefzdyvklj-28.example.com",
+  "kfkdlktxmpfurfksw-18.example.com",
+  "lbggn-83.example.com",
+  "ldhvmpqmccxxhnwlf-33.example.com",
+  "lkmpfhaneripan-73.example.com",
+  "lzvvdurqhkmwp-53.example.com",
+  "mjkhdecnxuj-0.example.com",
+  "mqvcntedzbtvigifob-8.example.com",
+  "mrzfqassqusettqukva-94.example.com",
+  "msixlaipexc-14.example.com",
+  "nxxrgvorvlekgo-19.example.com",
+  "oaihcrdvaqnjohaeyo-62.example.com",
+  "oecbpsay-90.example.com",
+  "oimrmprmw-12.example.com",
+  "oxlsbmu-82.example.com",
+  "pjup-79.example.com",
+  "pktl-77.example.com",
+  "pptrut-41.example.com",
+  "pqori-55.example.com",
+  "pujpszkdzxy-49.example.com",
+  "pytxmuhtk-29.example.com",
+  "qhncwnixbqj-24.example.com",
+  "qpdurbexlsccyicw-16.example.com",
+  "rfjccsudwjtpkqkmbb-15.example.com",
+  "rkmjtmoi-57.example.com",
+  "rllggjnblasmsq-71.example.com",
+  "rozlyeilbmlodqu-99.example.com",
+  "rxdvyjbsirrdzdgjf-25.example.com",
+  "sdbx-98.example.com",
+  "skbwvdvyrcylx-32.example.com",
+  "stfmbdjbjjjpx-46.example.com",
+  "tpluznjxbzfzqepaxh-95.example.com",
+  "tuaq-9.example.com",
+  "tvcbbhj-23.example.com",
+  "twwqunbzbi-85.example.com",
+  "txagczysl-5.example.com",
+  "txflaebp-69.example.com",
+  "ucmupwrlxvqngxbgxc-78.example.com",
+  "ukseo-92.example.com",
+  "umlhfxwrrxurgxtalk-2.example.com",
+  "unhouvxdcgarkkol-40.example.com",
+  "urizwhrqidvarkqrull-36.example.com",
+  "uzyohuaxvgmszxh-34.example.com",
+  "vdiv-7.example.com",
+  "vgywa-35.example.com",
+  "vqzlhgdsuyrb-39.example.com",
+  "vvdbll-81.example.com",
+  "wcoesattjldpbybhc-96.example.com",
+  "weuexclrhmsqul-38.example.com",
+  "xclermmpk-37.example.com",
+  "xltyjwli-89.example.com",
+  "xqzelkkom-51.example.com",
+  "xshwssq-1.example.com",
+  "xwncea-43.example.com",
+  "ykqfrtbidetgp-26.example.com",
+  "yowmaiuzjjryszuyga-54.example.com",
+  "yzvllutamioihmhfa-80.example.com",
+  "zjeuxsqyukujqu-87.example.com",
+  "znbcdmtacohjqh-17.example.com",
+  "zqrlsawq-13.example.com",
+  "zqukiqohnru-63.example.com",
+  "ztsvgtidbjjdmuej-91.example.com",
+}
\ No newline at end of file
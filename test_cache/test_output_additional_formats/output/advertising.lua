@@ -0,0 +1,4 @@
+return {
+  "one.domain",
+  "two.domain",
+}
\ No newline at end of file